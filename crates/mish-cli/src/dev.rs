@@ -0,0 +1,151 @@
+//! `mish-cli dev`: syncs a scripts directory to the server and prints what the scripts do.
+//!
+//! The directory's `mish.json` maps files to where their links go, e.g.
+//! `{"fish_tank.rhai": {"mish_state_name": "run", "path": "$.fish_tank.rhai"}}`.
+
+use {
+    super::{api_url, check, for_each_event, patch_mish_state, upload_raw},
+    anyhow::{Context, anyhow},
+    reqwest::{Client, Response, Url},
+    std::{collections::BTreeMap, path::Path, time::Duration},
+};
+
+/// How long to wait before subscribing to the script logs again after the stream ends
+const LOG_RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+struct ManifestEntry {
+    mish_state_name: String,
+    path: String,
+}
+
+fn parse_manifest(manifest: serde_json::Value) -> anyhow::Result<BTreeMap<String, ManifestEntry>> {
+    let serde_json::Value::Object(manifest) = manifest else {
+        return Err(anyhow!("mish.json must be an object of file names"));
+    };
+    manifest
+        .into_iter()
+        .map(|(file, entry)| {
+            let field = |name: &str| {
+                entry
+                    .get(name)
+                    .and_then(|value| value.as_str())
+                    .map(str::to_owned)
+                    .ok_or_else(|| anyhow!("{file} in mish.json has no {name} string"))
+            };
+            let entry = ManifestEntry {
+                mish_state_name: field("mish_state_name")?,
+                path: field("path")?,
+            };
+            Ok((file, entry))
+        })
+        .collect()
+}
+
+async fn subscribe_logs(client: &Client, server_url: &Url) -> anyhow::Result<Response> {
+    check(
+        client
+            .get(api_url(server_url, &["scripts", "logs"]))
+            .send()
+            .await?,
+    )
+    .await
+    .context("Failed to subscribe to script logs")
+}
+
+fn print_log(data: &str) {
+    let Ok(log) = serde_json::from_str::<serde_json::Value>(data) else {
+        println!("{data}");
+        return;
+    };
+    let field = |name: &str| log.get(name).and_then(|value| value.as_str()).unwrap_or("");
+    println!(
+        "[{}] {}: {}",
+        field("script"),
+        field("level"),
+        field("message")
+    );
+}
+
+pub async fn dev(
+    client: &Client,
+    server_url: &Url,
+    directory: &Path,
+    interval_ms: u64,
+) -> anyhow::Result<()> {
+    let manifest_path = directory.join("mish.json");
+    let manifest = tokio::fs::read(&manifest_path)
+        .await
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    let manifest = serde_json::from_slice(&manifest)
+        .with_context(|| format!("{} is not JSON", manifest_path.display()))?;
+    let manifest = parse_manifest(manifest)?;
+
+    let logs = subscribe_logs(client, server_url).await?;
+    tokio::spawn({
+        let (client, server_url) = (client.clone(), server_url.clone());
+        async move {
+            let mut logs = Some(logs);
+            loop {
+                if let Some(logs) = logs.take() {
+                    match for_each_event(logs, print_log).await {
+                        Ok(()) => eprintln!("Script log stream closed by the server, reconnecting"),
+                        Err(e) => eprintln!("Script log stream failed, reconnecting: {e:#}"),
+                    }
+                }
+                tokio::time::sleep(LOG_RECONNECT_DELAY).await;
+                match subscribe_logs(&client, &server_url).await {
+                    Ok(stream) => logs = Some(stream),
+                    Err(e) => eprintln!("{e:#}"),
+                }
+            }
+        }
+    });
+
+    // Content is compared rather than modification times so saving without changes is a no-op
+    let mut uploaded = BTreeMap::<String, Vec<u8>>::new();
+    // Failed content is retried every tick but only reported once
+    let mut failing = BTreeMap::<String, Vec<u8>>::new();
+    let mut interval = tokio::time::interval(Duration::from_millis(interval_ms));
+    loop {
+        interval.tick().await;
+        for (file, entry) in &manifest {
+            let content = match tokio::fs::read(directory.join(file)).await {
+                Ok(content) => content,
+                // Editors briefly remove files while saving, try again next tick
+                Err(_) => continue,
+            };
+            if uploaded.get(file) == Some(&content) {
+                continue;
+            }
+            let result = async {
+                let cid = upload_raw(client, server_url, content.clone()).await?;
+                patch_mish_state(
+                    client,
+                    server_url,
+                    &entry.mish_state_name,
+                    &entry.path,
+                    serde_json::json!({"/": cid}),
+                )
+                .await?;
+                anyhow::Ok(cid)
+            }
+            .await;
+            match result {
+                Ok(cid) => {
+                    eprintln!(
+                        "Uploaded {file} to {} {}: {cid}",
+                        entry.mish_state_name, entry.path
+                    );
+                    failing.remove(file);
+                    uploaded.insert(file.clone(), content);
+                }
+                Err(e) => {
+                    if failing.get(file) != Some(&content) {
+                        eprintln!("Failed to sync {file}, retrying until it works: {e:#}");
+                        failing.insert(file.clone(), content);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,23 @@
+mod dev;
 mod options;
-use {clap::Parser, options::Options, reqwest::Url};
+use {
+    anyhow::{Context, anyhow},
+    clap::Parser,
+    options::{Operation, Options},
+    reqwest::{Client, Response, Url},
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        path::Path,
+    },
+    tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+
+const DAG_JSON_CONTENT_TYPE: &str = "application/vnd.ipld.dag-json";
+const DAG_CBOR_CONTENT_TYPE: &str = "application/vnd.ipld.dag-cbor";
 
 #[tokio::main]
 async fn main() {
     let options = Options::parse();
-    println!("{:?}", options);
 
     let server_url = options
         .server_url
@@ -12,56 +25,391 @@ async fn main() {
 
     let client = reqwest::Client::new();
 
-    match options.operation {
-        options::Operation::UploadFile {
-            file_path,
-            mish_state_name,
-            path,
-        } => {
-            let data = tokio::fs::read(&file_path).await.unwrap();
-            let req = if file_path.ends_with(".json") {
-                let json = serde_json::from_slice::<serde_json::Value>(&data).unwrap();
-                client
-                    .post(server_url.join("/api/mish/blob.dag-json").unwrap())
-                    .json(&json)
-            } else {
-                client
-                    .post(server_url.join("/api/mish/blob.raw").unwrap())
-                    .body(data)
-                // .header("Content-Type", "application/octet-stream")
+    if let Err(e) = run(&client, &server_url, options.operation).await {
+        eprintln!("{e:#}");
+        std::process::exit(1);
+    }
+}
+
+/// `/api/mish/...` with each segment percent-encoded, so mish state names can be anything.
+fn api_url(server_url: &Url, segments: &[&str]) -> Url {
+    let mut url = server_url.clone();
+    url.path_segments_mut()
+        .expect("server URL must be a base URL")
+        .clear()
+        .extend(["api", "mish"])
+        .extend(segments);
+    url
+}
+
+/// Turns non-2xx responses into errors carrying the server's message.
+async fn check(response: Response) -> anyhow::Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        let text = response.text().await.unwrap_or_default();
+        Err(anyhow!("{status}: {text}"))
+    }
+}
+
+fn print_json(value: &serde_json::Value) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn upload_file(
+    client: &Client,
+    server_url: &Url,
+    file_path: &Path,
+) -> anyhow::Result<String> {
+    let data = tokio::fs::read(file_path)
+        .await
+        .with_context(|| format!("Failed to read {}", file_path.display()))?;
+    if file_path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        let json = serde_json::from_slice::<serde_json::Value>(&data)
+            .with_context(|| format!("{} is not JSON", file_path.display()))?;
+        let req = client
+            .post(api_url(server_url, &["blob.dag-json"]))
+            .json(&json);
+        let result = check(req.send().await?)
+            .await
+            .with_context(|| format!("Failed to upload {}", file_path.display()))?;
+        Ok(result.json::<String>().await?)
+    } else {
+        upload_raw(client, server_url, data)
+            .await
+            .with_context(|| format!("Failed to upload {}", file_path.display()))
+    }
+}
+
+async fn upload_raw(client: &Client, server_url: &Url, data: Vec<u8>) -> anyhow::Result<String> {
+    let req = client.post(api_url(server_url, &["blob.raw"])).body(data);
+    let result = check(req.send().await?).await?;
+    Ok(result.json::<String>().await?)
+}
+
+async fn patch_mish_state(
+    client: &Client,
+    server_url: &Url,
+    name: &str,
+    path: &str,
+    content: serde_json::Value,
+) -> anyhow::Result<()> {
+    let json = serde_json::json!({
+        "mish_state_name": name,
+        "path": path,
+        "content": content,
+    });
+    let req = client.post(api_url(server_url, &["state"])).json(&json);
+    check(req.send().await?)
+        .await
+        .with_context(|| format!("Failed to update mish state {name}"))?;
+    Ok(())
+}
+
+async fn copy_body(
+    mut response: Response,
+    output: &mut (impl AsyncWrite + Unpin),
+) -> anyhow::Result<u64> {
+    let mut written = 0;
+    while let Some(chunk) = response.chunk().await? {
+        output.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+    }
+    output.flush().await?;
+    Ok(written)
+}
+
+/// Calls `f` with the data of each server-sent event until the stream ends. Every event the
+/// server sends is a single `data:` line of JSON.
+async fn for_each_event(mut response: Response, mut f: impl FnMut(&str)) -> anyhow::Result<()> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line = buffer.drain(..=end).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                f(data.trim_start());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Collects the `{".": "file"}` references in a run.json.
+fn file_references(value: &serde_json::Value, references: &mut BTreeSet<String>) {
+    match value {
+        serde_json::Value::Object(map) => match (map.len(), map.get(".")) {
+            (1, Some(serde_json::Value::String(file))) => {
+                references.insert(file.clone());
+            }
+            _ => map
+                .values()
+                .for_each(|value| file_references(value, references)),
+        },
+        serde_json::Value::Array(list) => list
+            .iter()
+            .for_each(|value| file_references(value, references)),
+        _ => {}
+    }
+}
+
+/// Replaces `{".": "file"}` references with `{"/": cid}` links.
+fn link_file_references(value: &mut serde_json::Value, cids: &BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let cid = match (map.len(), map.get(".")) {
+                (1, Some(serde_json::Value::String(file))) => cids.get(file),
+                _ => None,
             };
-            let result = req.send().await.unwrap();
-            let cid = if result.status().is_success() {
-                let cid = result.json::<String>().await.unwrap();
-                println!("Uploaded file to Mish: {cid}");
-                cid
+            match cid {
+                Some(cid) => *value = serde_json::json!({"/": cid}),
+                None => map
+                    .values_mut()
+                    .for_each(|value| link_file_references(value, cids)),
+            }
+        }
+        serde_json::Value::Array(list) => list
+            .iter_mut()
+            .for_each(|value| link_file_references(value, cids)),
+        _ => {}
+    }
+}
+
+async fn run(client: &Client, server_url: &Url, operation: Operation) -> anyhow::Result<()> {
+    match operation {
+        Operation::List { full } => {
+            let result = check(client.get(api_url(server_url, &["states"])).send().await?)
+                .await
+                .context("Failed to list mish states")?;
+            let states = result.json::<Vec<serde_json::Value>>().await?;
+            if full {
+                print_json(&serde_json::Value::Array(states))
             } else {
-                panic!(
-                    "Failed to upload file to Mish: {}, {:?}",
-                    result.status(),
-                    result.text().await
-                );
+                print_json(&serde_json::Value::Array(
+                    states
+                        .into_iter()
+                        .filter_map(|state| state.get("name").cloned())
+                        .collect(),
+                ))
+            }
+        }
+        Operation::Get { name, path } => {
+            let value = match path {
+                None => {
+                    let url = api_url(server_url, &["state", name.as_str()]);
+                    check(client.get(url).send().await?)
+                        .await
+                        .with_context(|| format!("Failed to get mish state {name}"))?
+                        .json::<serde_json::Value>()
+                        .await?
+                }
+                Some(path) => {
+                    let mut segments = vec!["resolve", name.as_str()];
+                    segments.extend(path.split('/').filter(|segment| !segment.is_empty()));
+                    let resolved = check(client.get(api_url(server_url, &segments)).send().await?)
+                        .await
+                        .with_context(|| format!("Failed to resolve {name}/{path}"))?
+                        .json::<serde_json::Value>()
+                        .await?;
+                    resolved
+                        .get("value")
+                        .cloned()
+                        .ok_or_else(|| anyhow!("Resolved path has no value: {resolved}"))?
+                }
             };
-
-            let json = serde_json::json!({
-                "mish_state_name": mish_state_name,
-                "path": path,
-                "content": serde_json::json!({"/": cid}),
-            });
-
-            let req = client
-                .post(server_url.join("/api/mish/state").unwrap())
-                .json(&json);
-            let result = req.send().await.unwrap();
-            if result.status().is_success() {
-                println!("Updated Mish state: {}", result.text().await.unwrap());
+            print_json(&value)
+        }
+        Operation::Set { name, file_path } => {
+            let data = match &file_path {
+                Some(file_path) => tokio::fs::read(file_path)
+                    .await
+                    .with_context(|| format!("Failed to read {}", file_path.display()))?,
+                None => {
+                    let mut data = Vec::new();
+                    tokio::io::stdin().read_to_end(&mut data).await?;
+                    data
+                }
+            };
+            let state = serde_json::from_slice::<serde_json::Value>(&data)
+                .context("Mish state is not JSON")?;
+            let url = api_url(server_url, &["state", name.as_str()]);
+            check(client.put(url).json(&state).send().await?)
+                .await
+                .with_context(|| format!("Failed to set mish state {name}"))?;
+            print_json(&state)
+        }
+        Operation::Patch {
+            name,
+            path,
+            content,
+        } => {
+            let content = serde_json::from_str::<serde_json::Value>(&content)
+                .context("Content is not JSON, quote strings like '\"on\"'")?;
+            patch_mish_state(client, server_url, &name, &path, content).await
+        }
+        Operation::Delete { name } => {
+            let url = api_url(server_url, &["state", name.as_str()]);
+            check(client.delete(url).send().await?)
+                .await
+                .with_context(|| format!("Failed to delete mish state {name}"))?;
+            Ok(())
+        }
+        Operation::DownloadFile {
+            cid,
+            output_path,
+            file,
+        } => {
+            let url = api_url(
+                server_url,
+                &[if file { "file" } else { "blob" }, cid.as_str()],
+            );
+            let mut result = check(client.get(url.clone()).send().await?)
+                .await
+                .with_context(|| format!("Failed to download {cid}"))?;
+            let content_type = result
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .unwrap_or_default()
+                .to_owned();
+            if content_type == DAG_CBOR_CONTENT_TYPE {
+                let mut url = url;
+                url.query_pairs_mut().append_pair("format", "dag-json");
+                result = check(client.get(url).send().await?)
+                    .await
+                    .with_context(|| format!("Failed to download {cid} as DAG-JSON"))?;
+            }
+            if content_type == DAG_JSON_CONTENT_TYPE || content_type == DAG_CBOR_CONTENT_TYPE {
+                let json = result.json::<serde_json::Value>().await?;
+                match output_path {
+                    Some(output_path) => {
+                        tokio::fs::write(&output_path, serde_json::to_vec_pretty(&json)?)
+                            .await
+                            .with_context(|| format!("Failed to write {}", output_path.display()))
+                    }
+                    None => print_json(&json),
+                }
             } else {
-                panic!(
-                    "Failed to update Mish state: {}, {:?}",
-                    result.status(),
-                    result.text().await
-                );
+                match output_path {
+                    Some(output_path) => {
+                        let mut output =
+                            tokio::fs::File::create(&output_path)
+                                .await
+                                .with_context(|| {
+                                    format!("Failed to create {}", output_path.display())
+                                })?;
+                        copy_body(result, &mut output).await?;
+                        Ok(())
+                    }
+                    None => copy_body(result, &mut tokio::io::stdout())
+                        .await
+                        .map(|_| ()),
+                }
+            }
+        }
+        Operation::Watch { names, path } => {
+            let mut url = api_url(server_url, &["subscribe"]);
+            url.query_pairs_mut().append_pair("names", &names.join(","));
+            if let Some(path) = &path {
+                url.query_pairs_mut().append_pair("path", path);
             }
+            let result = check(client.get(url).send().await?)
+                .await
+                .context("Failed to subscribe")?;
+            for_each_event(result, |data| println!("{data}")).await?;
+            Err(anyhow!("Subscription closed by the server"))
+        }
+        Operation::Dev {
+            directory,
+            interval_ms,
+        } => dev::dev(client, server_url, &directory, interval_ms).await,
+        Operation::Install { directory } => {
+            let run_path = directory.join("run.json");
+            let data = tokio::fs::read(&run_path)
+                .await
+                .with_context(|| format!("Failed to read {}", run_path.display()))?;
+            let mut run = serde_json::from_slice::<serde_json::Value>(&data)
+                .with_context(|| format!("{} is not JSON", run_path.display()))?;
+            let mut references = BTreeSet::new();
+            file_references(&run, &mut references);
+            let mut cids = BTreeMap::new();
+            for file in references {
+                let cid = upload_file(client, server_url, &directory.join(&file)).await?;
+                eprintln!("Uploaded {file}: {cid}");
+                cids.insert(file, cid);
+            }
+            link_file_references(&mut run, &cids);
+            let url = api_url(server_url, &["state", "run"]);
+            check(client.put(url).json(&run).send().await?)
+                .await
+                .context("Failed to install the run mish state")?;
+            print_json(&run)
+        }
+        Operation::UploadFile {
+            file_path,
+            mish_state_name,
+            path,
+        } => {
+            let cid = upload_file(client, server_url, &file_path).await?;
+            patch_mish_state(
+                client,
+                server_url,
+                &mish_state_name,
+                &path,
+                serde_json::json!({"/": cid}),
+            )
+            .await?;
+            print_json(&serde_json::json!({"/": cid}))
+        }
+        Operation::Export {
+            output_path,
+            cid,
+            mish_state_name,
+            car_version,
+        } => {
+            let mut url = match (&cid, &mish_state_name) {
+                (Some(cid), _) => api_url(server_url, &["car", cid.as_str()]),
+                (None, Some(mish_state_name)) => {
+                    api_url(server_url, &["state", mish_state_name.as_str(), "car"])
+                }
+                (None, None) => unreachable!("clap requires --cid or --mish-state-name"),
+            };
+            url.query_pairs_mut()
+                .append_pair("version", &car_version.to_string());
+            let result = check(client.get(url).send().await?)
+                .await
+                .context("Failed to export CAR file")?;
+            let mut output = tokio::fs::File::create(&output_path)
+                .await
+                .with_context(|| format!("Failed to create {}", output_path.display()))?;
+            let bytes = copy_body(result, &mut output).await?;
+            print_json(&serde_json::json!({
+                "output_path": output_path,
+                "bytes": bytes,
+            }))
+        }
+        Operation::Import { file_path } => {
+            let data = tokio::fs::read(&file_path)
+                .await
+                .with_context(|| format!("Failed to read {}", file_path.display()))?;
+            let result = client
+                .post(api_url(server_url, &["car"]))
+                .header("Content-Type", "application/vnd.ipld.car")
+                .body(data)
+                .send()
+                .await?;
+            let result = check(result)
+                .await
+                .context("Failed to import CAR file")?
+                .json::<serde_json::Value>()
+                .await?;
+            print_json(&result)
         }
     }
 }
@@ -17,11 +17,79 @@ pub struct Options {
 #[derive(Subcommand, Debug)]
 #[command()]
 pub enum Operation {
-    // #[command(long)]
-    // DownloadFile {
-    //     cid: String,
-    //     path: PathBuf,
-    // },
+    /// List mish state names, or the states themselves with --full
+    #[command()]
+    List {
+        #[arg(long)]
+        full: bool,
+    },
+    /// Print a mish state, or the value at a path through it and any blocks it links to
+    #[command()]
+    Get {
+        name: String,
+
+        /// e.g. `config/lights/0`
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Replace a mish state with the JSON in a file, or stdin if no file is given
+    #[command()]
+    Set {
+        name: String,
+
+        file_path: Option<PathBuf>,
+    },
+    /// Set the value at a JSONPath in a mish state
+    #[command()]
+    Patch {
+        name: String,
+
+        /// e.g. `$.pump.on`
+        #[arg(long)]
+        path: String,
+
+        /// JSON value
+        content: String,
+    },
+    #[command()]
+    Delete { name: String },
+    /// Download a blob, printing DAG-JSON and DAG-CBOR blobs as pretty JSON
+    #[command()]
+    DownloadFile {
+        cid: String,
+
+        /// Write to this file instead of stdout
+        #[arg(long)]
+        output_path: Option<PathBuf>,
+
+        /// Reassemble a chunked raw upload instead of printing its root
+        #[arg(long)]
+        file: bool,
+    },
+    /// Print mish state events as JSON lines until interrupted
+    #[command()]
+    Watch {
+        /// Mish states to watch, all of them if none are given
+        names: Vec<String>,
+
+        /// JSONPath selecting part of each state
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Keep the files listed in a directory's mish.json synced to their mish state paths and
+    /// print script logs, until interrupted
+    #[command()]
+    Dev {
+        directory: PathBuf,
+
+        /// How often to check the files for changes
+        #[arg(long, default_value_t = 500)]
+        interval_ms: u64,
+    },
+    /// Upload the scripts referenced by `{".": "file.rhai"}` in a directory's run.json and
+    /// replace the `run` mish state with it
+    #[command()]
+    Install { directory: PathBuf },
     #[command()]
     UploadFile {
         file_path: PathBuf,
@@ -32,12 +100,26 @@ pub enum Operation {
         #[arg(long)]
         path: String,
     },
-    // ReadMishState {
-    //     name: String,
-    // },
+    /// Export a DAG as a CAR file, rooted at a CID or at every CID linked from a mish state
+    #[command()]
+    Export {
+        output_path: PathBuf,
+
+        #[arg(
+            long,
+            conflicts_with = "mish_state_name",
+            required_unless_present = "mish_state_name"
+        )]
+        cid: Option<String>,
 
-    // WriteMishState {
-    //     name: String,
-    //     path: PathBuf,
-    // },
+        #[arg(long)]
+        mish_state_name: Option<String>,
+
+        /// CAR version, 1 or 2
+        #[arg(long, default_value_t = 1)]
+        car_version: u8,
+    },
+    /// Import the blocks of a CARv1 or CARv2 file and pin its roots
+    #[command()]
+    Import { file_path: PathBuf },
 }
@@ -0,0 +1 @@
+ALTER TABLE mish_states ADD COLUMN schema_cid BYTEA;
@@ -0,0 +1,8 @@
+CREATE TABLE ipld_pins (
+    name VARCHAR(255) PRIMARY KEY,
+    cid BYTEA NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Blobs uploaded just before the state that references them is written must survive a GC run
+ALTER TABLE ipld_blobs ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
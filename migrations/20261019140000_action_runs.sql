@@ -0,0 +1,16 @@
+CREATE TABLE action_runs (
+    id UUID PRIMARY KEY,
+    action_id UUID NOT NULL,
+    -- Actions live in config, keep the name around for runs of deleted ones
+    action_name TEXT NOT NULL,
+    -- NULL for runs started by hand
+    scheduled_at TIMESTAMPTZ,
+    started_at TIMESTAMPTZ NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    attempts INTEGER NOT NULL,
+    result JSONB,
+    error TEXT
+);
+
+CREATE INDEX action_runs_action_id_started_at ON action_runs (action_id, started_at DESC);
+CREATE INDEX action_runs_started_at ON action_runs (started_at);
@@ -0,0 +1,9 @@
+CREATE TABLE scenes (
+    name TEXT PRIMARY KEY,
+    -- SceneDevice array, the captured state of each device
+    devices JSONB NOT NULL,
+    -- How many devices are changed at once when the scene is applied
+    concurrency INTEGER NOT NULL DEFAULT 4,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,24 @@
+CREATE TABLE config_history (
+    id BIGSERIAL PRIMARY KEY,
+    data JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Every write to config is recorded, whether it comes from an import, a rollback or adding and
+-- deleting actions, keeping the last 100 versions
+CREATE FUNCTION record_config_history() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.data IS DISTINCT FROM OLD.data THEN
+        INSERT INTO config_history (data) VALUES (NEW.data);
+        DELETE FROM config_history
+        WHERE id <= (SELECT id FROM config_history ORDER BY id DESC OFFSET 100 LIMIT 1);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER config_history
+AFTER INSERT OR UPDATE ON config
+FOR EACH ROW EXECUTE FUNCTION record_config_history();
+
+INSERT INTO config_history (data) SELECT data FROM config;
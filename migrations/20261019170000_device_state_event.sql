@@ -0,0 +1,29 @@
+-- Every change to a device's power state
+CREATE TABLE device_state_event (
+    id BIGSERIAL PRIMARY KEY,
+    device_id BIGINT NOT NULL REFERENCES device(id) ON DELETE CASCADE,
+    power_state INTEGER NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX device_state_event_device_id_changed_at
+ON device_state_event (device_id, changed_at);
+
+-- Vacation mode learns when devices are usually on from this, whether the change was polled from
+-- the device or made by IronNest, keeping 90 days
+CREATE FUNCTION record_device_state_event() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.power_state IS DISTINCT FROM OLD.power_state THEN
+        INSERT INTO device_state_event (device_id, power_state) VALUES (NEW.id, NEW.power_state);
+        DELETE FROM device_state_event
+        WHERE device_id = NEW.id AND changed_at < NOW() - INTERVAL '90 days';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER device_state_event
+AFTER INSERT OR UPDATE OF power_state ON device
+FOR EACH ROW EXECUTE FUNCTION record_device_state_event();
+
+INSERT INTO device_state_event (device_id, power_state) SELECT id, power_state FROM device;
@@ -0,0 +1,15 @@
+CREATE TYPE device_capability AS ENUM ('on_off', 'brightness', 'color_hsv', 'color_temp', 'power_meter', 'battery', 'media_remote', 'camera', 'children');
+
+-- What each device can do, set by its integration when it's discovered
+ALTER TABLE device ADD COLUMN capabilities device_capability[] NOT NULL DEFAULT '{}';
+
+-- Until the next discovery fills in the details
+UPDATE device SET capabilities = CASE device_type
+    WHEN 'kasa-plug' THEN '{on_off}'
+    WHEN 'kasa-light' THEN '{on_off, brightness}'
+    WHEN 'kasa-dimmer' THEN '{on_off, brightness}'
+    WHEN 'kasa-power-strip' THEN '{on_off, children}'
+    WHEN 'ring-doorbell' THEN '{battery, camera}'
+    WHEN 'roku-tv' THEN '{on_off, media_remote}'
+    ELSE '{}'
+END::device_capability[];
@@ -0,0 +1,40 @@
+CREATE TYPE device_state_source AS ENUM ('poll', 'command', 'automation');
+
+-- Battery changes are recorded too, with what made each change. Earlier changes are taken as
+-- polled, with the battery the device has now.
+ALTER TABLE device_state_event
+ADD COLUMN battery_percentage BIGINT,
+ADD COLUMN source device_state_source NOT NULL DEFAULT 'poll';
+
+UPDATE device_state_event event
+SET battery_percentage = device.battery_percentage
+FROM device
+WHERE device.id = event.device_id;
+
+ALTER TABLE device_state_event ALTER COLUMN source DROP DEFAULT;
+
+-- Changes are polled from the device unless the transaction making them says otherwise with
+-- set_config('iron_nest.state_source', 'command', true). Once set in a session the setting reads
+-- as '' outside such transactions. Old events are pruned by the server now.
+CREATE OR REPLACE FUNCTION record_device_state_event() RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT'
+        OR NEW.power_state IS DISTINCT FROM OLD.power_state
+        OR NEW.battery_percentage IS DISTINCT FROM OLD.battery_percentage
+    THEN
+        INSERT INTO device_state_event (device_id, power_state, battery_percentage, source)
+        VALUES (
+            NEW.id,
+            NEW.power_state,
+            NEW.battery_percentage,
+            COALESCE(NULLIF(current_setting('iron_nest.state_source', true), ''), 'poll')::device_state_source
+        );
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER device_state_event ON device;
+CREATE TRIGGER device_state_event
+AFTER INSERT OR UPDATE OF power_state, battery_percentage ON device
+FOR EACH ROW EXECUTE FUNCTION record_device_state_event();
@@ -0,0 +1,33 @@
+-- Power read from devices with a power meter, kept for a couple of weeks
+CREATE TABLE energy_sample (
+    id BIGSERIAL PRIMARY KEY,
+    device_id BIGINT NOT NULL REFERENCES device(id) ON DELETE CASCADE,
+    power_w DOUBLE PRECISION NOT NULL,
+    voltage_v DOUBLE PRECISION,
+    current_a DOUBLE PRECISION,
+    sampled_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX energy_sample_device_id_sampled_at ON energy_sample (device_id, sampled_at);
+CREATE INDEX energy_sample_sampled_at ON energy_sample (sampled_at);
+
+-- Energy used each day in the tariff's timezone, with its cost at the rates of the time
+CREATE TABLE energy_daily (
+    device_id BIGINT NOT NULL REFERENCES device(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    energy_wh DOUBLE PRECISION NOT NULL DEFAULT 0,
+    cost DOUBLE PRECISION NOT NULL DEFAULT 0,
+    PRIMARY KEY (device_id, day)
+);
+
+-- Times an energy alert rule fired, device_id is NULL for whole house rules
+CREATE TABLE energy_alert (
+    id BIGSERIAL PRIMARY KEY,
+    rule_name TEXT NOT NULL,
+    device_id BIGINT REFERENCES device(id) ON DELETE CASCADE,
+    power_w DOUBLE PRECISION NOT NULL,
+    threshold_watts DOUBLE PRECISION NOT NULL,
+    triggered_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX energy_alert_triggered_at ON energy_alert (triggered_at);
@@ -0,0 +1,142 @@
+-- Devices are told apart by their vendor's id for them, e.g. the Kasa deviceId, the Roku serial
+-- number or the Ring id, so they keep their row when DHCP gives them a new IP. Devices without
+-- one, like the stoplight, still go by their IP.
+ALTER TABLE device ADD COLUMN vendor_id TEXT;
+
+ALTER TABLE device DROP CONSTRAINT unique_ip_child_id;
+
+-- Rows made for a device each time its IP changed have the same type, name and child id, and
+-- only the one at its current IP is still seen. Two rows seen within an hour of each other, the
+-- longest discovery interval, are different devices that happen to share a name (e.g. two
+-- "Roku TV"s), so their group is left alone. Otherwise the rows are merged into the oldest, which
+-- actions and scenes most likely refer to, taking the address and state of the one seen last.
+CREATE TEMPORARY TABLE device_merge ON COMMIT DROP AS
+WITH seen AS (
+    SELECT device_type, name, coalesced_child_id,
+        last_seen - LAG(last_seen) OVER (
+            PARTITION BY device_type, name, coalesced_child_id
+            ORDER BY last_seen
+        ) AS since_previous
+    FROM device
+),
+ambiguous AS (
+    SELECT DISTINCT device_type, name, coalesced_child_id
+    FROM seen
+    WHERE since_previous < INTERVAL '1 hour'
+)
+SELECT duplicate.id AS duplicate_id, keeper.id AS keeper_id
+FROM device duplicate
+JOIN LATERAL (
+    SELECT id
+    FROM device
+    WHERE device_type = duplicate.device_type
+        AND name = duplicate.name
+        AND coalesced_child_id = duplicate.coalesced_child_id
+    ORDER BY id
+    LIMIT 1
+) keeper ON keeper.id <> duplicate.id
+WHERE NOT EXISTS (
+    SELECT 1
+    FROM ambiguous
+    WHERE ambiguous.device_type = duplicate.device_type
+        AND ambiguous.name = duplicate.name
+        AND ambiguous.coalesced_child_id = duplicate.coalesced_child_id
+);
+
+CREATE FUNCTION pg_temp.merged_device_id(device_id BIGINT) RETURNS BIGINT AS $$
+    SELECT COALESCE((SELECT keeper_id FROM device_merge WHERE duplicate_id = device_id), device_id)
+$$ LANGUAGE SQL;
+
+-- Points the device_id of each object in `items` at the merged device
+CREATE FUNCTION pg_temp.merge_device_ids(items JSONB) RETURNS JSONB AS $$
+    SELECT COALESCE(
+        jsonb_agg(
+            CASE WHEN jsonb_typeof(item->'device_id') = 'number'
+                THEN jsonb_set(
+                    item,
+                    '{device_id}',
+                    to_jsonb(pg_temp.merged_device_id((item->>'device_id')::BIGINT))
+                )
+                ELSE item
+            END
+            ORDER BY position
+        ),
+        '[]'::JSONB
+    )
+    FROM jsonb_array_elements(items) WITH ORDINALITY AS element(item, position)
+$$ LANGUAGE SQL;
+
+-- The state events of the duplicates are moved to the keeper below, so taking their state isn't a
+-- change to record
+ALTER TABLE device DISABLE TRIGGER device_state_event;
+
+UPDATE device keeper
+SET ip = latest.ip,
+    power_state = latest.power_state,
+    battery_percentage = latest.battery_percentage,
+    last_seen = latest.last_seen,
+    capabilities = latest.capabilities
+FROM (
+    SELECT DISTINCT ON (pair.keeper_id) pair.keeper_id, device.*
+    FROM device_merge pair
+    JOIN device ON device.id IN (pair.keeper_id, pair.duplicate_id)
+    ORDER BY pair.keeper_id, device.last_seen DESC
+) latest
+WHERE keeper.id = latest.keeper_id;
+
+ALTER TABLE device ENABLE TRIGGER device_state_event;
+
+UPDATE device_state_event
+SET device_id = pg_temp.merged_device_id(device_id)
+WHERE device_id IN (SELECT duplicate_id FROM device_merge);
+
+UPDATE energy_sample
+SET device_id = pg_temp.merged_device_id(device_id)
+WHERE device_id IN (SELECT duplicate_id FROM device_merge);
+
+UPDATE energy_alert
+SET device_id = pg_temp.merged_device_id(device_id)
+WHERE device_id IN (SELECT duplicate_id FROM device_merge);
+
+INSERT INTO energy_daily (device_id, day, energy_wh, cost)
+SELECT pg_temp.merged_device_id(device_id), day, energy_wh, cost
+FROM energy_daily
+WHERE device_id IN (SELECT duplicate_id FROM device_merge)
+ON CONFLICT (device_id, day) DO UPDATE SET
+    energy_wh = energy_daily.energy_wh + EXCLUDED.energy_wh,
+    cost = energy_daily.cost + EXCLUDED.cost;
+
+DELETE FROM tuya_device_data
+WHERE id IN (SELECT duplicate_id FROM device_merge);
+
+UPDATE scenes
+SET devices = pg_temp.merge_device_ids(devices)
+WHERE jsonb_typeof(devices) = 'array';
+
+UPDATE config
+SET data = jsonb_set(
+    jsonb_set(
+        data,
+        '{vacation,device_ids}',
+        (
+            SELECT COALESCE(jsonb_agg(DISTINCT pg_temp.merged_device_id(device_id::BIGINT)), '[]'::JSONB)
+            FROM jsonb_array_elements_text(data->'vacation'->'device_ids') AS device_id
+        )
+    ),
+    '{vacation,windows}',
+    pg_temp.merge_device_ids(COALESCE(data->'vacation'->'windows', '[]'::JSONB))
+)
+WHERE jsonb_typeof(data->'vacation'->'device_ids') = 'array';
+
+UPDATE config
+SET data = jsonb_set(data, '{energy,alerts}', pg_temp.merge_device_ids(data->'energy'->'alerts'))
+WHERE jsonb_typeof(data->'energy'->'alerts') = 'array';
+
+DELETE FROM device
+WHERE id IN (SELECT duplicate_id FROM device_merge);
+
+ALTER TABLE device ADD CONSTRAINT unique_vendor_id_child_id UNIQUE (vendor_id, coalesced_child_id);
+
+CREATE UNIQUE INDEX device_ip_child_id_without_vendor_id
+ON device (device_type, ip, coalesced_child_id)
+WHERE vendor_id IS NULL;
@@ -4,18 +4,11 @@ use {
             checkbox::Checkbox, device_list_card::DeviceListCard, device_modal::Modal,
             refresh_button::Refresh_Button,
         },
-        integrations::iron_nest::types::{Device, DeviceType},
-        server::{
-            dashboard_page::refresh_devices,
-            roku::handle_roku_tv_toggle,
-            tplink::{
-                handle_smart_light_toggle, handle_smart_plug_toggle,
-                handle_smart_power_strip_toggle,
-            },
-        },
+        integrations::iron_nest::types::{Device, capability::Capability},
+        server::{dashboard_page::refresh_devices, devices::set_device_on_off},
     },
-    leptos::{html::Div, prelude::*, task::spawn_local},
-    log::debug,
+    leptos::{prelude::*, task::spawn_local},
+    log::{debug, error},
 };
 
 #[component]
@@ -101,190 +94,33 @@ pub fn DeviceList(
     }
 }
 
+/// The device's card, with an on/off switch for devices that have one.
 #[component]
 pub fn DeviceListItem(device: Device) -> impl IntoView {
-    let _el = NodeRef::<Div>::new();
-
-    match device.device_type {
-        DeviceType::KasaPlug => view! {
-            <div>
-                <SmartPlugItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::KasaLight => view! {
-            <div>
-                <SmartLightItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::KasaDimmer => view! {
-            <div>
-                <SmartDimmerItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::KasaPowerStrip => view! {
-            <div>
-                <SmartPowerStripItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::TuyaLight => view! {
-            <div>
-                <SmartLightItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::TuyaGrowLight => view! {
-            <div>
-                <SmartLightItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::RingDoorbell => view! {
-            <div>
-                <RingDoorbellItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::Stoplight => view! {
-            <div>
-                <StoplightItem device=device />
-            </div>
-        }
-        .into_any(),
-        DeviceType::RokuTv => view! {
-            <div>
-                <RokuTvItem device=device />
-            </div>
-        }
-        .into_any(),
-    }
-}
-
-#[component]
-pub fn SmartPlugItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! {
-        <DeviceListCard device=device.clone()>
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-
-        </DeviceListCard>
-    }
-}
-
-#[component]
-pub fn SmartDimmerItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! {
-        <DeviceListCard device=device.clone()>
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-        </DeviceListCard>
-    }
-}
-
-#[component]
-pub fn SmartPowerStripItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        let child_id = device.child_id.clone().unwrap();
-        move |value| {
-            let ip = ip.clone();
-            let child_id = child_id.clone();
-            let value = *value;
-            async move {
-                handle_smart_power_strip_toggle(value, ip, child_id)
-                    .await
-                    .unwrap();
-            }
-        }
-    });
-
-    view! {
-        <DeviceListCard device=device.clone()>
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-
-        </DeviceListCard>
-    }
-}
-
-#[component]
-pub fn RingDoorbellItem(device: Device) -> impl IntoView {
-    view! {
-        <DeviceListCard device=device>
-            <div></div>
-        </DeviceListCard>
-    }
-}
-
-#[component]
-pub fn StoplightItem(device: Device) -> impl IntoView {
-    view! {
-        <DeviceListCard device=device>
-            <div></div>
-        </DeviceListCard>
-    }
-}
-
-#[component]
-pub fn SmartLightItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_light_toggle(value, ip).await.unwrap();
+    let toggle_action = Action::new(move |on: &bool| {
+        let on = *on;
+        async move {
+            if let Err(e) = set_device_on_off(device.id, on).await {
+                error!("Failed to switch device {}: {e}", device.id);
             }
         }
     });
+    let on_off = device.capabilities.contains(&Capability::OnOff);
 
     view! {
-        <DeviceListCard device=device.clone()>
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-
-        </DeviceListCard>
-    }
-}
-
-#[component]
-pub fn RokuTvItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_roku_tv_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! {
-        <DeviceListCard device=device.clone()>
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-
-        </DeviceListCard>
+        <div>
+            <DeviceListCard device=device.clone()>
+                {on_off
+                    .then(|| {
+                        view! {
+                            <Checkbox
+                                value=device.power_state == 1
+                                on_click=Some(toggle_action)
+                                on_click_fn=None
+                            />
+                        }
+                    })}
+            </DeviceListCard>
+        </div>
     }
 }
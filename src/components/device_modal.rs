@@ -2,27 +2,245 @@ use {
     super::checkbox::Checkbox,
     crate::{
         components::{color_picker::ColorPicker, slider::Slider},
-        integrations::iron_nest::types::{Device, DeviceType},
-        server::tplink::{
-            handle_smart_dimmer_brightness, handle_smart_light_brightness, handle_smart_light_hsl,
-            handle_smart_light_toggle, handle_smart_plug_toggle,
+        integrations::iron_nest::types::{Device, capability::Capability},
+        server::devices::{
+            get_device_timeline, send_device_remote_key, set_device_brightness, set_device_color,
+            set_device_color_temp, set_device_on_off,
         },
     },
+    chrono::{Duration, Utc},
     leptos::{prelude::*, task::spawn_local},
+    log::error,
 };
 
+/// Remote keys offered for devices with [`Capability::MediaRemote`], with their labels.
+const REMOTE_KEYS: &[(&str, &str)] = &[
+    ("Home", "Home"),
+    ("Back", "Back"),
+    ("Up", "Up"),
+    ("Down", "Down"),
+    ("Left", "Left"),
+    ("Right", "Right"),
+    ("Select", "OK"),
+    ("Play", "Play"),
+    ("VolumeDown", "Vol -"),
+    ("VolumeUp", "Vol +"),
+];
+
+/// Ranges offered by [`DeviceTimelineChart`], in hours, with their labels.
+const TIMELINE_RANGES: &[(i64, &str)] = &[(24, "24h"), (24 * 7, "7d"), (24 * 30, "30d")];
+
+fn log_error(result: Result<(), ServerFnError>) {
+    if let Err(e) = result {
+        error!("Failed to control device: {e}");
+    }
+}
+
+/// Controls for everything the device can do, going by its capabilities.
 #[component]
 pub fn DeviceView(device: Device) -> impl IntoView {
-    match device.device_type {
-        DeviceType::KasaPlug => view! { <SmartPlugView device=device /> }.into_any(),
-        DeviceType::KasaLight => view! { <SmartLightView device=device /> }.into_any(),
-        DeviceType::KasaDimmer => view! { <SmartDimmerView device=device /> }.into_any(),
-        DeviceType::KasaPowerStrip => view! { <SmartPowerStripView device=device /> }.into_any(),
-        DeviceType::TuyaLight => view! { <SmartLightView device=device /> }.into_any(),
-        DeviceType::TuyaGrowLight => view! { <SmartLightView device=device /> }.into_any(),
-        DeviceType::RingDoorbell => view! { <RingDoorbellView device=device /> }.into_any(),
-        DeviceType::RokuTv => view! { <RokuTvView device=device /> }.into_any(),
-        DeviceType::Stoplight => view! { <StoplightView device=device /> }.into_any(),
+    let id = device.id;
+    let has = |capability| device.capabilities.contains(&capability);
+    let toggle_action = Action::new(move |on: &bool| {
+        let on = *on;
+        async move { log_error(set_device_on_off(id, on).await) }
+    });
+
+    view! {
+        <div class="flex flex-col space-y-2">
+            {has(Capability::OnOff)
+                .then(|| {
+                    view! {
+                        <Checkbox
+                            value=device.power_state == 1
+                            on_click=Some(toggle_action)
+                            on_click_fn=None
+                        />
+                    }
+                })}
+            {has(Capability::Brightness)
+                .then(|| {
+                    view! {
+                        <Slider on_change=Box::new(move |brightness| {
+                            spawn_local(async move {
+                                log_error(set_device_brightness(id, brightness).await);
+                            });
+                        }) />
+                    }
+                })}
+            {has(Capability::ColorHsv)
+                .then(|| {
+                    view! {
+                        <ColorPicker
+                            label="Color".to_string()
+                            default_value="#e66465".to_string()
+                            on_change=Box::new(move |color| {
+                                spawn_local(async move {
+                                    log_error(set_device_color(id, color).await);
+                                });
+                            })
+                        />
+                    }
+                })}
+            {has(Capability::ColorTemp)
+                .then(|| {
+                    view! {
+                        <label class="text-sm text-gray-700">
+                            "Color temperature"
+                            <input
+                                type="range"
+                                min="2500"
+                                max="9000"
+                                step="100"
+                                on:change=move |ev| {
+                                    if let Ok(kelvin) = event_target_value(&ev).parse() {
+                                        spawn_local(async move {
+                                            log_error(set_device_color_temp(id, kelvin).await);
+                                        });
+                                    }
+                                }
+                            />
+                        </label>
+                    }
+                })}
+            {has(Capability::Battery)
+                .then(|| {
+                    view! {
+                        <div>"Battery: " {device.battery_percentage} "%"</div>
+                    }
+                })}
+            {has(Capability::MediaRemote)
+                .then(|| {
+                    view! {
+                        <div class="grid grid-cols-3 gap-1">
+                            {REMOTE_KEYS
+                                .iter()
+                                .map(|(key, label)| {
+                                    view! {
+                                        <button
+                                            class="rounded bg-indigo-600 px-2 py-1 text-sm text-white"
+                                            on:click=move |_| {
+                                                spawn_local(async move {
+                                                    log_error(
+                                                        send_device_remote_key(id, key.to_string()).await,
+                                                    );
+                                                });
+                                            }
+                                        >
+                                            {*label}
+                                        </button>
+                                    }
+                                })
+                                .collect::<Vec<_>>()}
+                        </div>
+                    }
+                })}
+            <DeviceTimelineChart device_id=id />
+            <p class="text-xs text-gray-500">
+                {device
+                    .capabilities
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")}
+            </p>
+        </div>
+    }
+}
+
+/// When the device was on over a chosen range, from its state history.
+#[component]
+fn DeviceTimelineChart(device_id: i64) -> impl IntoView {
+    const WIDTH: f64 = 300.0;
+    const HEIGHT: f64 = 20.0;
+
+    let (hours, set_hours) = signal(TIMELINE_RANGES[0].0);
+    let timeline = Resource::new(
+        move || hours.get(),
+        move |hours| {
+            let to = Utc::now();
+            get_device_timeline(device_id, to - Duration::hours(hours), to)
+        },
+    );
+    let chart = move || {
+        let timeline = match timeline.get()? {
+            Ok(timeline) => timeline,
+            Err(e) => return Some(view! { <p>"Error: " {e.to_string()}</p> }.into_any()),
+        };
+        let range = (timeline.to - timeline.from).num_seconds().max(1) as f64;
+        let x =
+            |at: chrono::DateTime<Utc>| (at - timeline.from).num_seconds() as f64 / range * WIDTH;
+        let spans = timeline
+            .on_spans()
+            .into_iter()
+            .map(|(start, end)| {
+                view! {
+                    <rect
+                        x=x(start)
+                        width=x(end) - x(start)
+                        height=HEIGHT
+                        class="fill-green-500"
+                    />
+                }
+            })
+            .collect::<Vec<_>>();
+        let on = timeline.on_duration();
+        let latest = timeline
+            .events
+            .iter()
+            .rev()
+            .take(5)
+            .map(|event| {
+                view! {
+                    <li>
+                        {event.changed_at.format("%Y-%m-%d %H:%M").to_string()} " "
+                        {if event.power_state != 0 { "on" } else { "off" }} " (" {event.source.to_string()}
+                        ")"
+                    </li>
+                }
+            })
+            .collect::<Vec<_>>();
+        Some(
+            view! {
+                <p class="text-sm">
+                    {format!("On for {}h {}m", on.num_hours(), on.num_minutes() % 60)}
+                </p>
+                <svg
+                    viewBox=format!("0 0 {WIDTH} {HEIGHT}")
+                    preserveAspectRatio="none"
+                    class="w-full h-5 bg-gray-200"
+                >
+                    {spans}
+                </svg>
+                <ul class="text-xs text-gray-500">{latest}</ul>
+            }
+            .into_any(),
+        )
+    };
+
+    view! {
+        <div class="flex flex-col space-y-1">
+            <div class="flex space-x-1">
+                {TIMELINE_RANGES
+                    .iter()
+                    .map(|(range, label)| {
+                        view! {
+                            <button
+                                class="rounded px-2 py-1 text-xs"
+                                class=("bg-indigo-600", move || hours.get() == *range)
+                                class=("text-white", move || hours.get() == *range)
+                                on:click=move |_| set_hours.set(*range)
+                            >
+                                {*label}
+                            </button>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </div>
+            <Suspense fallback=|| {
+                view! { <p>"Loading..."</p> }
+            }>{chart}</Suspense>
+        </div>
     }
 }
 
@@ -76,136 +294,3 @@ pub fn Modal(toggle_modal: WriteSignal<bool>, device: ReadSignal<Option<Device>>
         </div>
     }
 }
-
-#[component]
-pub fn SmartLightView(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_light_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! {
-        <div class="flex flex-col">
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-            <Slider on_change=Box::new({
-                let ip = device.ip.clone();
-                move |value| {
-                    let ip = ip.clone();
-                    spawn_local(async move {
-                        handle_smart_light_brightness(value, ip).await.unwrap();
-                    });
-                }
-            }) />
-
-            <ColorPicker
-                label="Color".to_string()
-                default_value="#e66465".to_string()
-                on_change=Box::new({
-                    let ip = device.ip.clone();
-                    move |value| {
-                        leptos::logging::log!("color_picker on change outer: {value}");
-                        let ip = ip.clone();
-                        spawn_local(async move {
-                            leptos::logging::log!("parsing: {value}");
-                            handle_smart_light_hsl(ip, value).await.unwrap();
-                        });
-                    }
-                })
-            />
-
-        </div>
-    }
-}
-
-#[component]
-pub fn SmartPlugView(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn SmartDimmerView(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! {
-        <div class="flex flex-col">
-            <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None />
-            <Slider on_change=Box::new({
-                let ip = device.ip.clone();
-                move |value| {
-                    let ip = ip.clone();
-                    spawn_local(async move {
-                        handle_smart_dimmer_brightness(value, ip).await.unwrap();
-                    });
-                }
-            }) />
-        </div>
-    }
-}
-
-#[component]
-pub fn SmartPowerStripView(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn RingDoorbellView(device: Device) -> impl IntoView {
-    view! { <div>"Power State: " {device.battery_percentage}</div> }
-}
-
-#[component]
-pub fn RokuTvView(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn StoplightView(device: Device) -> impl IntoView {
-    view! { <div>"Power State: " {device.battery_percentage}</div> }
-}
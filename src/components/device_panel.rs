@@ -1,16 +1,11 @@
 use {
     crate::{
         components::checkbox::Checkbox,
-        integrations::iron_nest::types::{Device, DeviceType},
-        server::{
-            roku::handle_roku_tv_toggle,
-            tplink::{
-                handle_smart_light_toggle, handle_smart_plug_toggle,
-                handle_smart_power_strip_toggle,
-            },
-        },
+        integrations::iron_nest::types::{Device, capability::Capability},
+        server::devices::set_device_on_off,
     },
     leptos::prelude::*,
+    log::error,
 };
 
 #[component]
@@ -64,129 +59,32 @@ pub fn DeviceListPanel(
 
 #[component]
 pub fn DeviceCard(device: Device) -> impl IntoView {
+    let toggle_action = Action::new(move |on: &bool| {
+        let on = *on;
+        async move {
+            if let Err(e) = set_device_on_off(device.id, on).await {
+                error!("Failed to switch device {}: {e}", device.id);
+            }
+        }
+    });
+
     view! {
         <div class="bg-white text-black p-2 rounded-lg shadow-md flex flex-col items-center justify-between">
             <p class="text-sm font-medium text-nowrap w-full">{device.name.clone()}</p>
             <div class="mt-2">
-                {match device.device_type {
-                    DeviceType::KasaPlug => {
-                        view! { <SmartPlugItem device=device.clone() /> }.into_any()
-                    }
-                    DeviceType::KasaLight => {
-                        view! { <SmartLightItem device=device.clone() /> }.into_any()
-                    }
-                    DeviceType::KasaDimmer => {
-                        view! { <SmartDimmerItem device=device.clone() /> }.into_any()
-                    }
-                    DeviceType::KasaPowerStrip => {
-                        view! { <SmartPowerStripItem device=device.clone() /> }.into_any()
-                    }
-                    DeviceType::TuyaLight => {
-                        view! { <SmartLightItem device=device.clone() /> }.into_any()
-                    }
-                    DeviceType::TuyaGrowLight => {
-                        view! { <SmartLightItem device=device.clone() /> }.into_any()
-                    }
-                    DeviceType::RingDoorbell => view! { <RingDoorbellItem /> }.into_any(),
-                    DeviceType::Stoplight => view! { <StoplightItem /> }.into_any(),
-                    DeviceType::RokuTv => view! { <RokuTvItem device=device.clone() /> }.into_any(),
-                }}
-
+                {device
+                    .capabilities
+                    .contains(&Capability::OnOff)
+                    .then(|| {
+                        view! {
+                            <Checkbox
+                                value=device.power_state == 1
+                                on_click=Some(toggle_action)
+                                on_click_fn=None
+                            />
+                        }
+                    })}
             </div>
         </div>
     }
 }
-
-#[component]
-pub fn SmartPlugItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn SmartDimmerItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_plug_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn SmartPowerStripItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        let child_id = device.child_id.clone().unwrap();
-        move |value| {
-            let ip = ip.clone();
-            let child_id = child_id.clone();
-            let value = *value;
-            async move {
-                handle_smart_power_strip_toggle(value, ip, child_id)
-                    .await
-                    .unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn RingDoorbellItem() -> impl IntoView {
-    view! { <></> }
-}
-
-#[component]
-pub fn StoplightItem() -> impl IntoView {
-    view! { <></> }
-}
-
-#[component]
-pub fn SmartLightItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_smart_light_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
-
-#[component]
-pub fn RokuTvItem(device: Device) -> impl IntoView {
-    let toggle_action = Action::new({
-        let ip = device.ip.clone();
-        move |value| {
-            let ip = ip.clone();
-            let value = *value;
-            async move {
-                handle_roku_tv_toggle(value, ip).await.unwrap();
-            }
-        }
-    });
-
-    view! { <Checkbox value=device.power_state == 1 on_click=Some(toggle_action) on_click_fn=None /> }
-}
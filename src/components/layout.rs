@@ -2,15 +2,17 @@ use {
     crate::{
         components::{
             mish::{
-                dag_inspector_page::DagInspectorPage, ipld_blob_page::IpldBlobPage,
+                dag_inspector_page::DagInspectorPage, dag_path_page::DagPathPage,
+                ipld_blob_page::IpldBlobPage, mish_dashboard::MishDashboardPage,
                 mish_state_page::MishStatePage,
             },
             navbar::Navbar,
             pages::{
                 actions_page::ActionsPage, configs_page::ConfigsPage,
-                dashboard_page::DashboardPage, devices_page::DevicesPage,
+                dashboard_page::DashboardPage, devices_page::DevicesPage, energy_page::EnergyPage,
                 integrations_page::IntegrationsPage, login_page::LoginPage,
-                settings_page::SettingsPage, websocket_page::WebSocketPage,
+                scenes_page::ScenesPage, settings_page::SettingsPage,
+                websocket_page::WebSocketPage,
             },
         },
         error_template::{AppError, ErrorTemplate},
@@ -234,6 +236,7 @@ pub fn App() -> impl IntoView {
                             <Route path=path!("/integrations") view=IntegrationsPage />
                             <Route path=path!("/integrations/:integration") view=LoginPage />
                             <Route path=path!("/actions") view=ActionsPage />
+                            <Route path=path!("/scenes") view=ScenesPage />
                             <Route path=path!("/settings") view=SettingsPage />
                             <Route path=path!("/settings/configs") view=ConfigsPage />
                             <Route path=path!("/settings/dag-inspector") view=DagInspectorPage />
@@ -245,7 +248,13 @@ pub fn App() -> impl IntoView {
                                 path=path!("/settings/dag-inspector/ipld-blob/:cid")
                                 view=IpldBlobPage
                             />
+                            <Route
+                                path=path!("/settings/dag-inspector/path/*path")
+                                view=DagPathPage
+                            />
+                            <Route path=path!("/dashboards/:name") view=MishDashboardPage />
                             <Route path=path!("/devices") view=DevicesPage />
+                            <Route path=path!("/energy") view=EnergyPage />
                             <Route path=path!("/websocket") view=WebSocketPage />
                         </Routes>
                     </main>
@@ -1,5 +1,9 @@
 use {
-    crate::components::mish::new_mish_state_dialog::NewMishStateDialog,
+    crate::components::mish::{
+        dag_path_page::DagPathInput,
+        ipld_pins::{IpldGc, IpldPins, IpldVerify},
+        new_mish_state_dialog::NewMishStateDialog,
+    },
     leptos::{
         prelude::*,
         server::{Resource, ServerAction},
@@ -56,6 +60,7 @@ pub fn DagInspectorPage() -> impl IntoView {
     view! {
         <main>
             <NewMishStateDialog />
+            <DagPathInput />
             <Suspense fallback=|| {
                 view! { <p>"Loading Mish States..."</p> }
             }>
@@ -92,6 +97,9 @@ pub fn DagInspectorPage() -> impl IntoView {
                         })
                 }}
             </Suspense>
+            <IpldPins />
+            <IpldGc />
+            <IpldVerify />
         </main>
     }
 }
@@ -0,0 +1,169 @@
+use {
+    crate::integrations::iron_nest::types::mish::ResolvedPath,
+    leptos::prelude::*,
+    leptos_router::{
+        hooks::{use_navigate, use_params},
+        params::Params,
+    },
+};
+
+#[server(ResolveMishPath)]
+async fn resolve_mish_path(path: String) -> Result<ResolvedPath, ServerFnError> {
+    use crate::integrations::iron_nest::mish::resolve::resolve_path;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    resolve_path(&pool, &path).await.map_err(ServerFnError::new)
+}
+
+fn path_href(segments: &[String]) -> String {
+    let segments = segments
+        .iter()
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>();
+    format!("/settings/dag-inspector/path/{}", segments.join("/"))
+}
+
+/// Jumps to the path browser, e.g. for `fish_tank/config/lights/0`.
+#[component]
+pub fn DagPathInput() -> impl IntoView {
+    let path = RwSignal::new(String::new());
+
+    view! {
+        <div>
+            <h2>"Browse path"</h2>
+            <input type="text" placeholder="fish_tank/config/lights/0" bind:value=path />
+            <button on:click=move |_| {
+                let segments = path
+                    .get()
+                    .split('/')
+                    .filter(|segment| !segment.is_empty())
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>();
+                let navigate = use_navigate();
+                navigate(&path_href(&segments), Default::default());
+            }>"Go"</button>
+        </div>
+    }
+}
+
+/// Browses a path through a mish state (or CID) and the blocks it links to, one segment at a time.
+#[component]
+pub fn DagPathPage() -> impl IntoView {
+    #[derive(Params, PartialEq)]
+    struct DagPathParams {
+        path: Option<String>,
+    }
+    let params = use_params::<DagPathParams>();
+    let segments = move || {
+        params
+            .read()
+            .as_ref()
+            .ok()
+            .and_then(|params| params.path.clone())
+            .unwrap_or_default()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                urlencoding::decode(segment)
+                    .map(|segment| segment.into_owned())
+                    .unwrap_or_else(|_| segment.to_owned())
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let resolved = Resource::new(segments, |segments| resolve_mish_path(segments.join("/")));
+
+    view! {
+        <main>
+            <div>
+                <a href="/settings/dag-inspector">"Back to Dag Inspector"</a>
+            </div>
+            <Suspense fallback=|| {
+                view! { <p>"Resolving path..."</p> }
+            }>
+                {move || {
+                    resolved
+                        .get()
+                        .map(|resolved| {
+                            let segments = segments();
+                            match resolved {
+                                Err(e) => {
+                                    view! { <p>"Error resolving path: " {e.to_string()}</p> }
+                                        .into_any()
+                                }
+                                Ok(resolved) => {
+                                    let breadcrumbs = (1..=segments.len())
+                                        .map(|len| {
+                                            let prefix = &segments[..len];
+                                            let link = resolved
+                                                .links
+                                                .iter()
+                                                .filter(|link| link.path == prefix.join("/"))
+                                                .map(|link| {
+                                                    view! {
+                                                        " ("
+                                                        <a href=format!(
+                                                            "/settings/dag-inspector/ipld-blob/{}",
+                                                            link.cid,
+                                                        )>{link.cid.clone()}</a>
+                                                        ")"
+                                                    }
+                                                })
+                                                .collect::<Vec<_>>();
+                                            view! {
+                                                " / "
+                                                <a href=path_href(prefix)>{segments[len - 1].clone()}</a>
+                                                {link}
+                                            }
+                                        })
+                                        .collect::<Vec<_>>();
+                                    let children = match &resolved.value {
+                                        serde_json::Value::Object(map) if !map.contains_key("/") => {
+                                            map.keys().cloned().collect::<Vec<_>>()
+                                        }
+                                        serde_json::Value::Array(list) => {
+                                            (0..list.len()).map(|i| i.to_string()).collect()
+                                        }
+                                        _ => Vec::new(),
+                                    };
+                                    let children = children
+                                        .into_iter()
+                                        .map(|child| {
+                                            let mut path = segments.clone();
+                                            path.push(child.clone());
+                                            view! {
+                                                <li>
+                                                    <a href=path_href(&path)>{child}</a>
+                                                </li>
+                                            }
+                                        })
+                                        .collect::<Vec<_>>();
+                                    // Raw blocks aren't followed, link to them instead
+                                    let raw_link = resolved
+                                        .value
+                                        .get("/")
+                                        .and_then(|cid| cid.as_str())
+                                        .map(|cid| {
+                                            view! {
+                                                <a href=format!(
+                                                    "/settings/dag-inspector/ipld-blob/{cid}",
+                                                )>"Open blob " {cid.to_owned()}</a>
+                                            }
+                                        });
+                                    view! {
+                                        <nav>{breadcrumbs}</nav>
+                                        <pre>
+                                            {serde_json::to_string_pretty(&resolved.value)
+                                                .unwrap_or_default()}
+                                        </pre>
+                                        {raw_link}
+                                        <ul>{children}</ul>
+                                    }
+                                        .into_any()
+                                }
+                            }
+                        })
+                }}
+            </Suspense>
+        </main>
+    }
+}
@@ -7,9 +7,12 @@ use {
     std::sync::Arc,
 };
 
+/// `schema` is the JSON Schema of `state` (if the mish state references one) and is used to
+/// render enums as selects and numbers as typed inputs.
 #[component]
 pub fn Editor(
     state: serde_json::Value,
+    schema: Option<serde_json::Value>,
     action: impl Fn(serde_json::Value) + 'static + Send + Sync,
 ) -> impl IntoView {
     let action = Arc::new(action);
@@ -20,9 +23,51 @@ pub fn Editor(
         move || {
             let state = state.clone();
             let action = action.clone();
+            let schema = schema.clone();
             if raw_editor_mode.get() {
                 view! { <JsonEditor state=Some(state) set_config_server_action=move |s| { action(s) } /> }.into_any()
+            } else if let Some(options) = schema_enum(schema.as_ref()) {
+                let selected = options.iter().position(|option| option == &state);
+                let values = options.clone();
+                view! {
+                    <select on:change:target=move |ev| {
+                        if let Ok(i) = ev.target().value().parse::<usize>() {
+                            action(values[i].clone());
+                        }
+                    }>
+                        {selected
+                            .is_none()
+                            .then(|| {
+                                view! {
+                                    <option selected=true disabled=true>
+                                        {state.to_string()}
+                                    </option>
+                                }
+                            })}
+                        {options
+                            .iter()
+                            .enumerate()
+                            .map(|(i, option)| {
+                                view! {
+                                    <option value=i.to_string() selected=selected == Some(i)>
+                                        {match option {
+                                            serde_json::Value::String(s) => s.clone(),
+                                            option => option.to_string(),
+                                        }}
+                                    </option>
+                                }
+                            })
+                            .collect::<Vec<_>>()}
+                    </select>
+                }
+                .into_any()
             } else {
+                // Some(true) for "integer", Some(false) for "number"
+                let integer_schema = match schema_type(schema.as_ref()) {
+                    Some("integer") => Some(true),
+                    Some("number") => Some(false),
+                    _ => None,
+                };
                 match state.clone() {
                     serde_json::Value::Bool(b) => view! {
                         <input
@@ -44,6 +89,31 @@ pub fn Editor(
                         />
                     }
                     .into_any(),
+                    serde_json::Value::Number(n) if integer_schema.is_some() => {
+                        let integer = integer_schema == Some(true);
+                        let schema = schema.unwrap_or_default();
+                        view! {
+                            <input
+                                type="number"
+                                value=n.to_string()
+                                min=schema.get("minimum").map(|v| v.to_string())
+                                max=schema.get("maximum").map(|v| v.to_string())
+                                step=if integer { "1" } else { "any" }
+                                on:change:target=move |ev| {
+                                    let value = ev.target().value();
+                                    let value = if integer {
+                                        value.parse::<i64>().ok().map(serde_json::Value::from)
+                                    } else {
+                                        value.parse::<f64>().ok().map(serde_json::Value::from)
+                                    };
+                                    if let Some(value) = value {
+                                        action(value);
+                                    }
+                                }
+                            />
+                        }
+                        .into_any()
+                    }
                     serde_json::Value::Number(n) => {
                         view! { <NumberEditor state=n.to_string() set_config_server_action=move |s| { action(s) } /> }
                             .into_any()
@@ -60,6 +130,7 @@ pub fn Editor(
                                     <div style="border-left: 5px solid black">
                                         <NestedEditor
                                             state=v.clone()
+                                            schema=schema.as_ref().and_then(|schema| schema.get("items")).cloned()
                                             action=Box::new(move |s| {
                                                 let mut a = a.clone();
                                                 a[i] = s;
@@ -86,14 +157,25 @@ pub fn Editor(
                                 .map(|(k, v)| {
                                     let o = o.clone();
                                     let action = action.clone();
+                                    let schema = schema
+                                        .as_ref()
+                                        .and_then(|schema| schema.get("properties"))
+                                        .and_then(|properties| properties.get(&k))
+                                        .cloned();
                                     view! {
                                         // <div>"Key: "{k.clone()}</div>
                                         // <div>
                                         // "Value: " {serde_json::to_string_pretty(&v).unwrap()}
                                         // </div>
+                                        {schema
+                                            .as_ref()
+                                            .and_then(|schema| schema.get("title"))
+                                            .and_then(|title| title.as_str())
+                                            .map(|title| view! { <div>{title.to_owned()}</div> })}
                                         <div style="border-left: 5px solid black">
                                             <NestedEditor
                                                 state=v.clone()
+                                                schema=schema.clone()
                                                 action=Box::new(move |s| {
                                                     let mut o = o.clone();
                                                     o.insert(k.clone(), s);
@@ -127,7 +209,16 @@ pub fn Editor(
 #[component]
 pub fn NestedEditor(
     state: serde_json::Value,
+    schema: Option<serde_json::Value>,
     action: Box<dyn Fn(serde_json::Value) + 'static + Send + Sync>,
 ) -> impl IntoView {
-    view! { <Editor state=state action=action /> }
+    view! { <Editor state=state schema=schema action=action /> }
+}
+
+fn schema_enum(schema: Option<&serde_json::Value>) -> Option<Vec<serde_json::Value>> {
+    schema?.get("enum")?.as_array().cloned()
+}
+
+fn schema_type(schema: Option<&serde_json::Value>) -> Option<&str> {
+    schema?.get("type")?.as_str()
 }
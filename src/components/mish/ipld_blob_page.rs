@@ -2,19 +2,17 @@ use {
     crate::{
         components::{
             layout::{Toast, ToastContext},
-            mish::{json_editor::JsonEditor, text_editor::TextEditor},
+            mish::{ipld_pins::PinButton, json_editor::JsonEditor, text_editor::TextEditor},
         },
         ipld_codecs,
     },
     cid::Cid,
-    ipld_core::codec::{Codec, Links},
     leptos::prelude::*,
     leptos_router::{
         hooks::{use_navigate, use_params},
         params::Params,
     },
     serde::{Deserialize, Serialize},
-    serde_ipld_dagjson::codec::DagJsonCodec,
 };
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -30,11 +28,36 @@ pub struct MishStateRow {
     state: serde_json::Value,
 }
 
+/// A blob along with its codec-aware decoding.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IpldBlob {
+    pub codec: u64,
+    pub content: Vec<u8>,
+    /// The DAG-JSON representation of DAG-JSON and DAG-CBOR blobs
+    pub json: Option<serde_json::Value>,
+    pub links: Vec<String>,
+}
+
 #[server(name = GetIpldBlob, encoding = "cbor")]
-async fn get_ipld_blob(cid: String) -> Result<Option<Vec<u8>>, ServerFnError> {
+async fn get_ipld_blob(cid: String) -> Result<Option<IpldBlob>, ServerFnError> {
     let pool = use_context::<sqlx::PgPool>().unwrap();
-    let ipld_blob = get_ipld_blob_query(&pool, &cid.parse::<Cid>().unwrap()).await?;
-    Ok(ipld_blob)
+    let cid = cid.parse::<Cid>()?;
+    let Some(content) = get_ipld_blob_query(&pool, &cid).await? else {
+        return Ok(None);
+    };
+    let codec = cid.codec();
+    let json = ipld_codecs::to_json(codec, &content).map_err(ServerFnError::new)?;
+    let links = ipld_codecs::links(codec, &content)
+        .map_err(ServerFnError::new)?
+        .into_iter()
+        .map(|link| link.to_string())
+        .collect();
+    Ok(Some(IpldBlob {
+        codec,
+        content,
+        json,
+        links,
+    }))
 }
 
 #[cfg(feature = "ssr")]
@@ -51,41 +74,73 @@ pub async fn get_ipld_blob_query(
         FROM ipld_blobs
         WHERE cid = $1
     ";
-    sqlx::query_as::<_, Row>(query)
+    let content = sqlx::query_as::<_, Row>(query)
         .bind(cid.to_bytes())
         .fetch_optional(pool)
-        .await
-        .map(|row| row.map(|row| row.content))
+        .await?
+        .map(|row| row.content);
+    if let Some(content) = &content
+        && ipld_codecs::verify(cid, content) == Some(false)
+    {
+        return Err(sqlx::Error::Decode(Box::new(CorruptedIpldBlob(*cid))));
+    }
+    Ok(content)
 }
 
+/// The stored content doesn't hash to its CID.
+#[derive(Debug, thiserror::Error)]
+#[error("IPLD blob {0} does not match its CID")]
+pub struct CorruptedIpldBlob(pub Cid);
+
+/// `content` is hex encoded. DAG-JSON content may be any JSON, it's re-encoded canonically
+/// before being stored.
 #[server(name = SetIpldBlob, encoding = "cbor")]
-async fn set_ipld_blob(content: String) -> Result<String, ServerFnError> {
+async fn set_ipld_blob(content: String, codec: u64) -> Result<String, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let content = hex::decode(content)?;
+    let content = match codec {
+        ipld_codecs::RAW => content,
+        codec => ipld_codecs::decode(codec, &content)
+            .and_then(|ipld| ipld_codecs::encode(codec, &ipld))
+            .map_err(ServerFnError::new)?,
+    };
+    let cid = set_ipld_blob_query(&pool, codec, content).await?;
+    Ok(cid.to_string())
+}
+
+#[server(name = ConvertIpldBlob, encoding = "cbor")]
+async fn convert_ipld_blob(cid: String, codec: u64) -> Result<String, ServerFnError> {
     let pool = use_context::<sqlx::PgPool>().unwrap();
-    let content = hex::decode(content).unwrap();
-    let cid = set_mish_state_query(&pool, content).await?;
-    println!("cid: {cid}");
+    let cid = cid.parse::<Cid>()?;
+    let content = get_ipld_blob_query(&pool, &cid)
+        .await?
+        .ok_or_else(|| ServerFnError::new(format!("IPLD blob {cid} not found")))?;
+    let content = ipld_codecs::convert(&content, cid.codec(), codec).map_err(ServerFnError::new)?;
+    let cid = set_ipld_blob_query(&pool, codec, content).await?;
     Ok(cid.to_string())
 }
 
 #[cfg(feature = "ssr")]
-pub async fn set_mish_state_query(
+pub async fn set_ipld_blob_query(
     pool: &sqlx::PgPool,
+    codec: u64,
     content: Vec<u8>,
 ) -> Result<Cid, sqlx::Error> {
-    use multihash_codetable::{Code, MultihashDigest};
-    // TODO support dag-json and raw?
-    let cid = Cid::new_v1(ipld_codecs::RAW, Code::Sha2_256.digest(&content));
+    let cid = ipld_codecs::cid(codec, &content);
+    // Storing a blob again restarts its GC grace period, it's likely about to be linked. The
+    // content matches the CID, so it also repairs a stored copy that got corrupted.
     let query = "
         INSERT INTO ipld_blobs (cid, content)
         VALUES ($1, $2)
-        ON CONFLICT (cid) DO NOTHING
+        ON CONFLICT (cid) DO UPDATE SET
+            content = EXCLUDED.content,
+            created_at = NOW()
     ";
     sqlx::query(query)
         .bind(cid.to_bytes())
         .bind(content)
         .execute(pool)
-        .await
-        .unwrap();
+        .await?;
     Ok(cid)
 }
 
@@ -114,6 +169,7 @@ pub fn IpldBlobPage() -> impl IntoView {
     );
 
     let set_ipld_blob_action = ServerAction::<SetIpldBlob>::new();
+    let convert_ipld_blob_action = ServerAction::<ConvertIpldBlob>::new();
     Effect::new(move || {
         let new_cid = set_ipld_blob_action.value().get();
         if let Some(Ok(new_cid)) = new_cid {
@@ -124,6 +180,16 @@ pub fn IpldBlobPage() -> impl IntoView {
             );
         }
     });
+    Effect::new(move || {
+        let new_cid = convert_ipld_blob_action.value().get();
+        if let Some(Ok(new_cid)) = new_cid {
+            let navigate = use_navigate();
+            navigate(
+                &format!("/settings/dag-inspector/ipld-blob/{new_cid}"),
+                Default::default(),
+            );
+        }
+    });
 
     let toast = use_context::<ToastContext>().unwrap();
     Resource::new(
@@ -134,8 +200,25 @@ pub fn IpldBlobPage() -> impl IntoView {
             )
         },
         move |(value, _version)| async move {
-            if matches!(value, Some(Ok(_))) {
-                toast.set(Some(Toast("Mish State saved".to_owned())));
+            match value {
+                Some(Ok(_)) => toast.set(Some(Toast("IPLD Blob saved".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("IPLD Blob not saved: {e}")))),
+                None => {}
+            }
+        },
+    );
+    Resource::new(
+        move || {
+            (
+                convert_ipld_blob_action.value().get(),
+                convert_ipld_blob_action.version().get(),
+            )
+        },
+        move |(value, _version)| async move {
+            match value {
+                Some(Ok(_)) => toast.set(Some(Toast("IPLD Blob converted".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("IPLD Blob not converted: {e}")))),
+                None => {}
             }
         },
     );
@@ -150,6 +233,13 @@ pub fn IpldBlobPage() -> impl IntoView {
                     <div>
                         <a href="/settings/dag-inspector">"Back to Dag Inspector"</a>
                     </div>
+                    <div>"Codec: " {move || ipld_codecs::name(cid().codec())}</div>
+                    <div>
+                        <a href=move || {
+                            format!("/settings/dag-inspector/path/{}", cid())
+                        }>"Browse linked blocks"</a>
+                    </div>
+                    {move || view! { <PinButton cid=cid().to_string() /> }}
                     <div>
                         <label for="raw-editor-mode">"RAW editor mode"</label>
                         <input type="checkbox" id="raw-editor-mode" bind:checked=raw_editor_mode />
@@ -169,10 +259,11 @@ pub fn IpldBlobPage() -> impl IntoView {
                                             }
                                             Ok(value) => {
                                                 value
-                                                    .map(|state| {
-                                                        let codec = cid().codec();
-                                                        match codec {
-                                                            ipld_codecs::RAW => {
+                                                    .map(|blob| {
+                                                        let codec = blob.codec;
+                                                        match (codec, blob.json) {
+                                                            (ipld_codecs::RAW, _) => {
+                                                                let state = blob.content;
                                                                 let checkbox = view! {
                                                                     <div>
                                                                         <label for="hex-editor-mode">"Hex editor mode"</label>
@@ -189,7 +280,11 @@ pub fn IpldBlobPage() -> impl IntoView {
                                                                         <TextEditor
                                                                             state=hex::encode(state)
                                                                             set_config_server_action=move |content| {
-                                                                                set_ipld_blob_action.dispatch(SetIpldBlob { content });
+                                                                                set_ipld_blob_action
+                                                                                    .dispatch(SetIpldBlob {
+                                                                                        content,
+                                                                                        codec: ipld_codecs::RAW,
+                                                                                    });
                                                                             }
                                                                         />
                                                                     }
@@ -205,6 +300,7 @@ pub fn IpldBlobPage() -> impl IntoView {
                                                                                         set_ipld_blob_action
                                                                                             .dispatch(SetIpldBlob {
                                                                                                 content: hex::encode(content),
+                                                                                                codec: ipld_codecs::RAW,
                                                                                             });
                                                                                     }
                                                                                 />
@@ -224,30 +320,45 @@ pub fn IpldBlobPage() -> impl IntoView {
                                                                 };
                                                                 view! { <div>{checkbox} {editor}</div> }.into_any()
                                                             }
-                                                            ipld_codecs::DAG_JSON => {
-                                                                let parsed = <DagJsonCodec as Codec<
-                                                                    serde_json::Value,
-                                                                >>::decode_from_slice(&state);
-                                                                match parsed {
-                                                                    Ok(parsed) => {
-                                                                        view! {
-                                                                            <JsonEditor
-                                                                                state=Some(parsed)
-                                                                                set_config_server_action=move |content| {
+                                                            (ipld_codecs::DAG_JSON | ipld_codecs::DAG_CBOR, Some(parsed)) => {
+                                                                let other_codec = if codec == ipld_codecs::DAG_JSON {
+                                                                    ipld_codecs::DAG_CBOR
+                                                                } else {
+                                                                    ipld_codecs::DAG_JSON
+                                                                };
+                                                                view! {
+                                                                    <JsonEditor
+                                                                        state=Some(parsed)
+                                                                        set_config_server_action=move |content| {
+                                                                            // Edits are made on the DAG-JSON representation
+                                                                            let content = serde_json::to_vec(&content)
+                                                                                .map_err(anyhow::Error::from)
+                                                                                .and_then(|content| {
+                                                                                    ipld_codecs::convert(&content, ipld_codecs::DAG_JSON, codec)
+                                                                                });
+                                                                            match content {
+                                                                                Ok(content) => {
                                                                                     set_ipld_blob_action
                                                                                         .dispatch(SetIpldBlob {
-                                                                                            content: hex::encode(serde_json::to_vec(&content).unwrap()),
+                                                                                            content: hex::encode(content),
+                                                                                            codec,
                                                                                         });
                                                                                 }
-                                                                            />
+                                                                                Err(e) => {
+                                                                                    toast.set(Some(Toast(format!("Invalid {}: {e}", ipld_codecs::name(codec)))));
+                                                                                }
+                                                                            }
                                                                         }
-                                                                            .into_any()
-                                                                    }
-                                                                    Err(e) => {
-                                                                        view! { <p>"Error parsing DAG-JSON: " {e.to_string()}</p> }
-                                                                            .into_any()
-                                                                    }
+                                                                    />
+                                                                    <button on:click=move |_| {
+                                                                        convert_ipld_blob_action
+                                                                            .dispatch(ConvertIpldBlob {
+                                                                                cid: cid().to_string(),
+                                                                                codec: other_codec,
+                                                                            });
+                                                                    }>"Convert to " {ipld_codecs::name(other_codec)}</button>
                                                                 }
+                                                                    .into_any()
                                                             }
                                                             _ => {
                                                                 view! { <p>"Unsupported codec: " {codec}</p> }.into_any()
@@ -269,36 +380,20 @@ pub fn IpldBlobPage() -> impl IntoView {
                         <div>{move || format!("{:?}", values.get())}</div>
                         <div>
                             {move || {
-                                if let Some(Ok(Some(data))) = values.get() {
-                                    if cid().codec() == ipld_codecs::DAG_JSON {
-                                        let links = <DagJsonCodec as Links>::links(&data);
-                                        match links {
-                                            Ok(links) => {
-                                                view! {
-                                                    {links
-                                                        .into_iter()
-                                                        .map(|link| {
-                                                            view! {
-                                                                <p>
-                                                                    <a href=format!(
-                                                                        "/settings/dag-inspector/ipld-blob/{link}",
-                                                                    )>"Link: "{link.to_string()}</a>
-                                                                </p>
-                                                            }
-                                                                .into_any()
-                                                        })
-                                                        .collect::<Vec<_>>()}
-                                                }
-                                                    .into_any()
+                                if let Some(Ok(Some(blob))) = values.get() {
+                                    blob.links
+                                        .into_iter()
+                                        .map(|link| {
+                                            view! {
+                                                <p>
+                                                    <a href=format!(
+                                                        "/settings/dag-inspector/ipld-blob/{link}",
+                                                    )>"Link: "{link}</a>
+                                                </p>
                                             }
-                                            Err(e) => {
-                                                view! { <p>{format!("Error getting links: {e}")}</p> }
-                                                    .into_any()
-                                            }
-                                        }
-                                    } else {
-                                        ().into_any()
-                                    }
+                                        })
+                                        .collect::<Vec<_>>()
+                                        .into_any()
                                 } else {
                                     ().into_any()
                                 }
@@ -0,0 +1,246 @@
+use {
+    crate::{
+        components::layout::{Toast, ToastContext},
+        integrations::iron_nest::types::mish::{GcReport, IpldPin, VerifyReport},
+    },
+    leptos::prelude::*,
+};
+
+#[server(GetIpldPins)]
+async fn get_ipld_pins() -> Result<Vec<IpldPin>, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let pins = get_ipld_pins_query(&pool).await?;
+    Ok(pins)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_ipld_pins_query(pool: &sqlx::PgPool) -> Result<Vec<IpldPin>, sqlx::Error> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        name: String,
+        cid: Vec<u8>,
+    }
+    let query = "
+        SELECT name, cid
+        FROM ipld_pins
+        ORDER BY name
+    ";
+    sqlx::query_as::<_, Row>(query)
+        .fetch_all(pool)
+        .await
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| IpldPin {
+                    name: row.name,
+                    cid: cid::Cid::try_from(row.cid)
+                        .map(|cid| cid.to_string())
+                        .unwrap_or_else(|e| format!("Invalid CID: {e}")),
+                })
+                .collect()
+        })
+}
+
+#[server(PinIpldBlob)]
+async fn pin_ipld_blob(name: String, cid: String) -> Result<(), ServerFnError> {
+    use crate::integrations::iron_nest::mish::gc::pin_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let cid = cid.parse::<cid::Cid>()?;
+    pin_query(&pool, &name, &cid).await?;
+    Ok(())
+}
+
+#[server(UnpinIpldBlob)]
+async fn unpin_ipld_blob(name: String) -> Result<(), ServerFnError> {
+    use crate::integrations::iron_nest::mish::gc::unpin_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    unpin_query(&pool, &name).await?;
+    Ok(())
+}
+
+#[server(RunIpldGc)]
+async fn run_ipld_gc(dry_run: bool) -> Result<GcReport, ServerFnError> {
+    use crate::integrations::iron_nest::mish::gc::run_gc;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    run_gc(&pool, dry_run).await.map_err(ServerFnError::new)
+}
+
+#[server(VerifyIpldBlobs)]
+async fn verify_ipld_blobs() -> Result<VerifyReport, ServerFnError> {
+    use crate::integrations::iron_nest::mish::blobs::verify_blobs;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    verify_blobs(&pool).await.map_err(ServerFnError::new)
+}
+
+#[component]
+pub fn IpldPins() -> impl IntoView {
+    let pin_action = ServerAction::<PinIpldBlob>::new();
+    let unpin_action = ServerAction::<UnpinIpldBlob>::new();
+    let pins = Resource::new(
+        move || (pin_action.version().get(), unpin_action.version().get()),
+        |_| get_ipld_pins(),
+    );
+    let name = RwSignal::new(String::new());
+    let cid = RwSignal::new(String::new());
+
+    view! {
+        <div>
+            <h2>"Pins"</h2>
+            <Suspense fallback=|| {
+                view! { <p>"Loading pins..."</p> }
+            }>
+                {move || {
+                    pins.get()
+                        .map(|pins| match pins {
+                            Err(e) => {
+                                view! { <p>"Error loading pins: " {e.to_string()}</p> }.into_any()
+                            }
+                            Ok(pins) => {
+                                pins.into_iter()
+                                    .map(|pin| {
+                                        let name = pin.name.clone();
+                                        view! {
+                                            <div>
+                                                {pin.name.clone()} ": "
+                                                <a href=format!(
+                                                    "/settings/dag-inspector/ipld-blob/{}",
+                                                    pin.cid,
+                                                )>{pin.cid.clone()}</a>
+                                                <button on:click=move |_| {
+                                                    unpin_action
+                                                        .dispatch(UnpinIpldBlob {
+                                                            name: name.clone(),
+                                                        });
+                                                }>"Unpin"</button>
+                                            </div>
+                                        }
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Suspense>
+            <div>
+                <input type="text" placeholder="Name" bind:value=name />
+                <input type="text" placeholder="CID" bind:value=cid />
+                <button on:click=move |_| {
+                    pin_action
+                        .dispatch(PinIpldBlob {
+                            name: name.get(),
+                            cid: cid.get(),
+                        });
+                }>"Pin"</button>
+            </div>
+        </div>
+    }
+}
+
+/// Pins a single blob, for the IPLD blob page.
+#[component]
+pub fn PinButton(cid: String) -> impl IntoView {
+    let pin_action = ServerAction::<PinIpldBlob>::new();
+    let name = RwSignal::new(String::new());
+
+    let toast = use_context::<ToastContext>().unwrap();
+    Effect::new(move || match pin_action.value().get() {
+        Some(Ok(_)) => toast.set(Some(Toast("Pinned".to_owned()))),
+        Some(Err(e)) => toast.set(Some(Toast(format!("Not pinned: {e}")))),
+        None => {}
+    });
+
+    view! {
+        <div>
+            <input type="text" placeholder="Pin name" bind:value=name />
+            <button on:click=move |_| {
+                pin_action
+                    .dispatch(PinIpldBlob {
+                        name: name.get(),
+                        cid: cid.clone(),
+                    });
+            }>"Pin"</button>
+        </div>
+    }
+}
+
+#[component]
+pub fn IpldGc() -> impl IntoView {
+    let gc_action = ServerAction::<RunIpldGc>::new();
+
+    view! {
+        <div>
+            <h2>"Garbage collection"</h2>
+            <button on:click=move |_| {
+                gc_action.dispatch(RunIpldGc { dry_run: true });
+            }>"Dry run"</button>
+            <button on:click=move |_| {
+                gc_action.dispatch(RunIpldGc { dry_run: false });
+            }>"Collect garbage"</button>
+            {move || {
+                gc_action
+                    .value()
+                    .get()
+                    .map(|report| match report {
+                        Ok(report) => {
+                            view! {
+                                <p>
+                                    {if report.dry_run { "Would delete " } else { "Deleted " }}
+                                    {report.reclaimable_blobs.len()} " blobs ("
+                                    {report.reclaimable_bytes} " bytes). "
+                                    {report.reachable_blobs} " blobs reachable from "
+                                    {report.roots} " roots."
+                                </p>
+                                <ul>
+                                    {report
+                                        .reclaimable_blobs
+                                        .into_iter()
+                                        .map(|cid| view! { <li>{cid}</li> })
+                                        .collect::<Vec<_>>()}
+                                </ul>
+                            }
+                                .into_any()
+                        }
+                        Err(e) => view! { <p>"GC failed: " {e.to_string()}</p> }.into_any(),
+                    })
+            }}
+        </div>
+    }
+}
+
+#[component]
+pub fn IpldVerify() -> impl IntoView {
+    let verify_action = ServerAction::<VerifyIpldBlobs>::new();
+
+    view! {
+        <div>
+            <h2>"Integrity"</h2>
+            <button on:click=move |_| {
+                verify_action.dispatch(VerifyIpldBlobs {});
+            }>"Verify blobs"</button>
+            {move || {
+                verify_action
+                    .value()
+                    .get()
+                    .map(|report| match report {
+                        Ok(report) => {
+                            view! {
+                                <p>
+                                    {report.checked} " blobs checked, "
+                                    {report.corrupted.len()} " corrupted, "
+                                    {report.unsupported.len()} " with unsupported hashes."
+                                </p>
+                                <ul>
+                                    {report
+                                        .corrupted
+                                        .into_iter()
+                                        .map(|cid| view! { <li>{cid}</li> })
+                                        .collect::<Vec<_>>()}
+                                </ul>
+                            }
+                                .into_any()
+                        }
+                        Err(e) => view! { <p>"Verification failed: " {e.to_string()}</p> }.into_any(),
+                    })
+            }}
+        </div>
+    }
+}
@@ -1,5 +1,7 @@
 use {
-    leptos::prelude::*,
+    crate::components::layout::{Toast, ToastContext},
+    leptos::{prelude::*, task::spawn_local},
+    leptos_router::hooks::use_navigate,
     serde::{Deserialize, Serialize},
 };
 
@@ -7,28 +9,133 @@ use {
 pub struct MishButtonValue {
     pub label: String,
     pub action: MishButtonAction,
+    /// Asked before running the action, e.g. "Turn everything off?"
+    #[serde(default)]
+    pub confirm: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MishButtonAction {
     Log(String),
+    /// Sets the value at a JSONPath in a mish state.
+    UpdateMishState {
+        name: String,
+        path: String,
+        value: serde_json::Value,
+    },
+    /// Calls one of the functions actions can run, e.g. `tplink_turn_plug_on`.
+    Function {
+        name: String,
+        #[serde(default)]
+        args: serde_json::Value,
+    },
+    /// Runs an install item of the `run` mish state now.
+    TriggerInstallItem(String),
+    /// Applies a scene by name.
+    ApplyScene(String),
+    Navigate(String),
+}
+
+/// Runs the server side actions, `action` being hex encoded JSON of a [`MishButtonAction`].
+/// Returns the message to show when it succeeds.
+#[server(RunMishButtonAction)]
+async fn run_mish_button_action(action: String) -> Result<String, ServerFnError> {
+    use {
+        crate::{
+            integrations::iron_nest::{
+                execute_function,
+                mish::{MishStateModification, trigger_install_item},
+                scenes::apply_scene,
+                types::mish::ScriptLog,
+            },
+            mish_api::{UpdateMishStateBody, update_mish_state},
+        },
+        tokio::sync::broadcast::Sender,
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let mish_state_modification_bus_sender =
+        use_context::<Sender<MishStateModification>>().unwrap();
+    let action = hex::decode(action).map_err(ServerFnError::new)?;
+    match serde_json::from_slice::<MishButtonAction>(&action).map_err(ServerFnError::new)? {
+        MishButtonAction::UpdateMishState { name, path, value } => {
+            update_mish_state(
+                &pool,
+                &mish_state_modification_bus_sender,
+                UpdateMishStateBody {
+                    mish_state_name: name.clone(),
+                    path,
+                    content: value,
+                },
+            )
+            .await
+            .map_err(ServerFnError::new)?;
+            Ok(format!("{name} updated"))
+        }
+        MishButtonAction::Function { name, args } => {
+            let result = execute_function(&pool, name, args)
+                .await
+                .map_err(ServerFnError::new)?;
+            Ok(result.to_string())
+        }
+        MishButtonAction::TriggerInstallItem(name) => {
+            let script_log_bus_sender = use_context::<Sender<ScriptLog>>().unwrap();
+            trigger_install_item(
+                &pool,
+                mish_state_modification_bus_sender,
+                script_log_bus_sender,
+                &name,
+            )
+            .await
+            .map_err(ServerFnError::new)?;
+            Ok(format!("{name} triggered"))
+        }
+        MishButtonAction::ApplyScene(name) => {
+            apply_scene(&pool, &name)
+                .await
+                .map_err(ServerFnError::new)?;
+            Ok(format!("{name} applied"))
+        }
+        MishButtonAction::Log(_) | MishButtonAction::Navigate(_) => Err(ServerFnError::new(
+            "Log and Navigate actions run in the browser",
+        )),
+    }
 }
 
 #[component]
 pub fn MishButton(value: serde_json::Value) -> impl IntoView {
     let value = serde_json::from_value::<MishButtonValue>(value);
+    let toast = use_context::<ToastContext>().unwrap();
+    let navigate = use_navigate();
     view! {
         {match value {
             Ok(value) => {
+                let label = value.label.clone();
                 view! {
                     <button on:click=move |_| {
+                        if let Some(confirm) = &value.confirm
+                            && !window().confirm_with_message(confirm).unwrap_or(false)
+                        {
+                            return;
+                        }
                         let action = value.action.clone();
                         match action {
                             MishButtonAction::Log(s) => {
                                 web_sys::console::log_1(&format!("action: {s}").into());
                             }
+                            MishButtonAction::Navigate(href) => navigate(&href, Default::default()),
+                            action => {
+                                let label = value.label.clone();
+                                let action = hex::encode(serde_json::to_vec(&action).unwrap());
+                                spawn_local(async move {
+                                    let message = match run_mish_button_action(action).await {
+                                        Ok(message) => format!("{label}: {message}"),
+                                        Err(e) => format!("{label} failed: {e}"),
+                                    };
+                                    toast.set(Some(Toast(message)));
+                                });
+                            }
                         }
-                    }>{value.label}</button>
+                    }>{label}</button>
                 }
                     .into_any()
             }
@@ -1,6 +1,24 @@
 use {
-    crate::components::ring_cameras::RingCameraPanelWithData,
+    crate::{
+        components::{
+            device_list::DeviceListItem,
+            device_list_card::DeviceListCard,
+            device_modal::DeviceView,
+            layout::{Toast, ToastContext},
+            mish::{
+                editor::Editor,
+                mish_state_page::{SetMishStateError, get_mish_state},
+                subscription::{provide_mish_state_subscriptions, use_mish_state},
+            },
+            pages::dashboard_page::get_dashboard_values,
+            ring_cameras::RingCameraPanelWithData,
+            roku_tv_remote::RokuTvRemote,
+        },
+        integrations::iron_nest::types::mish::select_json_via_jsonpath,
+        server::dashboard_page::get_device,
+    },
     leptos::prelude::*,
+    leptos_router::{hooks::use_params, params::Params},
     serde::{Deserialize, Serialize},
 };
 
@@ -12,52 +30,540 @@ pub struct MishDashboardValue {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MishDashboardPanel {
-    Ring { camera_id: String },
+    Ring {
+        camera_id: String,
+    },
+    /// A row of the `device` table.
+    Device {
+        device_id: i64,
+        #[serde(default)]
+        control: DeviceControl,
+    },
+    Roku,
+    /// The value at a JSONPath in a mish state, editable in place.
+    MishStateValue {
+        name: String,
+        path: String,
+        label: Option<String>,
+    },
+    /// Charts the readings at a JSONPath in a mish state, either numbers or objects with a
+    /// `watts` field (oldest first), e.g. as kept by a script polling a smart plug.
+    EnergyChart {
+        name: String,
+        path: String,
+        label: Option<String>,
+    },
+    /// Headings (`#`), bullet lists (`-` or `*`) and paragraphs of a small Markdown subset.
+    Text {
+        content: String,
+    },
+    /// Another dashboard nested in this panel.
+    Group {
+        layout: MishDashboardLayout,
+        panels: Vec<MishDashboardPanel>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum DeviceControl {
+    /// Just the on/off switch
+    #[default]
+    Toggle,
+    /// The switch plus brightness (and color, for lights) controls
+    Slider,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MishDashboardLayout {
-    Grid { columns: i32 },
+    Grid {
+        columns: i32,
+    },
+    /// Panels side by side, sized by their content.
+    FlexRow {
+        #[serde(default = "default_gap")]
+        gap: i32,
+        #[serde(default)]
+        wrap: bool,
+    },
+    /// A single column grid that gets the columns of the widest breakpoint the screen is at least
+    /// as wide as.
+    Responsive {
+        breakpoints: Vec<MishDashboardBreakpoint>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MishDashboardBreakpoint {
+    pub min_width: i32,
+    pub columns: i32,
+}
+
+fn default_gap() -> i32 {
+    10
+}
+
+#[server(GetMishStateValue)]
+async fn get_mish_state_value(
+    name: String,
+    path: String,
+) -> Result<Option<serde_json::Value>, ServerFnError> {
+    use crate::components::mish::mish_state_page::get_mish_state_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let Some(mish_state) = get_mish_state_query(&pool, &name).await? else {
+        return Ok(None);
+    };
+    select_json_via_jsonpath(&mish_state.state, &path)
+        .map(Some)
+        .map_err(|e| ServerFnError::new(e.to_string()))
+}
+
+#[server(SetMishStateValue)]
+async fn set_mish_state_value(
+    name: String,
+    path: String,
+    content: String,
+) -> Result<(), SetMishStateError> {
+    use {
+        crate::{
+            integrations::iron_nest::mish::{MishStateModification, schema::MishStateSchemaError},
+            mish_api::{UpdateMishStateBody, update_mish_state},
+        },
+        jsonpath_rust::parser::errors::JsonPathError,
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let mish_state_modification_bus_sender =
+        use_context::<tokio::sync::broadcast::Sender<MishStateModification>>().unwrap();
+    let content = hex::decode(content).map_err(|e| SetMishStateError::Hex(e.to_string()))?;
+    let content =
+        serde_json::from_slice(&content).map_err(|e| SetMishStateError::Json(e.to_string()))?;
+    let body = UpdateMishStateBody {
+        mish_state_name: name,
+        path,
+        content,
+    };
+    update_mish_state(&pool, &mish_state_modification_bus_sender, body)
+        .await
+        .map_err(|e| {
+            let message = e.to_string();
+            if e.is::<sqlx::Error>() {
+                SetMishStateError::Sql(message)
+            } else if e.is::<JsonPathError>() {
+                SetMishStateError::Path(message)
+            } else if e.is::<MishStateSchemaError>() {
+                SetMishStateError::Schema(message)
+            } else {
+                SetMishStateError::ServerFnError(ServerFnErrorErr::ServerError(message))
+            }
+        })
+}
+
+/// The value at `path` in the `name` mish state, kept up to date as the state changes.
+fn use_mish_state_value(
+    name: String,
+    path: String,
+) -> Signal<Option<Result<Option<serde_json::Value>, ServerFnError>>> {
+    let value = Resource::new(
+        {
+            let (name, path) = (name.clone(), path.clone());
+            move || (name.clone(), path.clone())
+        },
+        |(name, path)| get_mish_state_value(name, path),
+    );
+    let state = use_mish_state(move || name.clone());
+    Signal::derive(move || match state.get() {
+        Some(Some(state)) => Some(
+            select_json_via_jsonpath(&state, &path)
+                .map(Some)
+                .map_err(|e| ServerFnError::new(e.to_string())),
+        ),
+        Some(None) => Some(Ok(None)),
+        None => value.get(),
+    })
+}
+
+struct LayoutStyles {
+    class: String,
+    style: String,
+    panel_style: &'static str,
+    /// Stylesheet for layouts that need media queries
+    css: Option<String>,
+}
+
+fn layout_styles(layout: &MishDashboardLayout) -> LayoutStyles {
+    match layout {
+        MishDashboardLayout::Grid { columns } => LayoutStyles {
+            class: String::new(),
+            style: format!(
+                "display: grid; grid-template-columns: repeat({columns}, 1fr); gap: 10px;"
+            ),
+            panel_style: "",
+            css: None,
+        },
+        MishDashboardLayout::FlexRow { gap, wrap } => LayoutStyles {
+            class: String::new(),
+            style: format!(
+                "display: flex; flex-direction: row; flex-wrap: {}; gap: {gap}px;",
+                if *wrap { "wrap" } else { "nowrap" },
+            ),
+            panel_style: "flex: 1 1 auto;",
+            css: None,
+        },
+        MishDashboardLayout::Responsive { breakpoints } => {
+            let mut breakpoints = breakpoints.clone();
+            breakpoints.sort_by_key(|breakpoint| breakpoint.min_width);
+            // Named after the breakpoints so server and client render the same class
+            let class = breakpoints.iter().fold(
+                "mish-dashboard".to_owned(),
+                |class, MishDashboardBreakpoint { min_width, columns }| {
+                    format!("{class}-{min_width}-{columns}")
+                },
+            );
+            let css = breakpoints.iter().fold(
+                format!(".{class} {{ grid-template-columns: 1fr; }}"),
+                |css, MishDashboardBreakpoint { min_width, columns }| {
+                    format!(
+                        "{css} @media (min-width: {min_width}px) {{ .{class} {{ grid-template-columns: repeat({columns}, 1fr); }} }}"
+                    )
+                },
+            );
+            LayoutStyles {
+                class,
+                style: "display: grid; gap: 10px;".to_owned(),
+                panel_style: "",
+                css: Some(css),
+            }
+        }
+    }
 }
 
 #[component]
 pub fn MishDashboard(value: serde_json::Value) -> impl IntoView {
+    provide_mish_state_subscriptions();
     let value = serde_json::from_value::<MishDashboardValue>(value);
     view! {
         {match value {
             Ok(value) => {
-                view! {
-                    <div style=match value.layout {
-                        MishDashboardLayout::Grid { columns } => {
-                            format!(
-                                "display: grid; grid-template-columns: repeat({}, 1fr); gap: 10px;",
-                                columns,
-                            )
-                        }
-                    }>
-                        {value
-                            .panels
-                            .iter()
-                            .map(|panel| {
+                view! { <MishDashboardPanels layout=value.layout panels=value.panels /> }.into_any()
+            }
+            Err(e) => view! { <p>{format!("Error: {e}")}</p> }.into_any(),
+        }}
+    }
+}
+
+#[component]
+fn MishDashboardPanels(layout: MishDashboardLayout, panels: Vec<MishDashboardPanel>) -> AnyView {
+    let LayoutStyles {
+        class,
+        style,
+        panel_style,
+        css,
+    } = layout_styles(&layout);
+    view! {
+        {css.map(|css| view! { <style>{css}</style> })}
+        <div class=class style=style>
+            {panels
+                .into_iter()
+                .map(|panel| {
+                    view! {
+                        <div style=panel_style>
+                            <MishDashboardPanelView panel=panel />
+                        </div>
+                    }
+                })
+                .collect::<Vec<_>>()}
+        </div>
+    }
+    .into_any()
+}
+
+#[component]
+fn MishDashboardPanelView(panel: MishDashboardPanel) -> AnyView {
+    match panel {
+        MishDashboardPanel::Ring { camera_id } => {
+            view! { <RingCameraPanelWithData camera_id=camera_id /> }.into_any()
+        }
+        MishDashboardPanel::Device { device_id, control } => {
+            view! { <DevicePanel device_id=device_id control=control /> }.into_any()
+        }
+        MishDashboardPanel::Roku => {
+            let dashboard_values = Resource::new(|| (), |_| get_dashboard_values());
+            view! { <RokuTvRemote dashboard_values=dashboard_values /> }.into_any()
+        }
+        MishDashboardPanel::MishStateValue { name, path, label } => {
+            view! { <MishStateValuePanel name=name path=path label=label /> }.into_any()
+        }
+        MishDashboardPanel::EnergyChart { name, path, label } => {
+            view! { <EnergyChartPanel name=name path=path label=label /> }.into_any()
+        }
+        MishDashboardPanel::Text { content } => view! { <TextPanel content=content /> }.into_any(),
+        MishDashboardPanel::Group { layout, panels } => {
+            view! { <MishDashboardPanels layout=layout panels=panels /> }.into_any()
+        }
+    }
+}
+
+#[component]
+fn DevicePanel(device_id: i64, control: DeviceControl) -> impl IntoView {
+    let device = Resource::new(move || device_id, get_device);
+
+    view! {
+        <Suspense fallback=|| {
+            view! { <p>"Loading device..."</p> }
+        }>
+            {move || {
+                device
+                    .get()
+                    .map(|device| match device {
+                        Ok(Some(device)) => match control {
+                            DeviceControl::Toggle => {
+                                view! { <DeviceListItem device=device /> }.into_any()
+                            }
+                            DeviceControl::Slider => {
+                                view! {
+                                    <DeviceListCard device=device.clone()>
+                                        <DeviceView device=device />
+                                    </DeviceListCard>
+                                }
+                                    .into_any()
+                            }
+                        },
+                        Ok(None) => view! { <p>"Device " {device_id} " not found"</p> }.into_any(),
+                        Err(e) => view! { <p>"Error loading device: " {e.to_string()}</p> }.into_any(),
+                    })
+            }}
+        </Suspense>
+    }
+}
+
+#[component]
+fn MishStateValuePanel(name: String, path: String, label: Option<String>) -> impl IntoView {
+    let title = label.unwrap_or_else(|| format!("{name} {path}"));
+    let value = use_mish_state_value(name.clone(), path.clone());
+
+    let set_mish_state_value_action = ServerAction::<SetMishStateValue>::new();
+    let toast = use_context::<ToastContext>().unwrap();
+    Resource::new(
+        move || {
+            (
+                set_mish_state_value_action.value().get(),
+                set_mish_state_value_action.version().get(),
+            )
+        },
+        move |(value, _version)| async move {
+            if let Some(Err(e)) = value {
+                toast.set(Some(Toast(format!("Mish State not saved: {e}"))));
+            }
+        },
+    );
+
+    view! {
+        <div>
+            <h3>{title}</h3>
+            <Suspense fallback=|| {
+                view! { <p>"Loading..."</p> }
+            }>
+                {move || {
+                    value
+                        .get()
+                        .map(|value| match value {
+                            Ok(Some(state)) => {
+                                let (name, path) = (name.clone(), path.clone());
                                 view! {
-                                    <div>
-                                        {match panel {
-                                            MishDashboardPanel::Ring { camera_id } => {
-                                                view! {
-                                                    <RingCameraPanelWithData camera_id=camera_id.clone() />
-                                                }
-                                                    .into_any()
-                                            }
-                                        }}
-                                    </div>
+                                    <Editor
+                                        state=state
+                                        schema=None
+                                        action=move |content| {
+                                            set_mish_state_value_action
+                                                .dispatch(SetMishStateValue {
+                                                    name: name.clone(),
+                                                    path: path.clone(),
+                                                    content: hex::encode(
+                                                        serde_json::to_vec(&content).unwrap(),
+                                                    ),
+                                                });
+                                        }
+                                    />
                                 }
-                            })
-                            .collect::<Vec<_>>()}
-                    </div>
+                                    .into_any()
+                            }
+                            Ok(None) => view! { <p>"Mish state not found"</p> }.into_any(),
+                            Err(e) => view! { <p>"Error: " {e.to_string()}</p> }.into_any(),
+                        })
+                }}
+            </Suspense>
+        </div>
+    }
+}
+
+fn energy_readings(value: &serde_json::Value) -> Vec<f64> {
+    value
+        .as_array()
+        .map(|readings| {
+            readings
+                .iter()
+                .filter_map(|reading| reading.get("watts").unwrap_or(reading).as_f64())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[component]
+fn EnergyChartPanel(name: String, path: String, label: Option<String>) -> impl IntoView {
+    const WIDTH: f64 = 300.0;
+    const HEIGHT: f64 = 100.0;
+
+    let title = label.unwrap_or_else(|| format!("{name} {path}"));
+    let value = use_mish_state_value(name, path);
+    let chart = move || {
+        let readings = match value.get()? {
+            Ok(Some(value)) => energy_readings(&value),
+            Ok(None) => Vec::new(),
+            Err(e) => return Some(view! { <p>"Error: " {e.to_string()}</p> }.into_any()),
+        };
+        let Some(latest) = readings.last().copied() else {
+            return Some(view! { <p>"No readings"</p> }.into_any());
+        };
+        let max = readings.iter().copied().fold(f64::EPSILON, f64::max);
+        let step = WIDTH / (readings.len().max(2) - 1) as f64;
+        let points = readings
+            .iter()
+            .enumerate()
+            .map(|(i, watts)| format!("{},{}", i as f64 * step, HEIGHT - watts / max * HEIGHT))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Some(
+            view! {
+                <p>{format!("{latest:.1} W (max {max:.1} W)")}</p>
+                <svg viewBox=format!("0 0 {WIDTH} {HEIGHT}") preserveAspectRatio="none" class="w-full h-24">
+                    <polyline points=points fill="none" stroke="currentColor" stroke-width="2" />
+                </svg>
+            }
+            .into_any(),
+        )
+    };
+
+    view! {
+        <div>
+            <h3>{title}</h3>
+            <Suspense fallback=|| {
+                view! { <p>"Loading..."</p> }
+            }>{chart}</Suspense>
+        </div>
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum TextBlock {
+    Heading(usize, String),
+    List(Vec<String>),
+    Paragraph(String),
+}
+
+fn text_blocks(content: &str) -> Vec<TextBlock> {
+    let mut blocks = Vec::new();
+    for line in content.lines().map(str::trim) {
+        let heading_level = line.chars().take_while(|c| *c == '#').count();
+        if line.is_empty() {
+            // Blank lines end paragraphs and lists
+            blocks.push(TextBlock::Paragraph(String::new()));
+        } else if (1..=6).contains(&heading_level) && line[heading_level..].starts_with(' ') {
+            blocks.push(TextBlock::Heading(
+                heading_level,
+                line[heading_level..].trim().to_owned(),
+            ));
+        } else if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+            match blocks.last_mut() {
+                Some(TextBlock::List(items)) => items.push(item.to_owned()),
+                _ => blocks.push(TextBlock::List(vec![item.to_owned()])),
+            }
+        } else {
+            match blocks.last_mut() {
+                Some(TextBlock::Paragraph(paragraph)) if !paragraph.is_empty() => {
+                    paragraph.push(' ');
+                    paragraph.push_str(line);
                 }
-                    .into_any()
+                _ => blocks.push(TextBlock::Paragraph(line.to_owned())),
             }
-            Err(e) => view! { <p>{format!("Error: {e}")}</p> }.into_any(),
-        }}
+        }
+    }
+    blocks.retain(|block| *block != TextBlock::Paragraph(String::new()));
+    blocks
+}
+
+#[component]
+fn TextPanel(content: String) -> impl IntoView {
+    text_blocks(&content)
+        .into_iter()
+        .map(|block| match block {
+            TextBlock::Heading(1, text) => view! { <h1 class="text-2xl">{text}</h1> }.into_any(),
+            TextBlock::Heading(2, text) => view! { <h2 class="text-xl">{text}</h2> }.into_any(),
+            TextBlock::Heading(_, text) => view! { <h3 class="text-lg">{text}</h3> }.into_any(),
+            TextBlock::List(items) => view! {
+                <ul class="list-disc pl-5">
+                    {items.into_iter().map(|item| view! { <li>{item}</li> }).collect::<Vec<_>>()}
+                </ul>
+            }
+            .into_any(),
+            TextBlock::Paragraph(text) => view! { <p>{text}</p> }.into_any(),
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Full page view of a dashboard stored in a mish state, kept up to date as the state changes.
+#[component]
+pub fn MishDashboardPage() -> impl IntoView {
+    #[derive(Params, PartialEq)]
+    struct MishDashboardParams {
+        name: Option<String>,
+    }
+    let params = use_params::<MishDashboardParams>();
+    let name = move || params.read().as_ref().unwrap().name.clone().unwrap();
+
+    let value = Resource::new(name, get_mish_state);
+    provide_mish_state_subscriptions();
+    let mish_state = use_mish_state(name);
+
+    view! {
+        <main>
+            <Suspense fallback=|| {
+                view! { <p>"Loading dashboard..."</p> }
+            }>
+                {move || {
+                    let state = match mish_state.get() {
+                        Some(state) => Ok(state),
+                        None => value.get()?.map(|value| value.map(|value| value.state)),
+                    };
+                    Some(
+                        match state {
+                            Ok(Some(state)) => view! { <MishDashboard value=state /> }.into_any(),
+                            Ok(None) => view! { <p>"Dashboard not found"</p> }.into_any(),
+                            Err(e) => {
+                                view! { <p>"Error loading dashboard: " {e.to_string()}</p> }
+                                    .into_any()
+                            }
+                        },
+                    )
+                }}
+            </Suspense>
+        </main>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_blocks() {
+        let content = "# Kitchen\n\nLights turn off\nat midnight.\n\n- Pump\n* Heater\n## Notes";
+        assert_eq!(
+            text_blocks(content),
+            vec![
+                TextBlock::Heading(1, "Kitchen".to_owned()),
+                TextBlock::Paragraph("Lights turn off at midnight.".to_owned()),
+                TextBlock::List(vec!["Pump".to_owned(), "Heater".to_owned()]),
+                TextBlock::Heading(2, "Notes".to_owned()),
+            ]
+        );
     }
 }
@@ -1,7 +1,11 @@
 use {
     crate::components::{
         layout::{Toast, ToastContext},
-        mish::{editor::Editor, json_editor::JsonEditor},
+        mish::{
+            editor::Editor,
+            json_editor::JsonEditor,
+            subscription::{provide_mish_state_subscriptions, use_mish_state},
+        },
     },
     ipld_core::codec::Links,
     leptos::prelude::*,
@@ -11,6 +15,8 @@ use {
     },
     serde::{Deserialize, Serialize},
     serde_ipld_dagjson::codec::DagJsonCodec,
+    server_fn::codec::JsonEncoding,
+    std::fmt::Display,
     thaw::{Icon, Tooltip},
 };
 
@@ -28,7 +34,7 @@ pub struct MishStateRow {
 }
 
 #[server(GetMishState)]
-async fn get_mish_state(name: String) -> Result<Option<MishState>, ServerFnError> {
+pub async fn get_mish_state(name: String) -> Result<Option<MishState>, ServerFnError> {
     let pool = use_context::<sqlx::PgPool>().unwrap();
     let mish_state = get_mish_state_query(&pool, &name).await?;
     Ok(mish_state)
@@ -62,21 +68,92 @@ pub async fn get_mish_state_query(
         })
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SetMishStateError {
+    ServerFnError(ServerFnErrorErr),
+    /// The content sent isn't hex encoded
+    Hex(String),
+    /// The content sent isn't JSON
+    Json(String),
+    /// The JSONPath to update is invalid
+    Path(String),
+    Schema(String),
+    Sql(String),
+}
+
+impl FromServerFnError for SetMishStateError {
+    type Encoder = JsonEncoding;
+
+    fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
+        SetMishStateError::ServerFnError(value)
+    }
+}
+
+impl Display for SetMishStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetMishStateError::Hex(e) => write!(f, "Content is not hex: {e}"),
+            SetMishStateError::Json(e) => write!(f, "Content is not JSON: {e}"),
+            SetMishStateError::Path(e) => write!(f, "Invalid path: {e}"),
+            SetMishStateError::Schema(e) => write!(f, "{e}"),
+            _ => write!(f, "{self:?}"),
+        }
+    }
+}
+
 #[server(SetMishState)]
-async fn set_mish_state(name: String, state: String) -> Result<(), ServerFnError> {
-    use crate::integrations::iron_nest::mish::MishStateModification;
+async fn set_mish_state(name: String, state: String) -> Result<(), SetMishStateError> {
+    use crate::integrations::iron_nest::mish::{
+        MishStateModification, schema::validate_mish_state,
+    };
     let pool = use_context::<sqlx::PgPool>().unwrap();
     let mish_state_modification_bus_sender =
-        use_context::<tokio::sync::mpsc::UnboundedSender<MishStateModification>>().unwrap();
-    let state = hex::decode(state).unwrap();
-    let state = serde_json::from_slice(&state).unwrap();
-    set_mish_state_query(&pool, &name, &state).await?;
+        use_context::<tokio::sync::broadcast::Sender<MishStateModification>>().unwrap();
+    let state = hex::decode(state).map_err(|e| SetMishStateError::Hex(e.to_string()))?;
+    let state =
+        serde_json::from_slice(&state).map_err(|e| SetMishStateError::Json(e.to_string()))?;
+    validate_mish_state(&pool, &name, &state)
+        .await
+        .map_err(|e| SetMishStateError::Schema(e.to_string()))?;
+    set_mish_state_query(&pool, &name, &state)
+        .await
+        .map_err(|e| SetMishStateError::Sql(e.to_string()))?;
     mish_state_modification_bus_sender
         .send(MishStateModification::CreateOrUpdate { name, state })
         .unwrap();
     Ok(())
 }
 
+#[server(GetMishStateSchema)]
+pub async fn get_mish_state_schema(
+    name: String,
+) -> Result<Option<(String, serde_json::Value)>, ServerFnError> {
+    use crate::integrations::iron_nest::mish::schema::{get_mish_state_schema_cid, get_schema};
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let Some(schema_cid) = get_mish_state_schema_cid(&pool, &name).await? else {
+        return Ok(None);
+    };
+    let schema = get_schema(&pool, &schema_cid).await?;
+    Ok(Some((schema_cid.to_string(), schema)))
+}
+
+#[server(SetMishStateSchema)]
+async fn set_mish_state_schema(
+    name: String,
+    schema_cid: Option<String>,
+) -> Result<(), SetMishStateError> {
+    use crate::integrations::iron_nest::mish::schema::set_mish_state_schema_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let schema_cid = schema_cid
+        .filter(|cid| !cid.is_empty())
+        .map(|cid| cid.parse::<cid::Cid>())
+        .transpose()
+        .map_err(|e| SetMishStateError::Schema(e.to_string()))?;
+    set_mish_state_schema_query(&pool, &name, schema_cid)
+        .await
+        .map_err(|e| SetMishStateError::Schema(e.to_string()))
+}
+
 #[cfg(feature = "ssr")]
 pub async fn set_mish_state_query(
     pool: &sqlx::PgPool,
@@ -102,7 +179,7 @@ async fn delete_mish_state(name: String) -> Result<(), ServerFnError> {
     use crate::integrations::iron_nest::mish::MishStateModification;
     let pool = use_context::<sqlx::PgPool>().expect("PgPool context should be set");
     let mish_state_modification_bus_sender =
-        use_context::<tokio::sync::mpsc::UnboundedSender<MishStateModification>>().unwrap();
+        use_context::<tokio::sync::broadcast::Sender<MishStateModification>>().unwrap();
     delete_mish_state_query(&pool, &name).await?;
     mish_state_modification_bus_sender
         .send(MishStateModification::Delete { name })
@@ -138,6 +215,22 @@ pub fn MishStatePage() -> impl IntoView {
         |(_version, name)| get_mish_state(name),
     );
 
+    // Changes made elsewhere (rhai scripts, the API, other tabs) arrive over the subscription
+    // and take precedence over the last fetched state.
+    provide_mish_state_subscriptions();
+    let mish_state = use_mish_state(name);
+    let values = Signal::derive(move || {
+        values.get().map(|values| {
+            values.map(|value| match mish_state.get() {
+                Some(state) => state.map(|state| MishState {
+                    name: name(),
+                    state,
+                }),
+                None => value,
+            })
+        })
+    });
+
     let set_mish_state_action2 = move |state: serde_json::Value| {
         set_mish_state_action.dispatch(SetMishState {
             name: name(),
@@ -156,11 +249,47 @@ pub fn MishStatePage() -> impl IntoView {
             )
         },
         move |(value, _version)| async move {
-            if matches!(value, Some(Ok(_))) {
-                toast.set(Some(Toast("Mish State saved".to_owned())));
+            match value {
+                Some(Ok(_)) => toast.set(Some(Toast("Mish State saved".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Mish State not saved: {e}")))),
+                None => {}
+            }
+        },
+    );
+
+    let set_mish_state_schema_action = ServerAction::<SetMishStateSchema>::new();
+    let schema = Resource::new(
+        move || (set_mish_state_schema_action.version().get(), name()),
+        |(_version, name)| get_mish_state_schema(name),
+    );
+    let schema_cid_input = RwSignal::new(String::new());
+    Effect::new(move |_| {
+        if let Some(Ok(schema)) = schema.get() {
+            schema_cid_input.set(schema.map(|(cid, _)| cid).unwrap_or_default());
+        }
+    });
+    Resource::new(
+        move || {
+            (
+                set_mish_state_schema_action.value().get(),
+                set_mish_state_schema_action.version().get(),
+            )
+        },
+        move |(value, _version)| async move {
+            match value {
+                Some(Ok(_)) => toast.set(Some(Toast("Schema saved".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Schema not saved: {e}")))),
+                None => {}
             }
         },
     );
+    let schema_value = move || {
+        schema
+            .get()
+            .and_then(Result::ok)
+            .flatten()
+            .map(|(_, schema)| schema)
+    };
 
     Resource::new(
         move || {
@@ -183,6 +312,11 @@ pub fn MishStatePage() -> impl IntoView {
             <div>
                 <a href="/settings/dag-inspector">"Back to Dag Inspector"</a>
             </div>
+            <div>
+                <a href=move || {
+                    format!("/settings/dag-inspector/path/{}", urlencoding::encode(&name()))
+                }>"Browse linked blocks"</a>
+            </div>
             <Suspense fallback=|| {
                 view! { <p>"Loading Mish State..."</p> }
             }>
@@ -202,7 +336,11 @@ pub fn MishStatePage() -> impl IntoView {
                                         value
                                             .map(|state| {
                                                 view! {
-                                                    <Editor state=state.state action=set_mish_state_action2 />
+                                                    <Editor
+                                                        state=state.state
+                                                        schema=schema_value()
+                                                        action=set_mish_state_action2
+                                                    />
                                                 }
                                                     .into_any()
                                             })
@@ -259,6 +397,18 @@ pub fn MishStatePage() -> impl IntoView {
                         }
                     }}
                 </div>
+                <div>
+                    <label for="schema-cid">"Schema CID"</label>
+                    <input type="text" id="schema-cid" bind:value=schema_cid_input />
+                    <button on:click=move |_| {
+                        let schema_cid = schema_cid_input.get();
+                        set_mish_state_schema_action
+                            .dispatch(SetMishStateSchema {
+                                name: name(),
+                                schema_cid: (!schema_cid.is_empty()).then_some(schema_cid),
+                            });
+                    }>"Set schema"</button>
+                </div>
                 <button on:click=move |_| {
                     delete_mish_state_action.dispatch(DeleteMishState { name: name() });
                 }>"Delete"</button>
@@ -1,10 +1,13 @@
 pub mod dag_inspector_page;
+pub mod dag_path_page;
 pub mod editor;
 pub mod ipld_blob_page;
+pub mod ipld_pins;
 pub mod json_editor;
 pub mod mish_button;
 pub mod mish_dashboard;
 pub mod mish_state_page;
 pub mod new_mish_state_dialog;
 pub mod number_editor;
+pub mod subscription;
 pub mod text_editor;
@@ -0,0 +1,149 @@
+use {
+    crate::integrations::iron_nest::types::mish::MishStateEvent,
+    leptos::prelude::*,
+    std::collections::{BTreeMap, HashMap},
+};
+
+/// The mish states followed by a page, all through one `/api/mish/subscribe` connection since
+/// browsers only allow a handful of connections per origin.
+#[derive(Clone, Copy)]
+struct MishStateSubscriptions {
+    /// How many components follow each state
+    names: RwSignal<BTreeMap<String, usize>>,
+    /// The latest state received for each followed name, `None` once deleted
+    states: RwSignal<HashMap<String, Option<serde_json::Value>>>,
+}
+
+impl MishStateSubscriptions {
+    fn follow(self, name: String) {
+        self.names
+            .update(|names| *names.entry(name).or_default() += 1);
+    }
+
+    fn unfollow(self, name: &str) {
+        // The page may already be gone
+        self.names.try_update(|names| {
+            let Some(count) = names.get_mut(name) else {
+                return;
+            };
+            *count -= 1;
+            if *count == 0 {
+                names.remove(name);
+                self.states.try_update(|states| states.remove(name));
+            }
+        });
+    }
+}
+
+/// Shares one subscription between the [`use_mish_state`] calls below the current component,
+/// unless a component above already does.
+pub fn provide_mish_state_subscriptions() {
+    if use_context::<MishStateSubscriptions>().is_some() {
+        return;
+    }
+    let subscriptions = MishStateSubscriptions {
+        names: RwSignal::new(BTreeMap::new()),
+        states: RwSignal::new(HashMap::new()),
+    };
+    subscribe(
+        move || {
+            subscriptions
+                .names
+                .with(|names| names.keys().cloned().collect::<Vec<_>>().join(","))
+        },
+        move |event| {
+            subscriptions.states.update(|states| match event {
+                MishStateEvent::Update { name, value, .. } => {
+                    states.insert(name, Some(value));
+                }
+                MishStateEvent::Delete { name } => {
+                    states.insert(name, None);
+                }
+            })
+        },
+    );
+    provide_context(subscriptions);
+}
+
+/// The latest `name` mish state received since the page started following it: `None` until it
+/// changes and `Some(None)` once it's deleted. Needs [`provide_mish_state_subscriptions`].
+pub fn use_mish_state(
+    name: impl Fn() -> String + Send + Sync + 'static,
+) -> Memo<Option<Option<serde_json::Value>>> {
+    let subscriptions = use_context::<MishStateSubscriptions>()
+        .expect("provide_mish_state_subscriptions must be called by a parent component");
+    let name = Memo::new(move |_| name());
+    Effect::new(move |_| {
+        let name = name.get();
+        subscriptions.follow(name.clone());
+        on_cleanup(move || subscriptions.unfollow(&name));
+    });
+    Memo::new(move |_| {
+        subscriptions
+            .states
+            .with(|states| states.get(&*name.read()).cloned())
+    })
+}
+
+/// Subscribes to `/api/mish/subscribe` for the given comma separated mish state names and hands
+/// each event to `on_event`. Re-subscribes whenever `names` changes, nothing is subscribed to
+/// while it's empty.
+fn subscribe(
+    names: impl Fn() -> String + 'static,
+    on_event: impl Fn(MishStateEvent) + Clone + 'static,
+) {
+    #[cfg(feature = "hydrate")]
+    {
+        use wasm_bindgen::{JsCast, closure::Closure};
+
+        type Subscription = (
+            web_sys::EventSource,
+            Closure<dyn FnMut(web_sys::MessageEvent)>,
+        );
+        let subscription = StoredValue::new_local(None::<Subscription>);
+        let close = move || {
+            subscription.update_value(|subscription| {
+                if let Some((event_source, _onmessage)) = subscription.take() {
+                    event_source.close();
+                }
+            })
+        };
+
+        Effect::new(move |_| {
+            let names = names();
+            close();
+            if names.is_empty() {
+                return;
+            }
+            let url = format!("/api/mish/subscribe?names={}", urlencoding::encode(&names));
+            let event_source = match web_sys::EventSource::new(&url) {
+                Ok(event_source) => event_source,
+                Err(e) => {
+                    web_sys::console::error_1(&e);
+                    return;
+                }
+            };
+            let on_event = on_event.clone();
+            let onmessage = Closure::<dyn FnMut(web_sys::MessageEvent)>::new(
+                move |e: web_sys::MessageEvent| {
+                    let Some(data) = e.data().as_string() else {
+                        return;
+                    };
+                    match serde_json::from_str::<MishStateEvent>(&data) {
+                        Ok(event) => on_event(event),
+                        Err(e) => web_sys::console::error_1(
+                            &format!("Invalid mish state event: {e}").into(),
+                        ),
+                    }
+                },
+            );
+            event_source.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+            subscription.set_value(Some((event_source, onmessage)));
+        });
+        on_cleanup(close);
+    }
+    #[cfg(not(feature = "hydrate"))]
+    {
+        let _ = (names, on_event);
+    }
+}
@@ -62,6 +62,46 @@ pub fn Navbar() -> impl IntoView {
                 </svg>
             }.into_any(),
         },
+        NavbarItem {
+            path: "/scenes".to_owned(),
+            text: "Scenes".to_owned(),
+            image: view! {
+                <svg
+                    class="h-7 w-7 shrink-0"
+                    fill="none"
+                    viewBox="0 0 24 24"
+                    stroke-width="1.5"
+                    stroke="currentColor"
+                    aria-hidden="true"
+                >
+                    <path
+                        stroke-linecap="round"
+                        stroke-linejoin="round"
+                        d="M12 18v-5.25m0 0a6.01 6.01 0 001.5-.189m-1.5.189a6.01 6.01 0 01-1.5-.189m3.75 7.478a12.06 12.06 0 01-4.5 0m3.75 2.383a14.406 14.406 0 01-3 0M14.25 18v-.192c0-.983.658-1.823 1.508-2.316a7.5 7.5 0 10-7.517 0c.85.493 1.509 1.333 1.509 2.316V18"
+                    ></path>
+                </svg>
+            }.into_any(),
+        },
+        NavbarItem {
+            path: "/energy".to_owned(),
+            text: "Energy".to_owned(),
+            image: view! {
+                <svg
+                    class="h-7 w-7 shrink-0"
+                    fill="none"
+                    viewBox="0 0 24 24"
+                    stroke-width="1.5"
+                    stroke="currentColor"
+                    aria-hidden="true"
+                >
+                    <path
+                        stroke-linecap="round"
+                        stroke-linejoin="round"
+                        d="M3.75 13.5l10.5-11.25L12 10.5h8.25L9.75 21.75 12 13.5H3.75z"
+                    ></path>
+                </svg>
+            }.into_any(),
+        },
         NavbarItem {
             path: "/integrations".to_string(),
             text: "Integrations".to_string(),
@@ -2,13 +2,18 @@ use {
     crate::{
         components::{
             layout::{Toast, ToastContext},
-            select::Select,
             text_input::TextInput,
         },
-        integrations::iron_nest::types::RequiredAction,
-        server::actions::{AddAction, DeleteAction, RunAction, get_actions},
+        integrations::iron_nest::types::{
+            ActionRun, ActionStats, FunctionInfo, RequiredAction, SequenceCondition, SequenceStep,
+        },
+        server::actions::{
+            ACTION_RUN_RETENTION_DAYS, AddAction, DeleteAction, RunAction, get_action_runs,
+            get_action_stats, get_actions, get_functions, preview_action_schedule,
+        },
     },
     leptos::prelude::*,
+    uuid::Uuid,
 };
 
 #[component]
@@ -26,6 +31,16 @@ pub fn ActionsPage() -> impl IntoView {
         |_| get_actions(),
     );
 
+    let functions = Resource::new(|| (), |_| get_functions());
+    let runs = Resource::new(
+        move || run_action_action.version().get(),
+        |_| get_action_runs(),
+    );
+    let stats = Resource::new(
+        move || run_action_action.version().get(),
+        |_| get_action_stats(),
+    );
+
     let (show_create_action, set_show_create_action) = signal(false);
 
     let toast = use_context::<ToastContext>().unwrap();
@@ -63,8 +78,10 @@ pub fn ActionsPage() -> impl IntoView {
             )
         },
         move |value| async move {
-            if matches!(value.0, Some(Ok(_))) {
-                toast.set(Some(Toast("Action ran".to_owned())));
+            match value.0 {
+                Some(Ok(_)) => toast.set(Some(Toast("Action ran".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Action failed: {e}")))),
+                None => {}
             }
         },
     );
@@ -94,14 +111,38 @@ pub fn ActionsPage() -> impl IntoView {
                                                             let RequiredAction {
                                                                 name,
                                                                 cron,
+                                                                solar,
+                                                                timezone,
                                                                 function_name,
                                                                 function_args,
+                                                                sequence,
+                                                                retry_count,
+                                                                ..
                                                             } = action.fields;
+                                                            let run = match sequence {
+                                                                Some(sequence) => {
+                                                                    format!("{} steps", sequence.steps.len())
+                                                                }
+                                                                None => format!("{function_name}({function_args})"),
+                                                            };
+                                                            let schedule = match solar {
+                                                                Some(solar) => {
+                                                                    format!(
+                                                                        "{:?} {:+} min",
+                                                                        solar.event,
+                                                                        solar.offset_minutes,
+                                                                    )
+                                                                }
+                                                                None => cron,
+                                                            };
                                                             view! {
                                                                 <li>
                                                                     {format!(
-                                                                        "{name}: {cron} -> {function_name}({function_args})",
+                                                                        "{name}: {schedule} {} -> {run}",
+                                                                        timezone.as_deref().unwrap_or("UTC"),
                                                                     )}
+                                                                    {(retry_count > 0)
+                                                                        .then(|| format!(" (up to {retry_count} retries)"))}
                                                                     <button on:click=move |_| {
                                                                         delete_action_action
                                                                             .dispatch(DeleteAction { id: action.id });
@@ -109,6 +150,7 @@ pub fn ActionsPage() -> impl IntoView {
                                                                     <button on:click=move |_| {
                                                                         run_action_action.dispatch(RunAction { id: action.id });
                                                                     }>"Run"</button>
+                                                                    <ActionHistory action_id=action.id runs=runs stats=stats />
                                                                 </li>
                                                             }
                                                         })
@@ -218,12 +260,7 @@ pub fn ActionsPage() -> impl IntoView {
                                                                                 placeholder="New event".to_owned()
                                                                                 input_type="text".to_owned()
                                                                             />
-                                                                            <TextInput
-                                                                                label="Event Cron".to_owned()
-                                                                                name="cron".to_owned()
-                                                                                placeholder="".to_owned()
-                                                                                input_type="text".to_owned()
-                                                                            />
+                                                                            <ScheduleInput />
                                                                             <fieldset>
                                                                                 <legend class="text-sm font-medium leading-6 text-gray-900">
                                                                                     Repeat
@@ -290,19 +327,26 @@ pub fn ActionsPage() -> impl IntoView {
                                                                                             </div>
                                                                                         </div>
                                                                                     </div>
-                                                                                    <Select
-                                                                                        label="Function name".to_string()
-                                                                                        name="function_name".to_string()
-                                                                                        data=vec![
-                                                                                            "tplink_set_light_brightness".to_owned(),
-                                                                                            "tplink_set_dimmer_brightness".to_owned(),
-                                                                                            "tplink_turn_light_on_off".to_owned(),
-                                                                                            "tplink_turn_plug_on".to_owned(),
-                                                                                            "tplink_turn_plug_off".to_owned(),
-                                                                                            "handle_smart_light_toggle".to_owned(),
-                                                                                            "stoplight_toggle".to_owned(),
-                                                                                        ]
-                                                                                    />
+                                                                                    <Suspense fallback=|| {
+                                                                                        view! { <p>"Loading functions..."</p> }
+                                                                                    }>
+                                                                                        {move || {
+                                                                                            functions
+                                                                                                .get()
+                                                                                                .map(|functions| match functions {
+                                                                                                    Ok(functions) => {
+                                                                                                        view! {
+                                                                                                            <FunctionSelect functions=functions.clone() />
+                                                                                                            <SequenceEditor functions=functions />
+                                                                                                        }
+                                                                                                            .into_any()
+                                                                                                    }
+                                                                                                    Err(e) => {
+                                                                                                        view! { <p>{format!("GetFunctions error: {e}")}</p> }.into_any()
+                                                                                                    }
+                                                                                                })
+                                                                                        }}
+                                                                                    </Suspense>
 
                                                                                 </div>
                                                                             </fieldset>
@@ -312,6 +356,31 @@ pub fn ActionsPage() -> impl IntoView {
                                                                                 placeholder="".to_owned()
                                                                                 input_type="text".to_owned()
                                                                             />
+                                                                            <TextInput
+                                                                                label="Retries".to_owned()
+                                                                                name="retry_count".to_owned()
+                                                                                placeholder="0".to_owned()
+                                                                                input_type="number".to_owned()
+                                                                            />
+                                                                            <TextInput
+                                                                                label="Retry backoff (ms, doubles each retry)"
+                                                                                    .to_owned()
+                                                                                name="retry_backoff_ms".to_owned()
+                                                                                placeholder="1000".to_owned()
+                                                                                input_type="number".to_owned()
+                                                                            />
+                                                                            <TextInput
+                                                                                label="On failure function".to_owned()
+                                                                                name="on_failure_function_name".to_owned()
+                                                                                placeholder="tplink_turn_light_on".to_owned()
+                                                                                input_type="text".to_owned()
+                                                                            />
+                                                                            <TextInput
+                                                                                label="On failure function args".to_owned()
+                                                                                name="on_failure_function_args".to_owned()
+                                                                                placeholder="{}".to_owned()
+                                                                                input_type="text".to_owned()
+                                                                            />
                                                                         </div>
                                                                     </div>
                                                                 </div>
@@ -364,3 +433,419 @@ pub fn ActionsPage() -> impl IntoView {
         </main>
     }
 }
+
+/// Picks one of the registered functions, showing what it does and the arguments it takes.
+#[component]
+fn FunctionSelect(functions: Vec<FunctionInfo>) -> impl IntoView {
+    let selected = RwSignal::new(String::new());
+    let options = functions
+        .iter()
+        .map(|function| {
+            view! {
+                <option value=function.name.clone()>
+                    {format!("{} ({})", function.name, function.integration)}
+                </option>
+            }
+        })
+        .collect::<Vec<_>>();
+
+    view! {
+        <div>
+            <label for="function_name" class="block text-sm font-medium leading-6 text-gray-900">
+                "Function name"
+            </label>
+            <select
+                id="function_name"
+                name="function_name"
+                class="block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:max-w-xs sm:text-sm sm:leading-6"
+                on:change:target=move |ev| selected.set(ev.target().value())
+            >
+                <option value="" selected=true disabled=true>
+                    "Choose a function"
+                </option>
+                {options}
+            </select>
+            {move || {
+                functions
+                    .iter()
+                    .find(|function| function.name == selected.get())
+                    .map(|function| {
+                        view! {
+                            <p class="text-sm text-gray-500">{function.description.clone()}</p>
+                            <pre class="text-xs">
+                                {serde_json::to_string_pretty(&function.parameters).unwrap_or_default()}
+                            </pre>
+                        }
+                    })
+            }}
+        </div>
+    }
+}
+
+/// Cron expression and timezone inputs showing when the action would run next, or why the
+/// schedule is invalid, before it's saved.
+#[component]
+fn ScheduleInput() -> impl IntoView {
+    let cron = RwSignal::new(String::new());
+    let solar_event = RwSignal::new(String::new());
+    let solar_offset_minutes = RwSignal::new(String::new());
+    let solar_not_before = RwSignal::new(String::new());
+    let solar_not_after = RwSignal::new(String::new());
+    let timezone = RwSignal::new(String::new());
+    let next_runs = Resource::new(
+        move || {
+            (
+                cron.get(),
+                solar_event.get(),
+                solar_offset_minutes.get(),
+                solar_not_before.get(),
+                solar_not_after.get(),
+                timezone.get(),
+            )
+        },
+        |(cron, solar_event, solar_offset_minutes, solar_not_before, solar_not_after, timezone)| async move {
+            if cron.trim().is_empty() && solar_event.is_empty() {
+                return None;
+            }
+            Some(
+                preview_action_schedule(
+                    cron,
+                    solar_event,
+                    solar_offset_minutes,
+                    solar_not_before,
+                    solar_not_after,
+                    timezone,
+                    5,
+                )
+                .await,
+            )
+        },
+    );
+    let input_class = "block w-full rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 placeholder:text-gray-400 focus:ring-2 focus:ring-inset focus:ring-indigo-600 sm:text-sm sm:leading-6";
+
+    view! {
+        <div>
+            <label for="cron" class="block text-sm font-medium leading-6 text-white">
+                "Event Cron"
+            </label>
+            <input
+                type="text"
+                id="cron"
+                name="cron"
+                placeholder="0 30 7 * * *"
+                class=input_class
+                on:input:target=move |ev| cron.set(ev.target().value())
+            />
+            <label for="solar_event" class="block text-sm font-medium leading-6 text-white">
+                "Or follow the sun"
+            </label>
+            <select
+                id="solar_event"
+                name="solar_event"
+                class=input_class
+                on:change:target=move |ev| solar_event.set(ev.target().value())
+            >
+                <option value="" selected=true>
+                    "No, use the cron"
+                </option>
+                <option value="dawn">"Dawn"</option>
+                <option value="sunrise">"Sunrise"</option>
+                <option value="sunset">"Sunset"</option>
+                <option value="dusk">"Dusk"</option>
+            </select>
+            // Always in the form so the server function gets every field
+            <div class:hidden=move || solar_event.get().is_empty()>
+                <input
+                    type="number"
+                    name="solar_offset_minutes"
+                    placeholder="Minutes after, negative for before"
+                    class=input_class
+                    on:input:target=move |ev| solar_offset_minutes.set(ev.target().value())
+                />
+                <label class="text-sm text-white">
+                    "Not before "
+                    <input
+                        type="time"
+                        name="solar_not_before"
+                        on:input:target=move |ev| solar_not_before.set(ev.target().value())
+                    />
+                </label>
+                <label class="text-sm text-white">
+                    " Not after "
+                    <input
+                        type="time"
+                        name="solar_not_after"
+                        on:input:target=move |ev| solar_not_after.set(ev.target().value())
+                    />
+                </label>
+            </div>
+            <label for="timezone" class="block text-sm font-medium leading-6 text-white">
+                "Timezone"
+            </label>
+            <input
+                type="text"
+                id="timezone"
+                name="timezone"
+                placeholder="America/New_York (UTC if empty)"
+                class=input_class
+                on:input:target=move |ev| timezone.set(ev.target().value())
+            />
+            <label class="text-sm text-gray-900">
+                <input type="checkbox" name="catch_up" />
+                " Run once on startup if missed while the server was down"
+            </label>
+            <Transition>
+                {move || {
+                    next_runs
+                        .get()
+                        .flatten()
+                        .map(|next_runs| match next_runs {
+                            Ok(next_runs) => {
+                                view! {
+                                    <ul class="text-xs text-gray-500">
+                                        {next_runs
+                                            .into_iter()
+                                            .map(|time| view! { <li>{time}</li> })
+                                            .collect::<Vec<_>>()}
+                                    </ul>
+                                }
+                                    .into_any()
+                            }
+                            Err(e) => view! { <p class="text-xs text-red-600">{e.to_string()}</p> }.into_any(),
+                        })
+                }}
+            </Transition>
+        </div>
+    }
+}
+
+/// Builds the `sequence` field of the action form from steps edited as JSON, leaving it empty
+/// for actions that call a single function.
+#[component]
+fn SequenceEditor(functions: Vec<FunctionInfo>) -> impl IntoView {
+    let next_id = StoredValue::new(0_usize);
+    let steps = RwSignal::new(Vec::<(usize, RwSignal<String>)>::new());
+    let on_error = RwSignal::new("abort".to_owned());
+    let new_function = RwSignal::new(
+        functions
+            .first()
+            .map(|function| function.name.clone())
+            .unwrap_or_default(),
+    );
+    let add_step = move |step: SequenceStep| {
+        let id = next_id.get_value();
+        next_id.set_value(id + 1);
+        let json = serde_json::to_string_pretty(&step).unwrap_or_default();
+        steps.update(|steps| steps.push((id, RwSignal::new(json))));
+    };
+    let move_step = move |id: usize, by: isize| {
+        steps.update(|steps| {
+            let Some(index) = steps.iter().position(|(step_id, _)| *step_id == id) else {
+                return;
+            };
+            if let Some(other) = index.checked_add_signed(by)
+                && other < steps.len()
+            {
+                steps.swap(index, other);
+            }
+        })
+    };
+    // Steps are passed through as typed so the server reports what's wrong with them
+    let sequence = move || {
+        let steps = steps.get();
+        if steps.is_empty() {
+            return String::new();
+        }
+        let steps = steps
+            .iter()
+            .map(|(_, json)| json.get())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{"steps": [{steps}], "on_error": "{}"}}"#,
+            on_error.get()
+        )
+    };
+    let options = functions
+        .iter()
+        .map(|function| view! { <option value=function.name.clone()>{function.name.clone()}</option> })
+        .collect::<Vec<_>>();
+
+    view! {
+        <div>
+            <p class="text-sm font-medium leading-6 text-gray-900">"Or run steps"</p>
+            <input type="hidden" name="sequence" prop:value=sequence />
+            <For
+                each=move || steps.get()
+                key=|(id, _)| *id
+                children=move |(id, json)| {
+                    let summary = move || match serde_json::from_str::<SequenceStep>(&json.get()) {
+                        Ok(step) => describe_step(&step),
+                        Err(e) => format!("Invalid step: {e}"),
+                    };
+                    view! {
+                        <div class="rounded border p-1 mb-1">
+                            <p class="text-xs text-gray-500">{summary}</p>
+                            <textarea
+                                class="w-full font-mono text-xs text-gray-900"
+                                rows="4"
+                                prop:value=json.get_untracked()
+                                on:input:target=move |ev| json.set(ev.target().value())
+                            ></textarea>
+                            <button type="button" on:click=move |_| move_step(id, -1)>
+                                "Up"
+                            </button>
+                            <button type="button" on:click=move |_| move_step(id, 1)>
+                                "Down"
+                            </button>
+                            <button
+                                type="button"
+                                on:click=move |_| {
+                                    steps.update(|steps| steps.retain(|(step_id, _)| *step_id != id))
+                                }
+                            >
+                                "Remove"
+                            </button>
+                        </div>
+                    }
+                }
+            />
+            <select on:change:target=move |ev| new_function.set(ev.target().value())>
+                {options}
+            </select>
+            <button
+                type="button"
+                on:click=move |_| {
+                    add_step(SequenceStep::Function {
+                        function_name: new_function.get(),
+                        function_args: serde_json::json!({}),
+                    })
+                }
+            >
+                "Add function"
+            </button>
+            <button type="button" on:click=move |_| add_step(SequenceStep::Wait { seconds: 10.0 })>
+                "Add wait"
+            </button>
+            <button
+                type="button"
+                on:click=move |_| {
+                    add_step(SequenceStep::If {
+                        condition: SequenceCondition::MishState {
+                            name: String::new(),
+                            path: "$.on".to_owned(),
+                            equals: serde_json::Value::Bool(true),
+                        },
+                        then: Vec::new(),
+                        otherwise: Vec::new(),
+                    })
+                }
+            >
+                "Add condition"
+            </button>
+            <button
+                type="button"
+                on:click=move |_| {
+                    add_step(SequenceStep::Parallel {
+                        branches: vec![Vec::new(), Vec::new()],
+                    })
+                }
+            >
+                "Add parallel branches"
+            </button>
+            <select on:change:target=move |ev| on_error.set(ev.target().value())>
+                <option value="abort">"Stop at the first failed step"</option>
+                <option value="continue">"Keep going after a failed step"</option>
+            </select>
+        </div>
+    }
+}
+
+fn describe_step(step: &SequenceStep) -> String {
+    match step {
+        SequenceStep::Function { function_name, .. } => format!("Call {function_name}"),
+        SequenceStep::Wait { seconds } => format!("Wait {seconds} s"),
+        SequenceStep::If {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let condition = match condition {
+                SequenceCondition::DevicePowerState {
+                    device_id,
+                    power_state,
+                } => format!("device {device_id} has power state {power_state}"),
+                SequenceCondition::MishState { name, path, equals } => {
+                    format!("{path} of {name} is {equals}")
+                }
+            };
+            format!(
+                "If {condition}, {} step(s), otherwise {}",
+                then.len(),
+                otherwise.len()
+            )
+        }
+        SequenceStep::Parallel { branches } => format!("{} branches at once", branches.len()),
+    }
+}
+
+/// Success rate and latest runs of an action.
+#[component]
+fn ActionHistory(
+    action_id: Uuid,
+    runs: Resource<Result<Vec<ActionRun>, ServerFnError>>,
+    stats: Resource<Result<Vec<ActionStats>, ServerFnError>>,
+) -> impl IntoView {
+    let success_rate = move || {
+        stats.get().and_then(Result::ok).map(|stats| {
+            match stats.into_iter().find(|stats| stats.action_id == action_id) {
+                Some(ActionStats {
+                    runs: count,
+                    successes,
+                    ..
+                }) if count > 0 => format!(
+                    "{}% of {count} runs succeeded in the last {ACTION_RUN_RETENTION_DAYS} days",
+                    successes * 100 / count
+                ),
+                _ => format!("No runs in the last {ACTION_RUN_RETENTION_DAYS} days"),
+            }
+        })
+    };
+    let recent_runs = move || {
+        runs.get().and_then(Result::ok).map(|runs| {
+            runs.into_iter()
+                .filter(|run| run.action_id == action_id)
+                .map(|run| {
+                    let outcome = match (&run.result, &run.error) {
+                        (_, Some(error)) => format!("failed: {error}"),
+                        (Some(result), None) => format!("ok: {result}"),
+                        (None, None) => "ok".to_owned(),
+                    };
+                    let scheduled = run
+                        .scheduled_at
+                        .map(|scheduled_at| {
+                            format!(" (due {})", scheduled_at.format("%Y-%m-%d %H:%M:%S"))
+                        })
+                        .unwrap_or_else(|| " (by hand)".to_owned());
+                    view! {
+                        <li class="text-xs">
+                            {format!(
+                                "{}{scheduled} {}ms, {} attempt(s), {outcome}",
+                                run.started_at.format("%Y-%m-%d %H:%M:%S"),
+                                run.duration_ms,
+                                run.attempts,
+                            )}
+                        </li>
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    view! {
+        <details>
+            <summary class="text-sm text-gray-500">{success_rate}</summary>
+            <ul>{recent_runs}</ul>
+        </details>
+    }
+}
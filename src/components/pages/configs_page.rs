@@ -1,13 +1,21 @@
 use {
-    crate::components::layout::{Toast, ToastContext},
-    leptos::prelude::*,
+    crate::{
+        components::layout::{Toast, ToastContext},
+        integrations::iron_nest::types::config::{
+            ConfigFormat, ConfigProblem, ConfigVersion, DiffLine,
+        },
+    },
+    leptos::{html::Textarea, prelude::*},
     serde::{Deserialize, Serialize},
     server_fn::codec::JsonEncoding,
     std::fmt::Display,
 };
 
 #[cfg(feature = "ssr")]
-use crate::integrations::iron_nest::types::config::Config;
+use crate::integrations::iron_nest::types::config::{Config, Location};
+
+#[cfg(feature = "ssr")]
+const CONFIG_HISTORY_SHOWN: i64 = 20;
 
 #[cfg(feature = "ssr")]
 #[derive(sqlx::FromRow)]
@@ -15,18 +23,12 @@ struct Row {
     data: sqlx::types::Json<Config>,
 }
 
-#[server(GetConfig)]
-async fn get_config() -> Result<String, ServerFnError> {
+#[server(ExportConfig)]
+async fn export_config(format: ConfigFormat) -> Result<String, ServerFnError> {
+    use crate::integrations::iron_nest::config::{export_config_query, render};
     let pool = use_context::<sqlx::PgPool>().unwrap();
-    let config = get_config_query(&pool).await?;
-    let config = if let Some(config) = config {
-        config
-    } else {
-        let actions = crate::server::actions::get_actions_query(&pool).await?;
-        Config { actions }
-    };
-    let config = serde_yaml::to_string(&config).unwrap();
-    Ok(config)
+    let export = export_config_query(&pool).await?;
+    render(&export, format).map_err(ServerFnError::new)
 }
 
 #[cfg(feature = "ssr")]
@@ -41,46 +43,159 @@ pub async fn get_config_query(pool: &sqlx::PgPool) -> Result<Option<Config>, sql
         .map(|row| row.map(|row: Row| row.data.0))
 }
 
+#[cfg(feature = "ssr")]
+pub async fn get_location_query(
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<Location>, sqlx::Error> {
+    let query = "
+        SELECT data->'location'
+        FROM config
+    ";
+    sqlx::query_scalar::<_, Option<sqlx::types::Json<Option<Location>>>>(query)
+        .fetch_optional(executor)
+        .await
+        .map(|location| location.flatten().and_then(|location| location.0))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub enum SetConfigError {
+pub enum ImportConfigError {
     ServerFnError(ServerFnErrorErr),
-    ParseConfig(String),
-    SaveConfig(String),
+    Invalid(Vec<ConfigProblem>),
+    Sql(String),
+    Import(String),
     ScheduleTasks(String),
 }
 
-impl FromServerFnError for SetConfigError {
+impl FromServerFnError for ImportConfigError {
     type Encoder = JsonEncoding;
 
     fn from_server_fn_error(value: ServerFnErrorErr) -> Self {
-        SetConfigError::ServerFnError(value)
+        ImportConfigError::ServerFnError(value)
     }
 }
 
-impl Display for SetConfigError {
+impl Display for ImportConfigError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            ImportConfigError::Invalid(problems) => write!(
+                f,
+                "{}",
+                problems
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            ImportConfigError::Import(e) => write!(f, "{e}"),
+            _ => write!(f, "{self:?}"),
+        }
     }
 }
 
-#[server(SetConfig)]
-async fn set_config(config: String) -> Result<(), SetConfigError> {
+/// Checks `config`, YAML or JSON of a [`ConfigExport`], and imports it unless `dry_run`.
+/// Returns whether it was imported.
+///
+/// [`ConfigExport`]: crate::integrations::iron_nest::types::config::ConfigExport
+#[server(ImportConfig)]
+async fn import_config(config: String, dry_run: bool) -> Result<bool, ImportConfigError> {
+    use {
+        crate::integrations::iron_nest::{
+            config::{import_config_query, parse, validate},
+            cron::CronClient,
+            mish::MishStateModification,
+        },
+        tokio::sync::broadcast,
+    };
     let pool = use_context::<sqlx::PgPool>().unwrap();
-    let cron_client = use_context::<crate::integrations::iron_nest::cron::CronClient>().unwrap();
-    let config =
-        serde_yaml::from_str(&config).map_err(|e| SetConfigError::ParseConfig(e.to_string()))?;
-    set_config_query(&pool, config)
+    let cron_client = use_context::<CronClient>().unwrap();
+    let mish_state_modification_bus_sender =
+        use_context::<broadcast::Sender<MishStateModification>>().unwrap();
+
+    let export = parse(&config).map_err(|problem| ImportConfigError::Invalid(vec![problem]))?;
+    let problems = validate(&pool, &config, &export)
         .await
-        .map_err(|e| SetConfigError::SaveConfig(e.to_string()))?;
+        .map_err(|e| ImportConfigError::Sql(e.to_string()))?;
+    if !problems.is_empty() {
+        return Err(ImportConfigError::Invalid(problems));
+    }
+    if dry_run {
+        return Ok(false);
+    }
+    import_config_query(&pool, export, &mish_state_modification_bus_sender)
+        .await
+        .map_err(ImportConfigError::Import)?;
     cron_client
         .schedule_tasks(&pool)
         .await
-        .map_err(|e| SetConfigError::ScheduleTasks(e.to_string()))?;
+        .map_err(|e| ImportConfigError::ScheduleTasks(e.to_string()))?;
+    Ok(true)
+}
+
+#[server(GetConfigHistory)]
+async fn get_config_history() -> Result<Vec<ConfigVersion>, ServerFnError> {
+    use crate::integrations::iron_nest::config::get_config_history_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_config_history_query(&pool, CONFIG_HISTORY_SHOWN)
+        .await
+        .map_err(Into::into)
+}
+
+/// Restores the actions, location, vacation mode and energy settings of a version in the
+/// history. Scenes, mish states and integration switches aren't versioned, so whatever an import
+/// changed of them stays.
+#[server(RollBackConfig)]
+async fn roll_back_config(id: i64) -> Result<(), ServerFnError> {
+    use crate::integrations::iron_nest::{
+        config::{get_config_version_query, parse, validate},
+        cron::{CronClient, load_action_schedules},
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let cron_client = use_context::<CronClient>().unwrap();
+    let data = get_config_version_query(&pool, id)
+        .await?
+        .ok_or_else(|| ServerFnError::new(format!("Config version {id} not found")))?;
+    // Checked like an import, as the functions or the location it relies on may have changed
+    let source = serde_json::to_string_pretty(&data)?;
+    let export = parse(&source)
+        .map_err(|e| ServerFnError::new(format!("Config version {id} can't be read: {e}")))?;
+    let problems = validate(&pool, &source, &export).await?;
+    if !problems.is_empty() {
+        return Err(ServerFnError::new(format!(
+            "Config version {id} is no longer valid: {}",
+            problems
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        )));
+    }
+    let config = Config {
+        actions: export.actions,
+        location: export.location,
+        vacation: export.vacation,
+        energy: export.energy,
+    };
+
+    // Only saved once every action of the version can be scheduled
+    let mut tx = pool.begin().await?;
+    set_config_query(&mut *tx, config).await?;
+    let schedules = load_action_schedules(&mut *tx).await?;
+    if !schedules.problems.is_empty() {
+        return Err(ServerFnError::new(format!(
+            "Config version {id} can't be scheduled: {}",
+            schedules.problems.join("; ")
+        )));
+    }
+    tx.commit().await?;
+    cron_client.replace_tasks(&pool, schedules).await;
     Ok(())
 }
 
 #[cfg(feature = "ssr")]
-pub async fn set_config_query(pool: &sqlx::PgPool, config: Config) -> Result<(), sqlx::Error> {
+pub async fn set_config_query(
+    executor: impl sqlx::PgExecutor<'_>,
+    config: Config,
+) -> Result<(), sqlx::Error> {
     let query = "
         INSERT INTO config (id, data)
         VALUES (1, $1)
@@ -89,104 +204,291 @@ pub async fn set_config_query(pool: &sqlx::PgPool, config: Config) -> Result<(),
     ";
     sqlx::query(query)
         .bind(sqlx::types::Json(config))
-        .execute(pool)
+        .execute(executor)
         .await
         .map(|_| ())
 }
 
 #[component]
 pub fn ConfigsPage() -> impl IntoView {
-    let set_config_server_action = ServerAction::<SetConfig>::new();
-    let config = Resource::new(move || set_config_server_action.version(), |_| get_config());
+    let import_config_action = ServerAction::<ImportConfig>::new();
+    let roll_back_config_action = ServerAction::<RollBackConfig>::new();
+    let format = RwSignal::new(ConfigFormat::Yaml);
+    let imports = RwSignal::new(0);
+    let config = Resource::new(
+        move || {
+            (
+                format.get(),
+                imports.get(),
+                roll_back_config_action.version().get(),
+            )
+        },
+        |(format, ..)| export_config(format),
+    );
+    let history = Resource::new(
+        move || (imports.get(), roll_back_config_action.version().get()),
+        |_| get_config_history(),
+    );
+    let editor = NodeRef::<Textarea>::new();
+    let submit = move |dry_run| {
+        if let Some(editor) = editor.get() {
+            import_config_action.dispatch(ImportConfig {
+                config: editor.value(),
+                dry_run,
+            });
+        }
+    };
 
     let toast = use_context::<ToastContext>().unwrap();
     Resource::new(
         move || {
             (
-                set_config_server_action.value().get(),
-                set_config_server_action.version().get(),
+                import_config_action.value().get(),
+                import_config_action.version().get(),
             )
         },
         move |value| async move {
-            if matches!(value.0, Some(Ok(_))) {
-                toast.set(Some(Toast("Config saved".to_owned())));
+            match value.0 {
+                Some(Ok(true)) => {
+                    imports.update(|imports| *imports += 1);
+                    toast.set(Some(Toast("Config imported".to_owned())));
+                }
+                Some(Ok(false)) => toast.set(Some(Toast("Config is valid".to_owned()))),
+                _ => {}
+            }
+        },
+    );
+    Resource::new(
+        move || {
+            (
+                roll_back_config_action.value().get(),
+                roll_back_config_action.version().get(),
+            )
+        },
+        move |value| async move {
+            match value.0 {
+                Some(Ok(())) => toast.set(Some(Toast("Config rolled back".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Rollback failed: {e}")))),
+                None => {}
             }
         },
     );
 
+    let download = move || {
+        config.get().and_then(Result::ok).map(|config| {
+            format!(
+                "data:text/plain;charset=utf-8,{}",
+                urlencoding::encode(&config)
+            )
+        })
+    };
+    let file_name = move || match format.get() {
+        ConfigFormat::Yaml => "iron_nest.yaml",
+        ConfigFormat::Json => "iron_nest.json",
+    };
+
     view! {
-        <main class="lg:p-40 lg:pt-20 cursor-pointer">
+        <main class="lg:p-40 lg:pt-20">
             <div class="mx-auto max-w-2xl space-y-16 sm:space-y-20 lg:mx-0 lg:max-w-none">
                 <div>
                     <h2 class="text-base font-semibold leading-7 text-gray-900">"Configs"</h2>
                     <p class="mt-1 text-sm leading-6 text-gray-500">
-                        "Import and export your configuration in YAML format."
+                        "Export and import actions, integrations, scenes and mish states as YAML or JSON."
                     </p>
+                    <div class="flex gap-x-4 items-center my-2">
+                        <select
+                            class="rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 sm:text-sm"
+                            on:change:target=move |ev| {
+                                format
+                                    .set(
+                                        if ev.target().value() == "json" {
+                                            ConfigFormat::Json
+                                        } else {
+                                            ConfigFormat::Yaml
+                                        },
+                                    );
+                            }
+                        >
+                            <option value="yaml">"YAML"</option>
+                            <option value="json">"JSON"</option>
+                        </select>
+                        <a class="text-indigo-600" href=download download=file_name>
+                            "Download"
+                        </a>
+                    </div>
                     <Suspense fallback=|| {
                         view! { <p>"Loading config..."</p> }
                     }>
                         {move || {
                             config
                                 .get()
-                                .map(|config| {
-                                    match config {
-                                        Err(e) => {
-                                            view! { <p>"Error loading config: " {e.to_string()}</p> }
-                                                .into_any()
+                                .map(|config| match config {
+                                    Err(e) => {
+                                        view! { <p>"Error loading config: " {e.to_string()}</p> }
+                                            .into_any()
+                                    }
+                                    Ok(config) => {
+                                        view! {
+                                            <textarea
+                                                node_ref=editor
+                                                class="w-full font-mono text-sm"
+                                                style="text-wrap:nowrap; height:500px"
+                                                prop:value=config
+                                            ></textarea>
                                         }
-                                        Ok(config) => {
-                                            view! {
-                                                <ActionForm action=set_config_server_action>
-                                                    <textarea
-                                                        name="config"
-                                                        class="w-full"
-                                                        style="text-wrap:nowrap; height:500px"
-                                                    >
-                                                        {config}
-                                                    </textarea>
-                                                    <div class="flex flex-shrink-0 justify-end px-4 py-4">
-                                                        <button
-                                                            type="button"
-                                                            class="rounded-md bg-white px-3 py-2 text-sm font-semibold text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 hover:bg-gray-50"
-                                                        >
-                                                            // on:click=move |_| set_show_create_action.set(false)
-                                                            Cancel
-                                                        </button>
-                                                        <button
-                                                            type="submit"
-                                                            class="ml-4 inline-flex justify-center rounded-md bg-indigo-600 px-3 py-2 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500 focus-visible:outline focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600"
-                                                        >
-                                                            Create
-                                                        </button>
-                                                    </div>
-                                                    {move || {
-                                                        set_config_server_action
-                                                            .value()
-                                                            .get()
-                                                            .and_then(|value| {
-                                                                value
-                                                                    .map_err(|value| {
-                                                                        view! {
-                                                                            <div>
-                                                                                <p>"Error: " {value.to_string()}</p>
-                                                                            </div>
-                                                                        }
-                                                                    })
-                                                                    .err()
-                                                            })
-                                                    }}
-
-                                                </ActionForm>
-                                            }
-                                                .into_any()
+                                            .into_any()
+                                    }
+                                })
+                        }}
+                    </Suspense>
+                    <div class="flex flex-shrink-0 justify-end px-4 py-4">
+                        <button
+                            type="button"
+                            class="rounded-md bg-white px-3 py-2 text-sm font-semibold text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 hover:bg-gray-50"
+                            on:click=move |_| submit(true)
+                        >
+                            "Validate"
+                        </button>
+                        <button
+                            type="button"
+                            class="ml-4 inline-flex justify-center rounded-md bg-indigo-600 px-3 py-2 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500 focus-visible:outline focus-visible:outline-2 focus-visible:outline-offset-2 focus-visible:outline-indigo-600"
+                            on:click=move |_| submit(false)
+                        >
+                            "Import"
+                        </button>
+                    </div>
+                    {move || match import_config_action.value().get() {
+                        Some(Err(ImportConfigError::Invalid(problems))) => {
+                            view! {
+                                <ul class="text-sm text-red-600">
+                                    {problems
+                                        .into_iter()
+                                        .map(|problem| view! { <li>{problem.to_string()}</li> })
+                                        .collect::<Vec<_>>()}
+                                </ul>
+                            }
+                                .into_any()
+                        }
+                        Some(Err(e)) => {
+                            view! { <p class="text-sm text-red-600">"Error: " {e.to_string()}</p> }
+                                .into_any()
+                        }
+                        _ => ().into_any(),
+                    }}
+                </div>
+                <div>
+                    <h2 class="text-base font-semibold leading-7 text-gray-900">"History"</h2>
+                    <p class="mt-1 text-sm leading-6 text-gray-500">
+                        "Every saved version of the actions, location, vacation mode and energy settings, newest first. Rolling back leaves integrations, scenes and mish states as they are."
+                    </p>
+                    <Suspense fallback=|| {
+                        view! { <p>"Loading history..."</p> }
+                    }>
+                        {move || {
+                            history
+                                .get()
+                                .map(|history| match history {
+                                    Err(e) => {
+                                        view! { <p>"Error loading history: " {e.to_string()}</p> }
+                                            .into_any()
+                                    }
+                                    Ok(versions) => {
+                                        view! {
+                                            <ul class="space-y-2">
+                                                {versions
+                                                    .into_iter()
+                                                    .enumerate()
+                                                    .map(|(index, version)| {
+                                                        view! {
+                                                            <ConfigVersionItem
+                                                                version
+                                                                current=index == 0
+                                                                roll_back_config_action
+                                                            />
+                                                        }
+                                                    })
+                                                    .collect::<Vec<_>>()}
+                                            </ul>
                                         }
+                                            .into_any()
                                     }
                                 })
                         }}
-
                     </Suspense>
                 </div>
             </div>
         </main>
     }
 }
+
+#[component]
+fn ConfigVersionItem(
+    version: ConfigVersion,
+    current: bool,
+    roll_back_config_action: ServerAction<RollBackConfig>,
+) -> impl IntoView {
+    let id = version.id;
+    let changes = version
+        .diff
+        .iter()
+        .filter(|line| matches!(line, DiffLine::Added(_) | DiffLine::Removed(_)))
+        .count();
+    let roll_back = move |_| {
+        if window()
+            .confirm_with_message(
+                "Replace the current actions, location, vacation mode and energy settings with this version?",
+            )
+            .unwrap_or(false)
+        {
+            roll_back_config_action.dispatch(RollBackConfig { id });
+        }
+    };
+    view! {
+        <li>
+            <details>
+                <summary class="cursor-pointer text-sm">
+                    {format!(
+                        "{} ({changes} lines changed)",
+                        version.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    )}
+                    {current.then_some(" current")}
+                </summary>
+                <pre class="text-xs overflow-x-auto">
+                    {version
+                        .diff
+                        .into_iter()
+                        .map(|line| match line {
+                            DiffLine::Unchanged(line) => {
+                                view! { <div>{format!("  {line}")}</div> }.into_any()
+                            }
+                            DiffLine::Added(line) => {
+                                view! { <div class="text-green-700">{format!("+ {line}")}</div> }
+                                    .into_any()
+                            }
+                            DiffLine::Removed(line) => {
+                                view! { <div class="text-red-700">{format!("- {line}")}</div> }
+                                    .into_any()
+                            }
+                            DiffLine::Skipped(count) => {
+                                view! {
+                                    <div class="text-gray-400">
+                                        {format!("  … {count} unchanged lines")}
+                                    </div>
+                                }
+                                    .into_any()
+                            }
+                        })
+                        .collect::<Vec<_>>()}
+                </pre>
+                {(!current)
+                    .then(|| {
+                        view! {
+                            <button class="text-sm text-indigo-600" on:click=roll_back>
+                                "Roll back to this version"
+                            </button>
+                        }
+                    })}
+            </details>
+        </li>
+    }
+}
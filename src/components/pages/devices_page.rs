@@ -10,7 +10,7 @@ pub async fn get_devices() -> Result<Vec<Device>, ServerFnError> {
     let pool = use_context::<PgPool>().unwrap();
 
     let query = "
-        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, child_id 
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
         FROM device
     ";
     sqlx::query_as::<Postgres, Device>(query)
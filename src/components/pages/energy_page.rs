@@ -0,0 +1,431 @@
+use {
+    crate::{
+        components::layout::{Toast, ToastContext},
+        integrations::iron_nest::types::energy::{
+            DailyEnergy, DeviceEnergy, EnergySettings, PowerPoint,
+        },
+        server::energy::{
+            SetEnergySettings, get_daily_energy, get_device_energy, get_energy_alerts,
+            get_energy_settings, get_power_history,
+        },
+    },
+    leptos::prelude::*,
+};
+
+const WIDTH: f64 = 300.0;
+const HEIGHT: f64 = 100.0;
+/// Ranges offered for the power chart, in hours, with their labels.
+const POWER_RANGES: &[(u32, &str)] = &[(24, "24h"), (24 * 7, "7d")];
+const DAILY_DAYS: u32 = 30;
+
+#[component]
+pub fn EnergyPage() -> impl IntoView {
+    let set_energy_settings_action = ServerAction::<SetEnergySettings>::new();
+    let settings = Resource::new(
+        move || set_energy_settings_action.version().get(),
+        |_| get_energy_settings(),
+    );
+    let devices = Resource::new(|| (), |_| get_device_energy());
+    let alerts = Resource::new(|| (), |_| get_energy_alerts());
+    // The whole house when not set
+    let selected = RwSignal::new(None::<i64>);
+    let (hours, set_hours) = signal(POWER_RANGES[0].0);
+    let power = Resource::new(
+        move || (selected.get(), hours.get()),
+        |(device_id, hours)| get_power_history(device_id, hours),
+    );
+    let daily = Resource::new(
+        move || selected.get(),
+        |device_id| get_daily_energy(device_id, DAILY_DAYS),
+    );
+    let currency = Signal::derive(move || {
+        settings
+            .get()
+            .and_then(Result::ok)
+            .map(|settings| settings.tariff.currency)
+            .unwrap_or_default()
+    });
+
+    let toast = use_context::<ToastContext>().unwrap();
+    Resource::new(
+        move || {
+            (
+                set_energy_settings_action.value().get(),
+                set_energy_settings_action.version().get(),
+            )
+        },
+        move |value| async move {
+            match value.0 {
+                Some(Ok(())) => toast.set(Some(Toast("Energy settings saved".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Energy settings failed: {e}")))),
+                None => {}
+            }
+        },
+    );
+
+    view! {
+        <main class="lg:p-40 lg:pt-20">
+            <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8 space-y-8">
+                <div>
+                    <h1 class="text-lg">"Energy"</h1>
+                    <hr class="mb-2" />
+                    <Suspense fallback=|| {
+                        view! { <p>"Loading devices..."</p> }
+                    }>
+                        {move || {
+                            devices
+                                .get()
+                                .map(|devices| match devices {
+                                    Ok(devices) if devices.is_empty() => {
+                                        view! { <p>"No devices with a power meter"</p> }.into_any()
+                                    }
+                                    Ok(devices) => {
+                                        view! { <DeviceEnergyTable devices selected currency /> }
+                                            .into_any()
+                                    }
+                                    Err(e) => {
+                                        view! { <p>"Error loading devices: " {e.to_string()}</p> }
+                                            .into_any()
+                                    }
+                                })
+                        }}
+                    </Suspense>
+                </div>
+
+                <div>
+                    <div class="flex gap-x-2 items-baseline">
+                        <h2 class="text-base">"Power"</h2>
+                        {POWER_RANGES
+                            .iter()
+                            .map(|(range, label)| {
+                                view! {
+                                    <button
+                                        class="rounded px-2 py-1 text-xs"
+                                        class=("bg-indigo-600", move || hours.get() == *range)
+                                        class=("text-white", move || hours.get() == *range)
+                                        on:click=move |_| set_hours.set(*range)
+                                    >
+                                        {*label}
+                                    </button>
+                                }
+                            })
+                            .collect::<Vec<_>>()}
+                    </div>
+                    <Transition fallback=|| {
+                        view! { <p>"Loading..."</p> }
+                    }>
+                        {move || {
+                            power
+                                .get()
+                                .map(|points| match points {
+                                    Ok(points) => view! { <PowerChart points /> }.into_any(),
+                                    Err(e) => view! { <p>"Error: " {e.to_string()}</p> }.into_any(),
+                                })
+                        }}
+                    </Transition>
+                </div>
+
+                <div>
+                    <h2 class="text-base">{format!("Last {DAILY_DAYS} days")}</h2>
+                    <Transition fallback=|| {
+                        view! { <p>"Loading..."</p> }
+                    }>
+                        {move || {
+                            daily
+                                .get()
+                                .map(|days| match days {
+                                    Ok(days) => {
+                                        view! { <DailyChart days currency=currency.get() /> }.into_any()
+                                    }
+                                    Err(e) => view! { <p>"Error: " {e.to_string()}</p> }.into_any(),
+                                })
+                        }}
+                    </Transition>
+                </div>
+
+                <div>
+                    <h2 class="text-base">"Alerts"</h2>
+                    <hr class="mb-2" />
+                    <Suspense fallback=|| {
+                        view! { <p>"Loading alerts..."</p> }
+                    }>
+                        {move || {
+                            alerts
+                                .get()
+                                .map(|alerts| match alerts {
+                                    Ok(alerts) if alerts.is_empty() => {
+                                        view! { <p class="text-sm">"No alerts have fired"</p> }
+                                            .into_any()
+                                    }
+                                    Ok(alerts) => {
+                                        view! {
+                                            <ul class="text-sm">
+                                                {alerts
+                                                    .into_iter()
+                                                    .map(|alert| {
+                                                        view! {
+                                                            <li>
+                                                                {format!(
+                                                                    "{} {}: {:.0} W over {:.0} W",
+                                                                    alert.triggered_at.format("%Y-%m-%d %H:%M"),
+                                                                    alert.rule_name,
+                                                                    alert.power_w,
+                                                                    alert.threshold_watts,
+                                                                )}
+                                                            </li>
+                                                        }
+                                                    })
+                                                    .collect::<Vec<_>>()}
+                                            </ul>
+                                        }
+                                            .into_any()
+                                    }
+                                    Err(e) => {
+                                        view! { <p>"Error loading alerts: " {e.to_string()}</p> }
+                                            .into_any()
+                                    }
+                                })
+                        }}
+                    </Suspense>
+                </div>
+
+                <div>
+                    <h2 class="text-base">"Tariff and alert rules"</h2>
+                    <hr class="mb-2" />
+                    <Suspense fallback=|| {
+                        view! { <p>"Loading settings..."</p> }
+                    }>
+                        {move || {
+                            settings
+                                .get()
+                                .map(|settings| match settings {
+                                    Ok(settings) => {
+                                        view! {
+                                            <EnergySettingsForm settings set_energy_settings_action />
+                                        }
+                                            .into_any()
+                                    }
+                                    Err(e) => {
+                                        view! { <p>"Error loading settings: " {e.to_string()}</p> }
+                                            .into_any()
+                                    }
+                                })
+                        }}
+                    </Suspense>
+                </div>
+            </div>
+        </main>
+    }
+}
+
+/// Each device's power and use today, picking the one the charts show.
+#[component]
+fn DeviceEnergyTable(
+    devices: Vec<DeviceEnergy>,
+    selected: RwSignal<Option<i64>>,
+    currency: Signal<String>,
+) -> impl IntoView {
+    let total_w = devices
+        .iter()
+        .filter_map(|device| device.power_w)
+        .sum::<f64>();
+    let total_wh = devices.iter().map(|device| device.today_wh).sum::<f64>();
+    let total_cost = devices.iter().map(|device| device.today_cost).sum::<f64>();
+    let rows = devices
+        .into_iter()
+        .map(|device| {
+            let device_id = Some(device.device_id);
+            view! {
+                <tr
+                    class="cursor-pointer"
+                    class=("bg-indigo-100", move || selected.get() == device_id)
+                    on:click=move |_| selected.set(device_id)
+                >
+                    <td>{device.name}</td>
+                    <td>
+                        {device.power_w.map_or("-".to_owned(), |power_w| format!("{power_w:.1} W"))}
+                    </td>
+                    <td>{format!("{:.2} kWh", device.today_wh / 1000.0)}</td>
+                    <td>{move || format!("{:.2} {}", device.today_cost, currency.get())}</td>
+                </tr>
+            }
+        })
+        .collect::<Vec<_>>();
+    view! {
+        <table class="w-full text-sm text-left">
+            <thead>
+                <tr>
+                    <th>"Device"</th>
+                    <th>"Now"</th>
+                    <th>"Today"</th>
+                    <th>"Cost today"</th>
+                </tr>
+            </thead>
+            <tbody>
+                <tr
+                    class="cursor-pointer font-semibold"
+                    class=("bg-indigo-100", move || selected.get().is_none())
+                    on:click=move |_| selected.set(None)
+                >
+                    <td>"Whole house"</td>
+                    <td>{format!("{total_w:.1} W")}</td>
+                    <td>{format!("{:.2} kWh", total_wh / 1000.0)}</td>
+                    <td>{move || format!("{total_cost:.2} {}", currency.get())}</td>
+                </tr>
+                {rows}
+            </tbody>
+        </table>
+    }
+}
+
+#[component]
+fn PowerChart(points: Vec<PowerPoint>) -> impl IntoView {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return view! { <p class="text-sm">"No samples"</p> }.into_any();
+    };
+    let from = first.at;
+    let range = (last.at - from).num_seconds().max(1) as f64;
+    let max = points
+        .iter()
+        .map(|point| point.power_w)
+        .fold(f64::EPSILON, f64::max);
+    let polyline = points
+        .iter()
+        .map(|point| {
+            let x = (point.at - from).num_seconds() as f64 / range * WIDTH;
+            format!("{x},{}", HEIGHT - point.power_w / max * HEIGHT)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    view! {
+        <p class="text-sm">{format!("{:.1} W (max {max:.1} W)", last.power_w)}</p>
+        <svg viewBox=format!("0 0 {WIDTH} {HEIGHT}") preserveAspectRatio="none" class="w-full h-24">
+            <polyline points=polyline fill="none" stroke="currentColor" stroke-width="2" />
+        </svg>
+    }
+    .into_any()
+}
+
+#[component]
+fn DailyChart(days: Vec<DailyEnergy>, currency: String) -> impl IntoView {
+    if days.is_empty() {
+        return view! { <p class="text-sm">"No energy used yet"</p> }.into_any();
+    }
+    let total_wh = days.iter().map(|day| day.energy_wh).sum::<f64>();
+    let total_cost = days.iter().map(|day| day.cost).sum::<f64>();
+    let max = days
+        .iter()
+        .map(|day| day.energy_wh)
+        .fold(f64::EPSILON, f64::max);
+    let step = WIDTH / DAILY_DAYS as f64;
+    let last_day = days[days.len() - 1].day;
+    let bars = days
+        .iter()
+        .map(|day| {
+            let age = (last_day - day.day).num_days() as f64;
+            let height = day.energy_wh / max * HEIGHT;
+            view! {
+                <rect
+                    x=WIDTH - (age + 1.0) * step
+                    y=HEIGHT - height
+                    width=step * 0.8
+                    height=height
+                    class="fill-indigo-600"
+                >
+                    <title>
+                        {format!(
+                            "{}: {:.2} kWh, {:.2} {currency}",
+                            day.day,
+                            day.energy_wh / 1000.0,
+                            day.cost,
+                        )}
+                    </title>
+                </rect>
+            }
+        })
+        .collect::<Vec<_>>();
+    view! {
+        <p class="text-sm">
+            {format!("{:.2} kWh, {total_cost:.2} {currency}", total_wh / 1000.0)}
+        </p>
+        <svg viewBox=format!("0 0 {WIDTH} {HEIGHT}") preserveAspectRatio="none" class="w-full h-24">
+            {bars}
+        </svg>
+    }
+    .into_any()
+}
+
+/// The tariff and alert rules, the time-of-use periods and rules edited as JSON.
+#[component]
+fn EnergySettingsForm(
+    settings: EnergySettings,
+    set_energy_settings_action: ServerAction<SetEnergySettings>,
+) -> impl IntoView {
+    let input_class = "rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 sm:text-sm";
+    let EnergySettings { tariff, alerts } = settings;
+    view! {
+        <ActionForm action=set_energy_settings_action>
+            <div class="mt-4 space-y-4 text-sm">
+                <div class="flex gap-x-4">
+                    <label class="text-gray-900">
+                        "Rate "
+                        <input
+                            type="number"
+                            min="0"
+                            step="any"
+                            name="rate_per_kwh"
+                            class=format!("w-24 {input_class}")
+                            value=tariff.rate_per_kwh.to_string()
+                        />
+                        " per kWh"
+                    </label>
+                    <label class="text-gray-900">
+                        "Currency "
+                        <input
+                            type="text"
+                            name="currency"
+                            class=format!("w-20 {input_class}")
+                            value=tariff.currency
+                        />
+                    </label>
+                    <label class="text-gray-900">
+                        "Timezone "
+                        <input
+                            type="text"
+                            name="timezone"
+                            placeholder="America/New_York (UTC if empty)"
+                            class=input_class
+                            value=tariff.timezone
+                        />
+                    </label>
+                </div>
+                <label class="block text-gray-900">
+                    "Time-of-use periods"
+                    <textarea
+                        name="periods"
+                        rows="6"
+                        class=format!("block w-full font-mono {input_class}")
+                        placeholder=r#"[{"name": "Peak", "start": "16:00:00", "end": "21:00:00", "days": ["Mon", "Tue", "Wed", "Thu", "Fri"], "rate_per_kwh": 0.4}]"#
+                        prop:value=serde_json::to_string_pretty(&tariff.periods).unwrap_or_default()
+                    ></textarea>
+                </label>
+                <label class="block text-gray-900">
+                    "Alert rules"
+                    <textarea
+                        name="alerts"
+                        rows="6"
+                        class=format!("block w-full font-mono {input_class}")
+                        placeholder=r#"[{"name": "Heater left on", "device_id": 3, "threshold_watts": 1000, "for_minutes": 30, "function_name": "device_set_on_off", "function_args": {"device_id": 3, "on": false}}]"#
+                        prop:value=serde_json::to_string_pretty(&alerts).unwrap_or_default()
+                    ></textarea>
+                </label>
+                <button
+                    type="submit"
+                    class="rounded-md bg-indigo-600 px-3 py-2 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                >
+                    "Save"
+                </button>
+            </div>
+        </ActionForm>
+    }
+}
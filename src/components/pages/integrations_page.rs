@@ -1,7 +1,10 @@
 use {
     crate::{
         components::checkbox::Checkbox,
-        server::integrations_page::{get_integrations, toggle_integration},
+        integrations::iron_nest::types::IntegrationStatus,
+        server::integrations_page::{
+            get_integration_statuses, get_integrations, toggle_integration,
+        },
     },
     leptos::prelude::*,
 };
@@ -9,6 +12,7 @@ use {
 #[component]
 pub fn IntegrationsPage() -> impl IntoView {
     let integrations = Resource::new(|| (), |_| get_integrations());
+    let statuses = Resource::new(|| (), |_| get_integration_statuses());
 
     let toggle_action = Action::new(|(id, enabled, name): &(i64, bool, String)| {
         let id = *id;
@@ -59,14 +63,10 @@ pub fn IntegrationsPage() -> impl IntoView {
 
                                                         </div>
                                                     </div>
-                                                    <dl class="-my-3 divide-y divide-gray-100 px-6 py-4 text-sm leading-6">
-                                                        <div class="flex justify-between gap-x-4 py-3">
-                                                            <dt class="text-gray-500">"Last authenticated"</dt>
-                                                            <dd class="text-gray-700">
-                                                                <time datetime="2024-1-2">"January 2, 2024"</time>
-                                                            </dd>
-                                                        </div>
-                                                    </dl>
+                                                    <IntegrationStatusList
+                                                        name=data.name.clone()
+                                                        statuses=statuses
+                                                    />
                                                 </a>
                                             </li>
                                         }
@@ -93,3 +93,62 @@ pub fn IntegrationsPage() -> impl IntoView {
         </Suspense>
     }
 }
+
+/// What the integration's task is doing, or nothing for integrations without one.
+#[component]
+fn IntegrationStatusList(
+    name: String,
+    statuses: Resource<Result<Vec<IntegrationStatus>, ServerFnError>>,
+) -> impl IntoView {
+    let row = |label: &'static str, value: String| {
+        view! {
+            <div class="flex justify-between gap-x-4 py-3">
+                <dt class="text-gray-500">{label}</dt>
+                <dd class="text-gray-700 truncate" title=value.clone()>
+                    {value}
+                </dd>
+            </div>
+        }
+    };
+    view! {
+        <Suspense>
+            {move || {
+                let name = name.clone();
+                statuses
+                    .get()
+                    .and_then(Result::ok)
+                    .and_then(|statuses| statuses.into_iter().find(|status| status.name == name))
+                    .map(|status| {
+                        view! {
+                            <dl class="-my-3 divide-y divide-gray-100 px-6 py-4 text-sm leading-6">
+                                {row("Status", status.state.to_string())}
+                                {row("Restarts", status.restarts.to_string())}
+                                {row(
+                                    "Last success",
+                                    status
+                                        .last_success
+                                        .map_or("Never".to_owned(), |at| {
+                                            at.format("%Y-%m-%d %H:%M UTC").to_string()
+                                        }),
+                                )}
+                                {status.last_error.map(|e| row("Last error", e))}
+                                {status.health.map(|e| row("Health", e))}
+                                {(!status.capabilities.is_empty())
+                                    .then(|| {
+                                        row(
+                                            "Capabilities",
+                                            status
+                                                .capabilities
+                                                .iter()
+                                                .map(ToString::to_string)
+                                                .collect::<Vec<_>>()
+                                                .join(", "),
+                                        )
+                                    })}
+                            </dl>
+                        }
+                    })
+            }}
+        </Suspense>
+    }
+}
@@ -2,7 +2,9 @@ pub mod actions_page;
 pub mod configs_page;
 pub mod dashboard_page;
 pub mod devices_page;
+pub mod energy_page;
 pub mod integrations_page;
 pub mod login_page;
+pub mod scenes_page;
 pub mod settings_page;
 pub mod websocket_page;
@@ -0,0 +1,346 @@
+use {
+    crate::{
+        components::{
+            layout::{Toast, ToastContext},
+            pages::devices_page::get_devices,
+            text_input::TextInput,
+        },
+        integrations::iron_nest::types::{
+            Device, DeviceType,
+            scene::{DeviceState, Scene, SceneTarget},
+        },
+        server::scenes::{ApplyScene, CaptureScene, DeleteScene, get_scenes},
+    },
+    leptos::prelude::*,
+};
+
+#[component]
+pub fn ScenesPage() -> impl IntoView {
+    let capture_scene_action = ServerAction::<CaptureScene>::new();
+    let apply_scene_action = ServerAction::<ApplyScene>::new();
+    let delete_scene_action = ServerAction::<DeleteScene>::new();
+    let scenes = Resource::new(
+        move || {
+            (
+                capture_scene_action.version().get(),
+                delete_scene_action.version().get(),
+            )
+        },
+        |_| get_scenes(),
+    );
+    let devices = Resource::new(|| (), |_| get_devices());
+
+    let toast = use_context::<ToastContext>().unwrap();
+    Resource::new(
+        move || {
+            (
+                capture_scene_action.value().get(),
+                capture_scene_action.version().get(),
+            )
+        },
+        move |value| async move {
+            match value.0 {
+                Some(Ok(())) => toast.set(Some(Toast("Scene captured".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Capture failed: {e}")))),
+                None => {}
+            }
+        },
+    );
+    Resource::new(
+        move || {
+            (
+                apply_scene_action.value().get(),
+                apply_scene_action.version().get(),
+            )
+        },
+        move |value| async move {
+            match value.0 {
+                Some(Ok(())) => toast.set(Some(Toast("Scene applied".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Scene failed: {e}")))),
+                None => {}
+            }
+        },
+    );
+
+    view! {
+        <main class="lg:p-40 lg:pt-20">
+            <div class="flex min-h-full flex-col justify-center px-6 py-12 lg:px-8">
+                <h1 class="text-lg">"Scenes"</h1>
+                <hr class="mb-2" />
+                <Suspense fallback=move || {
+                    view! { <p>"Loading scenes..."</p> }
+                }>
+                    {move || {
+                        scenes
+                            .get()
+                            .map(|scenes| match scenes {
+                                Ok(scenes) if scenes.is_empty() => {
+                                    view! { <p>"No scenes yet"</p> }.into_any()
+                                }
+                                Ok(scenes) => {
+                                    view! {
+                                        <ul class="space-y-4">
+                                            {scenes
+                                                .into_iter()
+                                                .map(|scene| {
+                                                    view! {
+                                                        <SceneItem
+                                                            scene
+                                                            apply_scene_action
+                                                            capture_scene_action
+                                                            delete_scene_action
+                                                        />
+                                                    }
+                                                })
+                                                .collect::<Vec<_>>()}
+                                        </ul>
+                                    }
+                                        .into_any()
+                                }
+                                Err(e) => {
+                                    view! { <p>{format!("GetScenes error: {e}")}</p> }.into_any()
+                                }
+                            })
+                    }}
+                </Suspense>
+                <h2 class="mt-8 text-base">"Capture a scene"</h2>
+                <hr class="mb-2" />
+                <ActionForm action=capture_scene_action>
+                    <div class="space-y-4">
+                        <TextInput
+                            label="Scene name".to_owned()
+                            name="name".to_owned()
+                            placeholder="Movie night".to_owned()
+                            input_type="text".to_owned()
+                        />
+                        <TextInput
+                            label="Devices changed at once".to_owned()
+                            name="concurrency".to_owned()
+                            placeholder="4".to_owned()
+                            input_type="number".to_owned()
+                        />
+                        <Suspense fallback=move || {
+                            view! { <p>"Loading devices..."</p> }
+                        }>
+                            {move || {
+                                devices
+                                    .get()
+                                    .map(|devices| match devices {
+                                        Ok(devices) => view! { <DevicePicker devices /> }.into_any(),
+                                        Err(e) => {
+                                            view! { <p>{format!("GetDevices error: {e}")}</p> }
+                                                .into_any()
+                                        }
+                                    })
+                            }}
+                        </Suspense>
+                        <button
+                            type="submit"
+                            class="rounded-md bg-indigo-600 px-3 py-2 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                        >
+                            "Capture"
+                        </button>
+                    </div>
+                </ActionForm>
+            </div>
+        </main>
+    }
+}
+
+#[component]
+fn SceneItem(
+    scene: Scene,
+    apply_scene_action: ServerAction<ApplyScene>,
+    capture_scene_action: ServerAction<CaptureScene>,
+    delete_scene_action: ServerAction<DeleteScene>,
+) -> impl IntoView {
+    let targets = scene
+        .devices
+        .iter()
+        .map(|scene_device| SceneTarget {
+            device_id: scene_device.device_id,
+            transition_ms: scene_device.transition_ms,
+        })
+        .collect::<Vec<_>>();
+    let name = scene.name.clone();
+    let apply = {
+        let name = name.clone();
+        move |_| {
+            apply_scene_action.dispatch(ApplyScene { name: name.clone() });
+        }
+    };
+    let recapture = {
+        let name = name.clone();
+        let concurrency = scene.concurrency.to_string();
+        move |_| {
+            capture_scene_action.dispatch(CaptureScene {
+                name: name.clone(),
+                devices: serde_json::to_string(&targets).unwrap_or_default(),
+                concurrency: concurrency.clone(),
+            });
+        }
+    };
+    let delete = move |_| {
+        if window()
+            .confirm_with_message(&format!("Delete the scene {name}?"))
+            .unwrap_or(false)
+        {
+            delete_scene_action.dispatch(DeleteScene { name: name.clone() });
+        }
+    };
+    view! {
+        <li>
+            <div class="flex gap-x-2 items-baseline">
+                <span class="font-semibold">{scene.name.clone()}</span>
+                <span class="text-xs text-gray-500">
+                    {format!(
+                        "{} devices, {} at once, captured {}",
+                        scene.devices.len(),
+                        scene.concurrency,
+                        scene.updated_at.format("%Y-%m-%d %H:%M"),
+                    )}
+                </span>
+                <button on:click=apply>"Apply"</button>
+                <button on:click=recapture>"Recapture"</button>
+                <button on:click=delete>"Delete"</button>
+            </div>
+            <ul class="pl-4 text-sm text-gray-600">
+                {scene
+                    .devices
+                    .into_iter()
+                    .map(|scene_device| {
+                        let transition = (scene_device.transition_ms > 0)
+                            .then(|| format!(" over {} ms", scene_device.transition_ms));
+                        view! {
+                            <li>
+                                {format!(
+                                    "{}: {}{}",
+                                    scene_device.name,
+                                    describe_state(&scene_device.state),
+                                    transition.unwrap_or_default(),
+                                )}
+                            </li>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </ul>
+        </li>
+    }
+}
+
+/// Checkboxes for the devices scenes can capture, each with its transition, filling the hidden
+/// `devices` input with JSON of the chosen [`SceneTarget`]s.
+#[component]
+fn DevicePicker(devices: Vec<Device>) -> impl IntoView {
+    let targets = RwSignal::new(Vec::<SceneTarget>::new());
+    let rows = devices
+        .into_iter()
+        .filter(|device| {
+            matches!(
+                device.device_type,
+                DeviceType::KasaPlug
+                    | DeviceType::KasaLight
+                    | DeviceType::KasaDimmer
+                    | DeviceType::KasaPowerStrip
+                    | DeviceType::RokuTv
+            )
+        })
+        .map(|device| {
+            let device_id = device.id;
+            let fades = matches!(
+                device.device_type,
+                DeviceType::KasaLight | DeviceType::KasaDimmer
+            );
+            let selected = move || {
+                targets
+                    .get()
+                    .iter()
+                    .any(|target| target.device_id == device_id)
+            };
+            view! {
+                <li class="flex gap-x-2 items-center">
+                    <input
+                        type="checkbox"
+                        id=format!("scene-device-{device_id}")
+                        on:change:target=move |ev| {
+                            let checked = ev.target().checked();
+                            targets
+                                .update(|targets| {
+                                    targets.retain(|target| target.device_id != device_id);
+                                    if checked {
+                                        targets
+                                            .push(SceneTarget {
+                                                device_id,
+                                                transition_ms: 0,
+                                            });
+                                    }
+                                });
+                        }
+                    />
+                    <label for=format!("scene-device-{device_id}") class="text-sm">
+                        {format!("{} ({})", device.name, device.device_type)}
+                    </label>
+                    {fades
+                        .then(|| {
+                            view! {
+                                <input
+                                    type="number"
+                                    min="0"
+                                    placeholder="Transition ms"
+                                    class="w-32 rounded-md border-0 py-1 text-sm text-gray-900 ring-1 ring-inset ring-gray-300"
+                                    class:hidden=move || !selected()
+                                    on:input:target=move |ev| {
+                                        let transition_ms = ev.target().value().parse().unwrap_or(0);
+                                        targets
+                                            .update(|targets| {
+                                                if let Some(target) = targets
+                                                    .iter_mut()
+                                                    .find(|target| target.device_id == device_id)
+                                                {
+                                                    target.transition_ms = transition_ms;
+                                                }
+                                            });
+                                    }
+                                />
+                            }
+                        })}
+                </li>
+            }
+        })
+        .collect::<Vec<_>>();
+    view! {
+        <fieldset>
+            <legend class="text-sm font-medium leading-6 text-gray-900">"Devices"</legend>
+            <ul class="space-y-1">{rows}</ul>
+            <input
+                type="hidden"
+                name="devices"
+                prop:value=move || serde_json::to_string(&targets.get()).unwrap_or_default()
+            />
+        </fieldset>
+    }
+}
+
+fn describe_state(state: &DeviceState) -> String {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    match *state {
+        DeviceState::Relay { on } | DeviceState::Roku { on } => on_off(on).to_owned(),
+        DeviceState::Dimmer { on, brightness } => format!("{} at {brightness}%", on_off(on)),
+        DeviceState::Light {
+            on,
+            brightness,
+            hue,
+            saturation,
+            color_temp,
+        } => {
+            let color = match (color_temp, hue, saturation) {
+                (Some(color_temp), _, _) if color_temp > 0 => format!(", {color_temp}K"),
+                (_, Some(hue), Some(saturation)) => {
+                    format!(", hue {hue} saturation {saturation}%")
+                }
+                _ => String::new(),
+            };
+            format!("{} at {brightness}%{color}", on_off(on))
+        }
+    }
+}
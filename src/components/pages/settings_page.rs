@@ -1,4 +1,21 @@
-use leptos::prelude::*;
+use {
+    crate::{
+        components::{
+            layout::{Toast, ToastContext},
+            pages::devices_page::get_devices,
+        },
+        integrations::iron_nest::types::{
+            Device,
+            vacation::{
+                DEFAULT_JITTER_MINUTES, VacationMode, VacationPlan, VacationWindow, can_simulate,
+            },
+        },
+        server::vacation::{SetVacationMode, get_vacation_mode, get_vacation_plan},
+    },
+    chrono::{NaiveTime, Utc},
+    chrono_tz::Tz,
+    leptos::prelude::*,
+};
 
 #[component]
 pub fn SettingsPage() -> impl IntoView {
@@ -119,6 +136,8 @@ pub fn SettingsPage() -> impl IntoView {
                     </dl>
                 </div>
 
+                <VacationSettings />
+
                 <div>
                     <h2 class="text-base font-semibold leading-7 text-gray-900">"Advanced"</h2>
                     <p class="mt-1 text-sm leading-6 text-gray-500">"Advanced settings."</p>
@@ -135,3 +154,311 @@ pub fn SettingsPage() -> impl IntoView {
         </main>
     }
 }
+
+#[component]
+fn VacationSettings() -> impl IntoView {
+    let set_vacation_mode_action = ServerAction::<SetVacationMode>::new();
+    let mode = Resource::new(
+        move || set_vacation_mode_action.version().get(),
+        |_| get_vacation_mode(),
+    );
+    let plan = Resource::new(
+        move || set_vacation_mode_action.version().get(),
+        |_| get_vacation_plan(),
+    );
+    let devices = Resource::new(|| (), |_| get_devices());
+
+    let toast = use_context::<ToastContext>().unwrap();
+    Resource::new(
+        move || {
+            (
+                set_vacation_mode_action.value().get(),
+                set_vacation_mode_action.version().get(),
+            )
+        },
+        move |value| async move {
+            match value.0 {
+                Some(Ok(())) => toast.set(Some(Toast("Vacation mode saved".to_owned()))),
+                Some(Err(e)) => toast.set(Some(Toast(format!("Vacation mode failed: {e}")))),
+                None => {}
+            }
+        },
+    );
+
+    view! {
+        <div>
+            <h2 class="text-base font-semibold leading-7 text-gray-900">"Vacation mode"</h2>
+            <p class="mt-1 text-sm leading-6 text-gray-500">
+                "Switches lights and the TV on and off at their usual times while away, learned from the last weeks unless given."
+            </p>
+            <Suspense fallback=|| {
+                view! { <p>"Loading vacation mode..."</p> }
+            }>
+                {move || {
+                    match (mode.get(), devices.get()) {
+                        (Some(Ok(mode)), Some(Ok(devices))) => {
+                            view! { <VacationForm mode devices set_vacation_mode_action /> }
+                                .into_any()
+                        }
+                        (Some(Err(e)), _) | (_, Some(Err(e))) => {
+                            view! { <p>"Error loading vacation mode: " {e.to_string()}</p> }
+                                .into_any()
+                        }
+                        _ => ().into_any(),
+                    }
+                }}
+            </Suspense>
+            <Transition>
+                {move || {
+                    let timezone = mode
+                        .get()
+                        .and_then(Result::ok)
+                        .flatten()
+                        .map_or(Tz::UTC, |mode| mode.timezone());
+                    plan.get()
+                        .map(|plan| match plan {
+                            Ok(Some(plan)) => view! { <VacationPlanList plan timezone /> }.into_any(),
+                            Ok(None) => ().into_any(),
+                            Err(e) => {
+                                view! { <p>"Error planning vacation mode: " {e.to_string()}</p> }
+                                    .into_any()
+                            }
+                        })
+                }}
+            </Transition>
+        </div>
+    }
+}
+
+#[component]
+fn VacationForm(
+    mode: Option<VacationMode>,
+    devices: Vec<Device>,
+    set_vacation_mode_action: ServerAction<SetVacationMode>,
+) -> impl IntoView {
+    let input_class = "rounded-md border-0 py-1.5 text-gray-900 shadow-sm ring-1 ring-inset ring-gray-300 sm:text-sm";
+    let today = Utc::now().date_naive();
+    let device_ids = RwSignal::new(
+        mode.as_ref()
+            .map(|mode| mode.device_ids.clone())
+            .unwrap_or_default(),
+    );
+    let windows = RwSignal::new(
+        mode.as_ref()
+            .map(|mode| mode.windows.clone())
+            .unwrap_or_default(),
+    );
+    let rows = devices
+        .into_iter()
+        .filter(|device| can_simulate(&device.device_type))
+        .map(|device| {
+            let device_id = device.id;
+            let selected = move || device_ids.get().contains(&device_id);
+            let window = move || {
+                windows
+                    .get()
+                    .into_iter()
+                    .find(|window| window.device_id == device_id)
+            };
+            // Both times make a window, clearing either goes back to learned times
+            let set_window = move |on: Option<NaiveTime>, off: Option<NaiveTime>| {
+                windows.update(|windows| {
+                    let current = windows
+                        .iter()
+                        .position(|window| window.device_id == device_id)
+                        .map(|index| windows.remove(index));
+                    let on = on.or(current.map(|window| window.on));
+                    let off = off.or(current.map(|window| window.off));
+                    if let (Some(on), Some(off)) = (on, off) {
+                        windows.push(VacationWindow { device_id, on, off });
+                    }
+                });
+            };
+            let time = |value: String| NaiveTime::parse_from_str(&value, "%H:%M").ok();
+            view! {
+                <li class="flex gap-x-2 items-center">
+                    <input
+                        type="checkbox"
+                        id=format!("vacation-device-{device_id}")
+                        prop:checked=selected
+                        on:change:target=move |ev| {
+                            let checked = ev.target().checked();
+                            device_ids
+                                .update(|device_ids| {
+                                    device_ids.retain(|id| *id != device_id);
+                                    if checked {
+                                        device_ids.push(device_id);
+                                    }
+                                });
+                            if !checked {
+                                windows.update(|windows| {
+                                    windows.retain(|window| window.device_id != device_id)
+                                });
+                            }
+                        }
+                    />
+                    <label for=format!("vacation-device-{device_id}") class="text-sm">
+                        {format!("{} ({})", device.name, device.device_type)}
+                    </label>
+                    <span class="text-xs text-gray-500" class:hidden=move || !selected()>
+                        <input
+                            type="time"
+                            prop:value=move || {
+                                window().map(|window| window.on.format("%H:%M").to_string())
+                            }
+                            on:change:target=move |ev| {
+                                match time(ev.target().value()) {
+                                    Some(on) => set_window(Some(on), None),
+                                    None => {
+                                        windows
+                                            .update(|windows| {
+                                                windows.retain(|window| window.device_id != device_id)
+                                            })
+                                    }
+                                }
+                            }
+                        />
+                        " to "
+                        <input
+                            type="time"
+                            prop:value=move || {
+                                window().map(|window| window.off.format("%H:%M").to_string())
+                            }
+                            on:change:target=move |ev| {
+                                match time(ev.target().value()) {
+                                    Some(off) => set_window(None, Some(off)),
+                                    None => {
+                                        windows
+                                            .update(|windows| {
+                                                windows.retain(|window| window.device_id != device_id)
+                                            })
+                                    }
+                                }
+                            }
+                        />
+                        " (learned if empty)"
+                    </span>
+                </li>
+            }
+        })
+        .collect::<Vec<_>>();
+
+    view! {
+        <ActionForm action=set_vacation_mode_action>
+            <div class="mt-4 space-y-4 text-sm">
+                <label class="block text-gray-900">
+                    <input
+                        type="checkbox"
+                        name="enabled"
+                        checked=mode.as_ref().is_some_and(|mode| mode.enabled)
+                    />
+                    " Enabled"
+                </label>
+                <div class="flex gap-x-4">
+                    <label class="text-gray-900">
+                        "From "
+                        <input
+                            type="date"
+                            name="start_date"
+                            class=input_class
+                            value=mode.as_ref().map_or(today, |mode| mode.start_date).to_string()
+                        />
+                    </label>
+                    <label class="text-gray-900">
+                        "To "
+                        <input
+                            type="date"
+                            name="end_date"
+                            class=input_class
+                            value=mode.as_ref().map_or(today, |mode| mode.end_date).to_string()
+                        />
+                    </label>
+                </div>
+                <div class="flex gap-x-4">
+                    <label class="text-gray-900">
+                        "Jitter "
+                        <input
+                            type="number"
+                            min="0"
+                            name="jitter_minutes"
+                            class=format!("w-24 {input_class}")
+                            value=mode
+                                .as_ref()
+                                .map_or(DEFAULT_JITTER_MINUTES, |mode| mode.jitter_minutes)
+                                .to_string()
+                        />
+                        " minutes"
+                    </label>
+                    <label class="text-gray-900">
+                        "Timezone "
+                        <input
+                            type="text"
+                            name="timezone"
+                            placeholder="America/New_York (UTC if empty)"
+                            class=input_class
+                            value=mode.as_ref().and_then(|mode| mode.timezone.clone())
+                        />
+                    </label>
+                </div>
+                <fieldset>
+                    <legend class="font-medium leading-6 text-gray-900">"Devices"</legend>
+                    <ul class="space-y-1">{rows}</ul>
+                </fieldset>
+                <input
+                    type="hidden"
+                    name="device_ids"
+                    prop:value=move || serde_json::to_string(&device_ids.get()).unwrap_or_default()
+                />
+                <input
+                    type="hidden"
+                    name="windows"
+                    prop:value=move || serde_json::to_string(&windows.get()).unwrap_or_default()
+                />
+                <button
+                    type="submit"
+                    class="rounded-md bg-indigo-600 px-3 py-2 text-sm font-semibold text-white shadow-sm hover:bg-indigo-500"
+                >
+                    "Save"
+                </button>
+            </div>
+        </ActionForm>
+    }
+}
+
+#[component]
+fn VacationPlanList(plan: VacationPlan, timezone: Tz) -> impl IntoView {
+    view! {
+        <div class="mt-4 text-sm">
+            <h3 class="font-medium text-gray-900">{format!("Planned for {}", plan.date)}</h3>
+            <ul class="text-gray-600">
+                {plan
+                    .switches
+                    .into_iter()
+                    .map(|switch| {
+                        view! {
+                            <li>
+                                {format!(
+                                    "{} turn {} {}",
+                                    switch.at.with_timezone(&timezone).format("%a %H:%M"),
+                                    if switch.on { "on" } else { "off" },
+                                    switch.name,
+                                )}
+                            </li>
+                        }
+                    })
+                    .collect::<Vec<_>>()}
+            </ul>
+            {(!plan.unplanned.is_empty())
+                .then(|| {
+                    view! {
+                        <p class="text-xs text-gray-500">
+                            {format!(
+                                "Not enough history for {}, give them times",
+                                plan.unplanned.join(", "),
+                            )}
+                        </p>
+                    }
+                })}
+        </div>
+    }
+}
@@ -6,7 +6,7 @@ use {
 #[server(LaunchRokuApp)]
 pub async fn launch_roku_app(app_id: String) -> Result<(), ServerFnError> {
     use crate::integrations::roku::roku_launch_app;
-    roku_launch_app("10.0.0.217", &app_id).await;
+    roku_launch_app("10.0.0.217", &app_id).await?;
     Ok(())
 }
 
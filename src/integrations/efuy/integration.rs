@@ -0,0 +1,80 @@
+use {
+    super::{eufy_login, get_devices},
+    crate::integrations::iron_nest::{
+        get_auth_from_db, insert_auth,
+        supervisor::Integration,
+        types::{AuthState, Device, capability::Capability},
+    },
+    futures::{FutureExt, future::BoxFuture},
+    sqlx::PgPool,
+    std::time::Duration,
+};
+
+/// Eufy security devices. Only logs in and lists them so far, without saving any.
+pub struct Eufy;
+
+impl Integration for Eufy {
+    fn name(&self) -> &'static str {
+        "eufy"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+
+    fn discovery_interval(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    fn discover<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>> {
+        async {
+            let eufy_auth = get_auth_from_db(pool, "eufy").await;
+            if eufy_auth.auth_token.is_empty() {
+                return Err("Not logged in to Eufy".to_owned());
+            }
+            get_devices(eufy_auth.auth_token).await;
+            Ok(Vec::new())
+        }
+        .boxed()
+    }
+
+    fn auth_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(5 * 60 * 60))
+    }
+
+    /// Logs in if there's no token yet. Eufy tokens can't be refreshed so far.
+    fn refresh_auth<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async {
+            if get_auth_from_db(pool, "eufy")
+                .await
+                .refresh_token
+                .is_empty()
+            {
+                let res = eufy_login().await;
+                insert_auth(
+                    pool,
+                    "eufy",
+                    AuthState {
+                        refresh_token: res.data.auth_token.to_owned(),
+                        hardware_id: res.data.user_id,
+                        auth_token: res.data.auth_token,
+                    },
+                )
+                .await;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn health<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async {
+            if get_auth_from_db(pool, "eufy").await.auth_token.is_empty() {
+                Err("Not logged in to Eufy".to_owned())
+            } else {
+                Ok(())
+            }
+        }
+        .boxed()
+    }
+}
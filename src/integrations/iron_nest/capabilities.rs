@@ -0,0 +1,215 @@
+//! Controls devices through their capabilities, handing each [`DeviceCommand`] to the
+//! [`Integration`] that owns the device.
+//!
+//! [`Integration`]: super::supervisor::Integration
+
+use {
+    super::{
+        functions::{FunctionError, FunctionRegistry},
+        state_history::{current_source, set_power_states_query},
+        supervisor::supervisor,
+        types::{Device, DeviceReference, FunctionInfo, capability::DeviceCommand},
+    },
+    serde::Deserialize,
+    serde_json::{Value, json},
+    sqlx::PgPool,
+};
+
+fn failed(e: impl ToString) -> FunctionError {
+    FunctionError::Failed(e.to_string())
+}
+
+async fn get_device_query(pool: &PgPool, device_id: i64) -> Result<Option<Device>, sqlx::Error> {
+    let query = "
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+        FROM device
+        WHERE id = $1
+    ";
+    sqlx::query_as(query)
+        .bind(device_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// The device `reference` refers to, failing if there's none or, by name, more than one.
+pub async fn find_device_query(
+    pool: &PgPool,
+    reference: &DeviceReference,
+) -> Result<Device, FunctionError> {
+    let devices = match reference {
+        DeviceReference::Id(id) => get_device_query(pool, *id)
+            .await
+            .map_err(failed)?
+            .into_iter()
+            .collect(),
+        DeviceReference::Name(name) => {
+            let query = "
+                SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+                FROM device
+                WHERE name = $1
+            ";
+            sqlx::query_as::<_, Device>(query)
+                .bind(name)
+                .fetch_all(pool)
+                .await
+                .map_err(failed)?
+        }
+    };
+    let mut devices = devices.into_iter();
+    match (devices.next(), devices.next()) {
+        (Some(device), None) => Ok(device),
+        (None, _) => Err(failed(format!("No {reference}"))),
+        (Some(_), Some(_)) => Err(failed(format!(
+            "More than one device matches {reference}, refer to it by id"
+        ))),
+    }
+}
+
+/// Carries out `command` on the device `device_id` if it has the capability, keeping its
+/// `power_state` up to date.
+pub async fn control_device(
+    pool: &PgPool,
+    device_id: i64,
+    command: DeviceCommand,
+) -> Result<(), FunctionError> {
+    let device = get_device_query(pool, device_id)
+        .await
+        .map_err(failed)?
+        .ok_or_else(|| failed(format!("Device {device_id} not found")))?;
+    let capability = command.capability();
+    if !device.capabilities.contains(&capability) {
+        return Err(failed(format!(
+            "{} has no {capability} capability",
+            device.name
+        )));
+    }
+    let integration = device.device_type.integration();
+    supervisor()
+        .integration(integration)
+        .ok_or_else(|| {
+            failed(format!(
+                "The {integration} integration can't control devices"
+            ))
+        })?
+        .handle_command(&device, &command)
+        .await
+        .map_err(|e| failed(format!("{}: {e}", device.name)))?;
+
+    // Brightness and colors turn lights on too
+    let power_state = match command {
+        DeviceCommand::OnOff { on } => Some(on),
+        DeviceCommand::Brightness { .. }
+        | DeviceCommand::ColorHsv { .. }
+        | DeviceCommand::ColorTemp { .. } => Some(true),
+        DeviceCommand::MediaRemote { .. } => None,
+    };
+    if let Some(on) = power_state {
+        set_power_states_query(pool, &[(device.id, on)], current_source())
+            .await
+            .map_err(failed)?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ControlArgs {
+    device_id: i64,
+    #[serde(flatten)]
+    command: DeviceCommand,
+}
+
+fn function_info(
+    name: &str,
+    description: &str,
+    properties: Value,
+    required: &[&str],
+) -> FunctionInfo {
+    let mut parameters = json!({
+        "type": "object",
+        "properties": { "device_id": { "type": "integer", "description": "Id of the device" } },
+        "required": ["device_id"],
+    });
+    for (key, value) in properties.as_object().into_iter().flatten() {
+        parameters["properties"][key] = value.clone();
+    }
+    for key in required {
+        parameters["required"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!(key));
+    }
+    FunctionInfo {
+        name: name.to_owned(),
+        integration: "iron_nest".to_owned(),
+        description: description.to_owned(),
+        parameters,
+        ip_device_types: vec![],
+    }
+}
+
+/// `capability` is the [`DeviceCommand`] tag the function's arguments are read as.
+fn register_command(registry: &mut FunctionRegistry, capability: &'static str, info: FunctionInfo) {
+    registry.register(info, move |pool, mut args: Value| async move {
+        args["capability"] = json!(capability);
+        let args = serde_json::from_value::<ControlArgs>(args).map_err(failed)?;
+        control_device(&pool, args.device_id, args.command).await?;
+        Ok(json!({ "device_id": args.device_id }))
+    });
+}
+
+pub fn register_functions(registry: &mut FunctionRegistry) {
+    register_command(
+        registry,
+        "on_off",
+        function_info(
+            "device_set_on_off",
+            "Turn any device that can be switched on or off",
+            json!({ "on": { "type": "boolean" } }),
+            &["on"],
+        ),
+    );
+    register_command(
+        registry,
+        "brightness",
+        function_info(
+            "device_set_brightness",
+            "Set the brightness of a dimmable light or dimmer, turning it on",
+            json!({ "brightness": { "type": "integer", "minimum": 0, "maximum": 100 } }),
+            &["brightness"],
+        ),
+    );
+    register_command(
+        registry,
+        "color_hsv",
+        function_info(
+            "device_set_color",
+            "Set the color of a color light, turning it on",
+            json!({
+                "hue": { "type": "integer", "minimum": 0, "maximum": 360 },
+                "saturation": { "type": "integer", "minimum": 0, "maximum": 100 },
+                "brightness": { "type": "integer", "minimum": 0, "maximum": 100 },
+            }),
+            &["hue", "saturation", "brightness"],
+        ),
+    );
+    register_command(
+        registry,
+        "color_temp",
+        function_info(
+            "device_set_color_temp",
+            "Set the white temperature of a light in kelvin, turning it on",
+            json!({ "kelvin": { "type": "integer", "minimum": 1000, "maximum": 10000 } }),
+            &["kelvin"],
+        ),
+    );
+    register_command(
+        registry,
+        "media_remote",
+        function_info(
+            "device_send_remote_key",
+            "Press a remote control key on a TV, e.g. Home, Select, VolumeUp or PowerOff",
+            json!({ "key": { "type": "string" } }),
+            &["key"],
+        ),
+    );
+}
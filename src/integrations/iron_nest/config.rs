@@ -0,0 +1,645 @@
+//! Exports and imports the whole configuration as a [`ConfigExport`], and diffs the versions
+//! kept in `config_history`. Only the `config` row is versioned: rolling back restores actions,
+//! location, vacation mode and energy settings, not integrations, scenes or mish states.
+
+use {
+    super::{
+        cron::ActionSchedule,
+        energy,
+        functions::{FunctionError, function_registry},
+        mish::{
+            MishStateModification,
+            schema::{get_mish_state_schema_cid, get_schema, validate as validate_state},
+        },
+        sequence::validate_sequence,
+        types::config::{
+            Config, ConfigExport, ConfigFormat, ConfigProblem, ConfigVersion, DiffLine,
+            IntegrationSetting, MishStateExport,
+        },
+        vacation,
+    },
+    crate::{
+        components::pages::configs_page::{get_config_query, set_config_query},
+        server::{
+            actions::get_actions_query,
+            integrations_page::{apply_integration_enabled, update_integration_enabled_query},
+            scenes::{get_scenes_query, save_scene_query},
+        },
+    },
+    chrono::{DateTime, Utc},
+    cid::Cid,
+    serde_json::{Value, json},
+    std::{collections::HashSet, str::FromStr},
+    tokio::sync::broadcast,
+};
+
+pub async fn export_config_query(pool: &sqlx::PgPool) -> Result<ConfigExport, sqlx::Error> {
+    let Config {
+        actions,
+        location,
+        vacation,
+        energy,
+    } = match get_config_query(pool).await? {
+        Some(config) => config,
+        None => Config {
+            actions: get_actions_query(pool).await?,
+            location: None,
+            vacation: None,
+            energy: None,
+        },
+    };
+    let query = "
+        SELECT name, COALESCE(enabled, FALSE)
+        FROM integration
+        ORDER BY id
+    ";
+    let integrations = sqlx::query_as::<_, (String, bool)>(query)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(name, enabled)| IntegrationSetting { name, enabled })
+        .collect();
+    let query = "
+        SELECT name, state, schema_cid
+        FROM mish_states
+        ORDER BY name
+    ";
+    let mish_states = sqlx::query_as::<_, (String, Value, Option<Vec<u8>>)>(query)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(name, state, schema_cid)| MishStateExport {
+            name,
+            state,
+            schema_cid: schema_cid
+                .and_then(|cid| Cid::try_from(cid).ok())
+                .map(|cid| cid.to_string()),
+        })
+        .collect();
+    Ok(ConfigExport {
+        actions,
+        location,
+        vacation,
+        energy,
+        integrations,
+        scenes: get_scenes_query(pool).await?,
+        mish_states,
+    })
+}
+
+pub fn render(export: &ConfigExport, format: ConfigFormat) -> Result<String, String> {
+    match format {
+        ConfigFormat::Yaml => serde_yaml::to_string(export).map_err(|e| e.to_string()),
+        ConfigFormat::Json => serde_json::to_string_pretty(export).map_err(|e| e.to_string()),
+    }
+}
+
+/// Reads a [`ConfigExport`] from YAML or, if it starts with `{`, JSON.
+pub fn parse(source: &str) -> Result<ConfigExport, ConfigProblem> {
+    if source.trim_start().starts_with('{') {
+        serde_json::from_str(source)
+            .map_err(|e| positioned_problem(e.to_string(), Some((e.line(), e.column()))))
+    } else {
+        serde_yaml::from_str(source).map_err(|e| {
+            let position = e
+                .location()
+                .map(|location| (location.line(), location.column()));
+            positioned_problem(e.to_string(), position)
+        })
+    }
+}
+
+/// Moves the position both parsers append to their messages into the problem's fields.
+fn positioned_problem(message: String, position: Option<(usize, usize)>) -> ConfigProblem {
+    let message = match position {
+        Some((line, column)) => message
+            .strip_suffix(&format!(" at line {line} column {column}"))
+            .map(str::to_owned)
+            .unwrap_or(message),
+        None => message,
+    };
+    ConfigProblem {
+        line: position.map(|(line, _)| line),
+        column: position.map(|(_, column)| column),
+        path: String::new(),
+        message,
+    }
+}
+
+/// Finds the line an item of a list starts on, the first one with both `key` and `value`, e.g.
+/// `id: 7c6a…` or `"name": "Movie night"`, then the line of `field` from there. The parsers
+/// don't keep where values came from, so this is as close as we get to the problem.
+fn locate(source: &str, key: &str, value: &str, field: Option<&str>) -> Option<usize> {
+    if value.is_empty() {
+        return None;
+    }
+    let lines = source.lines().collect::<Vec<_>>();
+    let start = lines
+        .iter()
+        .position(|line| line.contains(key) && line.contains(value))?;
+    let offset = field
+        .and_then(|field| {
+            lines[start..].iter().position(|line| {
+                line.trim_start_matches([' ', '-', '"'])
+                    .strip_prefix(field)
+                    .is_some_and(|rest| rest.starts_with([':', '"']))
+            })
+        })
+        .unwrap_or(0);
+    Some(start + offset + 1)
+}
+
+struct Problems<'a> {
+    source: &'a str,
+    problems: Vec<ConfigProblem>,
+}
+
+impl Problems<'_> {
+    /// `anchor` is the key and value identifying the list item the problem is in.
+    fn add(
+        &mut self,
+        path: String,
+        anchor: (&str, &str),
+        field: Option<&str>,
+        message: impl ToString,
+    ) {
+        self.problems.push(ConfigProblem {
+            line: locate(self.source, anchor.0, anchor.1, field),
+            column: None,
+            path,
+            message: message.to_string(),
+        });
+    }
+}
+
+/// Finds what would make an import of `export`, parsed from `source`, fail or leave things
+/// broken, e.g. an action calling a function that doesn't exist.
+pub async fn validate(
+    pool: &sqlx::PgPool,
+    source: &str,
+    export: &ConfigExport,
+) -> Result<Vec<ConfigProblem>, sqlx::Error> {
+    let mut problems = Problems {
+        source,
+        problems: Vec::new(),
+    };
+
+    if let Some(location) = export.location
+        && (!(-90.0..=90.0).contains(&location.latitude)
+            || !(-180.0..=180.0).contains(&location.longitude))
+    {
+        problems.problems.push(ConfigProblem {
+            line: source
+                .lines()
+                .position(|line| line.contains("location"))
+                .map(|line| line + 1),
+            column: None,
+            path: "location".to_owned(),
+            message: "Latitude must be within ±90 and longitude within ±180 degrees".to_owned(),
+        });
+    }
+
+    let mut action_ids = HashSet::new();
+    for (index, action) in export.actions.iter().enumerate() {
+        let id = action.id.to_string();
+        let anchor = ("id", id.as_str());
+        let path = |field: &str| format!("actions[{index}].{field}");
+        let fields = &action.fields;
+        if !action_ids.insert(action.id) {
+            problems.add(path("id"), anchor, None, "Another action has this id");
+        }
+        if let Err(e) = ActionSchedule::parse(
+            &fields.cron,
+            fields.solar.clone(),
+            fields.timezone.as_deref(),
+            export.location,
+        ) {
+            let field = if e.starts_with("Invalid timezone") {
+                "timezone"
+            } else if fields.solar.is_some() {
+                "solar"
+            } else {
+                "cron"
+            };
+            problems.add(path(field), anchor, Some(field), e);
+        }
+        match &fields.sequence {
+            Some(sequence) => {
+                if let Err(e) = validate_sequence(sequence) {
+                    problems.add(path("sequence"), anchor, Some("sequence"), e);
+                }
+            }
+            None => {
+                match function_registry().validate(&fields.function_name, &fields.function_args) {
+                    Err(e @ FunctionError::Unknown(_)) => {
+                        problems.add(path("function_name"), anchor, Some("function_name"), e)
+                    }
+                    Err(e) => problems.add(path("function_args"), anchor, Some("function_args"), e),
+                    Ok(()) => {}
+                }
+            }
+        }
+        if let Some(hook) = &fields.on_failure_function_name {
+            let args = fields
+                .on_failure_function_args
+                .clone()
+                .unwrap_or_else(|| json!({}));
+            if let Err(e) = function_registry().validate(hook, &args) {
+                let field = "on_failure_function_name";
+                problems.add(path(field), anchor, Some(field), e);
+            }
+        }
+        if fields.retry_count < 0 || fields.retry_backoff_ms < 0 {
+            let field = "retry_count";
+            let message = "Retries and their backoff can't be negative";
+            problems.add(path(field), anchor, Some(field), message);
+        }
+    }
+
+    let query = "
+        SELECT name
+        FROM integration
+    ";
+    let integrations = sqlx::query_scalar::<_, String>(query)
+        .fetch_all(pool)
+        .await?;
+    for (index, integration) in export.integrations.iter().enumerate() {
+        if !integrations.contains(&integration.name) {
+            problems.add(
+                format!("integrations[{index}].name"),
+                ("name", &integration.name),
+                None,
+                format!(
+                    "Unknown integration {}, expected one of {}",
+                    integration.name,
+                    integrations.join(", ")
+                ),
+            );
+        }
+    }
+
+    let query = "
+        SELECT id
+        FROM device
+    ";
+    let device_ids = sqlx::query_scalar::<_, i64>(query)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    if let Some(mode) = &export.vacation {
+        for (field, message) in vacation::problems(mode, &device_ids) {
+            problems.add(
+                format!("vacation.{field}"),
+                ("vacation", ":"),
+                Some(field),
+                message,
+            );
+        }
+    }
+    if let Some(settings) = &export.energy {
+        for (path, message) in energy::problems(settings, &device_ids) {
+            let field = path.rsplit('.').next();
+            problems.add(format!("energy.{path}"), ("energy", ":"), field, message);
+        }
+    }
+
+    let mut scene_names = HashSet::new();
+    for (index, scene) in export.scenes.iter().enumerate() {
+        let anchor = ("name", scene.name.as_str());
+        let path = |field: &str| format!("scenes[{index}].{field}");
+        if scene.name.trim().is_empty() {
+            problems.add(path("name"), anchor, None, "Name the scene");
+        } else if !scene_names.insert(&scene.name) {
+            problems.add(path("name"), anchor, None, "Another scene has this name");
+        }
+        if scene.concurrency < 1 {
+            let message = "Concurrency must be at least 1";
+            problems.add(path("concurrency"), anchor, Some("concurrency"), message);
+        }
+        if scene.devices.is_empty() {
+            let message = "A scene needs at least one device";
+            problems.add(path("devices"), anchor, Some("devices"), message);
+        }
+        for (device_index, device) in scene.devices.iter().enumerate() {
+            if !device_ids.contains(&device.device_id) {
+                problems.add(
+                    path(&format!("devices[{device_index}].device_id")),
+                    anchor,
+                    Some("devices"),
+                    format!("No device {} ({})", device.device_id, device.name),
+                );
+            }
+        }
+    }
+
+    let mut mish_state_names = HashSet::new();
+    for (index, mish_state) in export.mish_states.iter().enumerate() {
+        let name = mish_state.name.as_str();
+        let anchor = ("name", name);
+        let path = |field: &str| format!("mish_states[{index}].{field}");
+        if !mish_state_names.insert(name) {
+            problems.add(
+                path("name"),
+                anchor,
+                None,
+                "Another mish state has this name",
+            );
+            continue;
+        }
+        let schema_cid = match &mish_state.schema_cid {
+            Some(schema_cid) => match Cid::from_str(schema_cid) {
+                Ok(schema_cid) => Some(schema_cid),
+                Err(e) => {
+                    problems.add(path("schema_cid"), anchor, Some("schema_cid"), e);
+                    continue;
+                }
+            },
+            None => match get_mish_state_schema_cid(pool, name).await {
+                Ok(schema_cid) => schema_cid,
+                Err(e) => {
+                    problems.add(path("schema_cid"), anchor, None, e);
+                    continue;
+                }
+            },
+        };
+        let Some(schema_cid) = schema_cid else {
+            continue;
+        };
+        match get_schema(pool, &schema_cid).await {
+            Ok(schema) => {
+                if let Err(e) = validate_state(name, &schema_cid, &schema, &mish_state.state) {
+                    problems.add(path("state"), anchor, Some("state"), e);
+                }
+            }
+            Err(e) => problems.add(path("schema_cid"), anchor, Some("schema_cid"), e),
+        }
+    }
+
+    Ok(problems.problems)
+}
+
+/// Replaces the config and the integration switches with the ones in `export`, and creates or
+/// replaces its scenes and mish states, keeping those it doesn't mention. All of it is written in
+/// one transaction, so a failure leaves the configuration as it was. Check `export` with
+/// [`validate`] first.
+pub async fn import_config_query(
+    pool: &sqlx::PgPool,
+    export: ConfigExport,
+    mish_state_modification_bus_sender: &broadcast::Sender<MishStateModification>,
+) -> Result<(), String> {
+    let ConfigExport {
+        actions,
+        location,
+        vacation,
+        energy,
+        integrations,
+        scenes,
+        mish_states,
+    } = export;
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| format!("Failed to start the import: {e}"))?;
+    set_config_query(
+        &mut *tx,
+        Config {
+            actions,
+            location,
+            vacation,
+            energy,
+        },
+    )
+    .await
+    .map_err(|e| format!("Failed to save the config: {e}"))?;
+    let mut switched = Vec::new();
+    for integration in integrations {
+        let changed =
+            update_integration_enabled_query(&mut *tx, &integration.name, integration.enabled)
+                .await
+                .map_err(|e| format!("Failed to update integration {}: {e}", integration.name))?;
+        if changed {
+            switched.push(integration);
+        }
+    }
+    for scene in scenes {
+        save_scene_query(&mut *tx, &scene)
+            .await
+            .map_err(|e| format!("Failed to save scene {}: {e}", scene.name))?;
+    }
+    let mut modifications = Vec::new();
+    for MishStateExport {
+        name,
+        state,
+        schema_cid,
+    } in mish_states
+    {
+        let schema_cid = schema_cid
+            .map(|schema_cid| Cid::from_str(&schema_cid))
+            .transpose()
+            .map_err(|e| format!("Invalid schema CID of mish state {name}: {e}"))?;
+        // `validate` checked the state against its schema, old or new
+        let query = "
+            INSERT INTO mish_states (name, state, schema_cid)
+            VALUES ($1, $2::jsonb, $3)
+            ON CONFLICT (name) DO UPDATE SET
+                state = EXCLUDED.state,
+                schema_cid = COALESCE(EXCLUDED.schema_cid, mish_states.schema_cid)
+        ";
+        sqlx::query(query)
+            .bind(&name)
+            .bind(&state)
+            .bind(schema_cid.map(|cid| cid.to_bytes()))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Failed to save mish state {name}: {e}"))?;
+        modifications.push(MishStateModification::CreateOrUpdate { name, state });
+    }
+    tx.commit()
+        .await
+        .map_err(|e| format!("Failed to save the import: {e}"))?;
+
+    for integration in switched {
+        apply_integration_enabled(&integration.name, integration.enabled).await;
+    }
+    for modification in modifications {
+        // Nobody listening is fine
+        let _ = mish_state_modification_bus_sender.send(modification);
+    }
+    Ok(())
+}
+
+/// The latest `limit` versions of the config, newest first, each diffed against the one before.
+pub async fn get_config_history_query(
+    pool: &sqlx::PgPool,
+    limit: i64,
+) -> Result<Vec<ConfigVersion>, sqlx::Error> {
+    let query = "
+        SELECT id, created_at, data
+        FROM config_history
+        ORDER BY id DESC
+        LIMIT $1
+    ";
+    let versions = sqlx::query_as::<_, (i64, DateTime<Utc>, Value)>(query)
+        .bind(limit + 1)
+        .fetch_all(pool)
+        .await?;
+    let yaml = |data: &Value| serde_yaml::to_string(data).unwrap_or_default();
+    Ok(versions
+        .iter()
+        .enumerate()
+        .take(limit as usize)
+        .map(|(index, (id, created_at, data))| {
+            let previous = versions
+                .get(index + 1)
+                .map(|(_, _, data)| yaml(data))
+                .unwrap_or_default();
+            ConfigVersion {
+                id: *id,
+                created_at: *created_at,
+                diff: diff_lines(&previous, &yaml(data), 3),
+            }
+        })
+        .collect())
+}
+
+pub async fn get_config_version_query(
+    pool: &sqlx::PgPool,
+    id: i64,
+) -> Result<Option<Value>, sqlx::Error> {
+    let query = "
+        SELECT data
+        FROM config_history
+        WHERE id = $1
+    ";
+    sqlx::query_scalar(query)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Line diff from `old` to `new`, keeping `context` unchanged lines around each change.
+pub fn diff_lines(old: &str, new: &str, context: usize) -> Vec<DiffLine> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    // Only the part between the common prefix and suffix needs the quadratic table
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+
+    // Length of the longest common subsequence of old_changed[i..] and new_changed[j..]
+    let mut common = vec![vec![0u32; new_changed.len() + 1]; old_changed.len() + 1];
+    for i in (0..old_changed.len()).rev() {
+        for j in (0..new_changed.len()).rev() {
+            common[i][j] = if old_changed[i] == new_changed[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let unchanged = |line: &&str| DiffLine::Unchanged((*line).to_owned());
+    let mut lines = old[..prefix].iter().map(unchanged).collect::<Vec<_>>();
+    let (mut i, mut j) = (0, 0);
+    while i < old_changed.len() || j < new_changed.len() {
+        if i < old_changed.len() && j < new_changed.len() && old_changed[i] == new_changed[j] {
+            lines.push(unchanged(&old_changed[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_changed.len()
+            && (j == new_changed.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(DiffLine::Removed(old_changed[i].to_owned()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new_changed[j].to_owned()));
+            j += 1;
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(unchanged));
+
+    let changed = lines
+        .iter()
+        .map(|line| !matches!(line, DiffLine::Unchanged(_)))
+        .collect::<Vec<_>>();
+    let mut collapsed = Vec::new();
+    for (index, line) in lines.into_iter().enumerate() {
+        let near_change = changed
+            [index.saturating_sub(context)..(index + context + 1).min(changed.len())]
+            .contains(&true);
+        if near_change {
+            collapsed.push(line);
+        } else if let Some(DiffLine::Skipped(count)) = collapsed.last_mut() {
+            *count += 1;
+        } else {
+            collapsed.push(DiffLine::Skipped(1));
+        }
+    }
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh";
+        let new = "a\nb\nc\nD\ne\nf\ng\nh\ni";
+        assert_eq!(
+            diff_lines(old, new, 1),
+            vec![
+                DiffLine::Skipped(2),
+                DiffLine::Unchanged("c".to_owned()),
+                DiffLine::Removed("d".to_owned()),
+                DiffLine::Added("D".to_owned()),
+                DiffLine::Unchanged("e".to_owned()),
+                DiffLine::Skipped(2),
+                DiffLine::Unchanged("h".to_owned()),
+                DiffLine::Added("i".to_owned()),
+            ]
+        );
+        assert_eq!(diff_lines(old, old, 3), vec![DiffLine::Skipped(8)]);
+        assert_eq!(
+            diff_lines("", "a", 3),
+            vec![DiffLine::Added("a".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_parse_positions() {
+        let yaml = "actions: []\nlocation: north\n";
+        let problem = parse(yaml).unwrap_err();
+        assert_eq!(problem.line, Some(2));
+        assert!(!problem.message.contains(" at line "), "{problem}");
+
+        let json = "{\n  \"actions\": [],\n  \"location\": 3\n}";
+        let problem = parse(json).unwrap_err();
+        assert_eq!(problem.line, Some(3));
+        assert!(!problem.message.contains(" at line "), "{problem}");
+    }
+
+    #[test]
+    fn test_locate() {
+        let source = "\
+actions:
+- id: 6f1c
+  name: Lights
+  function_name: tplink_turn_plug_on
+- id: 9a2b
+  name: Fan
+  function_args: {}
+  function_name: nope
+";
+        assert_eq!(locate(source, "id", "9a2b", Some("function_name")), Some(8));
+        assert_eq!(locate(source, "id", "9a2b", None), Some(5));
+        assert_eq!(locate(source, "id", "", None), None);
+    }
+}
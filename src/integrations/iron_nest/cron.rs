@@ -1,18 +1,113 @@
 use {
-    crate::{integrations::iron_nest::execute_function, server::actions::get_actions_query},
+    crate::{
+        components::pages::configs_page::get_location_query,
+        integrations::iron_nest::{
+            execute_function,
+            functions::{FunctionError, FunctionResult},
+            sequence::{StepRunner, run_sequence},
+            state_history::with_source,
+            types::{
+                ActionRun, FullAction, RequiredAction, SequenceCondition, SolarSchedule,
+                config::Location, state_history::StateSource,
+            },
+        },
+        server::actions::{
+            get_actions_query, get_last_scheduled_run_query, insert_action_run_query,
+        },
+    },
+    chrono::{DateTime, Utc},
+    chrono_tz::Tz,
     core::fmt,
+    futures::{FutureExt, future::BoxFuture},
+    serde_json::Value,
     sqlx::PgPool,
     std::{
         fmt::{Debug, Formatter},
-        sync::Arc,
+        str::FromStr,
+        sync::{
+            Arc,
+            atomic::{AtomicI32, Ordering},
+        },
+        time::{Duration, Instant},
     },
-    tokio::sync::RwLock,
-    tokio_cron_scheduler::{Job, JobScheduler},
+    tokio::{sync::Mutex, task::JoinHandle},
+    uuid::Uuid,
 };
 
-#[derive(Clone)]
+enum Schedule {
+    Cron(cron::Schedule),
+    Solar(SolarSchedule, Location),
+}
+
+/// When an action runs, read in its timezone so schedules keep their wall clock time across DST
+/// changes.
+pub struct ActionSchedule {
+    schedule: Schedule,
+    timezone: Tz,
+}
+
+impl ActionSchedule {
+    /// `timezone` is an IANA name like `America/New_York`, UTC if not given. `cron` is only
+    /// used without `solar`, which needs the location from the config.
+    pub fn parse(
+        cron: &str,
+        solar: Option<SolarSchedule>,
+        timezone: Option<&str>,
+        location: Option<Location>,
+    ) -> Result<Self, String> {
+        let schedule = match solar {
+            Some(solar) => Schedule::Solar(
+                solar,
+                location.ok_or("Solar schedules need a location in the config")?,
+            ),
+            None => Schedule::Cron(
+                cron::Schedule::from_str(cron)
+                    .map_err(|e| format!("Invalid cron expression {cron:?}: {e}"))?,
+            ),
+        };
+        let timezone = match timezone.filter(|timezone| !timezone.is_empty()) {
+            Some(timezone) => {
+                Tz::from_str(timezone).map_err(|e| format!("Invalid timezone {timezone:?}: {e}"))?
+            }
+            None => Tz::UTC,
+        };
+        Ok(Self { schedule, timezone })
+    }
+
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Tz>> {
+        match &self.schedule {
+            Schedule::Cron(schedule) => schedule.after(&time.with_timezone(&self.timezone)).next(),
+            Schedule::Solar(solar, location) => {
+                let date = location.solar_date(time);
+                // Starting a day back as offsets can push a run past midnight, and giving up
+                // after a year of polar days
+                (-1..=366)
+                    .find_map(|days| {
+                        solar
+                            .time_on(
+                                date + chrono::Duration::days(days),
+                                location,
+                                &self.timezone,
+                            )
+                            .filter(|run| *run > time)
+                    })
+                    .map(|run| run.with_timezone(&self.timezone))
+            }
+        }
+    }
+
+    pub fn next_runs(&self, n: usize) -> Vec<DateTime<Tz>> {
+        std::iter::successors(self.next_after(Utc::now()), |run| {
+            self.next_after(run.with_timezone(&Utc))
+        })
+        .take(n)
+        .collect()
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct CronClient {
-    job_scheduler: Arc<RwLock<JobScheduler>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
 }
 
 impl Debug for CronClient {
@@ -22,38 +117,374 @@ impl Debug for CronClient {
 }
 
 impl CronClient {
-    pub async fn new() -> Self {
-        Self {
-            job_scheduler: Arc::new(RwLock::new(JobScheduler::new().await.unwrap())),
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the scheduled actions with the ones in the config. Actions with an invalid
+    /// schedule, e.g. a solar one after the location was removed, are logged and left out.
+    pub async fn schedule_tasks(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let schedules = load_action_schedules(&mut *pool.acquire().await?).await?;
+        self.replace_tasks(pool, schedules).await;
+        Ok(())
+    }
+
+    /// Runs the actions in `schedules` instead of the ones scheduled before.
+    pub async fn replace_tasks(&self, pool: &PgPool, schedules: ActionSchedules) {
+        for problem in &schedules.problems {
+            log::error!("Not scheduling action {problem}");
+        }
+        let mut tasks = self.tasks.lock().await;
+        for task in tasks.drain(..) {
+            task.abort();
+        }
+        for (action, schedule) in schedules.scheduled {
+            println!("scheduling action: {}", action.fields.cron);
+            let pool = pool.clone();
+            tasks.push(tokio::spawn(async move {
+                while let Some(next) = schedule.next_after(Utc::now()) {
+                    let next = next.with_timezone(&Utc);
+                    tokio::time::sleep((next - Utc::now()).to_std().unwrap_or_default()).await;
+                    // Runs can outlast the time to the next one while retrying
+                    let pool = pool.clone();
+                    let action = action.clone();
+                    tokio::spawn(async move {
+                        // Failures are logged and recorded by execute_action
+                        let _ = execute_action(&pool, &action, Some(next)).await;
+                    });
+                }
+            }));
         }
     }
+}
 
-    pub async fn schedule_tasks(&self, pool: &PgPool) -> Result<(), anyhow::Error> {
-        let actions = get_actions_query(pool).await?;
+/// The actions in the config with their schedules, see [`load_action_schedules`].
+pub struct ActionSchedules {
+    scheduled: Vec<(Arc<FullAction>, ActionSchedule)>,
+    /// Why each action left out can't be scheduled
+    pub problems: Vec<String>,
+}
 
-        let mut job_scheduler = self.job_scheduler.write().await;
-        job_scheduler.shutdown().await?;
+/// Reads the actions and the location from the config through `conn`, which can be a
+/// transaction that changed them and isn't committed yet.
+pub async fn load_action_schedules(
+    conn: &mut sqlx::PgConnection,
+) -> Result<ActionSchedules, sqlx::Error> {
+    let location = get_location_query(&mut *conn).await?;
+    let mut schedules = ActionSchedules {
+        scheduled: Vec::new(),
+        problems: Vec::new(),
+    };
+    for action in get_actions_query(&mut *conn).await? {
+        match action_schedule(&action, location) {
+            Ok(schedule) => schedules.scheduled.push((Arc::new(action), schedule)),
+            Err(problem) => schedules.problems.push(problem),
+        }
+    }
+    Ok(schedules)
+}
 
-        *job_scheduler = JobScheduler::new().await?;
+fn action_schedule(
+    action: &FullAction,
+    location: Option<Location>,
+) -> Result<ActionSchedule, String> {
+    let fields = &action.fields;
+    ActionSchedule::parse(
+        &fields.cron,
+        fields.solar.clone(),
+        fields.timezone.as_deref(),
+        location,
+    )
+    .map_err(|e| format!("{}: {e}", fields.name))
+}
 
-        for action in actions {
-            println!("scheduling action: {}", action.fields.cron);
-            job_scheduler
-                .add(Job::new_async(
-                    action.fields.cron.as_ref(),
-                    move |_uuid, mut _l| {
-                        let function_name = action.fields.function_name.clone();
-                        let function_args = action.fields.function_args.clone();
-                        Box::pin(async move {
-                            println!("Calling {function_name}({function_args})");
-                            execute_function(function_name, function_args).await;
-                        })
-                    },
-                )?)
-                .await?;
-        }
-
-        job_scheduler.start().await?;
-        Ok(())
+/// Runs actions with `catch_up` set once if they were due while the server was down, going by
+/// the last scheduled run recorded for them. Actions without recorded runs are left alone.
+pub async fn catch_up_missed_runs(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    let location = get_location_query(pool).await?;
+    for action in get_actions_query(pool).await? {
+        if !action.fields.catch_up {
+            continue;
+        }
+        let Some(last_scheduled_at) = get_last_scheduled_run_query(pool, action.id).await? else {
+            continue;
+        };
+        // Actions with an invalid schedule aren't scheduled either, see schedule_tasks
+        let Ok(schedule) = action_schedule(&action, location) else {
+            continue;
+        };
+        let Some(missed) = schedule
+            .next_after(last_scheduled_at)
+            .map(|missed| missed.with_timezone(&Utc))
+            .filter(|missed| *missed < now)
+        else {
+            continue;
+        };
+        log::info!(
+            "Catching up on action {} missed at {missed}",
+            action.fields.name
+        );
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let _ = execute_action(&pool, &action, Some(missed)).await;
+        });
+    }
+    Ok(())
+}
+
+/// Longest wait between attempts, however many times an action is retried
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// How long to wait after failed attempt number `attempt`, `backoff_ms` doubled for each
+/// attempt before it.
+fn retry_backoff(backoff_ms: i64, attempt: i32) -> Duration {
+    let doublings = u32::try_from(attempt - 1).unwrap_or(0);
+    let backoff_ms = u64::try_from(backoff_ms)
+        .unwrap_or(0)
+        .saturating_mul(2_u64.saturating_pow(doublings));
+    Duration::from_millis(backoff_ms).min(MAX_RETRY_BACKOFF)
+}
+
+/// Runs `function` in its own task so a function that panics, e.g. on a device that doesn't
+/// answer, fails like any other instead of taking the run down with it.
+async fn attempt<F>(function: F) -> FunctionResult
+where
+    F: Future<Output = FunctionResult> + Send + 'static,
+{
+    tokio::spawn(function)
+        .await
+        .unwrap_or_else(|e| Err(FunctionError::Failed(e.to_string())))
+}
+
+/// Calls `function` until it succeeds, fails in a way retrying won't fix or has been retried
+/// `retry_count` times. Returns its last result and how many times it was called.
+async fn retry<F, Fut>(
+    name: &str,
+    retry_count: i32,
+    backoff_ms: i64,
+    mut function: F,
+) -> (FunctionResult, i32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = FunctionResult> + Send + 'static,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match attempt(function()).await {
+            // Unknown functions and invalid arguments won't fix themselves
+            Err(FunctionError::Failed(e)) if attempts <= retry_count => {
+                let backoff = retry_backoff(backoff_ms, attempts);
+                log::warn!(
+                    "Action {name} failed, retrying in {}ms: {e}",
+                    backoff.as_millis()
+                );
+                tokio::time::sleep(backoff).await;
+            }
+            result => break (result, attempts),
+        }
+    }
+}
+
+/// Runs the steps of an action's sequence, retrying a failed function call on its own as the
+/// action says so steps that already worked, e.g. toggles, don't run again.
+struct RetryingSteps<'a> {
+    pool: &'a PgPool,
+    fields: &'a RequiredAction,
+    /// The most attempts any call needed
+    attempts: AtomicI32,
+}
+
+impl StepRunner for RetryingSteps<'_> {
+    fn call<'a>(
+        &'a self,
+        function_name: &'a str,
+        function_args: &'a Value,
+    ) -> BoxFuture<'a, FunctionResult> {
+        async move {
+            let name = format!("{} step {function_name}", self.fields.name);
+            let (result, attempts) = retry(
+                &name,
+                self.fields.retry_count,
+                self.fields.retry_backoff_ms,
+                || {
+                    let pool = self.pool.clone();
+                    let function_name = function_name.to_owned();
+                    let function_args = function_args.clone();
+                    with_source(StateSource::Automation, async move {
+                        execute_function(&pool, function_name, function_args).await
+                    })
+                },
+            )
+            .await;
+            self.attempts.fetch_max(attempts, Ordering::Relaxed);
+            result
+        }
+        .boxed()
+    }
+
+    fn check<'a>(
+        &'a self,
+        condition: &'a SequenceCondition,
+    ) -> BoxFuture<'a, Result<bool, FunctionError>> {
+        self.pool.check(condition)
+    }
+}
+
+/// Runs an action's function, retrying failures as the action says, and records the run. Once
+/// every attempt has failed the action's failure hook is called. Sequences aren't started over,
+/// only their failed steps are retried.
+pub async fn execute_action(
+    pool: &PgPool,
+    action: &FullAction,
+    scheduled_at: Option<DateTime<Utc>>,
+) -> FunctionResult {
+    let fields = &action.fields;
+    let started_at = Utc::now();
+    let start = Instant::now();
+    let (result, attempts) = match &fields.sequence {
+        Some(sequence) => {
+            let steps = RetryingSteps {
+                pool,
+                fields,
+                attempts: AtomicI32::new(1),
+            };
+            let result = run_sequence(&steps, sequence).await;
+            (result, steps.attempts.into_inner())
+        }
+        None => {
+            retry(
+                &fields.name,
+                fields.retry_count,
+                fields.retry_backoff_ms,
+                || {
+                    let pool = pool.clone();
+                    let function_name = fields.function_name.clone();
+                    let function_args = fields.function_args.clone();
+                    with_source(StateSource::Automation, async move {
+                        execute_function(&pool, function_name, function_args).await
+                    })
+                },
+            )
+            .await
+        }
+    };
+
+    let run = ActionRun {
+        id: Uuid::new_v4(),
+        action_id: action.id,
+        action_name: fields.name.clone(),
+        scheduled_at,
+        started_at,
+        duration_ms: start.elapsed().as_millis() as i64,
+        attempts,
+        result: result.as_ref().ok().cloned(),
+        error: result.as_ref().err().map(ToString::to_string),
+    };
+    if let Err(e) = insert_action_run_query(pool, &run).await {
+        log::error!("Failed to record run of action {}: {e}", fields.name);
+    }
+
+    if let Err(e) = &result {
+        log::error!(
+            "Action {} failed after {attempts} attempts: {e}",
+            fields.name
+        );
+        if let Some(hook) = &fields.on_failure_function_name {
+            let args = fields
+                .on_failure_function_args
+                .clone()
+                .unwrap_or_else(|| serde_json::json!({}));
+            let pool = pool.clone();
+            let hook_name = hook.clone();
+            let result = attempt(with_source(StateSource::Automation, async move {
+                execute_function(&pool, hook_name, args).await
+            }))
+            .await;
+            if let Err(e) = result {
+                log::error!("Failure hook {hook} of action {} failed: {e}", fields.name);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn offline() -> FunctionError {
+        FunctionError::Failed("offline".to_owned())
+    }
+
+    fn invalid() -> FunctionError {
+        FunctionError::InvalidArguments {
+            name: "test".to_owned(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Calls `retry` with no backoff on a function that fails its first `failures` calls with
+    /// `error`, or panics if there's none.
+    async fn retry_failing(
+        retry_count: i32,
+        failures: i32,
+        error: Option<fn() -> FunctionError>,
+    ) -> (FunctionResult, i32) {
+        let calls = Arc::new(AtomicI32::new(0));
+        retry("test", retry_count, 0, || {
+            let calls = calls.clone();
+            async move {
+                if calls.fetch_add(1, Ordering::SeqCst) >= failures {
+                    return Ok(json!("done"));
+                }
+                match error {
+                    Some(error) => Err(error()),
+                    None => panic!("device didn't answer"),
+                }
+            }
+        })
+        .await
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        assert_eq!(retry_backoff(100, 1), Duration::from_millis(100));
+        assert_eq!(retry_backoff(100, 3), Duration::from_millis(400));
+        assert_eq!(retry_backoff(-100, 1), Duration::ZERO);
+        assert_eq!(retry_backoff(100, i32::MAX), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(i64::MAX, 2), MAX_RETRY_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let (result, attempts) = retry_failing(3, 2, Some(offline)).await;
+        assert_eq!(result.unwrap(), json!("done"));
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let (result, attempts) = retry_failing(1, 5, Some(offline)).await;
+        assert!(matches!(result, Err(FunctionError::Failed(e)) if e == "offline"));
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_skips_invalid_arguments() {
+        let (result, attempts) = retry_failing(3, 5, Some(invalid)).await;
+        assert!(matches!(
+            result,
+            Err(FunctionError::InvalidArguments { .. })
+        ));
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_panic() {
+        let (result, attempts) = retry_failing(2, 1, None).await;
+        assert_eq!(result.unwrap(), json!("done"));
+        assert_eq!(attempts, 2);
     }
 }
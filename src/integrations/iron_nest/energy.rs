@@ -0,0 +1,552 @@
+//! Energy monitoring: polls the power of every device with a power meter, adds up each device's
+//! daily energy and cost at the tariff's rate of the time, and fires the alert rules.
+
+use {
+    super::{
+        functions::function_registry,
+        state_history::with_source,
+        supervisor::supervisor,
+        types::{
+            Device,
+            energy::{
+                DailyEnergy, DeviceEnergy, EnergyAlert, EnergyAlertRule, EnergySettings,
+                PowerPoint, PowerReading, Tariff,
+            },
+            state_history::StateSource,
+        },
+    },
+    crate::server::energy::{get_energy_settings_query, set_energy_settings_query},
+    chrono::{DateTime, Duration, NaiveDate, Utc},
+    chrono_tz::Tz,
+    sqlx::PgPool,
+    std::{
+        collections::{HashMap, HashSet},
+        str::FromStr,
+    },
+};
+
+const SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// Samples further apart than this, e.g. across a restart, aren't counted as energy used
+const MAX_GAP_MINUTES: i64 = 5;
+/// Samples older than this are deleted, the daily totals are kept
+const SAMPLE_DAYS: i32 = 14;
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// What's wrong with `settings`, as the field and a message.
+pub fn problems(settings: &EnergySettings, device_ids: &HashSet<i64>) -> Vec<(String, String)> {
+    let mut problems = Vec::new();
+    let tariff = &settings.tariff;
+    if tariff.currency.trim().is_empty() {
+        problems.push(("tariff.currency".to_owned(), "Set a currency".to_owned()));
+    }
+    if !tariff.rate_per_kwh.is_finite() || tariff.rate_per_kwh < 0.0 {
+        problems.push((
+            "tariff.rate_per_kwh".to_owned(),
+            "The rate can't be negative".to_owned(),
+        ));
+    }
+    if let Some(timezone) = tariff
+        .timezone
+        .as_deref()
+        .filter(|timezone| !timezone.is_empty())
+        && let Err(e) = Tz::from_str(timezone)
+    {
+        problems.push((
+            "tariff.timezone".to_owned(),
+            format!("Invalid timezone {timezone:?}: {e}"),
+        ));
+    }
+    for (index, period) in tariff.periods.iter().enumerate() {
+        let path = |field| format!("tariff.periods[{index}].{field}");
+        if period.start == period.end {
+            problems.push((path("end"), format!("The {} period is empty", period.name)));
+        }
+        if !period.rate_per_kwh.is_finite() || period.rate_per_kwh < 0.0 {
+            problems.push((
+                path("rate_per_kwh"),
+                "The rate can't be negative".to_owned(),
+            ));
+        }
+    }
+
+    let mut names = HashSet::new();
+    for (index, rule) in settings.alerts.iter().enumerate() {
+        let path = |field| format!("alerts[{index}].{field}");
+        if rule.name.trim().is_empty() {
+            problems.push((path("name"), "Name the alert".to_owned()));
+        } else if !names.insert(&rule.name) {
+            problems.push((path("name"), "Another alert has this name".to_owned()));
+        }
+        if let Some(device_id) = rule.device_id
+            && !device_ids.contains(&device_id)
+        {
+            problems.push((path("device_id"), format!("No device {device_id}")));
+        }
+        if !rule.threshold_watts.is_finite() || rule.threshold_watts <= 0.0 {
+            problems.push((
+                path("threshold_watts"),
+                "The threshold must be more than 0 W".to_owned(),
+            ));
+        }
+        if let Some(function_name) = &rule.function_name {
+            let args = rule
+                .function_args
+                .clone()
+                .unwrap_or_else(|| serde_json::json!({}));
+            if let Err(e) = function_registry().validate(function_name, &args) {
+                problems.push((path("function_name"), e.to_string()));
+            }
+        }
+    }
+    problems
+}
+
+/// Saves `settings` in the config after checking them.
+pub async fn update_energy_settings(pool: &PgPool, settings: EnergySettings) -> Result<(), String> {
+    let query = "
+        SELECT id
+        FROM device
+    ";
+    let device_ids = sqlx::query_scalar::<_, i64>(query)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let problems = problems(&settings, &device_ids);
+    if !problems.is_empty() {
+        return Err(problems
+            .into_iter()
+            .map(|(field, message)| format!("{field}: {message}"))
+            .collect::<Vec<_>>()
+            .join("; "));
+    }
+    set_energy_settings_query(pool, &settings)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn get_meter_devices_query(pool: &PgPool) -> Result<Vec<Device>, sqlx::Error> {
+    let query = "
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+        FROM device
+        WHERE 'power_meter' = ANY(capabilities)
+        ORDER BY name
+    ";
+    sqlx::query_as(query).fetch_all(pool).await
+}
+
+async fn insert_sample_query(
+    pool: &PgPool,
+    device_id: i64,
+    reading: &PowerReading,
+    sampled_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO energy_sample (device_id, power_w, voltage_v, current_a, sampled_at)
+        VALUES ($1, $2, $3, $4, $5)
+    ";
+    sqlx::query(query)
+        .bind(device_id)
+        .bind(reading.power_w)
+        .bind(reading.voltage_v)
+        .bind(reading.current_a)
+        .bind(sampled_at)
+        .execute(pool)
+        .await
+        .map(drop)
+}
+
+async fn add_daily_query(
+    pool: &PgPool,
+    device_id: i64,
+    day: NaiveDate,
+    energy_wh: f64,
+    cost: f64,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO energy_daily (device_id, day, energy_wh, cost)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (device_id, day) DO UPDATE SET
+            energy_wh = energy_daily.energy_wh + EXCLUDED.energy_wh,
+            cost = energy_daily.cost + EXCLUDED.cost
+    ";
+    sqlx::query(query)
+        .bind(device_id)
+        .bind(day)
+        .bind(energy_wh)
+        .bind(cost)
+        .execute(pool)
+        .await
+        .map(drop)
+}
+
+async fn insert_alert_query(pool: &PgPool, alert: &EnergyAlert) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO energy_alert (rule_name, device_id, power_w, threshold_watts, triggered_at)
+        VALUES ($1, $2, $3, $4, $5)
+    ";
+    sqlx::query(query)
+        .bind(&alert.rule_name)
+        .bind(alert.device_id)
+        .bind(alert.power_w)
+        .bind(alert.threshold_watts)
+        .bind(alert.triggered_at)
+        .execute(pool)
+        .await
+        .map(drop)
+}
+
+pub async fn prune_query(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let query = "
+        DELETE FROM energy_sample
+        WHERE sampled_at < NOW() - make_interval(days => $1)
+    ";
+    sqlx::query(query)
+        .bind(SAMPLE_DAYS)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+}
+
+/// Average power in buckets of `bucket_minutes`, summed over the devices unless `device_id` is
+/// set.
+pub async fn get_power_query(
+    pool: &PgPool,
+    device_id: Option<i64>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket_minutes: u32,
+) -> Result<Vec<PowerPoint>, sqlx::Error> {
+    let query = "
+        SELECT at, SUM(power_w) AS power_w
+        FROM (
+            SELECT
+                device_id,
+                date_bin(make_interval(mins => $4), sampled_at, TIMESTAMPTZ 'epoch') AS at,
+                AVG(power_w) AS power_w
+            FROM energy_sample
+            WHERE sampled_at >= $2 AND sampled_at < $3 AND ($1::BIGINT IS NULL OR device_id = $1)
+            GROUP BY device_id, at
+        ) per_device
+        GROUP BY at
+        ORDER BY at
+    ";
+    sqlx::query_as(query)
+        .bind(device_id)
+        .bind(from)
+        .bind(to)
+        .bind(bucket_minutes as i32)
+        .fetch_all(pool)
+        .await
+}
+
+/// Daily totals from `from` to `to`, summed over the devices unless `device_id` is set.
+pub async fn get_daily_query(
+    pool: &PgPool,
+    device_id: Option<i64>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<DailyEnergy>, sqlx::Error> {
+    let query = "
+        SELECT day, SUM(energy_wh) AS energy_wh, SUM(cost) AS cost
+        FROM energy_daily
+        WHERE day >= $2 AND day <= $3 AND ($1::BIGINT IS NULL OR device_id = $1)
+        GROUP BY day
+        ORDER BY day
+    ";
+    sqlx::query_as(query)
+        .bind(device_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_device_energy_query(
+    pool: &PgPool,
+    today: NaiveDate,
+) -> Result<Vec<DeviceEnergy>, sqlx::Error> {
+    let query = "
+        SELECT
+            device.id AS device_id,
+            device.name,
+            latest.power_w,
+            COALESCE(daily.energy_wh, 0) AS today_wh,
+            COALESCE(daily.cost, 0) AS today_cost
+        FROM device
+        LEFT JOIN LATERAL (
+            SELECT power_w
+            FROM energy_sample
+            WHERE device_id = device.id AND sampled_at > NOW() - make_interval(mins => $2)
+            ORDER BY sampled_at DESC
+            LIMIT 1
+        ) latest ON TRUE
+        LEFT JOIN energy_daily daily ON daily.device_id = device.id AND daily.day = $1
+        WHERE 'power_meter' = ANY(device.capabilities)
+        ORDER BY device.name
+    ";
+    sqlx::query_as(query)
+        .bind(today)
+        .bind(MAX_GAP_MINUTES as i32)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_alerts_query(pool: &PgPool, limit: i64) -> Result<Vec<EnergyAlert>, sqlx::Error> {
+    let query = "
+        SELECT rule_name, device_id, power_w, threshold_watts, triggered_at
+        FROM energy_alert
+        ORDER BY triggered_at DESC
+        LIMIT $1
+    ";
+    sqlx::query_as(query).bind(limit).fetch_all(pool).await
+}
+
+/// Energy used between two samples, taking the power as changing evenly between them, and its
+/// cost at the rate when the first was taken.
+fn energy_between(
+    tariff: &Tariff,
+    (from, from_w): (DateTime<Utc>, f64),
+    (to, to_w): (DateTime<Utc>, f64),
+) -> Option<(f64, f64)> {
+    let elapsed = to - from;
+    if elapsed <= Duration::zero() || elapsed > Duration::minutes(MAX_GAP_MINUTES) {
+        return None;
+    }
+    let energy_wh = (from_w + to_w) / 2.0 * elapsed.num_milliseconds() as f64 / 3_600_000.0;
+    Some((energy_wh, energy_wh / 1000.0 * tariff.rate_at(from)))
+}
+
+#[derive(Default)]
+struct AlertState {
+    above_since: Option<DateTime<Utc>>,
+    fired: bool,
+}
+
+impl AlertState {
+    /// The power `rule` fires with, once it's been over the threshold for long enough and only
+    /// once until it drops below again. `power_w` is `None` when there's no reading.
+    fn check(
+        &mut self,
+        rule: &EnergyAlertRule,
+        power_w: Option<f64>,
+        now: DateTime<Utc>,
+    ) -> Option<f64> {
+        let Some(power_w) = power_w.filter(|power_w| *power_w > rule.threshold_watts) else {
+            *self = Self::default();
+            return None;
+        };
+        let above_since = *self.above_since.get_or_insert(now);
+        if self.fired || now - above_since < Duration::minutes(rule.for_minutes.into()) {
+            return None;
+        }
+        self.fired = true;
+        Some(power_w)
+    }
+}
+
+/// Keeps the previous sample of each device and the state of each alert rule between polls.
+#[derive(Default)]
+struct EnergyCollector {
+    previous: HashMap<i64, (DateTime<Utc>, f64)>,
+    alerts: HashMap<String, AlertState>,
+}
+
+impl EnergyCollector {
+    /// Samples every device and fires the alert rules. A device or alert that can't be saved is
+    /// logged and the rest carry on.
+    async fn tick(&mut self, pool: &PgPool, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        let settings = get_energy_settings_query(pool).await?.unwrap_or_default();
+        let supervisor = supervisor();
+        let mut readings = HashMap::new();
+        for device in get_meter_devices_query(pool).await? {
+            let name = device.device_type.integration();
+            if !supervisor.is_running(name).await {
+                continue;
+            }
+            let Some(integration) = supervisor.integration(name) else {
+                continue;
+            };
+            let reading = match integration.read_power(&device).await {
+                Ok(reading) => reading,
+                Err(e) => {
+                    log::warn!("Failed to read the power of {}: {e}", device.name);
+                    continue;
+                }
+            };
+            if let Err(e) = insert_sample_query(pool, device.id, &reading, now).await {
+                log::error!("Failed to save the power sample of {}: {e}", device.name);
+            }
+            let sample = (now, reading.power_w);
+            if let Some(previous) = self.previous.insert(device.id, sample)
+                && let Some((energy_wh, cost)) = energy_between(&settings.tariff, previous, sample)
+            {
+                let day = settings.tariff.day_of(now);
+                if let Err(e) = add_daily_query(pool, device.id, day, energy_wh, cost).await {
+                    log::error!("Failed to add to the daily energy of {}: {e}", device.name);
+                }
+            }
+            readings.insert(device.id, reading.power_w);
+        }
+        self.previous
+            .retain(|device_id, _| readings.contains_key(device_id));
+
+        self.alerts
+            .retain(|name, _| settings.alerts.iter().any(|rule| &rule.name == name));
+        for rule in &settings.alerts {
+            let power_w = match rule.device_id {
+                Some(device_id) => readings.get(&device_id).copied(),
+                None => Some(readings.values().sum()),
+            };
+            let state = self.alerts.entry(rule.name.clone()).or_default();
+            let Some(power_w) = state.check(rule, power_w, now) else {
+                continue;
+            };
+            if let Err(e) = fire(pool, rule, power_w, now).await {
+                log::error!("Failed to fire energy alert {}: {e}", rule.name);
+                // Try again on the next poll
+                state.fired = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn fire(
+    pool: &PgPool,
+    rule: &EnergyAlertRule,
+    power_w: f64,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    log::warn!(
+        "Energy alert {}: {power_w:.0} W is over {:.0} W",
+        rule.name,
+        rule.threshold_watts
+    );
+    insert_alert_query(
+        pool,
+        &EnergyAlert {
+            rule_name: rule.name.clone(),
+            device_id: rule.device_id,
+            power_w,
+            threshold_watts: rule.threshold_watts,
+            triggered_at: now,
+        },
+    )
+    .await?;
+    if let Some(function_name) = &rule.function_name {
+        let args = rule
+            .function_args
+            .clone()
+            .unwrap_or_else(|| serde_json::json!({}));
+        let result = with_source(
+            StateSource::Automation,
+            function_registry().call(pool, function_name, args),
+        )
+        .await;
+        if let Err(e) = result {
+            log::error!(
+                "Function {function_name} of energy alert {} failed: {e}",
+                rule.name
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn energy_job(pool: PgPool) {
+    let mut samples = tokio::time::interval(SAMPLE_INTERVAL);
+    let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+    let mut collector = EnergyCollector::default();
+    loop {
+        tokio::select! {
+            _ = samples.tick() => {
+                if let Err(e) = collector.tick(&pool, Utc::now()).await {
+                    log::error!("Collecting energy samples failed: {e}");
+                }
+            },
+            _ = prune.tick() => match prune_query(&pool).await {
+                Ok(deleted) => log::info!("Pruned {deleted} energy samples"),
+                Err(e) => log::error!("Pruning energy samples failed: {e}"),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    fn at(minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 19, 12, minute, second)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_energy_between() {
+        let tariff = Tariff {
+            rate_per_kwh: 0.5,
+            ..Tariff::default()
+        };
+        let (energy_wh, cost) = energy_between(&tariff, (at(0, 0), 100.0), (at(1, 0), 200.0))
+            .expect("A minute apart is counted");
+        assert!((energy_wh - 2.5).abs() < 1e-9);
+        assert!((cost - 0.00125).abs() < 1e-9);
+
+        let (energy_wh, _) = energy_between(
+            &tariff,
+            (at(0, 0), 60.0),
+            (at(MAX_GAP_MINUTES as u32, 0), 60.0),
+        )
+        .expect("The largest gap is counted");
+        assert!((energy_wh - 5.0).abs() < 1e-9);
+        assert_eq!(
+            energy_between(
+                &tariff,
+                (at(0, 0), 60.0),
+                (at(MAX_GAP_MINUTES as u32, 1), 60.0)
+            ),
+            None
+        );
+        assert_eq!(
+            energy_between(&tariff, (at(1, 0), 60.0), (at(1, 0), 60.0)),
+            None
+        );
+        assert_eq!(
+            energy_between(&tariff, (at(1, 0), 60.0), (at(0, 0), 60.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_alert_fires_once_until_below() {
+        let rule = EnergyAlertRule {
+            name: "Heater".to_owned(),
+            device_id: Some(1),
+            threshold_watts: 1000.0,
+            for_minutes: 2,
+            function_name: None,
+            function_args: None,
+        };
+        let mut state = AlertState::default();
+        assert_eq!(state.check(&rule, Some(1500.0), at(0, 0)), None);
+        assert_eq!(state.check(&rule, Some(1500.0), at(1, 0)), None);
+        assert_eq!(state.check(&rule, Some(1500.0), at(2, 0)), Some(1500.0));
+        assert_eq!(state.check(&rule, Some(1600.0), at(3, 0)), None);
+        assert_eq!(state.check(&rule, Some(1000.0), at(4, 0)), None);
+        assert_eq!(state.check(&rule, Some(1500.0), at(5, 0)), None);
+        assert_eq!(state.check(&rule, Some(1500.0), at(7, 0)), Some(1500.0));
+        // A missing reading counts as below the threshold
+        assert_eq!(state.check(&rule, None, at(8, 0)), None);
+        assert_eq!(state.check(&rule, Some(1500.0), at(8, 0)), None);
+
+        let rule = EnergyAlertRule {
+            for_minutes: 0,
+            ..rule
+        };
+        let mut state = AlertState::default();
+        assert_eq!(state.check(&rule, Some(1500.0), at(0, 0)), Some(1500.0));
+        assert_eq!(state.check(&rule, Some(1500.0), at(1, 0)), None);
+    }
+}
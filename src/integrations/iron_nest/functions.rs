@@ -0,0 +1,210 @@
+//! Named functions that actions, the assistant and mish buttons can call. Each integration
+//! registers its own with a JSON Schema for their arguments, see `register_functions` in the
+//! integration modules.
+//!
+//! Functions taking a device's `ip` or `device_id` can be given `device` instead, its id or
+//! name, which is looked up on each call so actions keep working when the device's IP changes.
+
+use {
+    super::{
+        capabilities::{self, find_device_query},
+        scenes,
+        types::{DeviceReference, FunctionInfo},
+        vacation,
+    },
+    crate::integrations::{roku, stoplight, tplink},
+    futures::{FutureExt, future::BoxFuture},
+    serde::de::DeserializeOwned,
+    serde_json::{Value, json},
+    sqlx::PgPool,
+    std::{borrow::Cow, collections::BTreeMap, sync::LazyLock},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FunctionError {
+    #[error("Unknown function {0}")]
+    Unknown(String),
+
+    #[error("Invalid arguments for {name}: {}", errors.join("; "))]
+    InvalidArguments { name: String, errors: Vec<String> },
+
+    #[error("{0}")]
+    Failed(String),
+}
+
+pub type FunctionResult = Result<Value, FunctionError>;
+
+type Handler = Box<dyn Fn(PgPool, Value) -> BoxFuture<'static, FunctionResult> + Send + Sync>;
+
+struct RegisteredFunction {
+    info: FunctionInfo,
+    validator: jsonschema::Validator,
+    handler: Handler,
+}
+
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: BTreeMap<String, RegisteredFunction>,
+}
+
+impl FunctionRegistry {
+    /// `handler` gets the arguments deserialized as `A` once they've matched
+    /// `info.parameters`.
+    pub fn register<A, F, Fut>(&mut self, info: FunctionInfo, handler: F)
+    where
+        A: DeserializeOwned,
+        F: Fn(PgPool, A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = FunctionResult> + Send + 'static,
+    {
+        let validator = jsonschema::validator_for(&info.parameters).unwrap_or_else(|e| {
+            panic!(
+                "Parameters of {} are not a valid JSON Schema: {e}",
+                info.name
+            )
+        });
+        let name = info.name.clone();
+        let handler: Handler = Box::new(move |pool, args| match serde_json::from_value(args) {
+            Ok(args) => handler(pool, args).boxed(),
+            Err(e) => {
+                let error = FunctionError::InvalidArguments {
+                    name: name.clone(),
+                    errors: vec![e.to_string()],
+                };
+                async move { Err(error) }.boxed()
+            }
+        });
+        self.functions.insert(
+            info.name.clone(),
+            RegisteredFunction {
+                info,
+                validator,
+                handler,
+            },
+        );
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &FunctionInfo> {
+        self.functions.values().map(|function| &function.info)
+    }
+
+    fn get(&self, name: &str) -> Result<&RegisteredFunction, FunctionError> {
+        self.functions
+            .get(name)
+            .ok_or_else(|| FunctionError::Unknown(name.to_owned()))
+    }
+
+    /// Checks that `name` exists and `args` match its parameters, e.g. before saving an action.
+    pub fn validate(&self, name: &str, args: &Value) -> Result<(), FunctionError> {
+        let function = self.get(name)?;
+        let args = match device_argument(&function.info, args)? {
+            // Whichever device it is, the address or id it's replaced with will be valid
+            Some((key, _)) => {
+                let mut args = args.clone();
+                if let Some(args) = args.as_object_mut() {
+                    args.remove("device");
+                }
+                args[key] = match key {
+                    "ip" => json!("0.0.0.0"),
+                    _ => json!(0),
+                };
+                Cow::Owned(args)
+            }
+            None => Cow::Borrowed(args),
+        };
+        let errors = function
+            .validator
+            .iter_errors(&args)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() {
+                    e.to_string()
+                } else {
+                    format!("{path}: {e}")
+                }
+            })
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(FunctionError::InvalidArguments {
+                name: name.to_owned(),
+                errors,
+            })
+        }
+    }
+
+    pub async fn call(&self, pool: &PgPool, name: &str, mut args: Value) -> FunctionResult {
+        let function = self.get(name)?;
+        if let Some((key, reference)) = device_argument(&function.info, &args)? {
+            let device = find_device_query(pool, &reference).await?;
+            if !function.info.ip_device_types.is_empty()
+                && !function.info.ip_device_types.contains(&device.device_type)
+            {
+                return Err(FunctionError::Failed(format!(
+                    "{name} can't control {}, a {}",
+                    device.name, device.device_type
+                )));
+            }
+            if let Some(args) = args.as_object_mut() {
+                args.remove("device");
+            }
+            args[key] = match key {
+                "ip" => json!(device.ip),
+                _ => json!(device.id),
+            };
+        }
+        self.validate(name, &args)?;
+        (function.handler)(pool.clone(), args).await
+    }
+}
+
+/// The `device` in `args` and the argument it stands for: `ip` for functions with
+/// [`FunctionInfo::ip_device_types`], otherwise `device_id`.
+fn device_argument(
+    info: &FunctionInfo,
+    args: &Value,
+) -> Result<Option<(&'static str, DeviceReference)>, FunctionError> {
+    let Some(reference) = args.get("device") else {
+        return Ok(None);
+    };
+    let invalid = |error: String| FunctionError::InvalidArguments {
+        name: info.name.clone(),
+        errors: vec![error],
+    };
+    let key = if !info.ip_device_types.is_empty() {
+        "ip"
+    } else if info.parameters["properties"].get("device_id").is_some() {
+        "device_id"
+    } else {
+        return Err(invalid("/device: takes no device".to_owned()));
+    };
+    if args.get(key).is_some() {
+        return Err(invalid(format!("/device: give either device or {key}")));
+    }
+    let reference = serde_json::from_value(reference.clone())
+        .map_err(|_| invalid("/device: must be a device id or name".to_owned()))?;
+    Ok(Some((key, reference)))
+}
+
+static FUNCTION_REGISTRY: LazyLock<FunctionRegistry> = LazyLock::new(|| {
+    let mut registry = FunctionRegistry::default();
+    tplink::functions::register_functions(&mut registry);
+    roku::functions::register_functions(&mut registry);
+    stoplight::functions::register_functions(&mut registry);
+    scenes::register_functions(&mut registry);
+    vacation::register_functions(&mut registry);
+    capabilities::register_functions(&mut registry);
+    registry
+});
+
+pub fn function_registry() -> &'static FunctionRegistry {
+    &FUNCTION_REGISTRY
+}
+
+/// JSON Schema of an `ip` argument.
+pub fn ip_parameter() -> Value {
+    serde_json::json!({
+        "type": "string",
+        "description": "IP address of the device",
+    })
+}
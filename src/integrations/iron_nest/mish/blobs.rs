@@ -0,0 +1,203 @@
+use {
+    crate::{
+        components::mish::ipld_blob_page::{get_ipld_blob_query, set_ipld_blob_query},
+        integrations::iron_nest::types::mish::VerifyReport,
+        ipld_codecs,
+    },
+    anyhow::anyhow,
+    cid::Cid,
+    ipld_core::ipld::Ipld,
+    std::{collections::BTreeMap, sync::LazyLock},
+    tokio::time::Duration,
+};
+
+/// Raw uploads larger than this are split into chunks of this size linked from a
+/// `mish-chunked-file` DAG-JSON root.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+const CHUNKED_FILE_MARKER: &str = "mish-chunked-file";
+
+const VERIFY_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const VERIFY_PAGE_SIZE: i64 = 100;
+
+/// Maximum size of a raw upload, `MISH_MAX_RAW_UPLOAD_BYTES` (default 1 GiB).
+pub static MAX_RAW_UPLOAD_BYTES: LazyLock<u64> =
+    LazyLock::new(|| env_limit("MISH_MAX_RAW_UPLOAD_BYTES", 1024 * 1024 * 1024));
+
+/// Maximum size of a DAG-JSON or DAG-CBOR upload, `MISH_MAX_DAG_UPLOAD_BYTES` (default 16 MiB).
+/// These are decoded in memory so they aren't chunked.
+pub static MAX_DAG_UPLOAD_BYTES: LazyLock<u64> =
+    LazyLock::new(|| env_limit("MISH_MAX_DAG_UPLOAD_BYTES", 16 * 1024 * 1024));
+
+fn env_limit(name: &str, default: u64) -> u64 {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|e| {
+            log::error!("Invalid {name} {value:?}, using {default}: {e}");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Splits raw content into chunks as it arrives so large uploads never sit in memory whole.
+pub struct ChunkedUpload<'a> {
+    pool: &'a sqlx::PgPool,
+    buffer: Vec<u8>,
+    chunks: Vec<Cid>,
+    size: u64,
+}
+
+impl<'a> ChunkedUpload<'a> {
+    pub fn new(pool: &'a sqlx::PgPool) -> Self {
+        Self {
+            pool,
+            buffer: Vec::new(),
+            chunks: Vec::new(),
+            size: 0,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<(), sqlx::Error> {
+        self.size += bytes.len() as u64;
+        self.buffer.extend_from_slice(bytes);
+        // Strictly greater so content of exactly CHUNK_SIZE stays a single raw blob
+        while self.buffer.len() > CHUNK_SIZE {
+            let chunk = self.buffer.drain(..CHUNK_SIZE).collect();
+            self.chunks
+                .push(set_ipld_blob_query(self.pool, ipld_codecs::RAW, chunk).await?);
+        }
+        Ok(())
+    }
+
+    /// Stores what's left and returns the CID of the file: a raw blob if it fit in one chunk,
+    /// otherwise the chunked file root.
+    pub async fn finish(mut self) -> anyhow::Result<Cid> {
+        let last = std::mem::take(&mut self.buffer);
+        if self.chunks.is_empty() {
+            return Ok(set_ipld_blob_query(self.pool, ipld_codecs::RAW, last).await?);
+        }
+        self.chunks
+            .push(set_ipld_blob_query(self.pool, ipld_codecs::RAW, last).await?);
+        let root = Ipld::Map(BTreeMap::from([
+            ("-".to_owned(), Ipld::String(CHUNKED_FILE_MARKER.to_owned())),
+            ("size".to_owned(), Ipld::Integer(self.size.into())),
+            (
+                "chunks".to_owned(),
+                Ipld::List(self.chunks.into_iter().map(Ipld::Link).collect()),
+            ),
+        ]));
+        let root = ipld_codecs::encode(ipld_codecs::DAG_JSON, &root)?;
+        Ok(set_ipld_blob_query(self.pool, ipld_codecs::DAG_JSON, root).await?)
+    }
+}
+
+/// The raw blobs making up the file at `cid`, in order. `None` if there is no such blob.
+pub async fn file_chunks(pool: &sqlx::PgPool, cid: &Cid) -> anyhow::Result<Option<Vec<Cid>>> {
+    if cid.codec() == ipld_codecs::RAW {
+        return Ok(Some(vec![*cid]));
+    }
+    let Some(content) = get_ipld_blob_query(pool, cid).await? else {
+        return Ok(None);
+    };
+    let root = ipld_codecs::decode(cid.codec(), &content)?;
+    if root.get("-")? != Some(&Ipld::String(CHUNKED_FILE_MARKER.to_owned())) {
+        return Err(anyhow!("{cid} is not a raw blob or chunked file"));
+    }
+    let Some(Ipld::List(chunks)) = root.get("chunks")? else {
+        return Err(anyhow!("Chunked file {cid} has no chunks list"));
+    };
+    chunks
+        .iter()
+        .map(|chunk| match chunk {
+            Ipld::Link(chunk) => Ok(*chunk),
+            _ => Err(anyhow!("Chunked file {cid} has a chunk that isn't a link")),
+        })
+        .collect::<anyhow::Result<_>>()
+        .map(Some)
+}
+
+/// Reads a whole raw blob or chunked file into memory.
+pub async fn read_file_query(pool: &sqlx::PgPool, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(chunks) = file_chunks(pool, cid).await? else {
+        return Ok(None);
+    };
+    let mut content = Vec::new();
+    for chunk in chunks {
+        content.extend(
+            get_ipld_blob_query(pool, &chunk)
+                .await?
+                .ok_or_else(|| anyhow!("Chunk {chunk} of {cid} not found"))?,
+        );
+    }
+    Ok(Some(content))
+}
+
+/// Re-hashes every stored blob and reports the ones that don't match their CID.
+pub async fn verify_blobs(pool: &sqlx::PgPool) -> anyhow::Result<VerifyReport> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        cid: Vec<u8>,
+        content: Vec<u8>,
+    }
+    let query = "
+        SELECT cid, content
+        FROM ipld_blobs
+        WHERE cid > $1
+        ORDER BY cid
+        LIMIT $2
+    ";
+    let mut report = VerifyReport::default();
+    let mut last = Vec::new();
+    loop {
+        let rows = sqlx::query_as::<_, Row>(query)
+            .bind(&last)
+            .bind(VERIFY_PAGE_SIZE)
+            .fetch_all(pool)
+            .await?;
+        let Some(row) = rows.last() else {
+            break;
+        };
+        last = row.cid.clone();
+        for row in rows {
+            report.checked += 1;
+            let cid = match Cid::try_from(row.cid.as_slice()) {
+                Ok(cid) => cid,
+                Err(e) => {
+                    report
+                        .corrupted
+                        .push(format!("{} (invalid CID: {e})", hex::encode(&row.cid)));
+                    continue;
+                }
+            };
+            match ipld_codecs::verify(&cid, &row.content) {
+                Some(true) => {}
+                Some(false) => report.corrupted.push(cid.to_string()),
+                None => report.unsupported.push(cid.to_string()),
+            }
+        }
+    }
+    Ok(report)
+}
+
+pub async fn verify_job(pool: sqlx::PgPool) {
+    let mut interval = tokio::time::interval(VERIFY_INTERVAL);
+    loop {
+        interval.tick().await;
+        match verify_blobs(&pool).await {
+            Ok(report) if report.corrupted.is_empty() => {
+                log::info!("Verified {} IPLD blobs", report.checked)
+            }
+            Ok(report) => log::error!(
+                "{} of {} IPLD blobs don't match their CID: {}",
+                report.corrupted.len(),
+                report.checked,
+                report.corrupted.join(", ")
+            ),
+            Err(e) => log::error!("IPLD blob verification failed: {e}"),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use {
+    crate::{
+        components::mish::ipld_blob_page::get_ipld_blob_query,
+        integrations::iron_nest::{mish::modules::imported_cids, types::mish::GcReport},
+        ipld_codecs,
+    },
+    chrono::{DateTime, TimeDelta, Utc},
+    cid::Cid,
+    std::collections::HashSet,
+    tokio::time::{Duration, Instant},
+};
+
+/// Unreferenced blobs younger than this are kept, they may be about to be linked (e.g. mish-cli
+/// uploads a blob and then writes its CID into a mish state).
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+const GC_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The first run waits this long after startup so a restart, e.g. to roll back a bad deploy,
+/// doesn't delete anything before it can be looked at.
+const GC_FIRST_RUN_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// The CIDs a mish state links to, including its schema and modules imported by inline scripts.
+fn state_roots(state: &serde_json::Value, schema_cid: Option<&[u8]>) -> anyhow::Result<Vec<Cid>> {
+    fn add_imports(value: &serde_json::Value, roots: &mut Vec<Cid>) {
+        match value {
+            serde_json::Value::String(script) => roots.extend(imported_cids(script)),
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|value| add_imports(value, roots))
+            }
+            serde_json::Value::Object(values) => {
+                values.values().for_each(|value| add_imports(value, roots))
+            }
+            _ => {}
+        }
+    }
+    let mut roots = ipld_codecs::links(ipld_codecs::DAG_JSON, &serde_json::to_vec(state)?)?;
+    add_imports(state, &mut roots);
+    if let Some(schema_cid) = schema_cid {
+        roots.push(Cid::try_from(schema_cid)?);
+    }
+    Ok(roots)
+}
+
+/// Whether a blob last stored at `created_at` can be deleted: it's unreachable and out of the
+/// grace period.
+fn is_reclaimable(
+    cid: &Cid,
+    created_at: DateTime<Utc>,
+    reachable: &HashSet<Cid>,
+    now: DateTime<Utc>,
+) -> bool {
+    !reachable.contains(cid)
+        && now
+            .signed_duration_since(created_at)
+            .to_std()
+            .unwrap_or_default()
+            >= GC_GRACE_PERIOD
+}
+
+/// CIDs linked from any mish state (including its schema and modules its inline scripts import)
+/// or pinned.
+pub async fn gc_roots(pool: &sqlx::PgPool) -> anyhow::Result<Vec<Cid>> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        state: serde_json::Value,
+        schema_cid: Option<Vec<u8>>,
+    }
+    let query = "
+        SELECT state, schema_cid
+        FROM mish_states
+    ";
+    let rows = sqlx::query_as::<_, Row>(query).fetch_all(pool).await?;
+    let mut roots = Vec::new();
+    for row in rows {
+        roots.extend(state_roots(&row.state, row.schema_cid.as_deref())?);
+    }
+
+    let query = "
+        SELECT cid
+        FROM ipld_pins
+    ";
+    for cid in sqlx::query_scalar::<_, Vec<u8>>(query)
+        .fetch_all(pool)
+        .await?
+    {
+        roots.push(Cid::try_from(cid)?);
+    }
+    Ok(roots)
+}
+
+/// Every CID reachable from `roots`, counting the modules a raw script imports as its links.
+/// Missing blocks are skipped, there is nothing to keep.
+pub async fn reachable(pool: &sqlx::PgPool, roots: &[Cid]) -> anyhow::Result<HashSet<Cid>> {
+    let mut seen = HashSet::new();
+    let mut stack = roots.to_vec();
+    while let Some(cid) = stack.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        let Some(block) = get_ipld_blob_query(pool, &cid).await? else {
+            continue;
+        };
+        if cid.codec() == ipld_codecs::RAW
+            && let Ok(script) = std::str::from_utf8(&block)
+        {
+            stack.extend(imported_cids(script));
+        }
+        match ipld_codecs::links(cid.codec(), &block) {
+            Ok(links) => stack.extend(links),
+            Err(e) => log::warn!("GC could not read links of {cid}, keeping it as a leaf: {e}"),
+        }
+    }
+    Ok(seen)
+}
+
+/// Deletes blobs that aren't reachable from a mish state or a pin. With `dry_run` only reports
+/// what would be deleted.
+pub async fn run_gc(pool: &sqlx::PgPool, dry_run: bool) -> anyhow::Result<GcReport> {
+    #[derive(sqlx::FromRow)]
+    struct Row {
+        cid: Vec<u8>,
+        size: i32,
+        created_at: DateTime<Utc>,
+    }
+    let now = Utc::now();
+    let roots = gc_roots(pool).await?;
+    let reachable = reachable(pool, &roots).await?;
+
+    let query = "
+        SELECT cid, octet_length(content) AS size, created_at
+        FROM ipld_blobs
+    ";
+    let rows = sqlx::query_as::<_, Row>(query).fetch_all(pool).await?;
+    let mut reclaimable = Vec::new();
+    let mut reclaimable_bytes = 0i64;
+    for row in rows {
+        let cid = match Cid::try_from(row.cid.as_slice()) {
+            Ok(cid) => cid,
+            Err(e) => {
+                log::warn!("GC skipped a blob with an invalid CID: {e}");
+                continue;
+            }
+        };
+        if is_reclaimable(&cid, row.created_at, &reachable, now) {
+            reclaimable.push(row.cid);
+            reclaimable_bytes += i64::from(row.size);
+        }
+    }
+
+    if !dry_run && !reclaimable.is_empty() {
+        // A blob uploaded again since the scan may be linked by now, its created_at was reset
+        let query = "
+            DELETE FROM ipld_blobs
+            WHERE cid = ANY($1)
+            AND created_at <= $2
+            RETURNING cid, octet_length(content) AS size, created_at
+        ";
+        let deleted = sqlx::query_as::<_, Row>(query)
+            .bind(&reclaimable)
+            .bind(now - TimeDelta::seconds(GC_GRACE_PERIOD.as_secs() as i64))
+            .fetch_all(pool)
+            .await?;
+        reclaimable_bytes = deleted.iter().map(|row| i64::from(row.size)).sum();
+        reclaimable = deleted.into_iter().map(|row| row.cid).collect();
+    }
+
+    Ok(GcReport {
+        roots: roots.len(),
+        reachable_blobs: reachable.len(),
+        reclaimable_blobs: reclaimable
+            .into_iter()
+            .filter_map(|cid| Cid::try_from(cid).ok())
+            .map(|cid| cid.to_string())
+            .collect(),
+        reclaimable_bytes,
+        dry_run,
+    })
+}
+
+pub async fn gc_job(pool: sqlx::PgPool) {
+    let mut interval = tokio::time::interval_at(Instant::now() + GC_FIRST_RUN_DELAY, GC_INTERVAL);
+    loop {
+        interval.tick().await;
+        match run_gc(&pool, false).await {
+            Ok(report) => log::info!(
+                "IPLD GC deleted {} blobs ({} bytes)",
+                report.reclaimable_blobs.len(),
+                report.reclaimable_bytes
+            ),
+            Err(e) => log::error!("IPLD GC failed: {e}"),
+        }
+    }
+}
+
+pub async fn pin_query(
+    executor: impl sqlx::PgExecutor<'_>,
+    name: &str,
+    cid: &Cid,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO ipld_pins (name, cid)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO UPDATE SET
+            cid = EXCLUDED.cid
+    ";
+    sqlx::query(query)
+        .bind(name)
+        .bind(cid.to_bytes())
+        .execute(executor)
+        .await
+        .map(|_| ())
+}
+
+pub async fn unpin_query(pool: &sqlx::PgPool, name: &str) -> Result<(), sqlx::Error> {
+    let query = "
+        DELETE FROM ipld_pins
+        WHERE name = $1
+    ";
+    sqlx::query(query)
+        .bind(name)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeDelta, serde_json::json};
+
+    fn raw_cid(content: &[u8]) -> Cid {
+        ipld_codecs::cid(ipld_codecs::RAW, content)
+    }
+
+    #[test]
+    fn test_state_roots() {
+        let linked = raw_cid(b"linked");
+        let nested = raw_cid(b"nested");
+        let schema = raw_cid(b"schema");
+        let imported = raw_cid(b"fn helper() {}");
+        let state = json!({
+            "blob": { "/": linked.to_string() },
+            "list": [{ "deep": { "/": nested.to_string() } }],
+            "text": "not a link",
+            "script": { "rhai": format!("import \"{imported}\" as helpers;") },
+        });
+        let mut roots = state_roots(&state, Some(&schema.to_bytes())).unwrap();
+        roots.sort();
+        let mut expected = vec![linked, nested, imported, schema];
+        expected.sort();
+        assert_eq!(roots, expected);
+        assert!(state_roots(&json!({}), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_is_reclaimable() {
+        let now = Utc::now();
+        let kept = raw_cid(b"kept");
+        let orphan = raw_cid(b"orphan");
+        let reachable = HashSet::from([kept]);
+        let old = now - TimeDelta::hours(2);
+        let recent = now - TimeDelta::minutes(5);
+        assert!(is_reclaimable(&orphan, old, &reachable, now));
+        assert!(!is_reclaimable(&orphan, recent, &reachable, now));
+        assert!(!is_reclaimable(&kept, old, &reachable, now));
+        // Clock skew between the database and the server doesn't make new blobs reclaimable
+        assert!(!is_reclaimable(
+            &orphan,
+            now + TimeDelta::minutes(1),
+            &reachable,
+            now
+        ));
+    }
+}
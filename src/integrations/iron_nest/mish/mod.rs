@@ -1,13 +1,38 @@
+pub mod blobs;
+pub mod gc;
+pub mod modules;
+pub mod resolve;
+pub mod schema;
+
 use {
     crate::{
-        components::mish::{
-            ipld_blob_page::get_ipld_blob_query, mish_state_page::get_mish_state_query,
+        components::{
+            mish::mish_state_page::get_mish_state_query, pages::configs_page::get_location_query,
+        },
+        integrations::{
+            iron_nest::{
+                capabilities::{control_device, find_device_query},
+                scenes::apply_scene,
+                solar::event_time,
+                state_history::with_source,
+                types::{
+                    DeviceReference, SolarEvent,
+                    capability::DeviceCommand,
+                    mish::{ScriptLog, ScriptLogLevel},
+                    state_history::StateSource,
+                },
+                vacation::update_vacation_mode,
+            },
+            tplink::{tplink_turn_plug_off, tplink_turn_plug_on},
         },
-        integrations::tplink::{tplink_turn_plug_off, tplink_turn_plug_on},
         mish_api::{UpdateMishStateBody, update_mish_state},
+        server::vacation::get_vacation_mode_query,
     },
+    blobs::read_file_query,
     cid::Cid,
     ipld_core::codec::Codec,
+    modules::BlobModuleResolver,
+    resolve::resolve_path,
     rhai::Dynamic,
     serde::{Deserialize, Serialize},
     serde_ipld_dagjson::codec::DagJsonCodec,
@@ -17,7 +42,7 @@ use {
         time::{SystemTime, UNIX_EPOCH},
     },
     tokio::{
-        sync::mpsc::{UnboundedReceiver, UnboundedSender},
+        sync::broadcast::{self, Receiver, Sender, error::RecvError},
         time::{Duration, Instant},
     },
     tokio_cron_scheduler::{Job, JobScheduler},
@@ -34,17 +59,48 @@ pub enum MishStateModification {
     },
 }
 
-pub fn create_mish_state_modification_bus() -> (
-    UnboundedSender<MishStateModification>,
-    UnboundedReceiver<MishStateModification>,
+// Slow subscribers (e.g. a stalled SSE client) lag behind and skip events rather than
+// holding up the writers, so this only needs to absorb short bursts.
+const MISH_STATE_MODIFICATION_BUS_CAPACITY: usize = 1024;
+
+/// The modification bus is a broadcast hub: the native query runner, SSE and WebSocket
+/// subscribers each get their own receiver via `subscribe()`.
+pub fn create_mish_state_modification_bus() -> Sender<MishStateModification> {
+    broadcast::channel(MISH_STATE_MODIFICATION_BUS_CAPACITY).0
+}
+
+const SCRIPT_LOG_BUS_CAPACITY: usize = 1024;
+
+/// Script output and failures, for `/api/mish/scripts/logs` subscribers such as `mish-cli dev`.
+pub fn create_script_log_bus() -> Sender<ScriptLog> {
+    broadcast::channel(SCRIPT_LOG_BUS_CAPACITY).0
+}
+
+fn send_script_log(
+    script_log_bus_sender: &Sender<ScriptLog>,
+    script: &str,
+    cid: Option<Cid>,
+    level: ScriptLogLevel,
+    message: String,
 ) {
-    tokio::sync::mpsc::unbounded_channel()
+    if level == ScriptLogLevel::Error {
+        log::error!("Script {script}: {message}");
+    }
+    // An error here only means nobody is listening
+    let _ = script_log_bus_sender.send(ScriptLog {
+        script: script.to_owned(),
+        cid: cid.map(|cid| cid.to_string()),
+        level,
+        message,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+    });
 }
 
 pub async fn register_native_queries(
     pool: &sqlx::PgPool,
-    mut mish_state_modification_bus_receiver: UnboundedReceiver<MishStateModification>,
-    mish_state_modification_bus_sender: UnboundedSender<MishStateModification>,
+    mut mish_state_modification_bus_receiver: Receiver<MishStateModification>,
+    mish_state_modification_bus_sender: Sender<MishStateModification>,
+    script_log_bus_sender: Sender<ScriptLog>,
 ) {
     let mut lookup = HashMap::new();
     let mut job_scheduler = JobScheduler::new().await.unwrap();
@@ -54,6 +110,7 @@ pub async fn register_native_queries(
         do_install(
             pool,
             mish_state_modification_bus_sender.clone(),
+            script_log_bus_sender.clone(),
             &mut lookup,
             &mut job_scheduler,
             state.state.clone(),
@@ -61,7 +118,17 @@ pub async fn register_native_queries(
         .await;
     }
 
-    while let Some(mish_state_modification) = mish_state_modification_bus_receiver.recv().await {
+    loop {
+        let mish_state_modification = match mish_state_modification_bus_receiver.recv().await {
+            Ok(mish_state_modification) => mish_state_modification,
+            Err(RecvError::Lagged(skipped)) => {
+                log::warn!(
+                    "Native queries lagged behind, skipped {skipped} mish state modifications"
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
         log::info!("Mish state modification: {:?}", mish_state_modification);
         match mish_state_modification {
             MishStateModification::CreateOrUpdate { name, state } => match name.as_str() {
@@ -69,6 +136,7 @@ pub async fn register_native_queries(
                     do_install(
                         pool,
                         mish_state_modification_bus_sender.clone(),
+                        script_log_bus_sender.clone(),
                         &mut lookup,
                         &mut job_scheduler,
                         state,
@@ -76,7 +144,7 @@ pub async fn register_native_queries(
                     .await;
                 }
                 name => {
-                    if let Some(item) = lookup.get(name).cloned() {
+                    if let Some((script, item)) = lookup.get(name).cloned() {
                         match item {
                             InstallItem::MishStateAtMostOnceRhai { rhai, .. } => {
                                 let scope = {
@@ -100,6 +168,8 @@ pub async fn register_native_queries(
                                 run_mish_state_at_most_once_rhai(
                                     pool.clone(),
                                     mish_state_modification_bus_sender.clone(),
+                                    script_log_bus_sender.clone(),
+                                    script,
                                     rhai,
                                     scope,
                                 )
@@ -135,8 +205,9 @@ enum InstallItem {
 
 async fn do_install(
     pool: &sqlx::PgPool,
-    mish_state_modification_bus_sender: UnboundedSender<MishStateModification>,
-    lookup: &mut HashMap<String, InstallItem>,
+    mish_state_modification_bus_sender: Sender<MishStateModification>,
+    script_log_bus_sender: Sender<ScriptLog>,
+    lookup: &mut HashMap<String, (String, InstallItem)>,
     job_scheduler: &mut JobScheduler,
     state: serde_json::Value,
 ) {
@@ -149,6 +220,9 @@ async fn do_install(
             job_scheduler.start().await.unwrap();
             for (name, item) in items {
                 log::info!("Installing {name}");
+                let (InstallItem::MishStateAtMostOnceRhai { rhai, .. }
+                | InstallItem::CronAtMostOnceRhai { rhai, .. }) = &item;
+                check_rhai(pool, &script_log_bus_sender, &name, rhai).await;
                 match item.clone() {
                     InstallItem::MishStateAtMostOnceRhai {
                         query_name,
@@ -156,7 +230,7 @@ async fn do_install(
                         run_on_startup,
                         ..
                     } => {
-                        lookup.insert(query_name.clone(), item.clone());
+                        lookup.insert(query_name.clone(), (name.clone(), item.clone()));
                         if run_on_startup {
                             let state = get_mish_state_query(pool, &query_name).await.unwrap();
                             if let Some(state) = state {
@@ -181,6 +255,8 @@ async fn do_install(
                                 run_mish_state_at_most_once_rhai(
                                     pool.clone(),
                                     mish_state_modification_bus_sender.clone(),
+                                    script_log_bus_sender.clone(),
+                                    name.clone(),
                                     rhai.clone(),
                                     scope,
                                 )
@@ -192,6 +268,8 @@ async fn do_install(
                         let pool = pool.clone();
                         let mish_state_modification_bus_sender =
                             mish_state_modification_bus_sender.clone();
+                        let script_log_bus_sender = script_log_bus_sender.clone();
+                        let name = name.clone();
                         let rhai = rhai.clone();
                         job_scheduler
                             .add(
@@ -199,12 +277,16 @@ async fn do_install(
                                     let pool = pool.clone();
                                     let mish_state_modification_bus_sender =
                                         mish_state_modification_bus_sender.clone();
+                                    let script_log_bus_sender = script_log_bus_sender.clone();
+                                    let name = name.clone();
                                     let rhai = rhai.clone();
                                     Box::pin(async move {
                                         let scope = rhai::Scope::new();
                                         run_mish_state_at_most_once_rhai(
                                             pool,
                                             mish_state_modification_bus_sender,
+                                            script_log_bus_sender,
+                                            name,
                                             rhai,
                                             scope,
                                         )
@@ -220,51 +302,194 @@ async fn do_install(
             }
         }
         Err(e) => {
-            log::error!("Failed to parse install items: {e}");
+            send_script_log(
+                &script_log_bus_sender,
+                "run",
+                None,
+                ScriptLogLevel::Error,
+                format!("Failed to parse install items: {e}"),
+            );
         }
     }
 }
 
+/// Runs the install item `item_name` from the `run` mish state straight away, with the same scope
+/// its trigger would give it.
+pub async fn trigger_install_item(
+    pool: &sqlx::PgPool,
+    mish_state_modification_bus_sender: Sender<MishStateModification>,
+    script_log_bus_sender: Sender<ScriptLog>,
+    item_name: &str,
+) -> Result<(), String> {
+    let run = get_mish_state_query(pool, "run")
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("No run mish state")?;
+    let mut items = serde_json::from_value::<HashMap<String, InstallItem>>(run.state)
+        .map_err(|e| format!("Failed to parse install items: {e}"))?;
+    let item = items
+        .remove(item_name)
+        .ok_or_else(|| format!("No install item named {item_name}"))?;
+    let (rhai, scope) = match item {
+        InstallItem::MishStateAtMostOnceRhai {
+            query_name, rhai, ..
+        } => {
+            let state = get_mish_state_query(pool, &query_name)
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("No {query_name} mish state"))?;
+            let state = serde_json::from_value::<Dynamic>(state.state)
+                .map_err(|e| format!("Failed to parse {query_name} mish state: {e}"))?;
+            let mut scope = rhai::Scope::new();
+            scope.push_constant("name", query_name);
+            scope.push_dynamic("state", state);
+            (rhai, scope)
+        }
+        InstallItem::CronAtMostOnceRhai { rhai, .. } => (rhai, rhai::Scope::new()),
+    };
+    run_mish_state_at_most_once_rhai(
+        pool.clone(),
+        mish_state_modification_bus_sender,
+        script_log_bus_sender,
+        item_name.to_owned(),
+        rhai,
+        scope,
+    )
+    .await;
+    Ok(())
+}
+
+/// A script is either inline source or a link to a raw blob (or chunked file) holding it.
+async fn load_rhai(
+    pool: &sqlx::PgPool,
+    rhai: &serde_json::Value,
+) -> Result<(String, Option<Cid>), String> {
+    let rhai_string = serde_json::from_value::<String>(rhai.clone());
+    let rhai_cid =
+        <DagJsonCodec as Codec<Cid>>::decode_from_slice(&serde_json::to_vec(rhai).unwrap());
+    match (rhai_string, rhai_cid) {
+        (Ok(rhai_string), Ok(rhai_cid)) => Err(format!(
+            "Both String and Cid should not be parsable at the same time: {rhai_string} and {rhai_cid}"
+        )),
+        (Ok(rhai_string), Err(_)) => Ok((rhai_string, None)),
+        (Err(_), Ok(rhai_cid)) => match read_file_query(pool, &rhai_cid).await {
+            Ok(Some(blob)) => String::from_utf8(blob)
+                .map(|rhai_string| (rhai_string, Some(rhai_cid)))
+                .map_err(|e| format!("Script {rhai_cid} is not UTF-8: {e}")),
+            Ok(None) => Err(format!("Script {rhai_cid} not found")),
+            Err(e) => Err(format!("Failed to read script {rhai_cid}: {e}")),
+        },
+        (Err(e1), Err(e2)) => Err(format!(
+            "Script is neither a string nor a link: {e1} AND {e2}"
+        )),
+    }
+}
+
+/// Compiles the script at install time so syntax errors show up straight away rather than on
+/// the next trigger.
+async fn check_rhai(
+    pool: &sqlx::PgPool,
+    script_log_bus_sender: &Sender<ScriptLog>,
+    script: &str,
+    rhai: &serde_json::Value,
+) {
+    let (level, cid, message) = match load_rhai(pool, rhai).await {
+        Ok((rhai, cid)) => match rhai::Engine::new().compile(&rhai) {
+            Ok(_) => (ScriptLogLevel::Info, cid, "Installed".to_owned()),
+            Err(e) => (ScriptLogLevel::Error, cid, format!("Compile error: {e}")),
+        },
+        Err(e) => (ScriptLogLevel::Error, None, e),
+    };
+    send_script_log(script_log_bus_sender, script, cid, level, message);
+}
+
 async fn run_mish_state_at_most_once_rhai(
     pool: sqlx::PgPool,
-    mish_state_modification_bus_sender: UnboundedSender<MishStateModification>,
+    mish_state_modification_bus_sender: Sender<MishStateModification>,
+    script_log_bus_sender: Sender<ScriptLog>,
+    script: String,
     rhai: serde_json::Value,
     scope: rhai::Scope<'static>,
 ) {
-    // TODO refactor this and the AST compilation step to happen in the "run" handler
-    let rhai_string = serde_json::from_value::<String>(rhai.clone());
-    let rhai_cid =
-        <DagJsonCodec as Codec<Cid>>::decode_from_slice(&serde_json::to_vec(&rhai).unwrap());
-    let rhai = match (rhai_string, rhai_cid) {
-        (Ok(rhai_string), Ok(rhai_cid)) => {
-            panic!(
-                "Both String and Cid should not be parsable at the same time: {rhai_string} and {rhai_cid}"
+    let (rhai, cid) = match load_rhai(&pool, &rhai).await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            send_script_log(
+                &script_log_bus_sender,
+                &script,
+                None,
+                ScriptLogLevel::Error,
+                e,
             );
-        }
-        (Ok(rhai_string), Err(_)) => rhai_string,
-        (Err(_), Ok(rhai_cid)) => {
-            if let Some(blob) = get_ipld_blob_query(&pool, &rhai_cid).await.unwrap() {
-                match String::from_utf8(blob) {
-                    Ok(rhai_string) => rhai_string,
-                    Err(e) => {
-                        log::error!("Failed to parse fish tank state string on: {e}");
-                        return;
-                    }
-                }
-            } else {
-                log::error!("Failed to get fish tank state on: {rhai_cid}");
-                return;
-            }
-        }
-        (Err(e1), Err(e2)) => {
-            log::error!("Failed to parse fish tank state on: {e1} AND {e2}");
             return;
         }
     };
+    let runtime = tokio::runtime::Handle::current();
     tokio::task::spawn_blocking(move || {
         let start = Instant::now();
         let mut scope = scope;
+        let resolve_pool = pool.clone();
+        let resolve_runtime = runtime.clone();
+        let sun_pool = pool.clone();
+        let sun_runtime = runtime.clone();
+        let sun_timestamp = move |event| {
+            solar_event_today(&sun_pool, &sun_runtime, event).map(|time| time.timestamp())
+        };
+        let sunrise_today = sun_timestamp.clone();
+        let sunset_today = sun_timestamp.clone();
+        let after_sunrise = sun_timestamp.clone();
+        let after_sunset = sun_timestamp;
+        let scene_pool = pool.clone();
+        let scene_runtime = runtime.clone();
+        let device_pool = pool.clone();
+        let device_runtime = runtime.clone();
+        let set_device_on_by_id = move |device: i64, on: bool| {
+            set_device_on(
+                &device_pool,
+                &device_runtime,
+                DeviceReference::Id(device),
+                on,
+            )
+        };
+        let device_pool = pool.clone();
+        let device_runtime = runtime.clone();
+        let set_device_on_by_name = move |device: String, on: bool| {
+            set_device_on(
+                &device_pool,
+                &device_runtime,
+                DeviceReference::Name(device),
+                on,
+            )
+        };
+        let vacation_pool = pool.clone();
+        let vacation_runtime = runtime.clone();
+        let is_vacation_pool = pool.clone();
+        let is_vacation_runtime = runtime.clone();
+        let module_resolver = BlobModuleResolver::new(pool.clone(), runtime.clone());
+        let print_sender = script_log_bus_sender.clone();
+        let print_script = script.clone();
+        let debug_sender = script_log_bus_sender.clone();
+        let debug_script = script.clone();
         let result = rhai::Engine::new()
+            .set_module_resolver(module_resolver)
+            .on_print(move |message| {
+                send_script_log(
+                    &print_sender,
+                    &print_script,
+                    cid,
+                    ScriptLogLevel::Print,
+                    message.to_owned(),
+                )
+            })
+            .on_debug(move |message, _source, position| {
+                send_script_log(
+                    &debug_sender,
+                    &debug_script,
+                    cid,
+                    ScriptLogLevel::Debug,
+                    format!("{position}: {message}"),
+                )
+            })
             .on_progress(move |_| {
                 if start.elapsed() > Duration::from_secs(10) {
                     // Return a dummy token just to force-terminate the script
@@ -292,13 +517,14 @@ async fn run_mish_state_at_most_once_rhai(
             })
             .register_fn(
                 "update_mish_state",
-                move |name: String, path: String, content: Dynamic| {
-                    let pool = pool.clone();
-                    let mish_state_modification_bus_sender =
-                        mish_state_modification_bus_sender.clone();
-                    let content = serde_json::to_value(&content).unwrap();
-                    tokio::task::spawn(async move {
-                        if let Err(e) = update_mish_state(
+                move |name: String,
+                      path: String,
+                      content: Dynamic|
+                      -> Result<(), Box<rhai::EvalAltResult>> {
+                    let content = serde_json::to_value(&content).map_err(|e| e.to_string())?;
+                    // Wait for the write so a state rejected by its schema fails the script
+                    runtime
+                        .block_on(update_mish_state(
                             &pool,
                             &mish_state_modification_bus_sender,
                             UpdateMishStateBody {
@@ -306,12 +532,17 @@ async fn run_mish_state_at_most_once_rhai(
                                 path,
                                 content,
                             },
-                        )
-                        .await
-                        {
-                            log::error!("Failed to update mish state: {e}");
-                        }
-                    });
+                        ))
+                        .map_err(|e| format!("Failed to update mish state: {e}").into())
+                },
+            )
+            .register_fn(
+                "resolve",
+                move |path: String| -> Result<Dynamic, Box<rhai::EvalAltResult>> {
+                    let resolved = resolve_runtime
+                        .block_on(resolve_path(&resolve_pool, &path))
+                        .map_err(|e| format!("Failed to resolve {path}: {e}"))?;
+                    rhai::serde::to_dynamic(resolved.value)
                 },
             )
             .register_fn(
@@ -320,13 +551,95 @@ async fn run_mish_state_at_most_once_rhai(
                     is_now_between(&timezone, &start, &up_to, chrono::Utc::now())
                 },
             )
+            .register_fn(
+                "apply_scene",
+                move |name: String| -> Result<(), Box<rhai::EvalAltResult>> {
+                    scene_runtime
+                        .block_on(apply_scene(&scene_pool, &name))
+                        .map(drop)
+                        .map_err(|e| format!("Failed to apply scene {name}: {e}").into())
+                },
+            )
+            // By the device's id or name, which unlike its IP don't change
+            .register_fn("set_device_on", set_device_on_by_id)
+            .register_fn("set_device_on", set_device_on_by_name)
+            .register_fn(
+                "set_vacation_mode",
+                move |enabled: bool| -> Result<(), Box<rhai::EvalAltResult>> {
+                    vacation_runtime
+                        .block_on(update_vacation_mode(&vacation_pool, |mode| {
+                            let mut mode = mode.ok_or("Vacation mode isn't set up")?;
+                            mode.enabled = enabled;
+                            Ok(mode)
+                        }))
+                        .map(drop)
+                        .map_err(|e| format!("Failed to set vacation mode: {e}").into())
+                },
+            )
+            // Whether vacation mode is replaying today
+            .register_fn(
+                "is_vacation_mode",
+                move || -> Result<bool, Box<rhai::EvalAltResult>> {
+                    let mode = is_vacation_runtime
+                        .block_on(get_vacation_mode_query(&is_vacation_pool))
+                        .map_err(|e| format!("Failed to get vacation mode: {e}"))?;
+                    Ok(mode.is_some_and(|mode| mode.is_active()))
+                },
+            )
+            // Unix timestamps, like unix_timestamp()
+            .register_fn("sunrise_today", move || sunrise_today(SolarEvent::Sunrise))
+            .register_fn("sunset_today", move || sunset_today(SolarEvent::Sunset))
+            .register_fn("is_after_sunrise", move || {
+                after_sunrise(SolarEvent::Sunrise)
+                    .map(|sunrise| chrono::Utc::now().timestamp() >= sunrise)
+            })
+            .register_fn("is_after_sunset", move || {
+                after_sunset(SolarEvent::Sunset)
+                    .map(|sunset| chrono::Utc::now().timestamp() >= sunset)
+            })
             .run_with_scope(&mut scope, &rhai);
         if let Err(e) = result {
-            log::error!("Failed to run fish tank script: {:?}", e);
+            send_script_log(
+                &script_log_bus_sender,
+                &script,
+                cid,
+                ScriptLogLevel::Error,
+                e.to_string(),
+            );
         }
     });
 }
 
+/// Today's `event` at the location in the config, for the rhai sun functions.
+fn solar_event_today(
+    pool: &sqlx::PgPool,
+    runtime: &tokio::runtime::Handle,
+    event: SolarEvent,
+) -> Result<chrono::DateTime<chrono::Utc>, Box<rhai::EvalAltResult>> {
+    let location = runtime
+        .block_on(get_location_query(pool))
+        .map_err(|e| format!("Failed to read the location: {e}"))?
+        .ok_or("Set a location in the config to use the sun functions")?;
+    let today = location.solar_date(chrono::Utc::now());
+    event_time(today, &location, event)
+        .ok_or_else(|| format!("The sun doesn't reach {event:?} today").into())
+}
+
+/// Switches a device on or off, for the rhai `set_device_on` functions.
+fn set_device_on(
+    pool: &sqlx::PgPool,
+    runtime: &tokio::runtime::Handle,
+    reference: DeviceReference,
+    on: bool,
+) -> Result<(), Box<rhai::EvalAltResult>> {
+    runtime
+        .block_on(with_source(StateSource::Automation, async {
+            let device = find_device_query(pool, &reference).await?;
+            control_device(pool, device.id, DeviceCommand::OnOff { on }).await
+        }))
+        .map_err(|e| format!("Failed to switch {reference}: {e}").into())
+}
+
 fn is_now_between(
     timezone: &str,
     start: &str,
@@ -0,0 +1,268 @@
+//! Lets mish scripts `import "bafy..." as helpers;` or `import "helpers" as helpers;` where
+//! `helpers` is an alias in the `rhai_modules` mish state, e.g. `{"helpers": {"/": "bafy..."}}`.
+
+use {
+    super::blobs::read_file_query,
+    crate::components::mish::mish_state_page::get_mish_state_query,
+    cid::Cid,
+    rhai::{Engine, EvalAltResult, Module, ModuleResolver, Position, Scope, Shared},
+    std::{
+        collections::{HashMap, VecDeque},
+        sync::{LazyLock, Mutex},
+    },
+    tokio::runtime::Handle,
+};
+
+/// Mish state mapping module names to the CIDs of their scripts.
+pub const MODULE_ALIASES_MISH_STATE: &str = "rhai_modules";
+
+/// How many compiled modules are kept, the oldest is dropped to make room for another.
+const MODULE_CACHE_CAPACITY: usize = 64;
+
+/// Blobs never change, so a module compiled once is good for every script run.
+static MODULE_CACHE: LazyLock<Mutex<ModuleCache>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+struct ModuleCache {
+    modules: HashMap<Cid, Shared<Module>>,
+    /// Cached CIDs, oldest first
+    order: VecDeque<Cid>,
+}
+
+impl ModuleCache {
+    fn get(&self, cid: &Cid) -> Option<Shared<Module>> {
+        self.modules.get(cid).cloned()
+    }
+
+    fn insert(&mut self, cid: Cid, module: Shared<Module>) {
+        if self.modules.insert(cid, module).is_some() {
+            return;
+        }
+        self.order.push_back(cid);
+        while self.order.len() > MODULE_CACHE_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.modules.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// The CIDs a script imports directly, e.g. `import "bafy..." as helpers;`. GC keeps them as
+/// the script only holds them as strings, not links. Aliases are links in
+/// [`MODULE_ALIASES_MISH_STATE`] already.
+pub fn imported_cids(script: &str) -> Vec<Cid> {
+    script
+        .match_indices("import")
+        .filter_map(|(index, import)| {
+            let path = script[index + import.len()..]
+                .trim_start()
+                .strip_prefix('"')?;
+            path[..path.find('"')?].parse().ok()
+        })
+        .collect()
+}
+
+/// Where modules' scripts and aliases are read from.
+trait ModuleSource: Send + Sync {
+    fn alias(&self, name: &str) -> Result<Option<Cid>, String>;
+
+    fn script(&self, cid: &Cid) -> Result<Option<Vec<u8>>, String>;
+}
+
+struct BlobModuleSource {
+    pool: sqlx::PgPool,
+    runtime: Handle,
+}
+
+impl ModuleSource for BlobModuleSource {
+    fn alias(&self, name: &str) -> Result<Option<Cid>, String> {
+        let aliases = self
+            .runtime
+            .block_on(get_mish_state_query(&self.pool, MODULE_ALIASES_MISH_STATE))
+            .map_err(|e| e.to_string())?
+            .map(|state| state.state)
+            .unwrap_or_default();
+        aliases
+            .get(name)
+            .and_then(|alias| alias.get("/"))
+            .and_then(|cid| cid.as_str())
+            .map(|cid| {
+                cid.parse::<Cid>()
+                    .map_err(|e| format!("Invalid CID for {name}: {e}"))
+            })
+            .transpose()
+    }
+
+    fn script(&self, cid: &Cid) -> Result<Option<Vec<u8>>, String> {
+        self.runtime
+            .block_on(read_file_query(&self.pool, cid))
+            .map_err(|e| e.to_string())
+    }
+}
+
+pub struct BlobModuleResolver {
+    source: Box<dyn ModuleSource>,
+    /// Modules currently being evaluated, outermost first, to catch cyclic imports
+    importing: Mutex<Vec<Cid>>,
+}
+
+impl BlobModuleResolver {
+    /// `runtime` is used to block on queries, so scripts must run outside of it (e.g. in
+    /// `spawn_blocking`).
+    pub fn new(pool: sqlx::PgPool, runtime: Handle) -> Self {
+        Self::with_source(BlobModuleSource { pool, runtime })
+    }
+
+    fn with_source(source: impl ModuleSource + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+            importing: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn resolve_cid(&self, path: &str) -> Result<Cid, String> {
+        if let Ok(cid) = path.parse::<Cid>() {
+            return Ok(cid);
+        }
+        self.source.alias(path)?.ok_or_else(|| {
+            format!("{path} is not a CID or an alias in {MODULE_ALIASES_MISH_STATE}")
+        })
+    }
+
+    fn load(&self, engine: &Engine, cid: &Cid) -> Result<Shared<Module>, String> {
+        let script = self
+            .source
+            .script(cid)?
+            .ok_or_else(|| format!("Blob {cid} not found"))?;
+        let script = String::from_utf8(script).map_err(|e| e.to_string())?;
+        let ast = engine.compile(script).map_err(|e| e.to_string())?;
+        let module =
+            Module::eval_ast_as_new(Scope::new(), &ast, engine).map_err(|e| e.to_string())?;
+        Ok(module.into())
+    }
+}
+
+impl ModuleResolver for BlobModuleResolver {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        _source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<Module>, Box<EvalAltResult>> {
+        let in_module = |error: String| {
+            Box::new(EvalAltResult::ErrorInModule(
+                path.to_owned(),
+                error.into(),
+                pos,
+            ))
+        };
+        let cid = self.resolve_cid(path).map_err(|e| {
+            log::error!("Failed to resolve rhai module {path}: {e}");
+            Box::new(EvalAltResult::ErrorModuleNotFound(path.to_owned(), pos))
+        })?;
+        if let Some(module) = MODULE_CACHE.lock().unwrap().get(&cid) {
+            return Ok(module);
+        }
+
+        {
+            let mut importing = self.importing.lock().unwrap();
+            if importing.contains(&cid) {
+                let cycle = importing
+                    .iter()
+                    .skip_while(|importing| **importing != cid)
+                    .chain([&cid])
+                    .map(Cid::to_string)
+                    .collect::<Vec<_>>();
+                return Err(in_module(format!("Cyclic import: {}", cycle.join(" -> "))));
+            }
+            importing.push(cid);
+        }
+        // Imports inside the module come back through this resolver while it's evaluated
+        let module = self.load(engine, &cid);
+        self.importing.lock().unwrap().pop();
+
+        let module = module.map_err(in_module)?;
+        MODULE_CACHE.lock().unwrap().insert(cid, module.clone());
+        Ok(module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ipld_codecs};
+
+    #[derive(Default)]
+    struct TestSource {
+        aliases: HashMap<String, Cid>,
+        scripts: HashMap<Cid, Vec<u8>>,
+    }
+
+    impl TestSource {
+        fn add(&mut self, alias: &str, script: &str) -> Cid {
+            let cid = ipld_codecs::cid(ipld_codecs::RAW, script.as_bytes());
+            self.aliases.insert(alias.to_owned(), cid);
+            self.scripts.insert(cid, script.as_bytes().to_vec());
+            cid
+        }
+    }
+
+    impl ModuleSource for TestSource {
+        fn alias(&self, name: &str) -> Result<Option<Cid>, String> {
+            Ok(self.aliases.get(name).copied())
+        }
+
+        fn script(&self, cid: &Cid) -> Result<Option<Vec<u8>>, String> {
+            Ok(self.scripts.get(cid).cloned())
+        }
+    }
+
+    fn engine(source: TestSource) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(BlobModuleResolver::with_source(source));
+        engine
+    }
+
+    #[test]
+    fn test_import_by_alias_and_cid() {
+        let mut source = TestSource::default();
+        let cid = source.add("helpers", "fn double(x) { x * 2 }");
+        let engine = engine(source);
+        let script =
+            format!(r#"import "helpers" as a; import "{cid}" as b; a::double(b::double(3))"#);
+        assert_eq!(engine.eval::<i64>(&script).unwrap(), 12);
+    }
+
+    #[test]
+    fn test_cyclic_import() {
+        let mut source = TestSource::default();
+        source.add("ping", r#"import "pong" as pong; fn ping() { 1 }"#);
+        source.add("pong", r#"import "ping" as ping; fn pong() { 2 }"#);
+        let engine = engine(source);
+        let error = engine.run(r#"import "ping" as ping;"#).unwrap_err();
+        assert!(error.to_string().contains("Cyclic import"), "{error}");
+    }
+
+    #[test]
+    fn test_module_cache_capacity() {
+        let mut cache = ModuleCache::default();
+        let cids = (0..=MODULE_CACHE_CAPACITY)
+            .map(|i| ipld_codecs::cid(ipld_codecs::RAW, &i.to_be_bytes()))
+            .collect::<Vec<_>>();
+        for cid in &cids {
+            cache.insert(*cid, Module::new().into());
+        }
+        assert_eq!(cache.modules.len(), MODULE_CACHE_CAPACITY);
+        assert!(cache.get(&cids[0]).is_none());
+        assert!(cache.get(&cids[MODULE_CACHE_CAPACITY]).is_some());
+    }
+
+    #[test]
+    fn test_imported_cids() {
+        let cid = ipld_codecs::cid(ipld_codecs::RAW, b"fn f() {}");
+        let script = format!(
+            "import \"{cid}\" as a;\nimport  \"helpers\" as b;\nlet important = \"{cid}\";"
+        );
+        assert_eq!(imported_cids(&script), vec![cid]);
+    }
+}
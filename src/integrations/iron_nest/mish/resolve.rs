@@ -0,0 +1,176 @@
+//! Resolves paths like `fish_tank/config/lights/0` through a mish state and the DAG-JSON and
+//! DAG-CBOR blocks it links to. The first segment is a mish state name or a CID.
+
+use {
+    crate::{
+        components::mish::{
+            ipld_blob_page::get_ipld_blob_query, mish_state_page::get_mish_state_query,
+        },
+        integrations::iron_nest::types::mish::{ResolvedLink, ResolvedPath},
+        ipld_codecs,
+    },
+    cid::Cid,
+    ipld_core::ipld::Ipld,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResolveError {
+    #[error("Database error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("Empty path")]
+    EmptyPath,
+
+    #[error("Mish state {0} not found")]
+    MishStateNotFound(String),
+
+    #[error("Block {0} not found")]
+    BlockNotFound(Cid),
+
+    #[error("Block {cid} could not be decoded: {error}")]
+    InvalidBlock { cid: Cid, error: String },
+
+    #[error("Mish state {name} is not valid DAG-JSON: {error}")]
+    InvalidMishState { name: String, error: String },
+
+    #[error("Nothing at {path}")]
+    NotFound { path: String },
+
+    #[error("Cannot traverse into {kind} at {path}")]
+    NotTraversable { path: String, kind: &'static str },
+}
+
+impl ResolveError {
+    /// Whether the error is due to something missing rather than something broken.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::MishStateNotFound(_) | Self::BlockNotFound(_) | Self::NotFound { .. }
+        )
+    }
+}
+
+fn kind(ipld: &Ipld) -> &'static str {
+    match ipld {
+        Ipld::Null => "null",
+        Ipld::Bool(_) => "a boolean",
+        Ipld::Integer(_) => "an integer",
+        Ipld::Float(_) => "a float",
+        Ipld::String(_) => "a string",
+        Ipld::Bytes(_) => "bytes",
+        Ipld::List(_) => "a list",
+        Ipld::Map(_) => "a map",
+        Ipld::Link(_) => "a link",
+    }
+}
+
+async fn load_block(pool: &sqlx::PgPool, cid: &Cid) -> Result<Ipld, ResolveError> {
+    let content = get_ipld_blob_query(pool, cid)
+        .await?
+        .ok_or(ResolveError::BlockNotFound(*cid))?;
+    ipld_codecs::decode(cid.codec(), &content).map_err(|e| ResolveError::InvalidBlock {
+        cid: *cid,
+        error: e.to_string(),
+    })
+}
+
+fn step(node: Ipld, segment: &str, path: &str) -> Result<Ipld, ResolveError> {
+    let not_found = || ResolveError::NotFound {
+        path: path.to_owned(),
+    };
+    match node {
+        Ipld::Map(mut map) => map.remove(segment).ok_or_else(not_found),
+        Ipld::List(mut list) => {
+            let index = segment.parse::<usize>().map_err(|_| not_found())?;
+            if index < list.len() {
+                Ok(list.swap_remove(index))
+            } else {
+                Err(not_found())
+            }
+        }
+        node => Err(ResolveError::NotTraversable {
+            path: path.to_owned(),
+            kind: kind(&node),
+        }),
+    }
+}
+
+/// Links are followed transparently, including one at the end of the path. Raw blocks are left
+/// as links since they have no structure to show.
+pub async fn resolve_path(pool: &sqlx::PgPool, path: &str) -> Result<ResolvedPath, ResolveError> {
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let root = segments.next().ok_or(ResolveError::EmptyPath)?;
+    let mut current = root.to_owned();
+    let mut links = Vec::new();
+    let mut node = match root.parse::<Cid>() {
+        Ok(cid) => Ipld::Link(cid),
+        Err(_) => {
+            let state = get_mish_state_query(pool, root)
+                .await?
+                .ok_or_else(|| ResolveError::MishStateNotFound(root.to_owned()))?;
+            // Mish states are stored as JSON but use the DAG-JSON link form
+            let content =
+                serde_json::to_vec(&state.state).map_err(|e| ResolveError::InvalidMishState {
+                    name: root.to_owned(),
+                    error: e.to_string(),
+                })?;
+            ipld_codecs::decode(ipld_codecs::DAG_JSON, &content).map_err(|e| {
+                ResolveError::InvalidMishState {
+                    name: root.to_owned(),
+                    error: e.to_string(),
+                }
+            })?
+        }
+    };
+    loop {
+        if let Ipld::Link(cid) = node
+            && cid.codec() != ipld_codecs::RAW
+        {
+            links.push(ResolvedLink {
+                path: current.clone(),
+                cid: cid.to_string(),
+            });
+            node = load_block(pool, &cid).await?;
+            continue;
+        }
+        let Some(segment) = segments.next() else {
+            break;
+        };
+        current = format!("{current}/{segment}");
+        node = step(node, segment, &current)?;
+    }
+    let value = ipld_codecs::encode(ipld_codecs::DAG_JSON, &node)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or(serde_json::Value::Null);
+    Ok(ResolvedPath { value, links })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::collections::BTreeMap};
+
+    #[test]
+    fn test_step() {
+        let node = Ipld::Map(BTreeMap::from([(
+            "lights".to_owned(),
+            Ipld::List(vec![Ipld::Bool(true), Ipld::Bool(false)]),
+        )]));
+        let lights = step(node, "lights", "tank/lights").unwrap();
+        assert_eq!(
+            step(lights.clone(), "1", "tank/lights/1").unwrap(),
+            Ipld::Bool(false)
+        );
+        assert!(matches!(
+            step(lights, "2", "tank/lights/2"),
+            Err(ResolveError::NotFound { path }) if path == "tank/lights/2"
+        ));
+        assert!(matches!(
+            step(Ipld::Bool(true), "on", "tank/lights/0/on"),
+            Err(ResolveError::NotTraversable {
+                kind: "a boolean",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+use {crate::components::mish::ipld_blob_page::get_ipld_blob_query, cid::Cid};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MishStateSchemaError {
+    #[error("Database error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("Invalid schema CID: {0}")]
+    InvalidCid(#[from] cid::Error),
+
+    #[error("Schema blob {0} not found")]
+    SchemaNotFound(Cid),
+
+    #[error("Schema blob {0} is not JSON: {1}")]
+    SchemaNotJson(Cid, serde_json::Error),
+
+    #[error("Schema blob {0} is not a valid JSON Schema: {1}")]
+    InvalidSchema(Cid, String),
+
+    #[error("Mish state {name} does not match schema {schema_cid}: {}", errors.join("; "))]
+    Invalid {
+        name: String,
+        schema_cid: Cid,
+        errors: Vec<String>,
+    },
+}
+
+pub async fn get_mish_state_schema_cid(
+    pool: &sqlx::PgPool,
+    name: &str,
+) -> Result<Option<Cid>, MishStateSchemaError> {
+    let query = "
+        SELECT schema_cid
+        FROM mish_states
+        WHERE name = $1
+    ";
+    let schema_cid = sqlx::query_scalar::<_, Option<Vec<u8>>>(query)
+        .bind(name)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+    Ok(schema_cid.map(Cid::try_from).transpose()?)
+}
+
+pub async fn get_schema(
+    pool: &sqlx::PgPool,
+    schema_cid: &Cid,
+) -> Result<serde_json::Value, MishStateSchemaError> {
+    let content = get_ipld_blob_query(pool, schema_cid)
+        .await?
+        .ok_or(MishStateSchemaError::SchemaNotFound(*schema_cid))?;
+    serde_json::from_slice(&content)
+        .map_err(|e| MishStateSchemaError::SchemaNotJson(*schema_cid, e))
+}
+
+pub async fn get_mish_state_schema(
+    pool: &sqlx::PgPool,
+    name: &str,
+) -> Result<Option<serde_json::Value>, MishStateSchemaError> {
+    match get_mish_state_schema_cid(pool, name).await? {
+        Some(schema_cid) => Ok(Some(get_schema(pool, &schema_cid).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Checks `state` against the schema the mish state references, if any. Call this before
+/// writing a new state.
+pub async fn validate_mish_state(
+    pool: &sqlx::PgPool,
+    name: &str,
+    state: &serde_json::Value,
+) -> Result<(), MishStateSchemaError> {
+    let Some(schema_cid) = get_mish_state_schema_cid(pool, name).await? else {
+        return Ok(());
+    };
+    let schema = get_schema(pool, &schema_cid).await?;
+    validate(name, &schema_cid, &schema, state)
+}
+
+pub fn validate(
+    name: &str,
+    schema_cid: &Cid,
+    schema: &serde_json::Value,
+    state: &serde_json::Value,
+) -> Result<(), MishStateSchemaError> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| MishStateSchemaError::InvalidSchema(*schema_cid, e.to_string()))?;
+    let errors = validator
+        .iter_errors(state)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            let path = if path.is_empty() {
+                "/".to_owned()
+            } else {
+                path
+            };
+            format!("{path}: {e}")
+        })
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(MishStateSchemaError::Invalid {
+            name: name.to_owned(),
+            schema_cid: *schema_cid,
+            errors,
+        })
+    }
+}
+
+/// Points the mish state at a new schema (or removes it), rejecting the change if the current
+/// state doesn't validate against the new schema.
+pub async fn set_mish_state_schema_query(
+    pool: &sqlx::PgPool,
+    name: &str,
+    schema_cid: Option<Cid>,
+) -> Result<(), MishStateSchemaError> {
+    if let Some(schema_cid) = schema_cid {
+        let schema = get_schema(pool, &schema_cid).await?;
+        let query = "
+            SELECT state
+            FROM mish_states
+            WHERE name = $1
+        ";
+        let state = sqlx::query_scalar::<_, serde_json::Value>(query)
+            .bind(name)
+            .fetch_optional(pool)
+            .await?;
+        if let Some(state) = state {
+            validate(name, &schema_cid, &schema, &state)?;
+        }
+    }
+    let query = "
+        INSERT INTO mish_states (name, state, schema_cid)
+        VALUES ($1, '{}'::jsonb, $2)
+        ON CONFLICT (name) DO UPDATE SET
+            schema_cid = EXCLUDED.schema_cid
+    ";
+    sqlx::query(query)
+        .bind(name)
+        .bind(schema_cid.map(|cid| cid.to_bytes()))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::ipld_codecs};
+
+    #[test]
+    fn test_validate() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pump": {
+                    "type": "object",
+                    "properties": { "on": { "type": "boolean" } }
+                },
+                "mode": { "enum": ["day", "night"] }
+            },
+            "required": ["mode"]
+        });
+        let schema_cid = ipld_codecs::dag_json_cid(&schema).unwrap();
+
+        let valid = serde_json::json!({ "pump": { "on": true }, "mode": "day" });
+        assert!(validate("fish_tank", &schema_cid, &schema, &valid).is_ok());
+
+        let invalid = serde_json::json!({ "pump": { "on": "yes" }, "mode": "evening" });
+        match validate("fish_tank", &schema_cid, &schema, &invalid) {
+            Err(MishStateSchemaError::Invalid { errors, .. }) => {
+                assert_eq!(errors.len(), 2);
+                assert!(errors.iter().any(|e| e.starts_with("/pump/on: ")));
+                assert!(errors.iter().any(|e| e.starts_with("/mode: ")));
+            }
+            other => panic!("Expected validation errors, got {other:?}"),
+        }
+
+        let missing = serde_json::json!({});
+        assert!(matches!(
+            validate("fish_tank", &schema_cid, &schema, &missing),
+            Err(MishStateSchemaError::Invalid { errors, .. }) if errors.len() == 1 && errors[0].starts_with("/: ")
+        ));
+    }
+}
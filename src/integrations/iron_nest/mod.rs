@@ -4,6 +4,16 @@ pub mod types;
 cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
   pub mod client;
   pub use client::*;
+  pub mod capabilities;
+  pub mod config;
   pub mod cron;
+  pub mod energy;
+  pub mod functions;
   pub mod mish;
+  pub mod scenes;
+  pub mod sequence;
+  pub mod solar;
+  pub mod state_history;
+  pub mod supervisor;
+  pub mod vacation;
 }}
@@ -0,0 +1,384 @@
+//! Captures the states of devices into [`Scene`]s and applies them again.
+
+use {
+    super::{
+        functions::{FunctionError, FunctionRegistry, FunctionResult},
+        state_history::{current_source, set_power_states_query},
+        types::{
+            Device, DeviceType, FunctionInfo,
+            scene::{DEFAULT_CONCURRENCY, DeviceState, Scene, SceneDevice, SceneTarget},
+        },
+    },
+    crate::{
+        integrations::{
+            roku::{roku_is_powered_on, roku_send_keypress},
+            tplink::{
+                tplink_get_sysinfo, tplink_set_relay_state, tplink_transition_dimmer,
+                tplink_transition_light_state,
+            },
+        },
+        server::scenes::{get_scene_query, save_scene_query},
+    },
+    chrono::Utc,
+    futures::{StreamExt, stream},
+    serde::Deserialize,
+    serde_json::{Value, json},
+    sqlx::PgPool,
+    std::{collections::HashMap, io},
+};
+
+fn failed(e: impl ToString) -> FunctionError {
+    FunctionError::Failed(e.to_string())
+}
+
+/// Reads the current state of `device` from the device itself.
+pub async fn capture_device_state(device: &Device) -> Result<DeviceState, FunctionError> {
+    let ip = device.ip.as_str();
+    match device.device_type {
+        DeviceType::KasaPlug => {
+            let sysinfo = tplink_get_sysinfo(ip).await.map_err(failed)?;
+            Ok(DeviceState::Relay {
+                on: sysinfo["relay_state"] == 1,
+            })
+        }
+        DeviceType::KasaPowerStrip => {
+            let child_id = device
+                .child_id
+                .as_deref()
+                .ok_or_else(|| failed("Power strip socket without a child id"))?;
+            let sysinfo = tplink_get_sysinfo(ip).await.map_err(failed)?;
+            // Depending on the firmware children have the full id or only the socket number
+            let child = sysinfo["children"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|child| {
+                    child["id"]
+                        .as_str()
+                        .is_some_and(|id| child_id.ends_with(id))
+                })
+                .ok_or_else(|| failed(format!("Socket {child_id} not found on {ip}")))?;
+            Ok(DeviceState::Relay {
+                on: child["state"] == 1,
+            })
+        }
+        DeviceType::KasaDimmer => {
+            let sysinfo = tplink_get_sysinfo(ip).await.map_err(failed)?;
+            Ok(DeviceState::Dimmer {
+                on: sysinfo["relay_state"] == 1,
+                brightness: percentage(&sysinfo["brightness"]).unwrap_or(100),
+            })
+        }
+        DeviceType::KasaLight => {
+            let sysinfo = tplink_get_sysinfo(ip).await.map_err(failed)?;
+            let light_state = &sysinfo["light_state"];
+            let on = light_state["on_off"] == 1;
+            // Lights that are off keep what they turn on to in dft_on_state
+            let values = if on {
+                light_state
+            } else {
+                &light_state["dft_on_state"]
+            };
+            let number = |key: &str| values[key].as_u64();
+            Ok(DeviceState::Light {
+                on,
+                brightness: percentage(&values["brightness"]).unwrap_or(100),
+                hue: (sysinfo["is_color"] == 1)
+                    .then(|| number("hue").and_then(|hue| u16::try_from(hue).ok()))
+                    .flatten(),
+                saturation: (sysinfo["is_color"] == 1)
+                    .then(|| percentage(&values["saturation"]))
+                    .flatten(),
+                color_temp: (sysinfo["is_variable_color_temp"] == 1)
+                    .then(|| number("color_temp").and_then(|temp| u16::try_from(temp).ok()))
+                    .flatten(),
+            })
+        }
+        DeviceType::RokuTv => Ok(DeviceState::Roku {
+            on: roku_is_powered_on(ip).await.map_err(failed)?,
+        }),
+        ref device_type => Err(failed(format!(
+            "Can't capture the state of a {device_type}"
+        ))),
+    }
+}
+
+fn percentage(value: &Value) -> Option<u8> {
+    value
+        .as_u64()
+        .and_then(|value| u8::try_from(value.min(100)).ok())
+}
+
+/// Sets `device` to `state`, fading lights and dimmers over `transition_ms`.
+pub async fn apply_device_state(
+    device: &Device,
+    state: DeviceState,
+    transition_ms: u32,
+) -> Result<(), FunctionError> {
+    let ip = device.ip.as_str();
+    match (state, &device.device_type) {
+        (DeviceState::Relay { on }, DeviceType::KasaPlug) => {
+            tplink_set_relay_state(ip, None, on).await
+        }
+        (DeviceState::Relay { on }, DeviceType::KasaPowerStrip) => {
+            tplink_set_relay_state(ip, device.child_id.as_deref(), on).await
+        }
+        (
+            DeviceState::Dimmer {
+                on: true,
+                brightness,
+            },
+            DeviceType::KasaDimmer,
+        ) => tplink_transition_dimmer(ip, brightness, transition_ms).await,
+        (DeviceState::Dimmer { on: false, .. }, DeviceType::KasaDimmer) => {
+            tplink_set_relay_state(ip, None, false).await
+        }
+        (
+            DeviceState::Light {
+                on,
+                brightness,
+                hue,
+                saturation,
+                color_temp,
+            },
+            DeviceType::KasaLight,
+        ) => {
+            let mut light_state = json!({ "on_off": u8::from(on) });
+            if on {
+                light_state["brightness"] = json!(brightness);
+                if let Some(hue) = hue {
+                    light_state["hue"] = json!(hue);
+                }
+                if let Some(saturation) = saturation {
+                    light_state["saturation"] = json!(saturation);
+                }
+                if let Some(color_temp) = color_temp {
+                    light_state["color_temp"] = json!(color_temp);
+                }
+            }
+            tplink_transition_light_state(ip, light_state, transition_ms).await
+        }
+        (DeviceState::Roku { on }, DeviceType::RokuTv) => {
+            roku_send_keypress(ip, if on { "PowerOn" } else { "PowerOff" })
+                .await
+                .map_err(io::Error::other)
+        }
+        (state, device_type) => {
+            return Err(failed(format!("A {device_type} can't be set to {state:?}")));
+        }
+    }
+    .map_err(|e| failed(format!("{ip}: {e}")))
+}
+
+async fn get_devices_query(
+    pool: &PgPool,
+    device_ids: &[i64],
+) -> Result<HashMap<i64, Device>, FunctionError> {
+    let query = "
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+        FROM device
+        WHERE id = ANY($1)
+    ";
+    let devices = sqlx::query_as::<_, Device>(query)
+        .bind(device_ids)
+        .fetch_all(pool)
+        .await
+        .map_err(failed)?;
+    Ok(devices
+        .into_iter()
+        .map(|device| (device.id, device))
+        .collect())
+}
+
+/// Captures the states of `targets` into the scene `name`, replacing it if it exists.
+pub async fn capture_scene(
+    pool: &PgPool,
+    name: &str,
+    targets: &[SceneTarget],
+    concurrency: i32,
+) -> Result<Scene, FunctionError> {
+    if targets.is_empty() {
+        return Err(failed("A scene needs at least one device"));
+    }
+    let device_ids = targets
+        .iter()
+        .map(|target| target.device_id)
+        .collect::<Vec<_>>();
+    let devices = get_devices_query(pool, &device_ids).await?;
+    let captured = stream::iter(targets)
+        .map(|target| {
+            let device = devices.get(&target.device_id);
+            async move {
+                let device = device
+                    .ok_or_else(|| failed(format!("Device {} not found", target.device_id)))?;
+                let state = capture_device_state(device)
+                    .await
+                    .map_err(|e| failed(format!("{}: {e}", device.name)))?;
+                Ok(SceneDevice {
+                    device_id: device.id,
+                    name: device.name.clone(),
+                    state,
+                    transition_ms: target.transition_ms,
+                })
+            }
+        })
+        .buffered(concurrency.max(1) as usize)
+        .collect::<Vec<Result<_, FunctionError>>>()
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    let scene = Scene {
+        name: name.to_owned(),
+        devices: captured,
+        concurrency,
+        updated_at: Utc::now(),
+    };
+    save_scene_query(pool, &scene).await.map_err(failed)?;
+    Ok(scene)
+}
+
+/// Sets every device of the scene `name` to its captured state, `scene.concurrency` devices at
+/// a time. Devices that fail don't stop the others.
+pub async fn apply_scene(pool: &PgPool, name: &str) -> FunctionResult {
+    let scene = get_scene_query(pool, name)
+        .await
+        .map_err(failed)?
+        .ok_or_else(|| failed(format!("Scene {name} not found")))?;
+    let device_ids = scene
+        .devices
+        .iter()
+        .map(|scene_device| scene_device.device_id)
+        .collect::<Vec<_>>();
+    let devices = get_devices_query(pool, &device_ids).await?;
+    let results = stream::iter(&scene.devices)
+        .map(|scene_device| {
+            let device = devices.get(&scene_device.device_id);
+            async move {
+                let result = match device {
+                    Some(device) => {
+                        apply_device_state(device, scene_device.state, scene_device.transition_ms)
+                            .await
+                    }
+                    None => Err(failed("Device not found")),
+                };
+                (scene_device, result)
+            }
+        })
+        .buffer_unordered(scene.concurrency.max(1) as usize)
+        .collect::<Vec<_>>()
+        .await;
+
+    let (applied, errors): (Vec<_>, Vec<_>) =
+        results.into_iter().partition(|(_, result)| result.is_ok());
+    let states = applied
+        .iter()
+        .map(|(scene_device, _)| (scene_device.device_id, scene_device.state.is_on()))
+        .collect::<Vec<_>>();
+    set_power_states_query(pool, &states, current_source())
+        .await
+        .map_err(failed)?;
+
+    if errors.is_empty() {
+        Ok(json!({ "scene": name, "devices": applied.len() }))
+    } else {
+        let errors = errors
+            .into_iter()
+            .filter_map(|(scene_device, result)| {
+                result.err().map(|e| format!("{}: {e}", scene_device.name))
+            })
+            .collect::<Vec<_>>();
+        Err(failed(format!(
+            "{} of {} devices failed: {}",
+            errors.len(),
+            scene.devices.len(),
+            errors.join("; ")
+        )))
+    }
+}
+
+#[derive(Deserialize)]
+struct ApplyArgs {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct CaptureArgs {
+    name: String,
+    devices: Option<Vec<SceneTarget>>,
+}
+
+fn function_info(name: &str, description: &str, parameters: Value) -> FunctionInfo {
+    FunctionInfo {
+        name: name.to_owned(),
+        integration: "iron_nest".to_owned(),
+        description: description.to_owned(),
+        parameters,
+        ip_device_types: vec![],
+    }
+}
+
+pub fn register_functions(registry: &mut FunctionRegistry) {
+    registry.register(
+        function_info(
+            "scene_apply",
+            "Apply a scene, setting its devices back to the states captured in it",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name of the scene" },
+                },
+                "required": ["name"],
+            }),
+        ),
+        |pool, args: ApplyArgs| async move { apply_scene(&pool, &args.name).await },
+    );
+    registry.register(
+        function_info(
+            "scene_capture",
+            "Capture the current states of devices into a scene, by default the devices already in it",
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string", "description": "Name of the scene" },
+                    "devices": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "device_id": { "type": "integer" },
+                                "transition_ms": { "type": "integer", "minimum": 0 },
+                            },
+                            "required": ["device_id"],
+                        },
+                    },
+                },
+                "required": ["name"],
+            }),
+        ),
+        |pool, args: CaptureArgs| async move {
+            let existing = get_scene_query(&pool, &args.name).await.map_err(failed)?;
+            let concurrency = existing
+                .as_ref()
+                .map_or(DEFAULT_CONCURRENCY, |scene| scene.concurrency);
+            let targets = match (args.devices, existing) {
+                (Some(devices), _) => devices,
+                (None, Some(scene)) => scene
+                    .devices
+                    .iter()
+                    .map(|scene_device| SceneTarget {
+                        device_id: scene_device.device_id,
+                        transition_ms: scene_device.transition_ms,
+                    })
+                    .collect(),
+                (None, None) => {
+                    return Err(failed(format!(
+                        "Scene {} not found, pass the devices to capture",
+                        args.name
+                    )));
+                }
+            };
+            let scene = capture_scene(&pool, &args.name, &targets, concurrency).await?;
+            serde_json::to_value(scene).map_err(failed)
+        },
+    );
+}
@@ -0,0 +1,386 @@
+//! Runs the steps of [`Sequence`] actions.
+
+use {
+    super::{
+        execute_function,
+        functions::{FunctionError, FunctionResult, function_registry},
+        types::{
+            Sequence, SequenceCondition, SequenceErrorPolicy, SequenceStep,
+            mish::select_json_via_jsonpath,
+        },
+    },
+    crate::components::mish::mish_state_page::get_mish_state_query,
+    futures::{FutureExt, future::BoxFuture},
+    serde_json::{Value, json},
+    sqlx::PgPool,
+    std::time::Duration,
+};
+
+/// Checks the functions and arguments of every step, e.g. before saving an action.
+pub fn validate_sequence(sequence: &Sequence) -> Result<(), String> {
+    fn validate_steps(steps: &[SequenceStep]) -> Result<(), String> {
+        steps.iter().try_for_each(|step| match step {
+            SequenceStep::Function {
+                function_name,
+                function_args,
+            } => function_registry()
+                .validate(function_name, function_args)
+                .map_err(|e| e.to_string()),
+            SequenceStep::Wait { seconds } if !seconds.is_finite() || *seconds < 0.0 => {
+                Err(format!("Can't wait {seconds} seconds"))
+            }
+            SequenceStep::Wait { .. } => Ok(()),
+            SequenceStep::If {
+                then, otherwise, ..
+            } => validate_steps(then).and_then(|()| validate_steps(otherwise)),
+            SequenceStep::Parallel { branches } => branches
+                .iter()
+                .try_for_each(|branch| validate_steps(branch)),
+        })
+    }
+    if sequence.steps.is_empty() {
+        return Err("A sequence needs at least one step".to_owned());
+    }
+    validate_steps(&sequence.steps)
+}
+
+#[derive(Default)]
+struct Outcome {
+    results: Vec<Value>,
+    errors: Vec<String>,
+}
+
+/// Carries out the functions and conditions of steps. The pool calls each function once, cron
+/// retries failed ones as their action says and tests use a fake.
+pub trait StepRunner: Sync {
+    fn call<'a>(
+        &'a self,
+        function_name: &'a str,
+        function_args: &'a Value,
+    ) -> BoxFuture<'a, FunctionResult>;
+
+    fn check<'a>(
+        &'a self,
+        condition: &'a SequenceCondition,
+    ) -> BoxFuture<'a, Result<bool, FunctionError>>;
+}
+
+impl StepRunner for PgPool {
+    fn call<'a>(
+        &'a self,
+        function_name: &'a str,
+        function_args: &'a Value,
+    ) -> BoxFuture<'a, FunctionResult> {
+        execute_function(self, function_name.to_owned(), function_args.clone()).boxed()
+    }
+
+    fn check<'a>(
+        &'a self,
+        condition: &'a SequenceCondition,
+    ) -> BoxFuture<'a, Result<bool, FunctionError>> {
+        check_condition(self, condition).boxed()
+    }
+}
+
+/// Returns what each step returned, failing once a step fails if the sequence aborts on errors
+/// or after the last step otherwise.
+pub async fn run_sequence(runner: &impl StepRunner, sequence: &Sequence) -> FunctionResult {
+    // Errors of nested steps are in their branch's results too, but those are only returned
+    // when nothing failed
+    let outcome = run_steps(runner, &sequence.steps, sequence.on_error).await?;
+    if outcome.errors.is_empty() {
+        Ok(Value::Array(outcome.results))
+    } else {
+        Err(FunctionError::Failed(format!(
+            "{} step(s) failed: {}",
+            outcome.errors.len(),
+            outcome.errors.join("; ")
+        )))
+    }
+}
+
+fn run_steps<'a, R: StepRunner>(
+    runner: &'a R,
+    steps: &'a [SequenceStep],
+    on_error: SequenceErrorPolicy,
+) -> BoxFuture<'a, Result<Outcome, FunctionError>> {
+    async move {
+        let mut outcome = Outcome::default();
+        for step in steps {
+            let result = match step {
+                SequenceStep::Function {
+                    function_name,
+                    function_args,
+                } => runner.call(function_name, function_args).await,
+                SequenceStep::Wait { seconds } => {
+                    let duration = Duration::try_from_secs_f64(*seconds).unwrap_or_default();
+                    tokio::time::sleep(duration).await;
+                    Ok(Value::Null)
+                }
+                SequenceStep::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => match runner.check(condition).await {
+                    Ok(holds) => {
+                        let branch = if holds { then } else { otherwise };
+                        let branch = run_steps(runner, branch, on_error).await?;
+                        outcome.errors.extend(branch.errors);
+                        Ok(json!({"condition": holds, "steps": branch.results}))
+                    }
+                    Err(e) => Err(e),
+                },
+                SequenceStep::Parallel { branches } => {
+                    let branches = futures::future::join_all(
+                        branches
+                            .iter()
+                            .map(|branch| run_steps(runner, branch, on_error)),
+                    )
+                    .await
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()?;
+                    let results = branches
+                        .into_iter()
+                        .map(|branch| {
+                            outcome.errors.extend(branch.errors);
+                            Value::Array(branch.results)
+                        })
+                        .collect();
+                    Ok(Value::Array(results))
+                }
+            };
+            match result {
+                Ok(result) => outcome.results.push(result),
+                Err(e) if on_error == SequenceErrorPolicy::Continue => {
+                    outcome.results.push(json!({"error": e.to_string()}));
+                    outcome.errors.push(e.to_string());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(outcome)
+    }
+    .boxed()
+}
+
+async fn check_condition(
+    pool: &PgPool,
+    condition: &SequenceCondition,
+) -> Result<bool, FunctionError> {
+    match condition {
+        SequenceCondition::DevicePowerState {
+            device_id,
+            power_state,
+        } => {
+            let query = "
+                SELECT power_state
+                FROM device
+                WHERE id = $1
+            ";
+            let actual = sqlx::query_scalar::<_, i32>(query)
+                .bind(device_id)
+                .fetch_optional(pool)
+                .await
+                .map_err(|e| FunctionError::Failed(e.to_string()))?
+                .ok_or_else(|| FunctionError::Failed(format!("Device {device_id} not found")))?;
+            Ok(actual == *power_state)
+        }
+        SequenceCondition::MishState { name, path, equals } => {
+            let mish_state = get_mish_state_query(pool, name)
+                .await
+                .map_err(|e| FunctionError::Failed(e.to_string()))?
+                .ok_or_else(|| FunctionError::Failed(format!("Mish state {name} not found")))?;
+            let value = select_json_via_jsonpath(&mish_state.state, path)
+                .map_err(|e| FunctionError::Failed(format!("{path} in {name}: {e}")))?;
+            Ok(value == *equals)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Functions named `fail*` fail, the others return their name. Device power conditions
+    /// hold for power state 1, mish state conditions can't be checked.
+    struct FakeRunner;
+
+    impl StepRunner for FakeRunner {
+        fn call<'a>(
+            &'a self,
+            function_name: &'a str,
+            _function_args: &'a Value,
+        ) -> BoxFuture<'a, FunctionResult> {
+            async move {
+                if function_name.starts_with("fail") {
+                    Err(FunctionError::Failed(format!("{function_name} failed")))
+                } else {
+                    Ok(json!(function_name))
+                }
+            }
+            .boxed()
+        }
+
+        fn check<'a>(
+            &'a self,
+            condition: &'a SequenceCondition,
+        ) -> BoxFuture<'a, Result<bool, FunctionError>> {
+            async move {
+                match condition {
+                    SequenceCondition::DevicePowerState { power_state, .. } => {
+                        Ok(*power_state == 1)
+                    }
+                    SequenceCondition::MishState { name, .. } => {
+                        Err(FunctionError::Failed(format!("{name} can't be checked")))
+                    }
+                }
+            }
+            .boxed()
+        }
+    }
+
+    fn call(function_name: &str, function_args: Value) -> SequenceStep {
+        SequenceStep::Function {
+            function_name: function_name.to_owned(),
+            function_args,
+        }
+    }
+
+    fn power_is(power_state: i32, then: Vec<SequenceStep>) -> SequenceStep {
+        SequenceStep::If {
+            condition: SequenceCondition::DevicePowerState {
+                device_id: 1,
+                power_state,
+            },
+            then,
+            otherwise: vec![call("otherwise", json!({}))],
+        }
+    }
+
+    /// Fails once at the top, once in an `If` and once in each branch of a `Parallel`.
+    fn failing_steps() -> Vec<SequenceStep> {
+        vec![
+            call("fail_first", json!({})),
+            power_is(
+                1,
+                vec![call("fail_then", json!({})), call("then", json!({}))],
+            ),
+            SequenceStep::Parallel {
+                branches: vec![
+                    vec![call("fail_left", json!({}))],
+                    vec![
+                        call("right", json!({})),
+                        power_is(1, vec![call("fail_right", json!({}))]),
+                    ],
+                ],
+            },
+            call("last", json!({})),
+        ]
+    }
+
+    fn sequence(steps: Vec<SequenceStep>, on_error: SequenceErrorPolicy) -> Sequence {
+        Sequence { steps, on_error }
+    }
+
+    #[test]
+    fn test_validate_sequence() {
+        let scene = || call("scene_apply", json!({"name": "Evening"}));
+        let validate = |steps| validate_sequence(&sequence(steps, SequenceErrorPolicy::Abort));
+
+        assert!(validate(vec![scene(), SequenceStep::Wait { seconds: 1.5 }]).is_ok());
+        assert!(validate(vec![]).is_err());
+        assert!(validate(vec![SequenceStep::Wait { seconds: -1.0 }]).is_err());
+        assert!(validate(vec![SequenceStep::Wait { seconds: f64::NAN }]).is_err());
+        assert!(
+            validate(vec![power_is(1, vec![scene()])])
+                .unwrap_err()
+                .contains("otherwise")
+        );
+        assert!(
+            validate(vec![SequenceStep::Parallel {
+                branches: vec![vec![scene()], vec![call("scene_apply", json!({}))]],
+            }])
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_steps_continue() {
+        let outcome = run_steps(&FakeRunner, &failing_steps(), SequenceErrorPolicy::Continue)
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome.errors,
+            [
+                "fail_first failed",
+                "fail_then failed",
+                "fail_left failed",
+                "fail_right failed"
+            ]
+        );
+        assert_eq!(
+            outcome.results,
+            [
+                json!({"error": "fail_first failed"}),
+                json!({"condition": true, "steps": [{"error": "fail_then failed"}, "then"]}),
+                json!([
+                    [{"error": "fail_left failed"}],
+                    ["right", {"condition": true, "steps": [{"error": "fail_right failed"}]}]
+                ]),
+                json!("last"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_steps_abort() {
+        let result = run_steps(
+            &FakeRunner,
+            &failing_steps()[1..],
+            SequenceErrorPolicy::Abort,
+        )
+        .await;
+        assert!(matches!(result, Err(FunctionError::Failed(e)) if e == "fail_then failed"));
+
+        let outcome = run_steps(
+            &FakeRunner,
+            &[power_is(0, vec![call("fail", json!({}))])],
+            SequenceErrorPolicy::Abort,
+        )
+        .await
+        .unwrap();
+        assert!(outcome.errors.is_empty());
+        assert_eq!(
+            outcome.results,
+            [json!({"condition": false, "steps": ["otherwise"]})]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_sequence_reports_each_error_once() {
+        let mut steps = failing_steps();
+        steps.push(SequenceStep::If {
+            condition: SequenceCondition::MishState {
+                name: "run".to_owned(),
+                path: "$.away".to_owned(),
+                equals: json!(true),
+            },
+            then: vec![],
+            otherwise: vec![],
+        });
+        let result =
+            run_sequence(&FakeRunner, &sequence(steps, SequenceErrorPolicy::Continue)).await;
+        assert!(matches!(
+            result,
+            Err(FunctionError::Failed(e)) if e == "5 step(s) failed: fail_first failed; \
+                fail_then failed; fail_left failed; fail_right failed; run can't be checked"
+        ));
+
+        let result = run_sequence(
+            &FakeRunner,
+            &sequence(vec![call("only", json!({}))], SequenceErrorPolicy::Continue),
+        )
+        .await;
+        assert_eq!(result.unwrap(), json!(["only"]));
+    }
+}
@@ -0,0 +1,231 @@
+//! Sunrise, sunset and civil twilight computed locally with NOAA's general solar position
+//! equations, good to a minute or two outside the polar regions.
+
+use {
+    super::types::{SolarEvent, SolarSchedule, config::Location},
+    chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc},
+    std::f64::consts::PI,
+};
+
+impl SolarEvent {
+    /// Degrees from the zenith of the sun's center, including refraction for sunrise and sunset.
+    fn zenith(self) -> f64 {
+        match self {
+            SolarEvent::Sunrise | SolarEvent::Sunset => 90.833,
+            SolarEvent::Dawn | SolarEvent::Dusk => 96.0,
+        }
+    }
+
+    fn rising(self) -> bool {
+        matches!(self, SolarEvent::Dawn | SolarEvent::Sunrise)
+    }
+}
+
+impl Location {
+    /// The date at the location by mean solar time, which is the day "today" means for
+    /// [`event_time`] without knowing the location's timezone.
+    pub fn solar_date(&self, time: DateTime<Utc>) -> NaiveDate {
+        let offset = Duration::seconds((self.longitude / 15.0 * 3600.0) as i64);
+        (time + offset).date_naive()
+    }
+}
+
+/// When `event` happens at `location` on its local `date`, `None` if the sun doesn't get there
+/// that day, e.g. no sunset under the midnight sun.
+pub fn event_time(
+    date: NaiveDate,
+    location: &Location,
+    event: SolarEvent,
+) -> Option<DateTime<Utc>> {
+    let days_in_year = if date.leap_year() { 366.0 } else { 365.0 };
+    // Fractional year at the location's solar noon
+    let gamma =
+        2.0 * PI / days_in_year * (f64::from(date.ordinal0()) - location.longitude / 15.0 / 24.0);
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+
+    let latitude = location.latitude.to_radians();
+    let cos_hour_angle = event.zenith().to_radians().cos() / (latitude.cos() * declination.cos())
+        - latitude.tan() * declination.tan();
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let hour_angle = if event.rising() {
+        hour_angle
+    } else {
+        -hour_angle
+    };
+    let minutes = 720.0 - 4.0 * (location.longitude + hour_angle) - equation_of_time;
+    let midnight = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?);
+    Some(midnight + Duration::milliseconds((minutes * 60_000.0) as i64))
+}
+
+impl SolarSchedule {
+    /// When an action on this schedule runs on the local `date`, with its offset and clamps
+    /// applied in `timezone`.
+    pub fn time_on<Tz: TimeZone>(
+        &self,
+        date: NaiveDate,
+        location: &Location,
+        timezone: &Tz,
+    ) -> Option<DateTime<Utc>> {
+        let time = event_time(date, location, self.event)? + Duration::minutes(self.offset_minutes);
+        let local = time.with_timezone(timezone);
+        let clamp_to = |bound| {
+            local
+                .date_naive()
+                .and_time(bound)
+                .and_local_timezone(timezone.clone())
+                .earliest()
+                .map(|bound| bound.with_timezone(&Utc))
+        };
+        match (self.not_before, self.not_after) {
+            (Some(not_before), _) if local.time() < not_before => clamp_to(not_before),
+            (_, Some(not_after)) if local.time() > not_after => clamp_to(not_after),
+            _ => Some(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::NaiveTime};
+
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.0060,
+    };
+
+    fn assert_close(date: NaiveDate, location: &Location, event: SolarEvent, expected: &str) {
+        let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+        let actual = event_time(date, location, event).unwrap();
+        let difference = (actual - expected.with_timezone(&Utc)).num_seconds().abs();
+        assert!(
+            difference <= 120,
+            "{event:?} on {date} was {actual}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn test_event_time() {
+        // Almanac times, rounded to the minute
+        let june = NaiveDate::from_ymd_opt(2024, 6, 20).unwrap();
+        let december = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert_close(
+            june,
+            &NEW_YORK,
+            SolarEvent::Sunrise,
+            "2024-06-20T05:25:00-04:00",
+        );
+        assert_close(
+            june,
+            &NEW_YORK,
+            SolarEvent::Sunset,
+            "2024-06-20T20:31:00-04:00",
+        );
+        assert_close(
+            june,
+            &NEW_YORK,
+            SolarEvent::Dusk,
+            "2024-06-20T21:04:00-04:00",
+        );
+        assert_close(
+            december,
+            &NEW_YORK,
+            SolarEvent::Sunrise,
+            "2024-12-21T07:16:00-05:00",
+        );
+        assert_close(
+            december,
+            &NEW_YORK,
+            SolarEvent::Sunset,
+            "2024-12-21T16:32:00-05:00",
+        );
+
+        let london = Location {
+            latitude: 51.5074,
+            longitude: -0.1278,
+        };
+        assert_close(
+            june,
+            &london,
+            SolarEvent::Sunrise,
+            "2024-06-20T04:43:00+01:00",
+        );
+        assert_close(
+            june,
+            &london,
+            SolarEvent::Sunset,
+            "2024-06-20T21:21:00+01:00",
+        );
+
+        let sydney = Location {
+            latitude: -33.8688,
+            longitude: 151.2093,
+        };
+        assert_close(
+            december,
+            &sydney,
+            SolarEvent::Sunrise,
+            "2024-12-21T05:41:00+11:00",
+        );
+        assert_close(
+            december,
+            &sydney,
+            SolarEvent::Sunset,
+            "2024-12-21T20:05:00+11:00",
+        );
+
+        let tromso = Location {
+            latitude: 69.6492,
+            longitude: 18.9553,
+        };
+        assert_eq!(event_time(december, &tromso, SolarEvent::Sunrise), None);
+        assert_eq!(event_time(june, &tromso, SolarEvent::Sunset), None);
+    }
+
+    #[test]
+    fn test_time_on_clamps() {
+        let june = NaiveDate::from_ymd_opt(2024, 6, 20).unwrap();
+        let timezone = chrono_tz::America::New_York;
+        let schedule = SolarSchedule {
+            event: SolarEvent::Sunset,
+            offset_minutes: 30,
+            not_before: None,
+            not_after: NaiveTime::from_hms_opt(20, 0, 0),
+        };
+        assert_eq!(
+            schedule.time_on(june, &NEW_YORK, &timezone),
+            Some(Utc.with_ymd_and_hms(2024, 6, 21, 0, 0, 0).unwrap()),
+        );
+
+        let schedule = SolarSchedule {
+            not_before: NaiveTime::from_hms_opt(21, 30, 0),
+            not_after: None,
+            ..schedule
+        };
+        assert_eq!(
+            schedule.time_on(june, &NEW_YORK, &timezone),
+            Some(Utc.with_ymd_and_hms(2024, 6, 21, 1, 30, 0).unwrap()),
+        );
+
+        let schedule = SolarSchedule {
+            not_before: None,
+            ..schedule
+        };
+        let sunset = event_time(june, &NEW_YORK, SolarEvent::Sunset).unwrap();
+        assert_eq!(
+            schedule.time_on(june, &NEW_YORK, &timezone),
+            Some(sunset + Duration::minutes(30)),
+        );
+    }
+}
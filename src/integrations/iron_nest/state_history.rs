@@ -0,0 +1,148 @@
+//! Records where device state changes come from and keeps `device_state_event` small. The
+//! `device_state_event` trigger records every change to `power_state` and `battery_percentage`,
+//! see its migration.
+
+use {
+    super::types::state_history::{DeviceStateEvent, DeviceTimeline, StateSource},
+    chrono::{DateTime, Utc},
+    sqlx::PgPool,
+    std::{future::Future, time::Duration},
+};
+
+/// Events older than this are thinned out to the last one of each hour
+const RAW_DAYS: i32 = 30;
+/// Events older than this are deleted
+const KEEP_DAYS: i32 = 365;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+tokio::task_local! {
+    static SOURCE: StateSource;
+}
+
+/// Runs `future` with the device changes it makes recorded as coming from `source`.
+pub async fn with_source<F: Future>(source: StateSource, future: F) -> F::Output {
+    SOURCE.scope(source, future).await
+}
+
+/// The source set by [`with_source`], or [`StateSource::Command`] outside of it.
+pub fn current_source() -> StateSource {
+    SOURCE
+        .try_with(|source| *source)
+        .unwrap_or(StateSource::Command)
+}
+
+/// Sets the power state of each device in `states`, recording the changes as coming from
+/// `source`.
+pub async fn set_power_states_query(
+    pool: &PgPool,
+    states: &[(i64, bool)],
+    source: StateSource,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query("SELECT set_config('iron_nest.state_source', $1::device_state_source::TEXT, true)")
+        .bind(source)
+        .execute(&mut *transaction)
+        .await?;
+    let query = "
+        UPDATE device
+        SET power_state = changed.power_state
+        FROM UNNEST($1::BIGINT[], $2::INTEGER[]) AS changed(id, power_state)
+        WHERE device.id = changed.id
+    ";
+    sqlx::query(query)
+        .bind(states.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+        .bind(
+            states
+                .iter()
+                .map(|(_, on)| i32::from(*on))
+                .collect::<Vec<_>>(),
+        )
+        .execute(&mut *transaction)
+        .await?;
+    transaction.commit().await
+}
+
+pub async fn get_timeline_query(
+    pool: &PgPool,
+    device_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<DeviceTimeline, sqlx::Error> {
+    let query = "
+        SELECT power_state, battery_percentage, source, changed_at
+        FROM device_state_event
+        WHERE device_id = $1 AND changed_at <= $2
+        ORDER BY changed_at DESC, id DESC
+        LIMIT 1
+    ";
+    let initial = sqlx::query_as(query)
+        .bind(device_id)
+        .bind(from)
+        .fetch_optional(pool)
+        .await?;
+    let query = "
+        SELECT power_state, battery_percentage, source, changed_at
+        FROM device_state_event
+        WHERE device_id = $1 AND changed_at > $2 AND changed_at <= $3
+        ORDER BY changed_at, id
+    ";
+    let events = sqlx::query_as::<_, DeviceStateEvent>(query)
+        .bind(device_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(DeviceTimeline {
+        from,
+        to,
+        initial,
+        events,
+    })
+}
+
+/// Deletes events older than [`KEEP_DAYS`] and keeps only the last event of each hour for those
+/// older than [`RAW_DAYS`], which is still the state the device ended the hour in. Returns how
+/// many were deleted.
+pub async fn prune_query(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let query = "
+        DELETE FROM device_state_event
+        WHERE changed_at < NOW() - make_interval(days => $1)
+    ";
+    let expired = sqlx::query(query)
+        .bind(KEEP_DAYS)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    let query = "
+        DELETE FROM device_state_event
+        WHERE id IN (
+            SELECT id
+            FROM (
+                SELECT id, ROW_NUMBER() OVER (
+                    PARTITION BY device_id, date_trunc('hour', changed_at)
+                    ORDER BY changed_at DESC, id DESC
+                ) AS position
+                FROM device_state_event
+                WHERE changed_at < NOW() - make_interval(days => $1)
+            ) ranked
+            WHERE position > 1
+        )
+    ";
+    let downsampled = sqlx::query(query)
+        .bind(RAW_DAYS)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    Ok(expired + downsampled)
+}
+
+pub async fn state_history_job(pool: PgPool) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match prune_query(&pool).await {
+            Ok(deleted) => log::info!("Pruned {deleted} device state events"),
+            Err(e) => log::error!("Pruning device state events failed: {e}"),
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Runs each [`Integration`] in a task of its own: discovering its devices and refreshing its
+//! auth on an interval, starting and stopping with [`ControlMessage`]s from the integrations
+//! page, and restarting with a backoff when it crashes.
+
+use {
+    super::{
+        client::{get_integrations, insert_devices_into_db},
+        types::{
+            ControlMessage, Device, IntegrationStatus, TaskState,
+            capability::{Capability, DeviceCommand},
+            energy::PowerReading,
+        },
+    },
+    chrono::Utc,
+    futures::{FutureExt, future::BoxFuture},
+    log::{error, info},
+    sqlx::PgPool,
+    std::{
+        any::Any,
+        collections::{BTreeMap, HashMap},
+        sync::{Arc, OnceLock},
+        time::Duration,
+    },
+    tokio::{
+        sync::{RwLock, mpsc},
+        task::{JoinError, JoinHandle},
+        time::Instant,
+    },
+};
+
+/// Wait before the first restart, doubled after each crash up to [`MAX_BACKOFF`]
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// A task that ran this long before crashing is restarted after [`MIN_BACKOFF`] again
+const STABLE_AFTER: Duration = Duration::from_secs(600);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A source of devices, e.g. TP-Link or Ring, run by the [`Supervisor`].
+pub trait Integration: Send + Sync + 'static {
+    /// The name of its row in the `integration` table
+    fn name(&self) -> &'static str;
+
+    /// Capabilities its devices can have
+    fn capabilities(&self) -> Vec<Capability>;
+
+    fn discovery_interval(&self) -> Duration;
+
+    /// Finds the integration's devices, which the supervisor then saves.
+    fn discover<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>>;
+
+    /// Reads the current state of the integration's devices when asked to, e.g. by the refresh
+    /// button on the devices list.
+    fn refresh<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>> {
+        self.discover(pool)
+    }
+
+    /// How often to call [`Integration::refresh_auth`], `None` if it has no auth
+    fn auth_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn refresh_auth<'a>(&'a self, _pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async { Ok(()) }.boxed()
+    }
+
+    /// Carries out a command on one of its devices, which has the capability.
+    fn handle_command<'a>(
+        &'a self,
+        _device: &'a Device,
+        _command: &'a DeviceCommand,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        let name = self.name();
+        async move { Err(format!("The {name} integration can't control devices")) }.boxed()
+    }
+
+    /// Reads the power meter of one of its devices, which has [`Capability::PowerMeter`].
+    fn read_power<'a>(
+        &'a self,
+        _device: &'a Device,
+    ) -> BoxFuture<'a, Result<PowerReading, String>> {
+        let name = self.name();
+        async move { Err(format!("The {name} integration has no power meters")) }.boxed()
+    }
+
+    /// Whether it can work at the moment, e.g. that it has credentials.
+    fn health<'a>(&'a self, _pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async { Ok(()) }.boxed()
+    }
+}
+
+struct Supervised {
+    integration: Arc<dyn Integration>,
+    sender: mpsc::Sender<ControlMessage>,
+    status: Arc<RwLock<IntegrationStatus>>,
+}
+
+/// Owns the integrations' tasks, see [`supervisor`].
+pub struct Supervisor {
+    pool: PgPool,
+    integrations: BTreeMap<&'static str, Supervised>,
+}
+
+static SUPERVISOR: OnceLock<Supervisor> = OnceLock::new();
+
+/// The supervisor started by [`Supervisor::start`].
+pub fn supervisor() -> &'static Supervisor {
+    SUPERVISOR
+        .get()
+        .expect("The integration supervisor hasn't been started")
+}
+
+impl Supervisor {
+    /// Starts a task for each of `integrations`, running if it's enabled in the `integration`
+    /// table.
+    pub async fn start(
+        pool: &PgPool,
+        integrations: Vec<Arc<dyn Integration>>,
+    ) -> Result<&'static Supervisor, sqlx::Error> {
+        let enabled = get_integrations(pool)
+            .await?
+            .into_iter()
+            .map(|integration| (integration.name, integration.enabled))
+            .collect::<HashMap<_, _>>();
+        let mut supervised = BTreeMap::new();
+        for integration in integrations {
+            let name = integration.name();
+            let (sender, receiver) = mpsc::channel(10);
+            let status = Arc::new(RwLock::new(IntegrationStatus {
+                name: name.to_owned(),
+                state: TaskState::Stopped,
+                restarts: 0,
+                last_success: None,
+                last_error: None,
+                health: None,
+                capabilities: integration.capabilities(),
+            }));
+            tokio::spawn(supervise(
+                pool.clone(),
+                integration.clone(),
+                receiver,
+                status.clone(),
+                enabled.get(name).copied().unwrap_or(false),
+            ));
+            supervised.insert(
+                name,
+                Supervised {
+                    integration,
+                    sender,
+                    status,
+                },
+            );
+        }
+        let supervisor = Supervisor {
+            pool: pool.clone(),
+            integrations: supervised,
+        };
+        if SUPERVISOR.set(supervisor).is_err() {
+            panic!("The integration supervisor was started twice");
+        }
+        Ok(self::supervisor())
+    }
+
+    pub fn integration(&self, name: &str) -> Option<&Arc<dyn Integration>> {
+        self.integrations
+            .get(name)
+            .map(|supervised| &supervised.integration)
+    }
+
+    /// Whether the integration `name` has a running task.
+    pub async fn is_running(&self, name: &str) -> bool {
+        match self.integrations.get(name) {
+            Some(supervised) => supervised.status.read().await.state == TaskState::Running,
+            None => false,
+        }
+    }
+
+    /// Sends `message` to the task of the integration `name`, if there's one.
+    pub async fn send(&self, name: &str, message: ControlMessage) -> Result<(), String> {
+        let Some(supervised) = self.integrations.get(name) else {
+            return Err(format!("The {name} integration has no task"));
+        };
+        supervised
+            .sender
+            .send(message)
+            .await
+            .map_err(|e| format!("The {name} integration's task has stopped: {e}"))
+    }
+
+    /// Stops every task, waiting until they've all been told to.
+    pub async fn shutdown(&self) {
+        for name in self.integrations.keys() {
+            if let Err(e) = self.send(name, ControlMessage::Shutdown).await {
+                error!("{e}");
+            }
+        }
+    }
+
+    /// The status of each task, with a fresh health check.
+    pub async fn statuses(&self) -> Vec<IntegrationStatus> {
+        let mut statuses = Vec::with_capacity(self.integrations.len());
+        for supervised in self.integrations.values() {
+            let mut status = supervised.status.read().await.clone();
+            status.health = match tokio::time::timeout(
+                HEALTH_TIMEOUT,
+                supervised.integration.health(&self.pool),
+            )
+            .await
+            {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e),
+                Err(_) => Some("The health check timed out".to_owned()),
+            };
+            statuses.push(status);
+        }
+        statuses
+    }
+
+    /// Refreshes the devices of every running integration, returning the errors of those that
+    /// failed.
+    pub async fn refresh_all(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for supervised in self.integrations.values() {
+            if supervised.status.read().await.state != TaskState::Running {
+                continue;
+            }
+            let integration = &supervised.integration;
+            let result = save(&self.pool, integration.refresh(&self.pool).await).await;
+            if let Err(e) = &result {
+                errors.push(format!("{}: {e}", integration.name()));
+            }
+            record(&supervised.status, result).await;
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+}
+
+async fn save(pool: &PgPool, devices: Result<Vec<Device>, String>) -> Result<(), String> {
+    insert_devices_into_db(pool, &devices?)
+        .await
+        .map_err(|e| format!("Failed to save devices: {e}"))
+}
+
+async fn record(status: &RwLock<IntegrationStatus>, result: Result<(), String>) {
+    let mut status = status.write().await;
+    match result {
+        Ok(()) => status.last_success = Some(Utc::now()),
+        Err(e) => status.last_error = Some(e),
+    }
+}
+
+/// Discovers devices and refreshes auth on the integration's intervals until it's aborted.
+async fn run(
+    pool: PgPool,
+    integration: Arc<dyn Integration>,
+    status: Arc<RwLock<IntegrationStatus>>,
+) {
+    let name = integration.name();
+    let auth_interval = integration.auth_interval();
+    // Discovery may need the auth, so refresh it first
+    let mut auth = tokio::time::interval(auth_interval.unwrap_or(MAX_BACKOFF));
+    if auth_interval.is_some() {
+        auth.tick().await;
+        let result = integration.refresh_auth(&pool).await;
+        if let Err(e) = &result {
+            error!("Failed to refresh the {name} auth: {e}");
+            record(&status, result).await;
+        }
+    }
+    let mut discovery = tokio::time::interval(integration.discovery_interval());
+    loop {
+        tokio::select! {
+            _ = auth.tick(), if auth_interval.is_some() => {
+                let result = integration.refresh_auth(&pool).await;
+                if let Err(e) = &result {
+                    error!("Failed to refresh the {name} auth: {e}");
+                    record(&status, result).await;
+                }
+            },
+            _ = discovery.tick() => {
+                let result = save(&pool, integration.discover(&pool).await).await;
+                if let Err(e) = &result {
+                    error!("{name} discovery failed: {e}");
+                }
+                record(&status, result).await;
+            },
+        }
+    }
+}
+
+fn panic_message(e: JoinError) -> String {
+    if !e.is_panic() {
+        return e.to_string();
+    }
+    let payload: Box<dyn Any + Send> = e.into_panic();
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panicked".to_owned())
+}
+
+/// Waits for the running task, or forever if there's none.
+async fn join(worker: &mut Option<(JoinHandle<()>, Instant)>) -> Result<(), JoinError> {
+    match worker {
+        Some((handle, _)) => handle.await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Starts, stops and restarts the integration's [`run`] task until it's shut down.
+async fn supervise(
+    pool: PgPool,
+    integration: Arc<dyn Integration>,
+    mut receiver: mpsc::Receiver<ControlMessage>,
+    status: Arc<RwLock<IntegrationStatus>>,
+    enabled: bool,
+) {
+    let name = integration.name();
+    let spawn = || {
+        let handle = tokio::spawn(run(pool.clone(), integration.clone(), status.clone()));
+        Some((handle, Instant::now()))
+    };
+    let set_state = |state| async move { status.write().await.state = state };
+    let mut worker = None;
+    let mut restart_at = None;
+    let mut backoff = MIN_BACKOFF;
+    if enabled {
+        worker = spawn();
+        set_state(TaskState::Running).await;
+    }
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => {
+                info!("The {name} integration received {message:?}");
+                match message {
+                    Some(ControlMessage::Start) => {
+                        if worker.is_none() {
+                            restart_at = None;
+                            worker = spawn();
+                            set_state(TaskState::Running).await;
+                        }
+                    }
+                    Some(ControlMessage::Stop) => {
+                        if let Some((handle, _)) = worker.take() {
+                            handle.abort();
+                        }
+                        restart_at = None;
+                        set_state(TaskState::Stopped).await;
+                    }
+                    Some(ControlMessage::Shutdown) | None => {
+                        if let Some((handle, _)) = worker.take() {
+                            handle.abort();
+                        }
+                        set_state(TaskState::Stopped).await;
+                        break;
+                    }
+                }
+            },
+            result = join(&mut worker) => {
+                let Some((_, started)) = worker.take() else {
+                    continue;
+                };
+                let e = match result {
+                    Ok(()) => "stopped by itself".to_owned(),
+                    Err(e) => panic_message(e),
+                };
+                if started.elapsed() >= STABLE_AFTER {
+                    backoff = MIN_BACKOFF;
+                }
+                error!("The {name} integration crashed, restarting in {backoff:?}: {e}");
+                restart_at = Some(Instant::now() + backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                let mut status = status.write().await;
+                status.state = TaskState::Restarting;
+                status.restarts += 1;
+                status.last_error = Some(e);
+            },
+            _ = tokio::time::sleep_until(restart_at.unwrap_or_else(Instant::now)), if restart_at.is_some() => {
+                restart_at = None;
+                worker = spawn();
+                set_state(TaskState::Running).await;
+            },
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use {
+    serde::{Deserialize, Serialize},
+    std::fmt,
+};
+
+/// Something a device can do or report, whichever integration it comes from. Integrations set
+/// these when they discover a device, and the UI and [`DeviceCommand`]s go by them instead of
+/// the device type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "ssr",
+    sqlx(type_name = "device_capability", rename_all = "snake_case")
+)]
+pub enum Capability {
+    OnOff,
+    /// 0-100%
+    Brightness,
+    /// Hue 0-360, saturation and brightness 0-100%
+    ColorHsv,
+    /// White temperature in kelvin
+    ColorTemp,
+    /// Reports the power it draws
+    PowerMeter,
+    /// Reports `battery_percentage`
+    Battery,
+    /// Takes remote control keys, like a TV
+    MediaRemote,
+    Camera,
+    /// One of several sockets or channels behind the same address, told apart by `child_id`
+    Children,
+}
+
+#[cfg(feature = "ssr")]
+impl sqlx::postgres::PgHasArrayType for Capability {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_device_capability")
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OnOff => write!(f, "On/off"),
+            Self::Brightness => write!(f, "Brightness"),
+            Self::ColorHsv => write!(f, "Color"),
+            Self::ColorTemp => write!(f, "Color temperature"),
+            Self::PowerMeter => write!(f, "Power meter"),
+            Self::Battery => write!(f, "Battery"),
+            Self::MediaRemote => write!(f, "Remote"),
+            Self::Camera => write!(f, "Camera"),
+            Self::Children => write!(f, "Children"),
+        }
+    }
+}
+
+/// A change to a device through one of its capabilities, carried out by the integration that
+/// owns the device.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "capability", rename_all = "snake_case")]
+pub enum DeviceCommand {
+    OnOff {
+        on: bool,
+    },
+    Brightness {
+        brightness: u8,
+    },
+    ColorHsv {
+        hue: u16,
+        saturation: u8,
+        brightness: u8,
+    },
+    ColorTemp {
+        kelvin: u16,
+    },
+    MediaRemote {
+        key: String,
+    },
+}
+
+impl DeviceCommand {
+    pub fn capability(&self) -> Capability {
+        match self {
+            Self::OnOff { .. } => Capability::OnOff,
+            Self::Brightness { .. } => Capability::Brightness,
+            Self::ColorHsv { .. } => Capability::ColorHsv,
+            Self::ColorTemp { .. } => Capability::ColorTemp,
+            Self::MediaRemote { .. } => Capability::MediaRemote,
+        }
+    }
+}
@@ -1,10 +1,113 @@
 use {
-    super::FullAction,
+    super::{FullAction, energy::EnergySettings, scene::Scene, vacation::VacationMode},
+    chrono::{DateTime, Utc},
     serde::{Deserialize, Serialize},
+    std::fmt,
 };
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
 pub struct Config {
     pub actions: Vec<FullAction>,
+    /// Where solar schedules and the rhai sun functions compute sunrise and sunset for
+    #[serde(default)]
+    pub location: Option<Location>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vacation: Option<VacationMode>,
+    /// The tariff energy costs are computed with and the energy alert rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy: Option<EnergySettings>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Location {
+    /// Degrees, north positive
+    pub latitude: f64,
+    /// Degrees, east positive
+    pub longitude: f64,
+}
+
+/// Everything the configs page exports and imports: the [`Config`] plus the integration
+/// switches, scenes and mish states kept in their own tables.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct ConfigExport {
+    pub actions: Vec<FullAction>,
+    #[serde(default)]
+    pub location: Option<Location>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vacation: Option<VacationMode>,
+    /// The tariff energy costs are computed with and the energy alert rules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub energy: Option<EnergySettings>,
+    #[serde(default)]
+    pub integrations: Vec<IntegrationSetting>,
+    #[serde(default)]
+    pub scenes: Vec<Scene>,
+    #[serde(default)]
+    pub mish_states: Vec<MishStateExport>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct IntegrationSetting {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct MishStateExport {
+    pub name: String,
+    pub state: serde_json::Value,
+    /// CID of the JSON Schema blob the state has to match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_cid: Option<String>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Json,
+}
+
+/// Why an imported config was rejected, with the 1-based position it was found at when known.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ConfigProblem {
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// e.g. `actions[2].function_args`, empty for syntax errors
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, "line {line}, column {column}: ")?,
+            (Some(line), None) => write!(f, "line {line}: ")?,
+            _ => {}
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A saved version of the [`Config`] with what changed since the version before it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ConfigVersion {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub diff: Vec<DiffLine>,
+}
+
+/// A line of a diff between the YAML of two configs.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum DiffLine {
+    Unchanged(String),
+    Added(String),
+    Removed(String),
+    /// Unchanged lines left out between changes
+    Skipped(usize),
 }
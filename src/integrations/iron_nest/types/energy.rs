@@ -0,0 +1,203 @@
+use {
+    chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc, Weekday},
+    chrono_tz::Tz,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    std::str::FromStr,
+};
+
+fn default_currency() -> String {
+    "USD".to_owned()
+}
+
+/// What energy costs, the base rate unless one of the periods covers the time.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Tariff {
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    pub rate_per_kwh: f64,
+    /// Time-of-use rates, the first covering a time wins
+    #[serde(default)]
+    pub periods: Vec<TariffPeriod>,
+    /// IANA timezone the periods and daily totals are read in, UTC if not set
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+/// A time-of-use rate, e.g. peak hours on weekdays. Ends the next day when `end` is before
+/// `start`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TariffPeriod {
+    pub name: String,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// Days it applies on, every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub rate_per_kwh: f64,
+}
+
+impl TariffPeriod {
+    /// Whether it covers `time` on `day`. The part after midnight of a period ending the next
+    /// day goes by the day it started.
+    pub fn covers(&self, day: Weekday, time: NaiveTime) -> bool {
+        let applies = |day| self.days.is_empty() || self.days.contains(&day);
+        if self.start <= self.end {
+            applies(day) && self.start <= time && time < self.end
+        } else {
+            (applies(day) && self.start <= time) || (applies(day.pred()) && time < self.end)
+        }
+    }
+}
+
+impl Tariff {
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+            .as_deref()
+            .and_then(|timezone| Tz::from_str(timezone).ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// The day `at` counts towards in daily totals.
+    pub fn day_of(&self, at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&self.timezone()).date_naive()
+    }
+
+    pub fn rate_at(&self, at: DateTime<Utc>) -> f64 {
+        let local = at.with_timezone(&self.timezone());
+        self.periods
+            .iter()
+            .find(|period| period.covers(local.weekday(), local.time()))
+            .map_or(self.rate_per_kwh, |period| period.rate_per_kwh)
+    }
+}
+
+impl Default for Tariff {
+    fn default() -> Self {
+        Self {
+            currency: default_currency(),
+            rate_per_kwh: 0.0,
+            periods: Vec::new(),
+            timezone: None,
+        }
+    }
+}
+
+/// Fires when a device, or the whole house, draws more than `threshold_watts` for
+/// `for_minutes`, and again only once it's dropped below.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct EnergyAlertRule {
+    pub name: String,
+    /// The whole house if not set
+    #[serde(default)]
+    pub device_id: Option<i64>,
+    pub threshold_watts: f64,
+    #[serde(default)]
+    pub for_minutes: u32,
+    /// Called when it fires, e.g. to turn the device off
+    #[serde(default)]
+    pub function_name: Option<String>,
+    #[serde(default)]
+    pub function_args: Option<Value>,
+}
+
+/// The tariff costs are computed with and the alert rules, kept in the config.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct EnergySettings {
+    #[serde(default)]
+    pub tariff: Tariff,
+    #[serde(default)]
+    pub alerts: Vec<EnergyAlertRule>,
+}
+
+/// What a power meter read at one moment.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PowerReading {
+    pub power_w: f64,
+    pub voltage_v: Option<f64>,
+    pub current_a: Option<f64>,
+}
+
+/// Average power over a bucket of time starting at `at`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct PowerPoint {
+    pub at: DateTime<Utc>,
+    pub power_w: f64,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct DailyEnergy {
+    pub day: NaiveDate,
+    pub energy_wh: f64,
+    pub cost: f64,
+}
+
+/// A device with a power meter, with what it draws now and has used today.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct DeviceEnergy {
+    pub device_id: i64,
+    pub name: String,
+    /// The latest sample, if there's a recent one
+    pub power_w: Option<f64>,
+    pub today_wh: f64,
+    pub today_cost: f64,
+}
+
+/// A time an [`EnergyAlertRule`] fired.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct EnergyAlert {
+    pub rule_name: String,
+    pub device_id: Option<i64>,
+    pub power_w: f64,
+    pub threshold_watts: f64,
+    pub triggered_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    #[test]
+    fn test_rate_at() {
+        let time = |time| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+        let tariff = Tariff {
+            currency: default_currency(),
+            rate_per_kwh: 0.2,
+            periods: vec![
+                TariffPeriod {
+                    name: "Peak".to_owned(),
+                    start: time("16:00"),
+                    end: time("21:00"),
+                    days: vec![
+                        Weekday::Mon,
+                        Weekday::Tue,
+                        Weekday::Wed,
+                        Weekday::Thu,
+                        Weekday::Fri,
+                    ],
+                    rate_per_kwh: 0.4,
+                },
+                TariffPeriod {
+                    name: "Night".to_owned(),
+                    start: time("23:00"),
+                    end: time("06:00"),
+                    days: Vec::new(),
+                    rate_per_kwh: 0.1,
+                },
+            ],
+            timezone: None,
+        };
+        // 2026-10-19 is a Monday
+        let at = |day, hour| Utc.with_ymd_and_hms(2026, 10, day, hour, 0, 0).unwrap();
+        assert_eq!(tariff.rate_at(at(19, 17)), 0.4);
+        assert_eq!(tariff.rate_at(at(19, 21)), 0.2);
+        assert_eq!(tariff.rate_at(at(18, 17)), 0.2);
+        assert_eq!(tariff.rate_at(at(19, 23)), 0.1);
+        assert_eq!(tariff.rate_at(at(20, 5)), 0.1);
+        assert_eq!(tariff.rate_at(at(20, 6)), 0.2);
+    }
+}
@@ -0,0 +1,102 @@
+use {
+    jsonpath_rust::{JsonPath, parser::errors::JsonPathError},
+    serde::{Deserialize, Serialize},
+};
+
+/// An event sent to `/api/mish/subscribe` subscribers.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum MishStateEvent {
+    /// `value` is the state (or the part of it selected by the subscription's path) and `cid`
+    /// is the CID that value would have as a DAG-JSON blob.
+    Update {
+        name: String,
+        value: serde_json::Value,
+        cid: String,
+    },
+    Delete {
+        name: String,
+    },
+}
+
+impl MishStateEvent {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Update { name, .. } | Self::Delete { name } => name,
+        }
+    }
+}
+
+/// The part of `state` selected by `path`, an array when the path selects several values.
+pub fn select_json_via_jsonpath(
+    state: &serde_json::Value,
+    path: &str,
+) -> Result<serde_json::Value, JsonPathError> {
+    let mut result = state.query(path)?;
+    Ok(if result.len() == 1 {
+        result.remove(0).clone()
+    } else {
+        serde_json::Value::Array(result.into_iter().cloned().collect())
+    })
+}
+
+/// Outcome of an IPLD blob garbage collection run.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct GcReport {
+    pub roots: usize,
+    pub reachable_blobs: usize,
+    pub reclaimable_blobs: Vec<String>,
+    pub reclaimable_bytes: i64,
+    pub dry_run: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct IpldPin {
+    pub name: String,
+    pub cid: String,
+}
+
+/// Outcome of re-hashing the stored IPLD blobs.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    pub checked: usize,
+    /// Blobs whose content doesn't match their CID
+    pub corrupted: Vec<String>,
+    /// Blobs using a hash function we can't check
+    pub unsupported: Vec<String>,
+}
+
+/// A block followed while resolving a path. `path` is the prefix of the path that led to it.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ResolvedLink {
+    pub path: String,
+    pub cid: String,
+}
+
+/// The value at a path through mish states and linked blocks, in its DAG-JSON representation.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ResolvedPath {
+    pub value: serde_json::Value,
+    pub links: Vec<ResolvedLink>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptLogLevel {
+    Info,
+    Print,
+    Debug,
+    Error,
+}
+
+/// Output from, or a failure of, an installed mish script. `script` is its name in the `run`
+/// mish state and `cid` the blob it was loaded from.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ScriptLog {
+    pub script: String,
+    pub cid: Option<String>,
+    pub level: ScriptLogLevel,
+    pub message: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: i64,
+}
@@ -1,14 +1,21 @@
 use {
-    chrono::{DateTime, Utc},
+    capability::Capability,
+    chrono::{DateTime, NaiveTime, Utc},
     serde::{Deserialize, Serialize},
     serde_json::Value,
     std::fmt,
     uuid::Uuid,
 };
 
+pub mod capability;
 pub mod config;
+pub mod energy;
+pub mod mish;
+pub mod scene;
+pub mod state_history;
+pub mod vacation;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "ssr", derive(sqlx::prelude::Type))]
 #[serde(rename_all = "kebab-case")]
 #[cfg_attr(
@@ -43,6 +50,19 @@ impl fmt::Display for DeviceType {
     }
 }
 
+impl DeviceType {
+    /// Name of the integration that discovers and controls devices of this type.
+    pub fn integration(&self) -> &'static str {
+        match self {
+            Self::KasaPlug | Self::KasaLight | Self::KasaDimmer | Self::KasaPowerStrip => "tplink",
+            Self::TuyaLight | Self::TuyaGrowLight => "tuya",
+            Self::RingDoorbell => "ring",
+            Self::RokuTv => "roku",
+            Self::Stoplight => "stoplight",
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
 pub struct Device {
@@ -54,7 +74,31 @@ pub struct Device {
     pub battery_percentage: i64,
     pub last_seen: DateTime<Utc>,
     pub mac_address: Option<String>,
+    /// What the vendor calls the device, e.g. the Kasa deviceId or the Roku serial number. It
+    /// stays the same when the IP changes, so devices with one are told apart by it.
+    pub vendor_id: Option<String>,
     pub child_id: Option<String>,
+    #[serde(default)]
+    #[cfg_attr(feature = "ssr", sqlx(default))]
+    pub capabilities: Vec<Capability>,
+}
+
+/// How actions and scripts refer to a device: by its id or, as a string, its name. Either stays
+/// the same when the device's IP changes.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum DeviceReference {
+    Id(i64),
+    Name(String),
+}
+
+impl fmt::Display for DeviceReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "device {id}"),
+            Self::Name(name) => write!(f, "device {name:?}"),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -78,9 +122,154 @@ pub struct FullAction {
 #[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
 pub struct RequiredAction {
     pub name: String,
+    /// With seconds, e.g. `0 30 7 * * *` for 07:30:00 every day. Unused with `solar`
     pub cron: String,
+    /// Runs daily relative to the sun instead of on `cron`
+    #[serde(default)]
+    #[cfg_attr(feature = "ssr", sqlx(json))]
+    pub solar: Option<SolarSchedule>,
+    /// IANA timezone `cron` is read in, e.g. `America/New_York`, UTC if not set
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Run once on startup if a scheduled run was missed while the server was down
+    #[serde(default)]
+    pub catch_up: bool,
     pub function_name: String,
     pub function_args: Value,
+    /// Runs these steps instead of `function_name`
+    #[serde(default)]
+    #[cfg_attr(feature = "ssr", sqlx(json))]
+    pub sequence: Option<Sequence>,
+    /// How many more times to try a run whose function failed, for sequences each failed step is
+    /// retried on its own
+    #[serde(default)]
+    pub retry_count: i32,
+    /// Wait before the first retry, doubled before each one after it
+    #[serde(default)]
+    pub retry_backoff_ms: i64,
+    /// Called once every attempt of a run has failed, e.g. to flash a light
+    #[serde(default)]
+    pub on_failure_function_name: Option<String>,
+    #[serde(default)]
+    pub on_failure_function_args: Option<Value>,
+}
+
+/// Runs an action each day at a solar event, e.g. 30 minutes after sunset but no later than
+/// 21:00, computed for the location in the config.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SolarSchedule {
+    pub event: SolarEvent,
+    /// Negative to run before the event
+    #[serde(default)]
+    pub offset_minutes: i64,
+    /// Earliest time of day to run, in the action's timezone
+    #[serde(default)]
+    pub not_before: Option<NaiveTime>,
+    /// Latest time of day to run, in the action's timezone
+    #[serde(default)]
+    pub not_after: Option<NaiveTime>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SolarEvent {
+    /// Start of civil twilight
+    Dawn,
+    Sunrise,
+    Sunset,
+    /// End of civil twilight
+    Dusk,
+}
+
+/// Steps an action runs in order, e.g. turn on the TV, wait 10 s, switch to HDMI2.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Sequence {
+    pub steps: Vec<SequenceStep>,
+    #[serde(default)]
+    pub on_error: SequenceErrorPolicy,
+}
+
+/// What a sequence does after a step fails. Either way the run counts as failed.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceErrorPolicy {
+    #[default]
+    Abort,
+    Continue,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SequenceStep {
+    Function {
+        function_name: String,
+        function_args: Value,
+    },
+    Wait {
+        seconds: f64,
+    },
+    If {
+        condition: SequenceCondition,
+        #[serde(default)]
+        then: Vec<SequenceStep>,
+        #[serde(default)]
+        otherwise: Vec<SequenceStep>,
+    },
+    /// Runs the branches at the same time, the steps of each in order
+    Parallel {
+        branches: Vec<Vec<SequenceStep>>,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SequenceCondition {
+    /// e.g. 1 for on
+    DevicePowerState { device_id: i64, power_state: i32 },
+    /// The value at a JSONPath in a mish state equals `equals`
+    MishState {
+        name: String,
+        path: String,
+        equals: Value,
+    },
+}
+
+/// One run of an action, including its retries.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct ActionRun {
+    pub id: Uuid,
+    pub action_id: Uuid,
+    pub action_name: String,
+    /// When the cron expression was due, `None` for runs started by hand
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    /// For sequences the most attempts any step needed
+    pub attempts: i32,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct ActionStats {
+    pub action_id: Uuid,
+    pub runs: i64,
+    pub successes: i64,
+}
+
+/// A function actions, the assistant and mish buttons can call by name.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// Name of the integration that registered it, e.g. `tplink`
+    pub integration: String,
+    pub description: String,
+    /// JSON Schema of the arguments object
+    pub parameters: Value,
+    /// Device types whose IPs are valid values for the `ip` argument, if it takes one
+    pub ip_device_types: Vec<DeviceType>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -98,3 +287,36 @@ pub enum ControlMessage {
     Stop,
     Shutdown,
 }
+
+/// What an integration's supervised task is doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskState {
+    Running,
+    Stopped,
+    /// Crashed and waiting to be restarted
+    Restarting,
+}
+
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Running => write!(f, "Running"),
+            Self::Stopped => write!(f, "Stopped"),
+            Self::Restarting => write!(f, "Restarting"),
+        }
+    }
+}
+
+/// How an integration's task is doing, for the integrations page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IntegrationStatus {
+    pub name: String,
+    pub state: TaskState,
+    /// Times the task crashed and was restarted since the server started
+    pub restarts: u32,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// `None` if the integration is healthy
+    pub health: Option<String>,
+    pub capabilities: Vec<Capability>,
+}
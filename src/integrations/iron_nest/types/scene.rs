@@ -0,0 +1,78 @@
+use {
+    chrono::{DateTime, Utc},
+    serde::{Deserialize, Serialize},
+};
+
+/// States of a set of devices, captured together and applied together.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct Scene {
+    pub name: String,
+    #[cfg_attr(feature = "ssr", sqlx(json))]
+    pub devices: Vec<SceneDevice>,
+    /// How many devices are changed at once when applying the scene
+    #[serde(default = "default_concurrency")]
+    pub concurrency: i32,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+pub const DEFAULT_CONCURRENCY: i32 = 4;
+
+fn default_concurrency() -> i32 {
+    DEFAULT_CONCURRENCY
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SceneDevice {
+    pub device_id: i64,
+    /// Name when captured, for showing scenes whose devices are gone
+    pub name: String,
+    pub state: DeviceState,
+    /// How long lights and dimmers take to fade to the state
+    #[serde(default)]
+    pub transition_ms: u32,
+}
+
+/// A device to capture into a scene.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SceneTarget {
+    pub device_id: i64,
+    #[serde(default)]
+    pub transition_ms: u32,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeviceState {
+    /// Plugs and power strip sockets
+    Relay {
+        on: bool,
+    },
+    Dimmer {
+        on: bool,
+        brightness: u8,
+    },
+    /// Color and temperature are left alone when `None`, e.g. for dimmable only bulbs.
+    Light {
+        on: bool,
+        brightness: u8,
+        hue: Option<u16>,
+        saturation: Option<u8>,
+        color_temp: Option<u16>,
+    },
+    Roku {
+        on: bool,
+    },
+}
+
+impl DeviceState {
+    pub fn is_on(&self) -> bool {
+        match *self {
+            DeviceState::Relay { on }
+            | DeviceState::Dimmer { on, .. }
+            | DeviceState::Light { on, .. }
+            | DeviceState::Roku { on } => on,
+        }
+    }
+}
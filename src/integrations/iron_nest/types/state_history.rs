@@ -0,0 +1,122 @@
+use {
+    chrono::{DateTime, Duration, Utc},
+    serde::{Deserialize, Serialize},
+    std::fmt,
+};
+
+/// What made a device's state change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ssr", derive(sqlx::Type))]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(
+    feature = "ssr",
+    sqlx(type_name = "device_state_source", rename_all = "snake_case")
+)]
+pub enum StateSource {
+    /// Seen when an integration discovered or refreshed the device
+    Poll,
+    /// Someone used the UI, the assistant or a mish button
+    Command,
+    /// An action, a sequence or vacation mode
+    Automation,
+}
+
+impl fmt::Display for StateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poll => write!(f, "Polled"),
+            Self::Command => write!(f, "Command"),
+            Self::Automation => write!(f, "Automation"),
+        }
+    }
+}
+
+/// A row of `device_state_event`, the state a device changed to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ssr", derive(sqlx::FromRow))]
+pub struct DeviceStateEvent {
+    pub power_state: i32,
+    pub battery_percentage: Option<i64>,
+    pub source: StateSource,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// How a device's state changed between `from` and `to`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceTimeline {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// The last change before `from`, if it's still kept
+    pub initial: Option<DeviceStateEvent>,
+    pub events: Vec<DeviceStateEvent>,
+}
+
+impl DeviceTimeline {
+    /// The stretches of time the device was on, cut to `from..to`.
+    pub fn on_spans(&self) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let mut spans = Vec::new();
+        let mut on_since = self
+            .initial
+            .as_ref()
+            .filter(|event| event.power_state != 0)
+            .map(|_| self.from);
+        for event in &self.events {
+            let at = event.changed_at.clamp(self.from, self.to);
+            match (on_since, event.power_state != 0) {
+                (None, true) => on_since = Some(at),
+                (Some(since), false) => {
+                    spans.push((since, at));
+                    on_since = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(since) = on_since {
+            spans.push((since, self.to));
+        }
+        spans
+    }
+
+    pub fn on_duration(&self) -> Duration {
+        self.on_spans()
+            .into_iter()
+            .fold(Duration::zero(), |total, (start, end)| {
+                total + (end - start)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    fn event(hour: u32, power_state: i32) -> DeviceStateEvent {
+        DeviceStateEvent {
+            power_state,
+            battery_percentage: None,
+            source: StateSource::Poll,
+            changed_at: Utc.with_ymd_and_hms(2026, 10, 19, hour, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_on_spans() {
+        let at = |hour| Utc.with_ymd_and_hms(2026, 10, 19, hour, 0, 0).unwrap();
+        // On since before the range, off at 2, on again at 5 until the end
+        let timeline = DeviceTimeline {
+            from: at(1),
+            to: at(8),
+            initial: Some(event(0, 1)),
+            events: vec![event(2, 0), event(5, 1), event(6, 1)],
+        };
+        assert_eq!(timeline.on_spans(), vec![(at(1), at(2)), (at(5), at(8))]);
+        assert_eq!(timeline.on_duration(), Duration::hours(4));
+
+        let timeline = DeviceTimeline {
+            initial: None,
+            events: vec![event(3, 0)],
+            ..timeline
+        };
+        assert!(timeline.on_spans().is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use {
+    super::DeviceType,
+    chrono::{DateTime, NaiveDate, NaiveTime, Utc},
+    chrono_tz::Tz,
+    serde::{Deserialize, Serialize},
+    std::str::FromStr,
+};
+
+pub const DEFAULT_JITTER_MINUTES: u32 = 20;
+
+fn default_jitter_minutes() -> u32 {
+    DEFAULT_JITTER_MINUTES
+}
+
+/// Makes the house look occupied while away by switching lights and the TV on and off each day,
+/// at the times they're usually on or in configured windows, shifted by random jitter.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct VacationMode {
+    pub enabled: bool,
+    /// First day to replay
+    pub start_date: NaiveDate,
+    /// Last day to replay, a window starting on it still switches off the morning after
+    pub end_date: NaiveDate,
+    pub device_ids: Vec<i64>,
+    /// Used for the devices in them instead of times learned from the device history
+    #[serde(default)]
+    pub windows: Vec<VacationWindow>,
+    /// Each switch happens up to this many minutes earlier or later than planned
+    #[serde(default = "default_jitter_minutes")]
+    pub jitter_minutes: u32,
+    /// IANA timezone the windows and dates are read in, UTC if not set
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+impl VacationMode {
+    pub fn is_active_on(&self, date: NaiveDate) -> bool {
+        self.enabled && self.start_date <= date && date <= self.end_date
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+            .as_deref()
+            .and_then(|timezone| Tz::from_str(timezone).ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    /// Whether it's replaying today.
+    pub fn is_active(&self) -> bool {
+        self.is_active_on(Utc::now().with_timezone(&self.timezone()).date_naive())
+    }
+}
+
+/// Whether vacation mode can switch devices of `device_type` on and off.
+pub fn can_simulate(device_type: &DeviceType) -> bool {
+    matches!(
+        device_type,
+        DeviceType::KasaPlug
+            | DeviceType::KasaPowerStrip
+            | DeviceType::KasaDimmer
+            | DeviceType::KasaLight
+            | DeviceType::RokuTv
+    )
+}
+
+/// A time of day a device is on. Ends the next day when `off` is before `on`.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct VacationWindow {
+    pub device_id: i64,
+    pub on: NaiveTime,
+    pub off: NaiveTime,
+}
+
+/// A planned switch of a device, jitter included.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct VacationSwitch {
+    pub device_id: i64,
+    pub name: String,
+    pub at: DateTime<Utc>,
+    pub on: bool,
+}
+
+/// The switches vacation mode plans for a day and the devices it has nothing to replay for.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct VacationPlan {
+    pub date: NaiveDate,
+    pub switches: Vec<VacationSwitch>,
+    /// Names of devices without configured windows or enough history to learn them
+    pub unplanned: Vec<String>,
+}
@@ -0,0 +1,550 @@
+//! Vacation mode: switches lights and the TV on and off each day at the times they're usually on,
+//! learned from the device state history or configured, so the house looks occupied.
+
+use {
+    super::{
+        capabilities::control_device,
+        functions::{FunctionError, FunctionRegistry},
+        state_history::{get_timeline_query, with_source},
+        types::{
+            Device, FunctionInfo,
+            capability::DeviceCommand,
+            state_history::StateSource,
+            vacation::{DEFAULT_JITTER_MINUTES, VacationMode, VacationPlan, VacationSwitch},
+        },
+    },
+    crate::server::vacation::{get_vacation_mode_query, set_vacation_mode_query},
+    chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc},
+    chrono_tz::Tz,
+    rand::{Rng, SeedableRng, rngs::StdRng},
+    serde::Deserialize,
+    serde_json::{Value, json},
+    sqlx::PgPool,
+    std::{
+        collections::{HashMap, HashSet},
+        str::FromStr,
+    },
+};
+
+/// How many days before the vacation usual on times are learned from
+const LEARN_DAYS: u32 = 28;
+const SLOT_MINUTES: u32 = 15;
+const SLOTS: usize = 24 * 60 / SLOT_MINUTES as usize;
+/// Jitter never brings a switch off closer than this to the switch on before it
+const MIN_ON_MINUTES: i64 = 10;
+const MAX_JITTER_MINUTES: u32 = 120;
+const VACATION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+fn failed(e: impl ToString) -> FunctionError {
+    FunctionError::Failed(e.to_string())
+}
+
+/// What's wrong with `mode` as the field it's in and a message, given the ids of the devices
+/// that exist.
+pub fn problems(mode: &VacationMode, device_ids: &HashSet<i64>) -> Vec<(&'static str, String)> {
+    let mut problems = Vec::new();
+    if let Some(timezone) = mode
+        .timezone
+        .as_deref()
+        .filter(|timezone| !timezone.is_empty())
+        && let Err(e) = Tz::from_str(timezone)
+    {
+        problems.push(("timezone", format!("Invalid timezone {timezone:?}: {e}")));
+    }
+    if mode.end_date < mode.start_date {
+        problems.push(("end_date", "The vacation ends before it starts".to_owned()));
+    }
+    if mode.device_ids.is_empty() {
+        problems.push(("device_ids", "Pick at least one device".to_owned()));
+    }
+    for device_id in &mode.device_ids {
+        if !device_ids.contains(device_id) {
+            problems.push(("device_ids", format!("No device {device_id}")));
+        }
+    }
+    for window in &mode.windows {
+        if !mode.device_ids.contains(&window.device_id) {
+            problems.push((
+                "windows",
+                format!("Device {} has a window but isn't picked", window.device_id),
+            ));
+        }
+        if window.on == window.off {
+            problems.push((
+                "windows",
+                format!("The window of device {} is empty", window.device_id),
+            ));
+        }
+    }
+    if mode.jitter_minutes > MAX_JITTER_MINUTES {
+        problems.push((
+            "jitter_minutes",
+            format!("Jitter can be at most {MAX_JITTER_MINUTES} minutes"),
+        ));
+    }
+    problems
+}
+
+fn slot_time(slot: usize) -> NaiveTime {
+    NaiveTime::MIN + Duration::minutes((slot as u32 * SLOT_MINUTES).into())
+}
+
+/// The times of day a device was on during at least half of the `days` days from `from`, given
+/// whether it was on before `from` and its changes after, in local time. Nothing when it never
+/// changed or was always on.
+pub fn learn_windows(
+    initial_on: bool,
+    changes: &[(NaiveDateTime, bool)],
+    from: NaiveDate,
+    days: u32,
+) -> Vec<(NaiveTime, NaiveTime)> {
+    if changes.is_empty() || days == 0 {
+        return Vec::new();
+    }
+    let mut on_days = [0; SLOTS];
+    let mut on = initial_on;
+    let mut changes = changes.iter().peekable();
+    for day in 0..days {
+        let midnight = (from + Duration::days(day.into())).and_time(NaiveTime::MIN);
+        for (slot, on_days) in on_days.iter_mut().enumerate() {
+            // Sampling the middle of each slot
+            let sample = midnight
+                + Duration::minutes((slot as u32 * SLOT_MINUTES + SLOT_MINUTES / 2).into());
+            while let Some((_, changed_on)) = changes.next_if(|(at, _)| *at <= sample) {
+                on = *changed_on;
+            }
+            if on {
+                *on_days += 1;
+            }
+        }
+    }
+    let usual = on_days.map(|on_days| on_days * 2 >= days);
+    // Starting from a slot that's off so windows over midnight come out whole
+    let Some(first_off) = usual.iter().position(|on| !on) else {
+        return Vec::new();
+    };
+    let mut windows = Vec::new();
+    let mut start = None;
+    for offset in 1..=SLOTS {
+        let slot = (first_off + offset) % SLOTS;
+        match (usual[slot], start) {
+            (true, None) => start = Some(slot),
+            (false, Some(on)) => {
+                windows.push((slot_time(on), slot_time(slot)));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    windows
+}
+
+/// When `time` is on the local `date`, moved past the gap when DST skips it.
+fn local_time(timezone: &Tz, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    let local = date.and_time(time);
+    local
+        .and_local_timezone(*timezone)
+        .earliest()
+        .or_else(|| {
+            (local + Duration::hours(1))
+                .and_local_timezone(*timezone)
+                .earliest()
+        })
+        .map(|time| time.with_timezone(&Utc))
+}
+
+async fn get_devices_query(pool: &PgPool, device_ids: &[i64]) -> Result<Vec<Device>, sqlx::Error> {
+    let query = "
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+        FROM device
+        WHERE id = ANY($1)
+        ORDER BY name
+    ";
+    sqlx::query_as(query).bind(device_ids).fetch_all(pool).await
+}
+
+/// The switches for the local `date`, each moved by jitter that's the same every time the same
+/// day is planned.
+pub async fn plan_day(
+    pool: &PgPool,
+    mode: &VacationMode,
+    date: NaiveDate,
+) -> Result<VacationPlan, sqlx::Error> {
+    let timezone = mode.timezone();
+    // Learning from the weeks before the vacation, or before today while it's yet to start
+    let learn_until = mode
+        .start_date
+        .min(Utc::now().with_timezone(&timezone).date_naive());
+    let learn_from = learn_until - Duration::days(LEARN_DAYS.into());
+    let jitter = i64::from(mode.jitter_minutes);
+    let mut switches = Vec::new();
+    let mut unplanned = Vec::new();
+    for device in get_devices_query(pool, &mode.device_ids).await? {
+        let mut windows = mode
+            .windows
+            .iter()
+            .filter(|window| window.device_id == device.id)
+            .map(|window| (window.on, window.off))
+            .collect::<Vec<_>>();
+        if windows.is_empty() {
+            let since = local_time(&timezone, learn_from, NaiveTime::MIN).unwrap_or_default();
+            let timeline = get_timeline_query(pool, device.id, since, Utc::now()).await?;
+            let initial_on = timeline.initial.is_some_and(|event| event.power_state != 0);
+            let changes = timeline
+                .events
+                .into_iter()
+                .map(|event| {
+                    (
+                        event.changed_at.with_timezone(&timezone).naive_local(),
+                        event.power_state != 0,
+                    )
+                })
+                .collect::<Vec<_>>();
+            windows = learn_windows(initial_on, &changes, learn_from, LEARN_DAYS);
+        }
+        if windows.is_empty() {
+            unplanned.push(device.name);
+            continue;
+        }
+        let seed = ((date.num_days_from_ce() as u64) << 32) ^ device.id as u64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        for (on, off) in windows {
+            let off_date = if off <= on {
+                date + Duration::days(1)
+            } else {
+                date
+            };
+            let (Some(on_at), Some(off_at)) = (
+                local_time(&timezone, date, on),
+                local_time(&timezone, off_date, off),
+            ) else {
+                continue;
+            };
+            let on_at = on_at + Duration::minutes(rng.gen_range(-jitter..=jitter));
+            let off_at = (off_at + Duration::minutes(rng.gen_range(-jitter..=jitter)))
+                .max(on_at + Duration::minutes(MIN_ON_MINUTES));
+            for (at, on) in [(on_at, true), (off_at, false)] {
+                switches.push(VacationSwitch {
+                    device_id: device.id,
+                    name: device.name.clone(),
+                    at,
+                    on,
+                });
+            }
+        }
+    }
+    switches.sort_by_key(|switch| switch.at);
+    Ok(VacationPlan {
+        date,
+        switches,
+        unplanned,
+    })
+}
+
+/// The plan for today if the vacation is on, or its first day if it's yet to start.
+pub async fn next_plan(
+    pool: &PgPool,
+    mode: &VacationMode,
+) -> Result<Option<VacationPlan>, sqlx::Error> {
+    let today = Utc::now().with_timezone(&mode.timezone()).date_naive();
+    let date = if mode.is_active_on(today) {
+        today
+    } else if mode.enabled && today < mode.start_date {
+        mode.start_date
+    } else {
+        return Ok(None);
+    };
+    plan_day(pool, mode, date).await.map(Some)
+}
+
+/// Turns `device` on or off, leaving the brightness and color of lights as they were.
+pub async fn set_device_power(
+    pool: &PgPool,
+    device: &Device,
+    on: bool,
+) -> Result<(), FunctionError> {
+    with_source(
+        StateSource::Automation,
+        control_device(pool, device.id, DeviceCommand::OnOff { on }),
+    )
+    .await
+}
+
+#[derive(Default)]
+struct VacationRunner {
+    mode: Option<VacationMode>,
+    plans: HashMap<NaiveDate, VacationPlan>,
+    last_tick: Option<DateTime<Utc>>,
+}
+
+impl VacationRunner {
+    async fn tick(&mut self, pool: &PgPool, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        let mode = get_vacation_mode_query(pool).await?;
+        if mode != self.mode {
+            self.plans.clear();
+            self.last_tick = None;
+            self.mode.clone_from(&mode);
+        }
+        let Some(mode) = mode.filter(|mode| mode.enabled) else {
+            return Ok(());
+        };
+        let today = now.with_timezone(&mode.timezone()).date_naive();
+        let yesterday = today - Duration::days(1);
+        self.plans.retain(|date, _| *date >= yesterday);
+        let mut due = Vec::new();
+        // Yesterday's windows can end after midnight
+        for date in [yesterday, today] {
+            if !mode.is_active_on(date) {
+                continue;
+            }
+            if !self.plans.contains_key(&date) {
+                let plan = plan_day(pool, &mode, date).await?;
+                self.plans.insert(date, plan);
+            }
+            due.extend(
+                self.plans[&date]
+                    .switches
+                    .iter()
+                    .filter(|switch| {
+                        switch.at <= now && self.last_tick.is_none_or(|last| switch.at > last)
+                    })
+                    .cloned(),
+            );
+        }
+        if self.last_tick.is_none() {
+            // Just enabled or restarted, only bring each device to where it should be by now
+            due.sort_by_key(|switch| switch.at);
+            due = due
+                .into_iter()
+                .map(|switch| (switch.device_id, switch))
+                .collect::<HashMap<_, _>>()
+                .into_values()
+                .collect();
+        }
+        self.last_tick = Some(now);
+        if due.is_empty() {
+            return Ok(());
+        }
+
+        let device_ids = due
+            .iter()
+            .map(|switch| switch.device_id)
+            .collect::<Vec<_>>();
+        let devices = get_devices_query(pool, &device_ids)
+            .await?
+            .into_iter()
+            .map(|device| (device.id, device))
+            .collect::<HashMap<_, _>>();
+        for switch in due {
+            let Some(device) = devices.get(&switch.device_id) else {
+                continue;
+            };
+            match set_device_power(pool, device, switch.on).await {
+                Ok(()) => log::info!(
+                    "Vacation mode turned {} {}",
+                    if switch.on { "on" } else { "off" },
+                    device.name
+                ),
+                Err(e) => log::error!("Vacation mode failed to switch {}: {e}", device.name),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub async fn vacation_job(pool: PgPool) {
+    let mut interval = tokio::time::interval(VACATION_INTERVAL);
+    let mut runner = VacationRunner::default();
+    loop {
+        interval.tick().await;
+        if let Err(e) = runner.tick(&pool, Utc::now()).await {
+            log::error!("Vacation mode failed: {e}");
+        }
+    }
+}
+
+/// Changes the vacation mode in the config after checking it, starting it with `update` when
+/// there's none yet.
+pub async fn update_vacation_mode(
+    pool: &PgPool,
+    update: impl FnOnce(Option<VacationMode>) -> Result<VacationMode, String>,
+) -> Result<VacationMode, String> {
+    let mode = update(
+        get_vacation_mode_query(pool)
+            .await
+            .map_err(|e| e.to_string())?,
+    )?;
+    let query = "
+        SELECT id
+        FROM device
+    ";
+    let device_ids = sqlx::query_scalar::<_, i64>(query)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let problems = problems(&mode, &device_ids);
+    if !problems.is_empty() {
+        return Err(problems
+            .into_iter()
+            .map(|(field, message)| format!("{field}: {message}"))
+            .collect::<Vec<_>>()
+            .join("; "));
+    }
+    set_vacation_mode_query(pool, &mode)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(mode)
+}
+
+#[derive(Deserialize)]
+struct SetArgs {
+    enabled: bool,
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+    device_ids: Option<Vec<i64>>,
+    jitter_minutes: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GetArgs {}
+
+fn function_info(name: &str, description: &str, parameters: Value) -> FunctionInfo {
+    FunctionInfo {
+        name: name.to_owned(),
+        integration: "iron_nest".to_owned(),
+        description: description.to_owned(),
+        parameters,
+        ip_device_types: vec![],
+    }
+}
+
+pub fn register_functions(registry: &mut FunctionRegistry) {
+    registry.register(
+        function_info(
+            "vacation_mode_set",
+            "Turn vacation mode on or off. While on it switches the picked lights and TV on and \
+             off at their usual times so the house looks occupied",
+            json!({
+                "type": "object",
+                "properties": {
+                    "enabled": { "type": "boolean" },
+                    "start_date": {
+                        "type": "string",
+                        "format": "date",
+                        "description": "First day, YYYY-MM-DD, needed the first time",
+                    },
+                    "end_date": {
+                        "type": "string",
+                        "format": "date",
+                        "description": "Last day, YYYY-MM-DD, needed the first time",
+                    },
+                    "device_ids": {
+                        "type": "array",
+                        "items": { "type": "integer" },
+                        "description": "Lights, plugs and TVs to switch, needed the first time",
+                    },
+                    "jitter_minutes": { "type": "integer", "minimum": 0 },
+                },
+                "required": ["enabled"],
+            }),
+        ),
+        |pool, args: SetArgs| async move {
+            let mode = update_vacation_mode(&pool, |mode| {
+                let mut mode = match (mode, args.start_date, args.end_date) {
+                    (Some(mode), _, _) => mode,
+                    (None, Some(start_date), Some(end_date)) => VacationMode {
+                        enabled: args.enabled,
+                        start_date,
+                        end_date,
+                        device_ids: Vec::new(),
+                        windows: Vec::new(),
+                        jitter_minutes: DEFAULT_JITTER_MINUTES,
+                        timezone: None,
+                    },
+                    (None, ..) => {
+                        return Err("Vacation mode isn't set up, pass its dates".to_owned());
+                    }
+                };
+                mode.enabled = args.enabled;
+                if let Some(start_date) = args.start_date {
+                    mode.start_date = start_date;
+                }
+                if let Some(end_date) = args.end_date {
+                    mode.end_date = end_date;
+                }
+                if let Some(device_ids) = args.device_ids {
+                    mode.device_ids = device_ids;
+                }
+                if let Some(jitter_minutes) = args.jitter_minutes {
+                    mode.jitter_minutes = jitter_minutes;
+                }
+                Ok(mode)
+            })
+            .await
+            .map_err(failed)?;
+            serde_json::to_value(mode).map_err(failed)
+        },
+    );
+    registry.register(
+        function_info(
+            "vacation_mode_get",
+            "Get the vacation mode settings and the switches planned for today, or for its \
+             first day if it's yet to start",
+            json!({ "type": "object", "properties": {} }),
+        ),
+        |pool, _: GetArgs| async move {
+            let Some(mode) = get_vacation_mode_query(&pool).await.map_err(failed)? else {
+                return Ok(json!({ "enabled": false }));
+            };
+            let plan = next_plan(&pool, &mode).await.map_err(failed)?;
+            Ok(json!({ "mode": mode, "plan": plan }))
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: NaiveDate, time: &str) -> NaiveDateTime {
+        date.and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_learn_windows() {
+        let from = NaiveDate::from_ymd_opt(2026, 9, 1).unwrap();
+        // On every evening from 18:00 to 23:30, and one morning at 07:00
+        let mut changes = (0..7)
+            .flat_map(|day| {
+                let date = from + Duration::days(day);
+                [(at(date, "18:00"), true), (at(date, "23:30"), false)]
+            })
+            .collect::<Vec<_>>();
+        changes.insert(0, (at(from, "07:00"), true));
+        changes.insert(1, (at(from, "08:00"), false));
+        assert_eq!(
+            learn_windows(false, &changes, from, 7),
+            vec![(time("18:00"), time("23:30"))]
+        );
+
+        // On past midnight
+        let changes = (0..4)
+            .flat_map(|day| {
+                let date = from + Duration::days(day);
+                [(at(date, "01:00"), false), (at(date, "22:00"), true)]
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            learn_windows(true, &changes, from, 4),
+            vec![(time("22:00"), time("01:00"))]
+        );
+
+        assert_eq!(learn_windows(true, &[], from, 7), vec![]);
+    }
+}
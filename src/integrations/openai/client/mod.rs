@@ -1,11 +1,12 @@
 use {
-    crate::integrations::iron_nest::{execute_function, types::Device},
-    chrono::Utc,
+    crate::integrations::iron_nest::{
+        execute_function, functions::function_registry, types::Device,
+    },
     futures::future::join_all,
     leptos::prelude::*,
     log::info,
     serde_json::json,
-    sqlx::{PgPool, Row},
+    sqlx::PgPool,
 };
 
 cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
@@ -13,7 +14,7 @@ cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
     futures::StreamExt,
     async_openai::{
         types::{
-            ChatCompletionFunctionsArgs,
+            ChatCompletionFunctionsArgs, ChatCompletionTool,
             ChatCompletionRequestUserMessageArgs, ChatCompletionToolArgs, ChatCompletionToolType,
             CreateChatCompletionRequestArgs,ChatCompletionMessageToolCall,
             ChatCompletionRequestAssistantMessageArgs,
@@ -25,6 +26,7 @@ cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
         },
         Client,
         config::OpenAIConfig,
+        error::OpenAIError,
     },
     };
 
@@ -39,48 +41,48 @@ cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
     }
 }}
 
+/// Tools for every registered function, with the IPs of known devices as the choices for `ip`
+/// arguments.
+fn function_tools(devices: &[Device]) -> Result<Vec<ChatCompletionTool>, OpenAIError> {
+    function_registry()
+        .functions()
+        .map(|function| {
+            let mut parameters = function.parameters.clone();
+            if !function.ip_device_types.is_empty()
+                && let Some(ip) = parameters.pointer_mut("/properties/ip")
+            {
+                let ips = devices
+                    .iter()
+                    .filter(|device| function.ip_device_types.contains(&device.device_type))
+                    .map(|device| device.ip.clone())
+                    .collect::<Vec<_>>();
+                ip["enum"] = json!(ips);
+            }
+            ChatCompletionToolArgs::default()
+                .r#type(ChatCompletionToolType::Function)
+                .function(
+                    ChatCompletionFunctionsArgs::default()
+                        .name(function.name.clone())
+                        .description(function.description.clone())
+                        .parameters(parameters)
+                        .build()?,
+                )
+                .build()
+        })
+        .collect()
+}
+
 pub async fn open_api_command(text: String, pool: &PgPool) -> Result<String, ServerFnError> {
     println!("calling assistant with {text:?}");
     let client = Client::new();
 
-    let mut tp_link_plug_ips: Vec<String> = Vec::new();
-    let mut tp_link_light_ips: Vec<String> = Vec::new();
-    let mut roku_ips: Vec<String> = Vec::new();
-    let mut devices: Vec<Device> = Vec::new();
-
-    let rows = sqlx::query("SELECT id, name, device_type, ip, power_state, last_seen FROM devices")
-        .fetch_all(pool)
-        .await?;
-
-    for row in rows {
-        let state_value: i32 = row.get("power_state");
-        let state: i32 = state_value;
-        let ip: String = row.get("ip");
-        // let battery_percentage_value: i64 = row.get("battery_percentage");
-        // let battery_percentage: u64 = battery_percentage_value
-        //     .try_into()
-        //     .expect("Value out of range for u64");
-
-        devices.push(Device {
-            id: row.get("id"),
-            name: row.get("name"),
-            device_type: row.get("device_type"),
-            ip: ip.to_string(),
-            power_state: state,
-            battery_percentage: 0,
-            last_seen: Utc::now(),
-            mac_address: None,
-            child_id: None,
-        });
-
-        let device_type: String = row.get("device_type");
-        match device_type.as_str() {
-            "roku" => roku_ips.push(ip.to_string()),
-            "smart-plug" => tp_link_plug_ips.push(ip),
-            "smart-light" => tp_link_light_ips.push(ip),
-            _ => (),
-        }
-    }
+    let query = "
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+        FROM device
+        ORDER BY name
+    ";
+    let devices = sqlx::query_as::<_, Device>(query).fetch_all(pool).await?;
+    let tools = function_tools(&devices)?;
 
     let initial_system_prompt = format!(
         "You are a home assistant named Iron Nest.
@@ -93,185 +95,15 @@ pub async fn open_api_command(text: String, pool: &PgPool) -> Result<String, Ser
     info!("Prompt Length: {}", initial_system_prompt.len());
 
     let request = CreateChatCompletionRequestArgs::default()
-    .max_tokens(512u16)
-    .model("gpt-3.5-turbo-1106")
-    .messages([
-        ChatCompletionRequestUserMessageArgs::default()
+        .max_tokens(512u16)
+        .model("gpt-3.5-turbo-1106")
+        .messages([ChatCompletionRequestUserMessageArgs::default()
             .content(initial_system_prompt.to_string())
             .build()?
-            .into()
-    ])
-    .tools(vec![
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("roku_send_keypress")
-                    .description("Send a keypress to a roku tv device")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "ip": { 
-                                "type": "string", 
-                                "enum": roku_ips,
-                            },
-                            "key": { 
-                                "type": "string", 
-                                "enum": [ 
-                                    "powerOn", "powerOff", "home", "rev", "fwd", "play", "select", "left", "right", "down", "up", "back", 
-                                    "replay", "info", "backspace", "enter", "volumeDown", "volumeUp", "volumeMute", "inputTuner", 
-                                    "inputHDMI1", "inputHDMI2", "inputHDMI3", "inputHDMI4", "inputAV1", "channelUp", "channelDown"
-                                ]
-                            },
-                        },
-                        "required": ["key", "ip"],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("tplink_turn_plug_on")
-                    .description("Turn on tplink smart plug")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "ip": { 
-                                "type": "string", 
-                                "enum": tp_link_plug_ips,
-                            },
-                        },
-                        "required": ["ip"],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("tplink_turn_plug_off")
-                    .description("Turn off tplink smart plug")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "ip": { 
-                                "type": "string", 
-                                "enum": tp_link_plug_ips,
-                            },
-                        },
-                        "required": ["ip"],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("tplink_turn_light_on_off")
-                    .description("Turn on or off tplink smart light")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "ip": { 
-                                "type": "string", 
-                                "enum": tp_link_light_ips,
-                            },
-                            "state": {
-                                "type": "number",
-                                "enum": [0, 1],
-                            }
-                        },
-                        "required": ["ip", "state"],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("tplink_set_light_brightness")
-                    .description("Set tplink smart light brightness (1 - 100)")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "ip": { 
-                                "type": "string", 
-                                "enum": tp_link_light_ips,
-                            },
-                            "brightness": {
-                                "type": "number",
-                            }
-                        },
-                        "required": ["ip", "brightness"],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("roku_search")
-                    .description("Open the Roku search page with the given search params")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "query": {
-                                "type": "string",
-                                "description": "The value to search for on the roku",
-                            },
-                        },
-                        "required": [],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-        ChatCompletionToolArgs::default()
-            .r#type(ChatCompletionToolType::Function)
-            .function(
-                ChatCompletionFunctionsArgs::default()
-                    .name("roku_launch_app")
-                    .description("")
-                    .parameters(json!({
-                        "type": "object",
-                        "properties": {
-                            "app_id": {
-                                "type": "string",
-                                "description": "Roku app_id or name, e,g YouTube or 837",
-                            },
-                        },
-                        "required": [],
-                    }))
-                    .build().unwrap()
-            )
-            .build().unwrap(),
-            ChatCompletionToolArgs::default()
-                .r#type(ChatCompletionToolType::Function)
-                .function(
-                    ChatCompletionFunctionsArgs::default()
-                        .name("stoplight_toggle")
-                        .description("Toggle current state of red, green, or yellow by name")
-                        .parameters(json!({
-                            "type": "object",
-                            "properties": {
-                                "color": {
-                                    "type": "string",
-                                    "description": "The color light to toggle",
-                                    "enum": ["red", "green", "yellow"],
-                                },
-                            },
-                            "required": [],
-                        }))
-                        .build().unwrap()
-                )
-                .build().unwrap(),
-    ])
-    .build().unwrap();
+            .into()])
+        .tools(tools)
+        .build()
+        .unwrap();
 
     println!("{}", serde_json::to_string(&request).unwrap());
 
@@ -289,10 +121,18 @@ pub async fn open_api_command(text: String, pool: &PgPool) -> Result<String, Ser
     let value = if let Some(tool_calls) = response_message.tool_calls {
         let tool_call_futs = tool_calls.iter().map(|tool_call| async {
             let function_name = tool_call.function.name.to_string();
-            let function_args: serde_json::Value = tool_call.function.arguments.parse().unwrap();
-            let function_response = execute_function(function_name, function_args).await;
+            // Errors go back to the model so it can tell the user what went wrong
+            let function_response = match tool_call.function.arguments.parse() {
+                Ok(function_args) => {
+                    match execute_function(pool, function_name, function_args).await {
+                        Ok(response) => response.to_string(),
+                        Err(e) => json!({ "error": e.to_string() }).to_string(),
+                    }
+                }
+                Err(e) => json!({ "error": format!("Arguments are not JSON: {e}") }).to_string(),
+            };
 
-            (tool_call.clone(), function_response.to_string())
+            (tool_call.clone(), function_response)
         });
         let function_responses = join_all(tool_call_futs).await;
 
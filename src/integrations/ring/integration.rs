@@ -0,0 +1,103 @@
+use {
+    super::{CAPABILITIES, client::RingRestClient, get_ring_camera},
+    crate::integrations::iron_nest::{
+        get_auth_from_db, insert_cameras_into_db,
+        supervisor::Integration,
+        types::{Device, DeviceType, capability::Capability},
+    },
+    chrono::Utc,
+    futures::{FutureExt, future::BoxFuture},
+    log::info,
+    sqlx::PgPool,
+    std::{sync::Arc, time::Duration},
+};
+
+/// Ring doorbells and their cameras, through the Ring API
+pub struct Ring {
+    pub client: Arc<RingRestClient>,
+}
+
+impl Integration for Ring {
+    fn name(&self) -> &'static str {
+        "ring"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        CAPABILITIES.to_vec()
+    }
+
+    fn discovery_interval(&self) -> Duration {
+        Duration::from_secs(5 * 60)
+    }
+
+    /// Also saves the cameras' snapshots and recordings.
+    fn discover<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>> {
+        async {
+            info!("Refreshing Ring Device Data");
+            let ring_devices = self
+                .client
+                .get_devices()
+                .await
+                .map_err(|e| format!("Failed to get the devices: {e}"))?;
+            let doorbots = ring_devices
+                .doorbots
+                .into_iter()
+                .chain(ring_devices.authorized_doorbots)
+                .collect::<Vec<_>>();
+
+            let mut cameras = Vec::with_capacity(doorbots.len());
+            for doorbot in doorbots.iter() {
+                cameras.push(get_ring_camera(&self.client, doorbot).await)
+            }
+            insert_cameras_into_db(pool, &cameras)
+                .await
+                .map_err(|e| format!("Failed to save the cameras: {e}"))?;
+
+            Ok(cameras
+                .iter()
+                .map(|camera| Device {
+                    id: 0,
+                    name: camera.description.to_string(),
+                    ip: camera.id.to_string(),
+                    device_type: DeviceType::RingDoorbell,
+                    power_state: 1,
+                    battery_percentage: camera.health,
+                    last_seen: Utc::now(),
+                    mac_address: None,
+                    vendor_id: Some(camera.id.to_string()),
+                    child_id: None,
+                    capabilities: CAPABILITIES.to_vec(),
+                })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn auth_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(5 * 60 * 60))
+    }
+
+    fn refresh_auth<'a>(&'a self, _pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async {
+            info!("Refreshing Ring auth token");
+            self.client.refresh_auth_token().await;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn health<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async {
+            if get_auth_from_db(pool, "ring")
+                .await
+                .refresh_token
+                .is_empty()
+            {
+                Err("Not logged in to Ring".to_owned())
+            } else {
+                Ok(())
+            }
+        }
+        .boxed()
+    }
+}
@@ -1,6 +1,12 @@
+use crate::integrations::iron_nest::types::capability::Capability;
+
 pub mod types;
 
+/// Doorbells report their battery and have a camera, but can't be controlled
+pub const CAPABILITIES: &[Capability] = &[Capability::Battery, Capability::Camera];
+
 cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
   pub mod client;
   pub use client::*;
+  pub mod integration;
 }}
@@ -0,0 +1,23 @@
+//! What Roku TVs can do and how they carry out [`DeviceCommand`]s.
+
+use {
+    super::roku_send_keypress,
+    crate::integrations::iron_nest::types::{
+        Device,
+        capability::{Capability, DeviceCommand},
+    },
+};
+
+pub const CAPABILITIES: &[Capability] = &[Capability::OnOff, Capability::MediaRemote];
+
+pub async fn execute(device: &Device, command: &DeviceCommand) -> Result<(), String> {
+    let key = match command {
+        DeviceCommand::OnOff { on: true } => "PowerOn",
+        DeviceCommand::OnOff { on: false } => "PowerOff",
+        DeviceCommand::MediaRemote { key } => key,
+        command => return Err(format!("A Roku TV can't do {command:?}")),
+    };
+    roku_send_keypress(&device.ip, key)
+        .await
+        .map_err(|e| e.to_string())
+}
@@ -71,45 +71,49 @@ pub async fn roku_get_channel_icon(ip: &str, app_id: &str) -> String {
     base64::engine::general_purpose::STANDARD.encode(res_bytes)
 }
 
-pub async fn roku_send_keypress(ip: &str, key: &str) -> serde_json::Value {
+pub async fn roku_send_keypress(ip: &str, key: &str) -> reqwest::Result<()> {
     post(ip, format!("keypress/{key}").as_str()).await
 }
 
-pub async fn roku_search(ip: &str, query: &str) -> serde_json::Value {
+pub async fn roku_search(ip: &str, query: &str) -> reqwest::Result<()> {
     post(ip, format!("search/browse?{query}=&matchAny=true").as_str()).await
 }
 
-pub async fn roku_launch_app(ip: &str, app_id: &str) -> serde_json::Value {
+pub async fn roku_launch_app(ip: &str, app_id: &str) -> reqwest::Result<()> {
     post(ip, format!("launch/{app_id}").as_str()).await
 }
 
-pub async fn post(ip: &str, query: &str) -> serde_json::Value {
+/// Sends an ECP command, failing if the TV can't be reached or rejects it.
+pub async fn post(ip: &str, query: &str) -> reqwest::Result<()> {
     let roku_url = format!("http://{ip}:8060/{query}");
-    println!("roku url: {roku_url}");
     let client = reqwest::Client::new();
 
-    match client.post(&roku_url).send().await {
-        Ok(data) => println!("input: {data:?}"),
-        Err(err) => println!("Error! {err}"),
-    };
-
-    json!({
-        "success": true,
-    })
+    client
+        .post(&roku_url)
+        .send()
+        .await?
+        .error_for_status()
+        .map(drop)
 }
 
 pub async fn get(ip: &str, query: &str) -> String {
+    try_get(ip, query).await.unwrap()
+}
+
+pub async fn try_get(ip: &str, query: &str) -> reqwest::Result<String> {
     let roku_url = format!("http://{ip}:8060/{query}");
     let client = reqwest::Client::new();
 
-    client
-        .get(roku_url)
-        .send()
-        .await
-        .unwrap()
-        .text()
+    client.get(roku_url).send().await?.text().await
+}
+
+/// Whether the TV is on rather than in standby.
+pub async fn roku_is_powered_on(ip: &str) -> Result<bool, String> {
+    let device_info = try_get(ip, "query/device-info")
         .await
-        .unwrap()
+        .map_err(|e| e.to_string())?;
+    let device_info = from_str::<RokuDeviceInfo>(&device_info).map_err(|e| e.to_string())?;
+    Ok(device_info.power_mode == "PowerOn")
 }
 
 pub async fn roku_ws() {
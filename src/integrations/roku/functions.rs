@@ -0,0 +1,109 @@
+use {
+    super::{roku_launch_app, roku_search, roku_send_keypress},
+    crate::integrations::iron_nest::{
+        functions::{FunctionError, FunctionRegistry, ip_parameter},
+        types::{DeviceType, FunctionInfo},
+    },
+    serde::Deserialize,
+    serde_json::{Value, json},
+};
+
+#[derive(Deserialize)]
+struct KeypressArgs {
+    ip: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    ip: String,
+    query: String,
+}
+
+#[derive(Deserialize)]
+struct LaunchAppArgs {
+    ip: String,
+    app_id: String,
+}
+
+/// The outcome of an ECP command, which fails if the TV is off the network or rejects it.
+fn sent(result: reqwest::Result<()>) -> Result<Value, FunctionError> {
+    result
+        .map(|()| json!({ "success": true }))
+        .map_err(|e| FunctionError::Failed(e.to_string()))
+}
+
+fn function_info(name: &str, description: &str, parameters: Value) -> FunctionInfo {
+    FunctionInfo {
+        name: name.to_owned(),
+        integration: "roku".to_owned(),
+        description: description.to_owned(),
+        parameters,
+        ip_device_types: vec![DeviceType::RokuTv],
+    }
+}
+
+pub fn register_functions(registry: &mut FunctionRegistry) {
+    registry.register(
+        function_info(
+            "roku_send_keypress",
+            "Send a keypress to a roku tv device",
+            json!({
+                "type": "object",
+                "properties": {
+                    "ip": ip_parameter(),
+                    "key": {
+                        "type": "string",
+                        "enum": [
+                            "powerOn", "powerOff", "home", "rev", "fwd", "play", "select", "left", "right", "down", "up", "back",
+                            "replay", "info", "backspace", "enter", "volumeDown", "volumeUp", "volumeMute", "inputTuner",
+                            "inputHDMI1", "inputHDMI2", "inputHDMI3", "inputHDMI4", "inputAV1", "channelUp", "channelDown"
+                        ]
+                    },
+                },
+                "required": ["key", "ip"],
+            }),
+        ),
+        |_pool, args: KeypressArgs| async move {
+            sent(roku_send_keypress(&args.ip, &args.key).await)
+        },
+    );
+    registry.register(
+        function_info(
+            "roku_search",
+            "Open the Roku search page with the given search params",
+            json!({
+                "type": "object",
+                "properties": {
+                    "ip": ip_parameter(),
+                    "query": {
+                        "type": "string",
+                        "description": "The value to search for on the roku",
+                    },
+                },
+                "required": ["ip", "query"],
+            }),
+        ),
+        |_pool, args: SearchArgs| async move { sent(roku_search(&args.ip, &args.query).await) },
+    );
+    registry.register(
+        function_info(
+            "roku_launch_app",
+            "Launch an app on a roku tv device",
+            json!({
+                "type": "object",
+                "properties": {
+                    "ip": ip_parameter(),
+                    "app_id": {
+                        "type": "string",
+                        "description": "Roku app_id or name, e,g YouTube or 837",
+                    },
+                },
+                "required": ["ip", "app_id"],
+            }),
+        ),
+        |_pool, args: LaunchAppArgs| async move {
+            sent(roku_launch_app(&args.ip, &args.app_id).await)
+        },
+    );
+}
@@ -0,0 +1,76 @@
+use {
+    super::{capabilities, roku_discover, roku_get_device_info},
+    crate::integrations::iron_nest::{
+        extract_ip,
+        supervisor::Integration,
+        types::{
+            Device, DeviceType,
+            capability::{Capability, DeviceCommand},
+        },
+    },
+    chrono::Utc,
+    futures::{FutureExt, future::BoxFuture},
+    sqlx::PgPool,
+    std::time::Duration,
+};
+
+/// Roku TVs found with SSDP
+pub struct Roku;
+
+impl Integration for Roku {
+    fn name(&self) -> &'static str {
+        "roku"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        capabilities::CAPABILITIES.to_vec()
+    }
+
+    fn discovery_interval(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    fn discover<'a>(&'a self, _pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>> {
+        async {
+            let mut devices = Vec::new();
+            for device in roku_discover().await {
+                let ip = extract_ip(&device.location)
+                    .map_err(|e| format!("Invalid location {}: {e}", device.location))?;
+                let device_info = roku_get_device_info(&ip).await;
+                let power_state = if device_info.power_mode == "PowerOn" {
+                    1
+                } else {
+                    0
+                };
+                // The USN is "uuid:roku:ecp:<serial number>"
+                let serial_number = Some(device_info.serial_number)
+                    .filter(|serial_number| !serial_number.is_empty())
+                    .or_else(|| device.usn.rsplit(':').next().map(str::to_owned))
+                    .filter(|serial_number| !serial_number.is_empty());
+                devices.push(Device {
+                    id: 0,
+                    name: device_info.user_device_name,
+                    device_type: DeviceType::RokuTv,
+                    ip,
+                    power_state,
+                    battery_percentage: 0,
+                    last_seen: Utc::now(),
+                    mac_address: device_info.wifi_mac,
+                    vendor_id: serial_number,
+                    child_id: None,
+                    capabilities: capabilities::CAPABILITIES.to_vec(),
+                });
+            }
+            Ok(devices)
+        }
+        .boxed()
+    }
+
+    fn handle_command<'a>(
+        &'a self,
+        device: &'a Device,
+        command: &'a DeviceCommand,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        capabilities::execute(device, command).boxed()
+    }
+}
@@ -1,6 +1,9 @@
 pub mod types;
 
 cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
+    pub mod capabilities;
     pub mod client;
     pub use client::*;
+    pub mod functions;
+    pub mod integration;
 }}
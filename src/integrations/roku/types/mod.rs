@@ -28,6 +28,10 @@ pub struct RokuDeviceInfo {
     pub user_device_name: String,
     #[serde(rename = "power-mode")]
     pub power_mode: String,
+    #[serde(rename = "serial-number", default)]
+    pub serial_number: String,
+    #[serde(rename = "wifi-mac", default)]
+    pub wifi_mac: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -0,0 +1,42 @@
+use {
+    super::toggle_stoplight,
+    crate::integrations::iron_nest::{
+        functions::{FunctionError, FunctionRegistry},
+        types::FunctionInfo,
+    },
+    serde::Deserialize,
+    serde_json::json,
+};
+
+#[derive(Deserialize)]
+struct ToggleArgs {
+    color: String,
+}
+
+pub fn register_functions(registry: &mut FunctionRegistry) {
+    registry.register(
+        FunctionInfo {
+            name: "stoplight_toggle".to_owned(),
+            integration: "stoplight".to_owned(),
+            description: "Toggle current state of red, green, or yellow by name".to_owned(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "color": {
+                        "type": "string",
+                        "description": "The color light to toggle",
+                        "enum": ["red", "green", "yellow"],
+                    },
+                },
+                "required": ["color"],
+            }),
+            ip_device_types: Vec::new(),
+        },
+        |_pool, args: ToggleArgs| async move {
+            toggle_stoplight(&args.color)
+                .await
+                .map(|_| json!({"success": true}))
+                .map_err(|e| FunctionError::Failed(e.to_string()))
+        },
+    );
+}
@@ -0,0 +1,150 @@
+//! What Kasa devices can do, read from the sysinfo they answer discovery with, and how they
+//! carry out [`DeviceCommand`]s.
+
+use {
+    super::{
+        tplink_get_emeter_realtime, tplink_set_relay_state, tplink_transition_dimmer,
+        tplink_transition_light_state, types::DeviceData,
+    },
+    crate::integrations::iron_nest::types::{
+        Device, DeviceType,
+        capability::{Capability, DeviceCommand},
+        energy::PowerReading,
+    },
+    serde_json::{Value, json},
+};
+
+/// Plugs and strips with an energy meter list `ENE` in their features, e.g. `TIM:ENE`.
+fn has_energy_meter(feature: &str) -> bool {
+    feature.split(':').any(|feature| feature == "ENE")
+}
+
+pub fn capabilities(data: &DeviceData) -> Vec<Capability> {
+    let mut capabilities = vec![Capability::OnOff];
+    match data {
+        DeviceData::SmartPlug(data) => {
+            if has_energy_meter(&data.feature) {
+                capabilities.push(Capability::PowerMeter);
+            }
+        }
+        DeviceData::SmartDimmer(_) => capabilities.push(Capability::Brightness),
+        DeviceData::SmartLight(data) => {
+            if data.is_dimmable == 1 {
+                capabilities.push(Capability::Brightness);
+            }
+            if data.is_color == 1 {
+                capabilities.push(Capability::ColorHsv);
+            }
+            if data.is_variable_color_temp == 1 {
+                capabilities.push(Capability::ColorTemp);
+            }
+        }
+        DeviceData::SmartPowerStrip(data) => {
+            capabilities.push(Capability::Children);
+            if has_energy_meter(&data.feature) {
+                capabilities.push(Capability::PowerMeter);
+            }
+        }
+    }
+    capabilities
+}
+
+pub async fn execute(device: &Device, command: &DeviceCommand) -> Result<(), String> {
+    let ip = device.ip.as_str();
+    let light = |state| tplink_transition_light_state(ip, state, 0);
+    match (command, &device.device_type) {
+        (DeviceCommand::OnOff { on }, DeviceType::KasaPlug | DeviceType::KasaDimmer) => {
+            tplink_set_relay_state(ip, None, *on).await
+        }
+        (DeviceCommand::OnOff { on }, DeviceType::KasaPowerStrip) => {
+            tplink_set_relay_state(ip, device.child_id.as_deref(), *on).await
+        }
+        (DeviceCommand::OnOff { on }, DeviceType::KasaLight) => {
+            light(json!({ "on_off": u8::from(*on) })).await
+        }
+        (DeviceCommand::Brightness { brightness }, DeviceType::KasaDimmer) => {
+            tplink_transition_dimmer(ip, (*brightness).min(100), 0).await
+        }
+        (DeviceCommand::Brightness { brightness }, DeviceType::KasaLight) => {
+            light(json!({ "on_off": 1, "brightness": (*brightness).min(100) })).await
+        }
+        (
+            DeviceCommand::ColorHsv {
+                hue,
+                saturation,
+                brightness,
+            },
+            DeviceType::KasaLight,
+        ) => {
+            // A color temperature other than 0 overrides the hue
+            light(json!({
+                "on_off": 1,
+                "hue": (*hue).min(360),
+                "saturation": (*saturation).min(100),
+                "brightness": (*brightness).min(100),
+                "color_temp": 0,
+            }))
+            .await
+        }
+        (DeviceCommand::ColorTemp { kelvin }, DeviceType::KasaLight) => {
+            light(json!({ "on_off": 1, "color_temp": kelvin })).await
+        }
+        (command, device_type) => {
+            return Err(format!("A {device_type} can't do {command:?}"));
+        }
+    }
+    .map_err(|e| format!("{ip}: {e}"))
+}
+
+/// Newer hardware reports milliwatts, millivolts and milliamps, older watts, volts and amps.
+fn power_reading(realtime: &Value) -> Option<PowerReading> {
+    let read = |milli: &str, unit: &str| {
+        realtime[milli]
+            .as_f64()
+            .map(|value| value / 1000.)
+            .or_else(|| realtime[unit].as_f64())
+    };
+    Some(PowerReading {
+        power_w: read("power_mw", "power")?,
+        voltage_v: read("voltage_mv", "voltage"),
+        current_a: read("current_ma", "current"),
+    })
+}
+
+pub async fn read_power(device: &Device) -> Result<PowerReading, String> {
+    let ip = device.ip.as_str();
+    let realtime = tplink_get_emeter_realtime(ip, device.child_id.as_deref())
+        .await
+        .map_err(|e| format!("{ip}: {e}"))?;
+    power_reading(&realtime).ok_or_else(|| format!("{ip}: No power in {realtime}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_reading() {
+        let reading = power_reading(&json!({
+            "err_code": 0, "power_mw": 12500, "voltage_mv": 120100, "current_ma": 104, "total_wh": 30
+        }));
+        assert_eq!(
+            reading,
+            Some(PowerReading {
+                power_w: 12.5,
+                voltage_v: Some(120.1),
+                current_a: Some(0.104),
+            })
+        );
+        let reading = power_reading(&json!({"err_code": 0, "power": 3.25, "total": 0.1}));
+        assert_eq!(
+            reading,
+            Some(PowerReading {
+                power_w: 3.25,
+                voltage_v: None,
+                current_a: None,
+            })
+        );
+        assert_eq!(power_reading(&json!({"err_code": 0})), None);
+    }
+}
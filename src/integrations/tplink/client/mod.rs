@@ -15,6 +15,10 @@ use {
 };
 
 const KEY: u8 = 0xAB;
+const MAX_RESPONSE_LEN: usize = 1 << 20;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long a device has to answer once connected, some accept and then never respond
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub async fn discover_devices() -> Result<Vec<DeviceData>, Box<dyn Error + Send>> {
     let port = 9999;
@@ -112,21 +116,36 @@ pub async fn send(ip: &str, json: serde_json::Value) -> io::Result<Value> {
         }
     };
 
-    let mut stream = TcpStream::connect((_ip, 9999)).await?;
+    let mut stream = timeout(CONNECT_TIMEOUT, TcpStream::connect((_ip, 9999)))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("{ip} didn't connect")))??;
 
     let msg_bytes =
         serde_json::to_vec(&json).expect("Should be able to serialize hardcoded data w/o error");
     let discover_msg = encrypt_with_header(&msg_bytes, KEY);
 
-    stream.write_all(&discover_msg).await.unwrap();
-
-    let mut buf = [0u8; 1024];
-    let bytes_read = stream.read(&mut buf).await?;
-    println!("bytes_read: {bytes_read}");
-    let decrypted_msg = decrypt_with_header(&buf, KEY);
-    let msg = serde_json::from_slice::<Value>(&decrypted_msg).unwrap();
-    println!("msg: {msg:?}");
-    Ok(msg)
+    let exchange = async {
+        stream.write_all(&discover_msg).await?;
+
+        // Responses, e.g. the sysinfo of a power strip, often don't fit in one read
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).await?;
+        let len = u32::from_be_bytes(header) as usize;
+        if len > MAX_RESPONSE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{ip} sent a {len} byte response"),
+            ));
+        }
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).await?;
+        Ok::<_, io::Error>(buf)
+    };
+    let buf = timeout(RESPONSE_TIMEOUT, exchange)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, format!("{ip} didn't answer")))??;
+    serde_json::from_slice::<Value>(&decrypt(&buf, KEY))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
 pub async fn tplink_set_alias(ip: &str, alias: &str) {
@@ -251,24 +270,69 @@ pub async fn tplink_set_light_hsl(ip: &str, color: String) {
     }
 }
 
-pub async fn tplink_kasa_get_energy_usage(ip: &str, id: &str) -> io::Result<Value> {
-    send(
-        ip,
-        json!({"context": {
-                "child_ids": [id]
-            },"system":{"get_energy_usage":{}}}),
-    )
-    .await
+/// The `method` part of a response, failing if the device reported an error.
+fn response_of(mut msg: Value, module: &str, method: &str) -> io::Result<Value> {
+    let response = msg
+        .pointer_mut(&format!("/{module}/{method}"))
+        .map(Value::take)
+        .unwrap_or_default();
+    match response["err_code"].as_i64() {
+        Some(0) => Ok(response),
+        _ => Err(io::Error::other(format!(
+            "{module}.{method} failed: {response}"
+        ))),
+    }
 }
 
-pub async fn tplink_kasa_get_emeter_data(ip: &str, id: &str) -> io::Result<Value> {
-    send(
+pub async fn tplink_get_sysinfo(ip: &str) -> io::Result<Value> {
+    let msg = send(ip, json!({"system":{"get_sysinfo":{}}})).await?;
+    response_of(msg, "system", "get_sysinfo")
+}
+
+/// Switches a plug, dimmer or, with `child_id`, a power strip socket.
+pub async fn tplink_set_relay_state(ip: &str, child_id: Option<&str>, on: bool) -> io::Result<()> {
+    let mut request = json!({"system":{"set_relay_state":{"state": u8::from(on)}}});
+    if let Some(child_id) = child_id {
+        request["context"] = json!({"child_ids": [child_id]});
+    }
+    let msg = send(ip, request).await?;
+    response_of(msg, "system", "set_relay_state").map(drop)
+}
+
+/// Fades a dimmer to `brightness`, turning it on.
+pub async fn tplink_transition_dimmer(
+    ip: &str,
+    brightness: u8,
+    transition_ms: u32,
+) -> io::Result<()> {
+    let msg = send(
         ip,
-        json!({"context": {
-                "child_ids": [id]
-            },"system":{"get_emeter_data":{}}}),
+        json!({"smartlife.iot.dimmer":{"set_dimmer_transition":{"brightness": brightness, "duration": transition_ms.max(1)}}}),
     )
-    .await
+    .await?;
+    response_of(msg, "smartlife.iot.dimmer", "set_dimmer_transition").map(drop)
+}
+
+/// Fades a light to `state`, an object with any of `on_off`, `brightness`, `hue`, `saturation`
+/// and `color_temp`.
+pub async fn tplink_transition_light_state(
+    ip: &str,
+    mut state: Value,
+    transition_ms: u32,
+) -> io::Result<()> {
+    state["transition_period"] = json!(transition_ms);
+    let msg = send(ip, json!({LIGHT_SERVICE:{"transition_light_state": state}})).await?;
+    response_of(msg, LIGHT_SERVICE, "transition_light_state").map(drop)
+}
+
+/// Reads the energy meter of a plug or, with `child_id`, a power strip socket.
+pub async fn tplink_get_emeter_realtime(ip: &str, child_id: Option<&str>) -> io::Result<Value> {
+    let mut request = json!({"emeter":{"get_realtime":{}}});
+    if let Some(child_id) = child_id {
+        request["context"] = json!({"child_ids": [child_id]});
+    }
+    let msg = send(ip, request).await?;
+    response_of(msg, "emeter", "get_realtime")
 }
 
 fn encrypt_with_header(input: &[u8], first_key: u8) -> Vec<u8> {
@@ -303,10 +367,3 @@ fn encrypt(input: &[u8], first_key: u8) -> Vec<u8> {
     }
     buf
 }
-
-fn decrypt_with_header(input: &[u8], first_key: u8) -> Vec<u8> {
-    let len = u32::from_be_bytes(input[0..4].try_into().unwrap());
-    let mut msg = decrypt(&input[4..], first_key);
-    msg.truncate(len as usize);
-    msg
-}
@@ -0,0 +1,183 @@
+use {
+    super::{tplink_set_relay_state, tplink_transition_dimmer, tplink_transition_light_state},
+    crate::{
+        integrations::iron_nest::{
+            functions::{FunctionError, FunctionRegistry, ip_parameter},
+            types::{DeviceType, FunctionInfo},
+        },
+        server::tplink::smart_light_toggle_query,
+    },
+    serde::Deserialize,
+    serde_json::{Value, json},
+    std::io,
+};
+
+#[derive(Deserialize)]
+struct IpArgs {
+    ip: String,
+}
+
+#[derive(Deserialize)]
+struct LightOnOffArgs {
+    ip: String,
+    /// Older actions store the state as a string
+    state: Value,
+}
+
+#[derive(Deserialize)]
+struct BrightnessArgs {
+    ip: String,
+    brightness: u8,
+}
+
+#[derive(Deserialize)]
+struct ToggleArgs {
+    ip: String,
+    state: bool,
+}
+
+fn function_info(
+    name: &str,
+    description: &str,
+    parameters: Value,
+    ip_device_types: Vec<DeviceType>,
+) -> FunctionInfo {
+    FunctionInfo {
+        name: name.to_owned(),
+        integration: "tplink".to_owned(),
+        description: description.to_owned(),
+        parameters,
+        ip_device_types,
+    }
+}
+
+fn brightness_parameters() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "ip": ip_parameter(),
+            "brightness": { "type": "integer", "minimum": 0, "maximum": 100 },
+        },
+        "required": ["ip", "brightness"],
+    })
+}
+
+fn success() -> Value {
+    json!({
+        "message": "success"
+    })
+}
+
+/// The outcome of a request to a device, which fails if it's offline or refuses it.
+fn sent(result: io::Result<()>) -> Result<Value, FunctionError> {
+    result
+        .map(|()| success())
+        .map_err(|e| FunctionError::Failed(e.to_string()))
+}
+
+pub fn register_functions(registry: &mut FunctionRegistry) {
+    let ip_parameters = json!({
+        "type": "object",
+        "properties": { "ip": ip_parameter() },
+        "required": ["ip"],
+    });
+    registry.register(
+        function_info(
+            "tplink_turn_plug_on",
+            "Turn on tplink smart plug",
+            ip_parameters.clone(),
+            vec![DeviceType::KasaPlug],
+        ),
+        |_pool, args: IpArgs| async move {
+            sent(tplink_set_relay_state(&args.ip, None, true).await)
+        },
+    );
+    registry.register(
+        function_info(
+            "tplink_turn_plug_off",
+            "Turn off tplink smart plug",
+            ip_parameters,
+            vec![DeviceType::KasaPlug],
+        ),
+        |_pool, args: IpArgs| async move {
+            sent(tplink_set_relay_state(&args.ip, None, false).await)
+        },
+    );
+    registry.register(
+        function_info(
+            "tplink_turn_light_on_off",
+            "Turn on or off tplink smart light",
+            json!({
+                "type": "object",
+                "properties": {
+                    "ip": ip_parameter(),
+                    "state": {
+                        "enum": [0, 1, "0", "1"],
+                        "description": "1 for on, 0 for off",
+                    },
+                },
+                "required": ["ip", "state"],
+            }),
+            vec![DeviceType::KasaLight],
+        ),
+        |_pool, args: LightOnOffArgs| async move {
+            let state = match &args.state {
+                Value::String(state) => state.parse::<u8>().ok(),
+                state => state.as_u64().and_then(|state| u8::try_from(state).ok()),
+            };
+            match state {
+                Some(state) => sent(
+                    tplink_transition_light_state(&args.ip, json!({ "on_off": state }), 0).await,
+                ),
+                None => Err(FunctionError::Failed(format!(
+                    "Invalid state {}",
+                    args.state
+                ))),
+            }
+        },
+    );
+    registry.register(
+        function_info(
+            "tplink_set_light_brightness",
+            "Set tplink smart light brightness (1 - 100)",
+            brightness_parameters(),
+            vec![DeviceType::KasaLight],
+        ),
+        |_pool, args: BrightnessArgs| async move {
+            let state = json!({ "brightness": args.brightness });
+            sent(tplink_transition_light_state(&args.ip, state, 0).await)
+        },
+    );
+    registry.register(
+        function_info(
+            "tplink_set_dimmer_brightness",
+            "Set tplink smart dimmer brightness (1 - 100)",
+            brightness_parameters(),
+            vec![DeviceType::KasaDimmer],
+        ),
+        |_pool, args: BrightnessArgs| async move {
+            sent(tplink_transition_dimmer(&args.ip, args.brightness, 0).await)
+        },
+    );
+    registry.register(
+        function_info(
+            "handle_smart_light_toggle",
+            "Turn a smart light on or off and record its new state",
+            json!({
+                "type": "object",
+                "properties": {
+                    "ip": ip_parameter(),
+                    "state": { "type": "boolean" },
+                },
+                "required": ["ip", "state"],
+            }),
+            vec![DeviceType::KasaLight],
+        ),
+        |pool, args: ToggleArgs| async move {
+            smart_light_toggle_query(&pool, args.state, &args.ip)
+                .await
+                .map(|()| success())
+                .map_err(|e| FunctionError::Failed(e.to_string()))
+        },
+    );
+}
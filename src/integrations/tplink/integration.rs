@@ -0,0 +1,142 @@
+use {
+    super::{capabilities, discover_devices, types::DeviceData},
+    crate::integrations::iron_nest::{
+        supervisor::Integration,
+        types::{
+            Device, DeviceType,
+            capability::{Capability, DeviceCommand},
+            energy::PowerReading,
+        },
+    },
+    chrono::Utc,
+    futures::{FutureExt, future::BoxFuture},
+    sqlx::PgPool,
+    std::time::Duration,
+};
+
+/// Kasa plugs, strips, dimmers and lights on the local network
+pub struct TpLink;
+
+/// A device for each plug, dimmer and light, and each outlet of a power strip.
+fn devices(tp_link_devices: Vec<DeviceData>) -> Vec<Device> {
+    let mut devices = Vec::new();
+    for device_data in tp_link_devices {
+        let capabilities = capabilities::capabilities(&device_data);
+        match device_data {
+            DeviceData::SmartPlug(data) => {
+                if let Some(ip) = data.ip {
+                    devices.push(Device {
+                        id: 0,
+                        name: data.alias,
+                        device_type: DeviceType::KasaPlug,
+                        ip: ip.to_string(),
+                        power_state: data.relay_state,
+                        battery_percentage: 0,
+                        last_seen: Utc::now(),
+                        mac_address: Some(data.mac.clone()),
+                        vendor_id: Some(data.device_id.clone()),
+                        child_id: None,
+                        capabilities,
+                    });
+                }
+            }
+            DeviceData::SmartLight(data) => {
+                if let Some(ip) = data.ip {
+                    devices.push(Device {
+                        id: 0,
+                        name: data.alias,
+                        device_type: DeviceType::KasaLight,
+                        ip: ip.to_string(),
+                        power_state: data.light_state.on_off,
+                        battery_percentage: 0,
+                        last_seen: Utc::now(),
+                        mac_address: data.mic_mac.clone(),
+                        vendor_id: data.device_id.clone(),
+                        child_id: None,
+                        capabilities,
+                    });
+                }
+            }
+            DeviceData::SmartDimmer(data) => {
+                if let Some(ip) = data.ip {
+                    devices.push(Device {
+                        id: 0,
+                        name: data.alias,
+                        device_type: DeviceType::KasaDimmer,
+                        ip: ip.to_string(),
+                        power_state: data.relay_state,
+                        battery_percentage: 0,
+                        last_seen: Utc::now(),
+                        mac_address: Some(data.mac.clone()),
+                        vendor_id: Some(data.device_id.clone()),
+                        child_id: None,
+                        capabilities,
+                    });
+                }
+            }
+            DeviceData::SmartPowerStrip(data) => {
+                if let Some(ip) = data.ip {
+                    for outlet in data.children {
+                        devices.push(Device {
+                            id: 0,
+                            name: outlet.alias,
+                            device_type: DeviceType::KasaPowerStrip,
+                            ip: ip.to_string(),
+                            power_state: outlet.state,
+                            battery_percentage: 0,
+                            last_seen: Utc::now(),
+                            mac_address: Some(data.mac.clone()),
+                            vendor_id: Some(data.device_id.clone()),
+                            child_id: Some(format!("{}{}", data.device_id, outlet.id)),
+                            capabilities: capabilities.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    devices
+}
+
+impl Integration for TpLink {
+    fn name(&self) -> &'static str {
+        "tplink"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![
+            Capability::OnOff,
+            Capability::Brightness,
+            Capability::ColorHsv,
+            Capability::ColorTemp,
+            Capability::PowerMeter,
+            Capability::Children,
+        ]
+    }
+
+    fn discovery_interval(&self) -> Duration {
+        Duration::from_secs(300)
+    }
+
+    fn discover<'a>(&'a self, _pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>> {
+        async {
+            discover_devices()
+                .await
+                .map(devices)
+                .map_err(|e| format!("Error discovering devices: {e}"))
+        }
+        .boxed()
+    }
+
+    fn handle_command<'a>(
+        &'a self,
+        device: &'a Device,
+        command: &'a DeviceCommand,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        capabilities::execute(device, command).boxed()
+    }
+
+    fn read_power<'a>(&'a self, device: &'a Device) -> BoxFuture<'a, Result<PowerReading, String>> {
+        capabilities::read_power(device).boxed()
+    }
+}
@@ -113,6 +113,12 @@ pub struct TPLinkSmartLightData {
     pub light_state: LightState,
     pub is_dimmable: u8,
     pub is_color: u8,
+    #[serde(default)]
+    pub is_variable_color_temp: u8,
+    #[serde(rename = "deviceId", default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub mic_mac: Option<String>,
     pub ip: Option<IpAddr>,
 }
 
@@ -0,0 +1,111 @@
+use {
+    super::{discover_tuya_devices, get_devices, get_refresh_token},
+    crate::integrations::iron_nest::{
+        get_auth_from_db, insert_auth,
+        supervisor::Integration,
+        types::{AuthState, Device, DeviceType, capability::Capability},
+    },
+    chrono::Utc,
+    futures::{FutureExt, future::BoxFuture},
+    log::{error, info},
+    sqlx::PgPool,
+    std::{net::Ipv4Addr, time::Duration},
+};
+
+// @TODO refactor user_id to come from db
+const USER_ID: &str = "az17063780590351Cr1b";
+
+/// Tuya lights, through the Tuya cloud
+pub struct Tuya;
+
+impl Integration for Tuya {
+    fn name(&self) -> &'static str {
+        "tuya"
+    }
+
+    /// Nothing can be controlled through Tuya yet
+    fn capabilities(&self) -> Vec<Capability> {
+        Vec::new()
+    }
+
+    fn discovery_interval(&self) -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    fn discover<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<Vec<Device>, String>> {
+        async {
+            let tuya_auth = get_auth_from_db(pool, "tuya").await;
+            if tuya_auth.auth_token.is_empty() {
+                return Err("Not logged in to Tuya".to_owned());
+            }
+            let res = get_devices(USER_ID, &tuya_auth.auth_token)
+                .await
+                .map_err(|e| format!("Failed to get the devices: {e}"))?;
+            let devices = res
+                .result
+                .iter()
+                .enumerate()
+                .map(|(index, device)| Device {
+                    id: 0,
+                    name: device.name.clone(),
+                    device_type: DeviceType::TuyaLight,
+                    ip: Ipv4Addr::new(0, 0, 0, 0).to_string(),
+                    power_state: 0,
+                    battery_percentage: 0,
+                    last_seen: Utc::now(),
+                    mac_address: None,
+                    vendor_id: Some(device.id.clone()),
+                    child_id: Some(index.to_string()),
+                    capabilities: Vec::new(),
+                })
+                .collect();
+
+            // task for local network discovery
+            tokio::task::spawn(async {
+                match tokio::time::timeout(Duration::from_secs(10), discover_tuya_devices()).await {
+                    Ok(Ok(_)) => info!("Local Tuya discovery completed."),
+                    Ok(Err(e)) => error!("Error during local Tuya discovery: {e}"),
+                    Err(_) => error!("Local Tuya discovery timed out."),
+                }
+            });
+            Ok(devices)
+        }
+        .boxed()
+    }
+
+    fn auth_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(60 * 60))
+    }
+
+    /// Gets a new token, which works whether or not there's a refresh token yet.
+    fn refresh_auth<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async {
+            let res = get_refresh_token()
+                .await
+                .map_err(|e| format!("Failed to get a token: {e}"))?;
+            insert_auth(
+                pool,
+                "tuya",
+                AuthState {
+                    refresh_token: res.result.refresh_token,
+                    hardware_id: res.result.uid,
+                    auth_token: res.result.access_token,
+                },
+            )
+            .await;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn health<'a>(&'a self, pool: &'a PgPool) -> BoxFuture<'a, Result<(), String>> {
+        async {
+            if get_auth_from_db(pool, "tuya").await.auth_token.is_empty() {
+                Err("Not logged in to Tuya".to_owned())
+            } else {
+                Ok(())
+            }
+        }
+        .boxed()
+    }
+}
@@ -3,4 +3,5 @@ pub mod types;
 cfg_if::cfg_if! { if #[cfg(feature = "ssr")] {
   mod client;
   pub use client::*;
+  pub mod integration;
 }}
@@ -19,9 +19,141 @@ pub fn hydrate() {
 }
 
 pub mod ipld_codecs {
-    use {ipld_core::codec::Codec, serde_ipld_dagjson::codec::DagJsonCodec};
+    use {
+        anyhow::anyhow,
+        cid::Cid,
+        ipld_core::{
+            codec::{Codec, Links},
+            ipld::Ipld,
+        },
+        multihash_codetable::{Code, MultihashDigest},
+        serde_ipld_dagcbor::codec::DagCborCodec,
+        serde_ipld_dagjson::codec::DagJsonCodec,
+    };
 
     // https://github.com/multiformats/multicodec/blob/3bc7f4c20afe28e10d9d539e2a565578de6dd71c/table.csv#L41
     pub const RAW: u64 = 0x55;
     pub const DAG_JSON: u64 = <DagJsonCodec as Codec<serde_json::Value>>::CODE;
+    pub const DAG_CBOR: u64 = <DagCborCodec as Codec<Ipld>>::CODE;
+
+    pub fn name(codec: u64) -> &'static str {
+        match codec {
+            RAW => "raw",
+            DAG_JSON => "dag-json",
+            DAG_CBOR => "dag-cbor",
+            _ => "unknown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<u64> {
+        match name {
+            "raw" => Some(RAW),
+            "dag-json" => Some(DAG_JSON),
+            "dag-cbor" => Some(DAG_CBOR),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(codec: u64) -> &'static str {
+        match codec {
+            DAG_JSON => "application/vnd.ipld.dag-json",
+            DAG_CBOR => "application/vnd.ipld.dag-cbor",
+            _ => "application/octet-stream",
+        }
+    }
+
+    pub fn cid(codec: u64, content: &[u8]) -> Cid {
+        Cid::new_v1(codec, Code::Sha2_256.digest(content))
+    }
+
+    /// Whether `content` hashes to the CID's multihash, `None` if we don't support its hash
+    /// function.
+    pub fn verify(cid: &Cid, content: &[u8]) -> Option<bool> {
+        let code = Code::try_from(cid.hash().code()).ok()?;
+        Some(code.digest(content) == *cid.hash())
+    }
+
+    /// CID the value would get if it were uploaded as a DAG-JSON blob.
+    pub fn dag_json_cid(
+        value: &serde_json::Value,
+    ) -> Result<Cid, <DagJsonCodec as Codec<serde_json::Value>>::Error> {
+        let content = DagJsonCodec::encode_to_vec(value)?;
+        Ok(cid(DAG_JSON, &content))
+    }
+
+    pub fn decode(codec: u64, content: &[u8]) -> anyhow::Result<Ipld> {
+        match codec {
+            RAW => Ok(Ipld::Bytes(content.to_vec())),
+            DAG_JSON => Ok(DagJsonCodec::decode_from_slice(content)?),
+            DAG_CBOR => Ok(DagCborCodec::decode_from_slice(content)?),
+            _ => Err(anyhow!("Unsupported codec: {codec:#x}")),
+        }
+    }
+
+    pub fn encode(codec: u64, ipld: &Ipld) -> anyhow::Result<Vec<u8>> {
+        match codec {
+            DAG_JSON => Ok(DagJsonCodec::encode_to_vec(ipld)?),
+            DAG_CBOR => Ok(DagCborCodec::encode_to_vec(ipld)?),
+            _ => Err(anyhow!("Cannot encode IPLD data as codec {codec:#x}")),
+        }
+    }
+
+    /// Re-encodes a DAG-JSON or DAG-CBOR blob as the other codec. Links and bytes go through the
+    /// IPLD data model, so they survive the round trip.
+    pub fn convert(content: &[u8], from: u64, to: u64) -> anyhow::Result<Vec<u8>> {
+        if from == to {
+            return Ok(content.to_vec());
+        }
+        if from == RAW || to == RAW {
+            return Err(anyhow!(
+                "Cannot convert between {} and {}",
+                name(from),
+                name(to)
+            ));
+        }
+        encode(to, &decode(from, content)?)
+    }
+
+    /// The blob as JSON for display and editing, using the DAG-JSON representation for links
+    /// and bytes. `None` for raw blobs.
+    pub fn to_json(codec: u64, content: &[u8]) -> anyhow::Result<Option<serde_json::Value>> {
+        match codec {
+            RAW => Ok(None),
+            DAG_JSON => Ok(Some(DagJsonCodec::decode_from_slice(content)?)),
+            _ => Ok(Some(serde_json::from_slice(&convert(
+                content, codec, DAG_JSON,
+            )?)?)),
+        }
+    }
+
+    pub fn links(codec: u64, content: &[u8]) -> anyhow::Result<Vec<Cid>> {
+        match codec {
+            RAW => Ok(Vec::new()),
+            DAG_JSON => Ok(<DagJsonCodec as Links>::links(content)?.collect()),
+            DAG_CBOR => Ok(<DagCborCodec as Links>::links(content)?.collect()),
+            _ => Err(anyhow!("Unsupported codec: {codec:#x}")),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_convert_preserves_links() {
+            let link = cid(RAW, b"hello");
+            let dag_json =
+                format!(r#"{{"bytes":{{"/":{{"bytes":"AQI"}}}},"link":{{"/":"{link}"}}}}"#);
+
+            let dag_cbor = convert(dag_json.as_bytes(), DAG_JSON, DAG_CBOR).unwrap();
+            assert_eq!(links(DAG_CBOR, &dag_cbor).unwrap(), vec![link]);
+            let ipld = decode(DAG_CBOR, &dag_cbor).unwrap();
+            assert_eq!(ipld.get("link").unwrap(), Some(&Ipld::Link(link)));
+            assert_eq!(ipld.get("bytes").unwrap(), Some(&Ipld::Bytes(vec![1, 2])));
+
+            let round_trip = convert(&dag_cbor, DAG_CBOR, DAG_JSON).unwrap();
+            assert_eq!(round_trip, dag_json.as_bytes());
+            assert!(convert(&dag_cbor, DAG_CBOR, RAW).is_err());
+        }
+    }
 }
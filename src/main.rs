@@ -9,6 +9,7 @@ async fn main() {
     use {
         axum::{
             Router,
+            extract::DefaultBodyLimit,
             routing::{get, post},
         },
         dotenv::dotenv,
@@ -18,21 +19,36 @@ async fn main() {
             integrations::{
                 iron_nest::{
                     client::AppState,
-                    cron::CronClient,
-                    mish::{create_mish_state_modification_bus, register_native_queries},
+                    cron::{CronClient, catch_up_missed_runs},
+                    energy::energy_job,
+                    mish::{
+                        blobs::{MAX_DAG_UPLOAD_BYTES, verify_job},
+                        create_mish_state_modification_bus, create_script_log_bus,
+                        gc::gc_job,
+                        register_native_queries,
+                    },
                     run_devices_tasks,
+                    state_history::state_history_job,
+                    vacation::vacation_job,
                 },
                 ring::RingRestClient,
             },
-            mish_api::{update_mish_state_handler, upload_dag_json_file, upload_raw_file},
+            mish_api::{
+                convert_blob_handler, delete_mish_state_handler, download_blob_handler,
+                download_file_handler, export_car_handler, export_mish_state_car_handler,
+                get_mish_state_handler, import_car_handler, list_mish_states_handler,
+                resolve_path_handler, script_logs_sse_handler, set_mish_state_handler,
+                set_mish_state_schema_handler, subscribe_sse_handler, subscribe_ws_handler,
+                update_mish_state_handler, upload_dag_cbor_file, upload_dag_json_file,
+                upload_raw_file,
+            },
         },
         leptos::prelude::*,
         leptos_axum::{LeptosRoutes, generate_route_list},
         log::{LevelFilter, error},
         simple_logger::SimpleLogger,
         sqlx::postgres::PgPoolOptions,
-        std::{collections::HashMap, sync::Arc},
-        tokio::sync::RwLock,
+        std::sync::Arc,
     };
 
     dotenv().ok();
@@ -56,32 +72,62 @@ async fn main() {
     let leptos_options = conf.leptos_options;
     let addr = leptos_options.site_addr;
     let ring_rest_client = Arc::new(RingRestClient::new(shared_pool.clone()).await);
-    let control_senders = Arc::new(RwLock::new(HashMap::new()));
-    let (mish_state_modification_bus_sender, mish_state_modification_bus_receiver) =
-        create_mish_state_modification_bus();
+    let mish_state_modification_bus_sender = create_mish_state_modification_bus();
+    let mish_state_modification_bus_receiver = mish_state_modification_bus_sender.subscribe();
+    let script_log_bus_sender = create_script_log_bus();
     let app_state = AppState {
         leptos_options: leptos_options.clone(),
         ring_rest_client: ring_rest_client.clone(),
         pool: shared_pool.clone(),
-        cron_client: CronClient::new().await,
-        control_senders: control_senders.clone(),
+        cron_client: CronClient::new(),
         mish_state_modification_bus_sender: mish_state_modification_bus_sender.clone(),
+        script_log_bus_sender: script_log_bus_sender.clone(),
     };
 
-    app_state
-        .cron_client
-        .schedule_tasks(&shared_pool)
-        .await
-        .unwrap();
+    if let Err(e) = app_state.cron_client.schedule_tasks(&shared_pool).await {
+        error!("Failed to schedule actions: {e}");
+    }
+    if let Err(e) = catch_up_missed_runs(&shared_pool).await {
+        error!("Failed to catch up on missed actions: {e}");
+    }
 
+    let dag_upload_limit = usize::try_from(*MAX_DAG_UPLOAD_BYTES).unwrap_or(usize::MAX);
     let iron_nest_router = Router::new()
         .route(
             "/roku/{device_id}/keypress/{key}",
             get(roku_keypress_handler),
         )
-        .route("/mish/blob.dag-json", post(upload_dag_json_file))
+        .route(
+            "/mish/blob.dag-json",
+            post(upload_dag_json_file).layer(DefaultBodyLimit::max(dag_upload_limit)),
+        )
+        .route(
+            "/mish/blob.dag-cbor",
+            post(upload_dag_cbor_file).layer(DefaultBodyLimit::max(dag_upload_limit)),
+        )
         .route("/mish/blob.raw", post(upload_raw_file))
+        .route("/mish/blob/{cid}", get(download_blob_handler))
+        .route(
+            "/mish/blob/{cid}/convert/{codec}",
+            post(convert_blob_handler),
+        )
+        .route("/mish/car", post(import_car_handler))
+        .route("/mish/file/{cid}", get(download_file_handler))
+        .route("/mish/car/{cid}", get(export_car_handler))
+        .route("/mish/resolve/{*path}", get(resolve_path_handler))
         .route("/mish/state", post(update_mish_state_handler))
+        .route("/mish/states", get(list_mish_states_handler))
+        .route(
+            "/mish/state/{name}",
+            get(get_mish_state_handler)
+                .put(set_mish_state_handler)
+                .delete(delete_mish_state_handler),
+        )
+        .route("/mish/state/{name}/car", get(export_mish_state_car_handler))
+        .route("/mish/state/schema", post(set_mish_state_schema_handler))
+        .route("/mish/scripts/logs", get(script_logs_sse_handler))
+        .route("/mish/subscribe", get(subscribe_sse_handler))
+        .route("/mish/subscribe/ws", get(subscribe_ws_handler))
         .with_state(app_state.clone());
 
     let routes = generate_route_list(App);
@@ -95,8 +141,8 @@ async fn main() {
                     provide_context(app_state.ring_rest_client.clone());
                     provide_context(app_state.pool.clone());
                     provide_context(app_state.cron_client.clone());
-                    provide_context(app_state.control_senders.clone());
                     provide_context(mish_state_modification_bus_sender.clone());
+                    provide_context(app_state.script_log_bus_sender.clone());
                 }
             },
             {
@@ -108,26 +154,26 @@ async fn main() {
         .fallback(leptos_axum::file_and_error_handler(shell::shell))
         .with_state(leptos_options);
 
-    run_devices_tasks(ring_rest_client, &shared_pool, control_senders)
+    let supervisor = run_devices_tasks(ring_rest_client, &shared_pool)
         .await
         .unwrap();
 
+    tokio::spawn(gc_job(shared_pool.clone()));
+    tokio::spawn(verify_job(shared_pool.clone()));
+    tokio::spawn(vacation_job(shared_pool.clone()));
+    tokio::spawn(state_history_job(shared_pool.clone()));
+    tokio::spawn(energy_job(shared_pool.clone()));
+
     tokio::spawn(async move {
         register_native_queries(
             &shared_pool,
             mish_state_modification_bus_receiver,
             mish_state_modification_bus_sender,
+            script_log_bus_sender,
         )
         .await;
     });
 
-    // tplink_kasa_get_energy_usage("10.0.0.223", "1")
-    //     .await
-    //     .unwrap();
-    // tplink_kasa_get_energy_usage("10.0.0.223", "1")
-    //     .await
-    //     .unwrap();
-
     let http_server = {
         let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
         log::info!("listening on http://{}", &addr);
@@ -137,6 +183,7 @@ async fn main() {
     tokio::select! {
         e = http_server => error!("HTTP server exiting with error {e:?}")
     }
+    supervisor.shutdown().await;
 }
 
 #[cfg(not(feature = "ssr"))]
@@ -0,0 +1,321 @@
+//! Content addressable archives (CAR) for moving IPLD blobs between instances.
+//!
+//! https://ipld.io/specs/transport/car/carv1/ and https://ipld.io/specs/transport/car/carv2/
+
+use {
+    crate::{components::mish::ipld_blob_page::get_ipld_blob_query, ipld_codecs},
+    cid::Cid,
+    ipld_core::codec::Codec,
+    serde::{Deserialize, Serialize},
+    serde_ipld_dagcbor::codec::DagCborCodec,
+    std::collections::{HashSet, VecDeque},
+    tokio::io::{AsyncRead, AsyncReadExt},
+};
+
+/// Blocks larger than this are rejected on import rather than buffered
+const MAX_SECTION_SIZE: u64 = 64 * 1024 * 1024;
+
+const CAR_V2_PRAGMA: [u8; 11] = [
+    0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02,
+];
+const CAR_V2_HEADER_SIZE: u64 = 40;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CarError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid CAR header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Unsupported CAR version: {0}")]
+    UnsupportedVersion(u64),
+
+    #[error("Invalid varint")]
+    InvalidVarint,
+
+    #[error("CAR section of {0} bytes is too large")]
+    SectionTooLarge(u64),
+
+    #[error("Invalid CID in CAR section: {0}")]
+    InvalidCid(#[from] cid::Error),
+
+    #[error("Unsupported multihash code {code:#x} for block {cid}")]
+    UnsupportedMultihash { cid: Cid, code: u64 },
+
+    #[error("Block {0} does not match its multihash")]
+    HashMismatch(Cid),
+
+    #[error("Block {0} not found")]
+    BlockNotFound(Cid),
+
+    #[error("Block {cid} could not be decoded: {error}")]
+    InvalidBlock { cid: Cid, error: String },
+
+    #[error("Database error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct CarHeader {
+    roots: Vec<Cid>,
+    version: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum CarVersion {
+    #[serde(rename = "1")]
+    V1,
+    #[serde(rename = "2")]
+    V2,
+}
+
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Returns `None` at a clean end of stream (no bytes read).
+async fn read_varint<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<u64>, CarError> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte).await? == 0 {
+            return if i == 0 {
+                Ok(None)
+            } else {
+                Err(CarError::InvalidVarint)
+            };
+        }
+        value |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(CarError::InvalidVarint)
+}
+
+async fn read_section<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>, CarError> {
+    let Some(len) = read_varint(reader).await? else {
+        return Ok(None);
+    };
+    if len > MAX_SECTION_SIZE {
+        return Err(CarError::SectionTooLarge(len));
+    }
+    let mut section = vec![0; len as usize];
+    reader.read_exact(&mut section).await?;
+    Ok(Some(section))
+}
+
+/// Reads CARv1 or CARv2 (ignoring any index) one block at a time, verifying each block against
+/// its CID before handing it out.
+pub struct CarReader<R> {
+    reader: R,
+    roots: Vec<Cid>,
+    /// Bytes of CARv1 data left to read, for CARv2 archives
+    remaining: Option<u64>,
+}
+
+impl<R: AsyncRead + Unpin> CarReader<R> {
+    pub async fn new(mut reader: R) -> Result<Self, CarError> {
+        let header = read_section(&mut reader)
+            .await?
+            .ok_or_else(|| CarError::InvalidHeader("empty archive".to_owned()))?;
+        if header[..] != CAR_V2_PRAGMA[1..] {
+            return Ok(Self {
+                reader,
+                roots: parse_v1_header(&header)?,
+                remaining: None,
+            });
+        }
+
+        let mut v2_header = [0u8; CAR_V2_HEADER_SIZE as usize];
+        reader.read_exact(&mut v2_header).await?;
+        let data_offset = u64::from_le_bytes(v2_header[16..24].try_into().unwrap());
+        let data_size = u64::from_le_bytes(v2_header[24..32].try_into().unwrap());
+        let skip = data_offset
+            .checked_sub(CAR_V2_PRAGMA.len() as u64 + CAR_V2_HEADER_SIZE)
+            .ok_or_else(|| CarError::InvalidHeader(format!("data offset {data_offset}")))?;
+        tokio::io::copy(&mut (&mut reader).take(skip), &mut tokio::io::sink()).await?;
+        let mut data = (&mut reader).take(data_size);
+        let header = read_section(&mut data)
+            .await?
+            .ok_or_else(|| CarError::InvalidHeader("empty CARv2 data payload".to_owned()))?;
+        let roots = parse_v1_header(&header)?;
+        let remaining = data.limit();
+        Ok(Self {
+            reader,
+            roots,
+            remaining: Some(remaining),
+        })
+    }
+
+    pub fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+
+    pub async fn next_block(&mut self) -> Result<Option<(Cid, Vec<u8>)>, CarError> {
+        let section = match self.remaining {
+            Some(0) => return Ok(None),
+            Some(remaining) => {
+                let mut reader = (&mut self.reader).take(remaining);
+                let section = read_section(&mut reader).await?;
+                self.remaining = Some(reader.limit());
+                section
+            }
+            None => read_section(&mut self.reader).await?,
+        };
+        let Some(section) = section else {
+            return Ok(None);
+        };
+        let mut cursor = std::io::Cursor::new(&section);
+        let cid = Cid::read_bytes(&mut cursor)?;
+        let block = section[cursor.position() as usize..].to_vec();
+        verify_block(&cid, &block)?;
+        Ok(Some((cid, block)))
+    }
+}
+
+fn parse_v1_header(header: &[u8]) -> Result<Vec<Cid>, CarError> {
+    let header = <DagCborCodec as Codec<CarHeader>>::decode_from_slice(header)
+        .map_err(|e| CarError::InvalidHeader(e.to_string()))?;
+    if header.version != 1 {
+        return Err(CarError::UnsupportedVersion(header.version));
+    }
+    Ok(header.roots)
+}
+
+pub fn verify_block(cid: &Cid, block: &[u8]) -> Result<(), CarError> {
+    match ipld_codecs::verify(cid, block) {
+        Some(true) => Ok(()),
+        Some(false) => Err(CarError::HashMismatch(*cid)),
+        None => Err(CarError::UnsupportedMultihash {
+            cid: *cid,
+            code: cid.hash().code(),
+        }),
+    }
+}
+
+pub fn write_car(
+    version: CarVersion,
+    roots: &[Cid],
+    blocks: &[(Cid, Vec<u8>)],
+) -> Result<Vec<u8>, CarError> {
+    let header = DagCborCodec::encode_to_vec(&CarHeader {
+        roots: roots.to_vec(),
+        version: 1,
+    })
+    .map_err(|e| CarError::InvalidHeader(e.to_string()))?;
+    let mut data = Vec::new();
+    write_varint(&mut data, header.len() as u64);
+    data.extend(header);
+    for (cid, block) in blocks {
+        let cid = cid.to_bytes();
+        write_varint(&mut data, (cid.len() + block.len()) as u64);
+        data.extend(cid);
+        data.extend(block);
+    }
+    Ok(match version {
+        CarVersion::V1 => data,
+        CarVersion::V2 => {
+            let data_offset = CAR_V2_PRAGMA.len() as u64 + CAR_V2_HEADER_SIZE;
+            let mut car = CAR_V2_PRAGMA.to_vec();
+            // Characteristics, none of which we set
+            car.extend([0u8; 16]);
+            car.extend(data_offset.to_le_bytes());
+            car.extend((data.len() as u64).to_le_bytes());
+            // Index offset, we don't write an index
+            car.extend(0u64.to_le_bytes());
+            car.extend(data);
+            car
+        }
+    })
+}
+
+/// Collects every block reachable from `roots`, each block once, in breadth first order.
+pub async fn collect_dag(
+    pool: &sqlx::PgPool,
+    roots: &[Cid],
+) -> Result<Vec<(Cid, Vec<u8>)>, CarError> {
+    let mut seen = HashSet::new();
+    let mut queue = roots.iter().copied().collect::<VecDeque<_>>();
+    let mut blocks = Vec::new();
+    while let Some(cid) = queue.pop_front() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        let block = get_ipld_blob_query(pool, &cid)
+            .await?
+            .ok_or(CarError::BlockNotFound(cid))?;
+        let links =
+            ipld_codecs::links(cid.codec(), &block).map_err(|e| CarError::InvalidBlock {
+                cid,
+                error: e.to_string(),
+            })?;
+        queue.extend(links);
+        blocks.push((cid, block));
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read_all(car: &[u8]) -> (Vec<Cid>, Vec<(Cid, Vec<u8>)>) {
+        let mut reader = CarReader::new(car).await.unwrap();
+        let roots = reader.roots().to_vec();
+        let mut blocks = Vec::new();
+        while let Some(block) = reader.next_block().await.unwrap() {
+            blocks.push(block);
+        }
+        (roots, blocks)
+    }
+
+    fn blocks() -> Vec<(Cid, Vec<u8>)> {
+        let leaf = b"hello".to_vec();
+        let leaf_cid = ipld_codecs::cid(ipld_codecs::RAW, &leaf);
+        let root = format!(r#"{{"leaf":{{"/":"{leaf_cid}"}}}}"#).into_bytes();
+        let root_cid = ipld_codecs::cid(ipld_codecs::DAG_JSON, &root);
+        vec![(root_cid, root), (leaf_cid, leaf)]
+    }
+
+    #[tokio::test]
+    async fn test_car_v1_roundtrip() {
+        let blocks = blocks();
+        let car = write_car(CarVersion::V1, &[blocks[0].0], &blocks).unwrap();
+        assert_eq!(read_all(&car).await, (vec![blocks[0].0], blocks));
+    }
+
+    #[tokio::test]
+    async fn test_car_v2_roundtrip() {
+        let blocks = blocks();
+        let mut car = write_car(CarVersion::V2, &[blocks[0].0], &blocks).unwrap();
+        // Trailing bytes after the data payload (e.g. an index) are not read as blocks
+        car.extend([1, 2, 3]);
+        assert_eq!(read_all(&car).await, (vec![blocks[0].0], blocks));
+    }
+
+    #[tokio::test]
+    async fn test_car_rejects_tampered_block() {
+        let mut blocks = blocks();
+        blocks[1].1 = b"jello".to_vec();
+        let car = write_car(CarVersion::V1, &[blocks[0].0], &blocks).unwrap();
+        let mut reader = CarReader::new(&car[..]).await.unwrap();
+        assert!(reader.next_block().await.unwrap().is_some());
+        assert!(matches!(
+            reader.next_block().await,
+            Err(CarError::HashMismatch(cid)) if cid == blocks[1].0
+        ));
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, vec![0xac, 0x02]);
+    }
+}
@@ -1,60 +1,325 @@
+pub mod car;
+
 use {
     crate::{
-        integrations::iron_nest::{AppState, mish::MishStateModification},
+        components::mish::{
+            dag_inspector_page::{MishState, get_mish_states_query},
+            ipld_blob_page::{get_ipld_blob_query, set_ipld_blob_query},
+            mish_state_page::{
+                delete_mish_state_query, get_mish_state_query, set_mish_state_query,
+            },
+        },
+        integrations::iron_nest::{
+            AppState,
+            mish::{
+                MishStateModification,
+                blobs::{ChunkedUpload, MAX_RAW_UPLOAD_BYTES, file_chunks},
+                gc::pin_query,
+                resolve::{ResolveError, resolve_path},
+                schema::{MishStateSchemaError, set_mish_state_schema_query, validate_mish_state},
+            },
+            types::mish::{MishStateEvent, ResolvedPath, ScriptLog, select_json_via_jsonpath},
+        },
         ipld_codecs,
     },
-    axum::{Json, extract::State},
+    axum::{
+        Json,
+        body::Body,
+        extract::{
+            Path, Query, State,
+            ws::{Message, WebSocket, WebSocketUpgrade},
+        },
+        http::{StatusCode, header},
+        response::{
+            IntoResponse, Response,
+            sse::{Event, KeepAlive, Sse},
+        },
+    },
     bytes::Bytes,
+    car::{CarError, CarReader, CarVersion, collect_dag, write_car},
     cid::Cid,
+    futures::{Stream, StreamExt},
     ipld_core::codec::Codec,
     jsonpath_rust::{JsonPath, parser::errors::JsonPathError, query::queryable::Queryable},
-    multihash_codetable::{Code, MultihashDigest},
-    serde::Deserialize,
+    serde::{Deserialize, Serialize},
     serde_ipld_dagjson::codec::DagJsonCodec,
-    tokio::sync::mpsc::UnboundedSender,
+    tokio::sync::broadcast::{Receiver, Sender, error::RecvError},
+    tokio_util::io::StreamReader,
 };
 
+/// Uploading the same content twice returns the same CID.
 pub async fn upload_dag_json_file(
     State(state): State<AppState>,
     Json(body): Json<serde_json::Value>,
-) -> Result<Json<String>, ()> {
-    let content = DagJsonCodec::encode_to_vec(&body).unwrap();
-    let cid = Cid::new_v1(
-        <DagJsonCodec as Codec<serde_json::Value>>::CODE,
-        Code::Sha2_256.digest(&content),
-    );
-    let query = "
-        INSERT INTO ipld_blobs (cid, content)
-        VALUES ($1, $2)
-    ";
-    sqlx::query(query)
-        .bind(cid.to_bytes())
-        .bind(content)
-        .execute(&state.pool)
+) -> Result<Json<String>, (StatusCode, String)> {
+    let content = DagJsonCodec::encode_to_vec(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid DAG-JSON: {e}")))?;
+    let cid = set_ipld_blob_query(&state.pool, ipld_codecs::DAG_JSON, content)
         .await
-        .unwrap();
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(Json(cid.to_string()))
 }
 
+/// Streams the body into storage, splitting content larger than one chunk into a chunked file.
 pub async fn upload_raw_file(
+    State(state): State<AppState>,
+    body: Body,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let mut stream = body.into_data_stream();
+    let mut upload = ChunkedUpload::new(&state.pool);
+    while let Some(bytes) = stream.next().await {
+        let bytes = bytes.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        if upload.size() + bytes.len() as u64 > *MAX_RAW_UPLOAD_BYTES {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Uploads are limited to {} bytes", *MAX_RAW_UPLOAD_BYTES),
+            ));
+        }
+        upload
+            .write(&bytes)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    let cid = upload
+        .finish()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(cid.to_string()))
+}
+
+/// Streams a raw blob or chunked file back, one chunk at a time.
+pub async fn download_file_handler(
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let cid = cid
+        .parse::<Cid>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid CID: {e}")))?;
+    let chunks = file_chunks(&state.pool, &cid)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, format!("File {cid} not found")))?;
+    let pool = state.pool.clone();
+    let body = futures::stream::iter(chunks).then(move |chunk| {
+        let pool = pool.clone();
+        async move {
+            get_ipld_blob_query(&pool, &chunk)
+                .await
+                .map_err(std::io::Error::other)?
+                .map(Bytes::from)
+                .ok_or_else(|| std::io::Error::other(format!("Chunk {chunk} not found")))
+        }
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+pub async fn upload_dag_cbor_file(
     State(state): State<AppState>,
     content: Bytes,
-) -> Result<Json<String>, ()> {
-    let cid = Cid::new_v1(ipld_codecs::RAW, Code::Sha2_256.digest(&content));
-    let query = "
-        INSERT INTO ipld_blobs (cid, content)
-        VALUES ($1, $2)
-        ON CONFLICT (cid) DO NOTHING
-    ";
-    sqlx::query(query)
-        .bind(cid.to_bytes())
-        .bind(content.to_vec())
-        .execute(&state.pool)
+) -> Result<Json<String>, (StatusCode, String)> {
+    ipld_codecs::decode(ipld_codecs::DAG_CBOR, &content)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid DAG-CBOR: {e}")))?;
+    let cid = set_ipld_blob_query(&state.pool, ipld_codecs::DAG_CBOR, content.to_vec())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(cid.to_string()))
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DownloadBlobQuery {
+    /// Codec name (`dag-json` or `dag-cbor`) to convert the blob to before sending it
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+pub async fn download_blob_handler(
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+    Query(query): Query<DownloadBlobQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let cid = cid
+        .parse::<Cid>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid CID: {e}")))?;
+    let content = get_ipld_blob_query(&state.pool, &cid)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, format!("IPLD blob {cid} not found")))?;
+    let codec = match query.format.as_deref() {
+        Some(format) => ipld_codecs::from_name(format)
+            .ok_or((StatusCode::BAD_REQUEST, format!("Unknown format: {format}")))?,
+        None => cid.codec(),
+    };
+    let content = ipld_codecs::convert(&content, cid.codec(), codec)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    Ok((
+        [(header::CONTENT_TYPE, ipld_codecs::content_type(codec))],
+        content,
+    )
+        .into_response())
+}
+
+/// Stores the blob re-encoded with the codec named by `codec` and returns the new CID.
+pub async fn convert_blob_handler(
+    State(state): State<AppState>,
+    Path((cid, codec)): Path<(String, String)>,
+) -> Result<Json<String>, (StatusCode, String)> {
+    let cid = cid
+        .parse::<Cid>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid CID: {e}")))?;
+    let codec = ipld_codecs::from_name(&codec)
+        .ok_or((StatusCode::BAD_REQUEST, format!("Unknown codec: {codec}")))?;
+    let content = get_ipld_blob_query(&state.pool, &cid)
         .await
-        .unwrap();
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, format!("IPLD blob {cid} not found")))?;
+    let content = ipld_codecs::convert(&content, cid.codec(), codec)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let cid = set_ipld_blob_query(&state.pool, codec, content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     Ok(Json(cid.to_string()))
 }
 
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportCarQuery {
+    /// CAR version, 1 when not given
+    #[serde(default)]
+    pub version: Option<CarVersion>,
+}
+
+fn car_error_response(e: CarError) -> (StatusCode, String) {
+    let status = match e {
+        CarError::BlockNotFound(_) => StatusCode::NOT_FOUND,
+        CarError::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+    (status, e.to_string())
+}
+
+async fn export_car(
+    pool: &sqlx::PgPool,
+    roots: &[Cid],
+    version: Option<CarVersion>,
+) -> Result<Response, (StatusCode, String)> {
+    let blocks = collect_dag(pool, roots).await.map_err(car_error_response)?;
+    let car =
+        write_car(version.unwrap_or(CarVersion::V1), roots, &blocks).map_err(car_error_response)?;
+    Ok(([(header::CONTENT_TYPE, "application/vnd.ipld.car")], car).into_response())
+}
+
+/// Exports the DAG rooted at `cid` as a CAR file.
+pub async fn export_car_handler(
+    State(state): State<AppState>,
+    Path(cid): Path<String>,
+    Query(query): Query<ExportCarQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let cid = cid
+        .parse::<Cid>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid CID: {e}")))?;
+    export_car(&state.pool, &[cid], query.version).await
+}
+
+/// Exports every DAG linked from the mish state as a CAR file, with the links as roots.
+pub async fn export_mish_state_car_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<ExportCarQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let mish_state = get_mish_state_query(&state.pool, &name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Mish state {name} not found"),
+        ))?;
+    let state_json = serde_json::to_vec(&mish_state.state)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let roots = ipld_codecs::links(ipld_codecs::DAG_JSON, &state_json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    export_car(&state.pool, &roots, query.version).await
+}
+
+/// Resolves a path like `fish_tank/config/lights/0`, following links into other blocks.
+pub async fn resolve_path_handler(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+) -> Result<Json<ResolvedPath>, (StatusCode, String)> {
+    resolve_path(&state.pool, &path)
+        .await
+        .map(Json)
+        .map_err(resolve_error_response)
+}
+
+fn resolve_error_response(e: ResolveError) -> (StatusCode, String) {
+    let status = if matches!(e, ResolveError::Sqlx(_)) {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else if e.is_not_found() {
+        StatusCode::NOT_FOUND
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    (status, e.to_string())
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportCarResponse {
+    pub roots: Vec<String>,
+    pub blocks: usize,
+}
+
+/// Imports a CARv1 or CARv2 body block by block. Every block is checked against its CID and
+/// nothing is stored unless the whole archive is valid. The roots are pinned as `car:<cid>` so
+/// GC keeps the imported DAG until they're unpinned.
+pub async fn import_car_handler(
+    State(state): State<AppState>,
+    body: Body,
+) -> Result<Json<ImportCarResponse>, (StatusCode, String)> {
+    let reader = StreamReader::new(
+        body.into_data_stream()
+            .map(|chunk| chunk.map_err(std::io::Error::other)),
+    );
+    let mut reader = CarReader::new(reader).await.map_err(car_error_response)?;
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut blocks = 0;
+    while let Some((cid, block)) = reader.next_block().await.map_err(car_error_response)? {
+        // The block was checked against its CID, so it can replace a corrupted stored copy
+        let query = "
+            INSERT INTO ipld_blobs (cid, content)
+            VALUES ($1, $2)
+            ON CONFLICT (cid) DO UPDATE SET
+                content = EXCLUDED.content,
+                created_at = NOW()
+        ";
+        sqlx::query(query)
+            .bind(cid.to_bytes())
+            .bind(block)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        blocks += 1;
+    }
+    for root in reader.roots() {
+        pin_query(&mut *tx, &format!("car:{root}"), root)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(ImportCarResponse {
+        roots: reader.roots().iter().map(ToString::to_string).collect(),
+        blocks,
+    }))
+}
+
 #[derive(Deserialize)]
 pub struct UpdateMishStateBody {
     pub mish_state_name: String,
@@ -70,16 +335,110 @@ struct MishStateRow {
 pub async fn update_mish_state_handler(
     State(state): State<AppState>,
     Json(body): Json<UpdateMishStateBody>,
-) -> Result<(), String> {
+) -> Result<(), (StatusCode, String)> {
     update_mish_state(&state.pool, &state.mish_state_modification_bus_sender, body)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(mish_state_error_response)
+}
+
+/// Schema violations are the client's fault, anything else is ours.
+fn schema_error_status(e: &MishStateSchemaError) -> StatusCode {
+    match e {
+        MishStateSchemaError::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        MishStateSchemaError::InvalidCid(_) => StatusCode::BAD_REQUEST,
+        MishStateSchemaError::SchemaNotFound(_) => StatusCode::NOT_FOUND,
+        MishStateSchemaError::SchemaNotJson(..)
+        | MishStateSchemaError::InvalidSchema(..)
+        | MishStateSchemaError::Invalid { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+fn mish_state_error_response(e: anyhow::Error) -> (StatusCode, String) {
+    let status = e
+        .downcast_ref::<MishStateSchemaError>()
+        .map_or(StatusCode::INTERNAL_SERVER_ERROR, schema_error_status);
+    (status, e.to_string())
+}
+
+pub async fn list_mish_states_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<MishState>>, (StatusCode, String)> {
+    get_mish_states_query(&state.pool)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn get_mish_state_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    get_mish_state_query(&state.pool, &name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(|mish_state| Json(mish_state.state))
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Mish state {name} not found"),
+        ))
+}
+
+/// Replaces the whole state, creating it if needed.
+pub async fn set_mish_state_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<(), (StatusCode, String)> {
+    validate_mish_state(&state.pool, &name, &body)
+        .await
+        .map_err(|e| mish_state_error_response(e.into()))?;
+    set_mish_state_query(&state.pool, &name, &body)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    // No receivers just means nobody is listening right now
+    let _ = state
+        .mish_state_modification_bus_sender
+        .send(MishStateModification::CreateOrUpdate { name, state: body });
+    Ok(())
+}
+
+pub async fn delete_mish_state_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<(), (StatusCode, String)> {
+    delete_mish_state_query(&state.pool, &name)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let _ = state
+        .mish_state_modification_bus_sender
+        .send(MishStateModification::Delete { name });
     Ok(())
 }
 
+#[derive(Deserialize)]
+pub struct SetMishStateSchemaBody {
+    pub mish_state_name: String,
+    pub schema_cid: Option<String>,
+}
+
+pub async fn set_mish_state_schema_handler(
+    State(state): State<AppState>,
+    Json(body): Json<SetMishStateSchemaBody>,
+) -> Result<(), (StatusCode, String)> {
+    let error_response = |e: MishStateSchemaError| (schema_error_status(&e), e.to_string());
+    let schema_cid = body
+        .schema_cid
+        .map(|cid| cid.parse::<Cid>())
+        .transpose()
+        .map_err(|e| error_response(e.into()))?;
+    set_mish_state_schema_query(&state.pool, &body.mish_state_name, schema_cid)
+        .await
+        .map_err(error_response)
+}
+
 pub async fn update_mish_state(
     pool: &sqlx::PgPool,
-    mish_state_modification_bus_sender: &UnboundedSender<MishStateModification>,
+    mish_state_modification_bus_sender: &Sender<MishStateModification>,
     body: UpdateMishStateBody,
 ) -> Result<(), anyhow::Error> {
     let query = "
@@ -94,6 +453,7 @@ pub async fn update_mish_state(
 
     if let Some(mut mish_state) = row {
         update_json_via_jsonpath(&mut mish_state.state, &body.path, &body.content)?;
+        validate_mish_state(pool, &body.mish_state_name, &mish_state.state).await?;
 
         // Update the state in the database
         let update_query = "
@@ -145,3 +505,167 @@ fn update_json_via_jsonpath(
     }
     Ok(())
 }
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SubscribeQuery {
+    /// Comma separated mish state names, all states when empty.
+    #[serde(default)]
+    pub names: Option<String>,
+    /// JSONPath selecting the part of each state to send, the whole state when empty.
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl SubscribeQuery {
+    fn matches(&self, name: &str) -> bool {
+        match &self.names {
+            Some(names) if !names.is_empty() => names.split(',').any(|n| n.trim() == name),
+            _ => true,
+        }
+    }
+
+    fn to_event(&self, modification: MishStateModification) -> Option<MishStateEvent> {
+        match modification {
+            MishStateModification::CreateOrUpdate { name, state } => {
+                if !self.matches(&name) {
+                    return None;
+                }
+                let value = match self.path.as_deref() {
+                    Some(path) if !path.is_empty() => {
+                        match select_json_via_jsonpath(&state, path) {
+                            Ok(value) => value,
+                            Err(e) => {
+                                log::warn!("Invalid subscription path {path}: {e}");
+                                return None;
+                            }
+                        }
+                    }
+                    _ => state,
+                };
+                let cid = match ipld_codecs::dag_json_cid(&value) {
+                    Ok(cid) => cid.to_string(),
+                    Err(e) => {
+                        log::error!("Failed to compute CID for mish state {name}: {e}");
+                        return None;
+                    }
+                };
+                Some(MishStateEvent::Update { name, value, cid })
+            }
+            MishStateModification::Delete { name } => self
+                .matches(&name)
+                .then_some(MishStateEvent::Delete { name }),
+        }
+    }
+}
+
+pub fn mish_state_events(
+    receiver: Receiver<MishStateModification>,
+    query: SubscribeQuery,
+) -> impl Stream<Item = MishStateEvent> {
+    futures::stream::unfold((receiver, query), |(mut receiver, query)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(modification) => {
+                    if let Some(event) = query.to_event(modification) {
+                        return Some((event, (receiver, query)));
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Mish state subscriber lagged behind, skipped {skipped} events");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+pub async fn subscribe_sse_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SubscribeQuery>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.mish_state_modification_bus_sender.subscribe();
+    let events = mish_state_events(receiver, query).map(|event| Event::default().json_data(event));
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ScriptLogsQuery {
+    /// Comma separated script names, all scripts when empty.
+    #[serde(default)]
+    pub scripts: Option<String>,
+}
+
+impl ScriptLogsQuery {
+    fn matches(&self, script: &str) -> bool {
+        match &self.scripts {
+            Some(scripts) if !scripts.is_empty() => scripts.split(',').any(|s| s.trim() == script),
+            _ => true,
+        }
+    }
+}
+
+/// Streams script output and errors, e.g. for `mish-cli dev`.
+pub async fn script_logs_sse_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ScriptLogsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.script_log_bus_sender.subscribe();
+    let logs = futures::stream::unfold((receiver, query), |(mut receiver, query)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(log) if query.matches(&log.script) => return Some((log, (receiver, query))),
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Script log subscriber lagged behind, skipped {skipped} logs");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(logs.map(|log: ScriptLog| Event::default().json_data(log)))
+        .keep_alive(KeepAlive::default())
+}
+
+pub async fn subscribe_ws_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SubscribeQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let receiver = state.mish_state_modification_bus_sender.subscribe();
+    ws.on_upgrade(move |socket| forward_mish_state_events(socket, receiver, query))
+}
+
+async fn forward_mish_state_events(
+    mut socket: WebSocket,
+    receiver: Receiver<MishStateModification>,
+    query: SubscribeQuery,
+) {
+    let events = mish_state_events(receiver, query);
+    futures::pin_mut!(events);
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else {
+                    break;
+                };
+                let text = match serde_json::to_string(&event) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        log::error!("Failed to serialize mish state event: {e}");
+                        continue;
+                    }
+                };
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                // Subscribers don't send anything, we only watch for the socket going away
+                match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use {
-    crate::integrations::iron_nest::types::FullAction,
+    crate::integrations::iron_nest::types::{ActionRun, ActionStats, FullAction, FunctionInfo},
     leptos::prelude::*,
     serde::{Deserialize, Serialize},
     server_fn::codec::JsonEncoding,
@@ -14,25 +14,37 @@ pub async fn get_actions() -> Result<Vec<FullAction>, ServerFnError> {
 }
 
 #[cfg(feature = "ssr")]
-pub async fn get_actions_query(pool: &sqlx::PgPool) -> Result<Vec<FullAction>, sqlx::Error> {
+pub async fn get_actions_query(
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<FullAction>, sqlx::Error> {
     let query = "
         SELECT
             (actions.action->>'id')::UUID AS id,
             (actions.action->>'name') AS name,
             (actions.action->>'cron') AS cron,
+            COALESCE(actions.action->'solar', 'null'::JSONB) AS solar,
+            COALESCE(actions.action->'sequence', 'null'::JSONB) AS sequence,
+            (actions.action->>'timezone') AS timezone,
+            COALESCE((actions.action->>'catch_up')::BOOLEAN, FALSE) AS catch_up,
             (actions.action->>'function_name') AS function_name,
-            (actions.action->>'function_args')::JSONB AS function_args
+            (actions.action->>'function_args')::JSONB AS function_args,
+            COALESCE((actions.action->>'retry_count')::INT, 0) AS retry_count,
+            COALESCE((actions.action->>'retry_backoff_ms')::BIGINT, 0) AS retry_backoff_ms,
+            (actions.action->>'on_failure_function_name') AS on_failure_function_name,
+            (actions.action->'on_failure_function_args') AS on_failure_function_args
         FROM
             config,
             LATERAL jsonb_array_elements(data->'actions') AS actions(action)
         ORDER BY name
     ";
-    sqlx::query_as(query).fetch_all(pool).await
+    sqlx::query_as(query).fetch_all(executor).await
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum AddActionError {
     ServerFnError(ServerFnErrorErr),
+    InvalidFunction(String),
+    InvalidSchedule(String),
     ScheduleTasks(String),
     Sql(String),
 }
@@ -47,19 +59,104 @@ impl FromServerFnError for AddActionError {
 
 impl Display for AddActionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
+        match self {
+            AddActionError::InvalidFunction(e) | AddActionError::InvalidSchedule(e) => {
+                write!(f, "{e}")
+            }
+            _ => write!(f, "{self:?}"),
+        }
     }
 }
 
+#[server(GetFunctions)]
+pub async fn get_functions() -> Result<Vec<FunctionInfo>, ServerFnError> {
+    use crate::integrations::iron_nest::functions::function_registry;
+    Ok(function_registry().functions().cloned().collect())
+}
+
 #[server(AddAction)]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_action(
     name: String,
     cron: String,
-    function_name: String,
+    solar_event: String,
+    solar_offset_minutes: String,
+    solar_not_before: String,
+    solar_not_after: String,
+    timezone: String,
+    catch_up: Option<String>,
+    function_name: Option<String>,
     function_args: String,
+    sequence: String,
+    retry_count: String,
+    retry_backoff_ms: String,
+    on_failure_function_name: String,
+    on_failure_function_args: String,
 ) -> Result<(), AddActionError> {
-    let function_args = serde_json::from_str::<serde_json::Value>(&function_args).unwrap();
+    use crate::{
+        components::pages::configs_page::get_location_query,
+        integrations::iron_nest::{
+            cron::ActionSchedule, functions::function_registry, sequence::validate_sequence,
+            types::Sequence,
+        },
+    };
     let pool = use_context::<sqlx::PgPool>().unwrap();
+    let solar = parse_solar_schedule(
+        &solar_event,
+        &solar_offset_minutes,
+        &solar_not_before,
+        &solar_not_after,
+    )
+    .map_err(AddActionError::InvalidSchedule)?;
+    let location = get_location_query(&pool)
+        .await
+        .map_err(|e| AddActionError::Sql(e.to_string()))?;
+    ActionSchedule::parse(&cron, solar.clone(), Some(&timezone), location)
+        .map_err(AddActionError::InvalidSchedule)?;
+    let timezone = Some(timezone).filter(|timezone| !timezone.is_empty());
+    let (function_name, function_args, sequence) = if sequence.is_empty() {
+        let function_name = function_name.ok_or_else(|| {
+            AddActionError::InvalidFunction("Choose a function or add steps".to_owned())
+        })?;
+        let function_args = serde_json::from_str::<serde_json::Value>(&function_args)
+            .map_err(|e| AddActionError::InvalidFunction(format!("Arguments are not JSON: {e}")))?;
+        function_registry()
+            .validate(&function_name, &function_args)
+            .map_err(|e| AddActionError::InvalidFunction(e.to_string()))?;
+        (function_name, function_args, None)
+    } else {
+        let sequence = serde_json::from_str::<Sequence>(&sequence)
+            .map_err(|e| AddActionError::InvalidFunction(format!("Invalid steps: {e}")))?;
+        validate_sequence(&sequence).map_err(AddActionError::InvalidFunction)?;
+        (String::new(), serde_json::json!({}), Some(sequence))
+    };
+    let parse_number = |field: &str, value: &str| {
+        if value.trim().is_empty() {
+            return Ok(0);
+        }
+        value
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| AddActionError::InvalidFunction(format!("{field} is not a number: {e}")))
+    };
+    let retry_count = parse_number("Retry count", &retry_count)?;
+    let retry_backoff_ms = parse_number("Retry backoff", &retry_backoff_ms)?;
+    let on_failure_function_args = if on_failure_function_name.is_empty() {
+        None
+    } else {
+        let args = if on_failure_function_args.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str::<serde_json::Value>(&on_failure_function_args).map_err(|e| {
+                AddActionError::InvalidFunction(format!("Failure hook arguments are not JSON: {e}"))
+            })?
+        };
+        function_registry()
+            .validate(&on_failure_function_name, &args)
+            .map_err(|e| AddActionError::InvalidFunction(format!("Failure hook: {e}")))?;
+        Some(args)
+    };
+    let on_failure_function_name = Some(on_failure_function_name).filter(|name| !name.is_empty());
     let cron_client = use_context::<crate::integrations::iron_nest::cron::CronClient>().unwrap();
     let query = r#"
         UPDATE config
@@ -70,8 +167,16 @@ pub async fn add_action(
                 'id', $1::TEXT,
                 'name', $2::TEXT,
                 'cron', $3::TEXT,
-                'function_name', $4::TEXT,
-                'function_args', $5::JSONB
+                'solar', $4::JSONB,
+                'timezone', $5::TEXT,
+                'catch_up', $6::BOOLEAN,
+                'function_name', $7::TEXT,
+                'function_args', $8::JSONB,
+                'sequence', $9::JSONB,
+                'retry_count', $10::INT,
+                'retry_backoff_ms', $11::BIGINT,
+                'on_failure_function_name', $12::TEXT,
+                'on_failure_function_args', $13::JSONB
             )
         )
     "#;
@@ -79,8 +184,16 @@ pub async fn add_action(
         .bind(Uuid::new_v4())
         .bind(name)
         .bind(cron)
+        .bind(solar.map(sqlx::types::Json))
+        .bind(timezone)
+        .bind(catch_up.is_some())
         .bind(function_name)
         .bind(function_args)
+        .bind(sequence.map(sqlx::types::Json))
+        .bind(retry_count as i32)
+        .bind(retry_backoff_ms as i64)
+        .bind(on_failure_function_name)
+        .bind(on_failure_function_args)
         .execute(&pool)
         .await
         .map_err(|e| AddActionError::Sql(e.to_string()))?;
@@ -119,12 +232,178 @@ pub async fn delete_action_query(pool: &sqlx::PgPool, id: Uuid) -> Result<(), sq
 pub async fn run_action(id: Uuid) -> Result<(), ServerFnError> {
     let pool = use_context::<sqlx::PgPool>().unwrap();
     let actions = get_actions_query(&pool).await?;
-    let action = actions.iter().find(|a| a.id == id).unwrap();
-    crate::integrations::iron_nest::execute_function(
-        action.fields.function_name.clone(),
-        action.fields.function_args.clone(),
-    )
-    .await;
+    let action = actions
+        .iter()
+        .find(|a| a.id == id)
+        .ok_or_else(|| ServerFnError::new(format!("Action {id} not found")))?;
+    crate::integrations::iron_nest::cron::execute_action(&pool, action, None)
+        .await
+        .map_err(ServerFnError::new)?;
 
     Ok(())
 }
+
+/// The next `count` times an action with this schedule would run, formatted in its timezone.
+#[server(PreviewActionSchedule)]
+#[allow(clippy::too_many_arguments)]
+pub async fn preview_action_schedule(
+    cron: String,
+    solar_event: String,
+    solar_offset_minutes: String,
+    solar_not_before: String,
+    solar_not_after: String,
+    timezone: String,
+    count: usize,
+) -> Result<Vec<String>, ServerFnError> {
+    use crate::{
+        components::pages::configs_page::get_location_query,
+        integrations::iron_nest::cron::ActionSchedule,
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let solar = parse_solar_schedule(
+        &solar_event,
+        &solar_offset_minutes,
+        &solar_not_before,
+        &solar_not_after,
+    )
+    .map_err(ServerFnError::new)?;
+    let location = get_location_query(&pool).await?;
+    let schedule = ActionSchedule::parse(&cron, solar, Some(&timezone), location)
+        .map_err(ServerFnError::new)?;
+    Ok(schedule
+        .next_runs(count.min(20))
+        .into_iter()
+        .map(|time| time.format("%a %Y-%m-%d %H:%M:%S %Z").to_string())
+        .collect())
+}
+
+/// Reads the solar fields of the action form, `None` when no event is chosen.
+#[cfg(feature = "ssr")]
+fn parse_solar_schedule(
+    event: &str,
+    offset_minutes: &str,
+    not_before: &str,
+    not_after: &str,
+) -> Result<Option<crate::integrations::iron_nest::types::SolarSchedule>, String> {
+    if event.is_empty() {
+        return Ok(None);
+    }
+    let event = serde_json::from_value(serde_json::Value::String(event.to_owned()))
+        .map_err(|_| format!("Unknown solar event {event:?}"))?;
+    let offset_minutes = match offset_minutes.trim() {
+        "" => 0,
+        offset_minutes => offset_minutes
+            .parse()
+            .map_err(|e| format!("Offset {offset_minutes:?} is not a number: {e}"))?,
+    };
+    let parse_time = |time: &str| match time.trim() {
+        "" => Ok(None),
+        time => chrono::NaiveTime::parse_from_str(time, "%H:%M")
+            .or_else(|_| chrono::NaiveTime::parse_from_str(time, "%H:%M:%S"))
+            .map(Some)
+            .map_err(|e| format!("{time:?} is not a time of day: {e}")),
+    };
+    Ok(Some(crate::integrations::iron_nest::types::SolarSchedule {
+        event,
+        offset_minutes,
+        not_before: parse_time(not_before)?,
+        not_after: parse_time(not_after)?,
+    }))
+}
+
+/// Runs are kept this long, so success rates cover it too.
+pub const ACTION_RUN_RETENTION_DAYS: i32 = 30;
+
+/// The latest runs of every action, newest first.
+#[server(GetActionRuns)]
+pub async fn get_action_runs() -> Result<Vec<ActionRun>, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_action_runs_query(&pool, 10).await.map_err(Into::into)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_action_runs_query(
+    pool: &sqlx::PgPool,
+    runs_per_action: i64,
+) -> Result<Vec<ActionRun>, sqlx::Error> {
+    let query = "
+        SELECT id, action_id, action_name, scheduled_at, started_at, duration_ms, attempts, result, error
+        FROM (
+            SELECT
+                *,
+                ROW_NUMBER() OVER (PARTITION BY action_id ORDER BY started_at DESC) AS row_number
+            FROM action_runs
+        ) AS runs
+        WHERE row_number <= $1
+        ORDER BY started_at DESC
+    ";
+    sqlx::query_as(query)
+        .bind(runs_per_action)
+        .fetch_all(pool)
+        .await
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_last_scheduled_run_query(
+    pool: &sqlx::PgPool,
+    action_id: Uuid,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, sqlx::Error> {
+    let query = "
+        SELECT MAX(scheduled_at)
+        FROM action_runs
+        WHERE action_id = $1
+    ";
+    sqlx::query_scalar(query)
+        .bind(action_id)
+        .fetch_one(pool)
+        .await
+}
+
+#[server(GetActionStats)]
+pub async fn get_action_stats() -> Result<Vec<ActionStats>, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_action_stats_query(&pool).await.map_err(Into::into)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_action_stats_query(pool: &sqlx::PgPool) -> Result<Vec<ActionStats>, sqlx::Error> {
+    let query = "
+        SELECT
+            action_id,
+            COUNT(*) AS runs,
+            COUNT(*) FILTER (WHERE error IS NULL) AS successes
+        FROM action_runs
+        GROUP BY action_id
+    ";
+    sqlx::query_as(query).fetch_all(pool).await
+}
+
+/// Records a run and drops runs older than [`ACTION_RUN_RETENTION_DAYS`].
+#[cfg(feature = "ssr")]
+pub async fn insert_action_run_query(
+    pool: &sqlx::PgPool,
+    run: &ActionRun,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO action_runs
+            (id, action_id, action_name, scheduled_at, started_at, duration_ms, attempts, result, error)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+    ";
+    sqlx::query(query)
+        .bind(run.id)
+        .bind(run.action_id)
+        .bind(&run.action_name)
+        .bind(run.scheduled_at)
+        .bind(run.started_at)
+        .bind(run.duration_ms)
+        .bind(run.attempts)
+        .bind(&run.result)
+        .bind(&run.error)
+        .execute(pool)
+        .await?;
+    sqlx::query("DELETE FROM action_runs WHERE started_at < NOW() - make_interval(days => $1)")
+        .bind(ACTION_RUN_RETENTION_DAYS)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
@@ -10,7 +10,7 @@ pub async fn get_devices() -> Result<Vec<Device>, ServerFnError> {
     let pool = use_context::<PgPool>().unwrap();
 
     let query = "
-        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, child_id 
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
         FROM device
         ORDER BY name
     ";
@@ -22,9 +22,28 @@ pub async fn get_devices() -> Result<Vec<Device>, ServerFnError> {
 
 #[server(RefreshDevices)]
 pub async fn refresh_devices() -> Result<(), ServerFnError> {
-    use {crate::integrations::iron_nest::refresh_tplink_devices, sqlx::PgPool};
+    use crate::integrations::iron_nest::supervisor::supervisor;
+
+    supervisor().refresh_all().await.map_err(ServerFnError::new)
+}
+
+#[server(GetDevice)]
+pub async fn get_device(id: i64) -> Result<Option<Device>, ServerFnError> {
+    use {
+        crate::integrations::iron_nest::types::Device,
+        sqlx::{PgPool, Postgres},
+    };
 
     let pool = use_context::<PgPool>().unwrap();
-    refresh_tplink_devices(pool).await;
-    Ok(())
+
+    let query = "
+        SELECT id, name, device_type, ip, power_state, battery_percentage, last_seen, mac_address, vendor_id, child_id, capabilities
+        FROM device
+        WHERE id = $1
+    ";
+    sqlx::query_as::<Postgres, Device>(query)
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(Into::into)
 }
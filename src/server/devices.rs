@@ -0,0 +1,68 @@
+//! Controls for any device, going by its capabilities rather than its integration.
+
+use {
+    crate::integrations::iron_nest::types::{
+        capability::DeviceCommand, state_history::DeviceTimeline,
+    },
+    chrono::{DateTime, Utc},
+    leptos::prelude::*,
+};
+
+#[cfg(feature = "ssr")]
+async fn control_device(device_id: i64, command: DeviceCommand) -> Result<(), ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    crate::integrations::iron_nest::capabilities::control_device(&pool, device_id, command)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+#[server(SetDeviceOnOff)]
+pub async fn set_device_on_off(device_id: i64, on: bool) -> Result<(), ServerFnError> {
+    control_device(device_id, DeviceCommand::OnOff { on }).await
+}
+
+#[server(SetDeviceBrightness)]
+pub async fn set_device_brightness(device_id: i64, brightness: u8) -> Result<(), ServerFnError> {
+    control_device(device_id, DeviceCommand::Brightness { brightness }).await
+}
+
+/// `color` is anything CSS accepts, e.g. the `#rrggbb` from a color input.
+#[server(SetDeviceColor)]
+pub async fn set_device_color(device_id: i64, color: String) -> Result<(), ServerFnError> {
+    let [hue, saturation, value, _alpha] = csscolorparser::parse(&color)
+        .map_err(|e| ServerFnError::new(format!("Invalid color {color:?}: {e}")))?
+        .to_hsva();
+    let command = DeviceCommand::ColorHsv {
+        hue: (hue.rem_euclid(360.)) as u16,
+        saturation: (saturation * 100.).round() as u8,
+        brightness: (value * 100.).round() as u8,
+    };
+    control_device(device_id, command).await
+}
+
+#[server(SetDeviceColorTemp)]
+pub async fn set_device_color_temp(device_id: i64, kelvin: u16) -> Result<(), ServerFnError> {
+    control_device(device_id, DeviceCommand::ColorTemp { kelvin }).await
+}
+
+#[server(SendDeviceRemoteKey)]
+pub async fn send_device_remote_key(device_id: i64, key: String) -> Result<(), ServerFnError> {
+    control_device(device_id, DeviceCommand::MediaRemote { key }).await
+}
+
+/// How the device's state changed between `from` and `to`.
+#[server(GetDeviceTimeline)]
+pub async fn get_device_timeline(
+    device_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<DeviceTimeline, ServerFnError> {
+    use crate::integrations::iron_nest::state_history::get_timeline_query;
+    if to <= from {
+        return Err(ServerFnError::new("The range must end after it starts"));
+    }
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_timeline_query(&pool, device_id, from, to)
+        .await
+        .map_err(Into::into)
+}
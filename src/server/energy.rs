@@ -0,0 +1,145 @@
+use {
+    crate::integrations::iron_nest::types::energy::{
+        DailyEnergy, DeviceEnergy, EnergyAlert, EnergyAlertRule, EnergySettings, PowerPoint, Tariff,
+    },
+    leptos::prelude::*,
+};
+
+#[server(GetEnergySettings)]
+pub async fn get_energy_settings() -> Result<EnergySettings, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_energy_settings_query(&pool)
+        .await
+        .map(Option::unwrap_or_default)
+        .map_err(Into::into)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_energy_settings_query(
+    pool: &sqlx::PgPool,
+) -> Result<Option<EnergySettings>, sqlx::Error> {
+    let query = "
+        SELECT data->'energy'
+        FROM config
+    ";
+    sqlx::query_scalar::<_, Option<sqlx::types::Json<Option<EnergySettings>>>>(query)
+        .fetch_optional(pool)
+        .await
+        .map(|settings| settings.flatten().and_then(|settings| settings.0))
+}
+
+#[cfg(feature = "ssr")]
+pub async fn set_energy_settings_query(
+    pool: &sqlx::PgPool,
+    settings: &EnergySettings,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO config (id, data)
+        VALUES (1, jsonb_build_object('actions', '[]'::JSONB, 'energy', $1::JSONB))
+        ON CONFLICT (id) DO UPDATE SET
+            data = jsonb_set(config.data, '{energy}', $1::JSONB)
+    ";
+    sqlx::query(query)
+        .bind(sqlx::types::Json(settings))
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Saves the tariff and alert rules from the energy page. `periods` and `alerts` are JSON.
+#[server(SetEnergySettings)]
+pub async fn set_energy_settings(
+    currency: String,
+    rate_per_kwh: String,
+    periods: String,
+    timezone: String,
+    alerts: String,
+) -> Result<(), ServerFnError> {
+    use crate::integrations::iron_nest::energy::update_energy_settings;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let settings = EnergySettings {
+        tariff: Tariff {
+            currency: currency.trim().to_owned(),
+            rate_per_kwh: rate_per_kwh
+                .trim()
+                .parse()
+                .map_err(|_| ServerFnError::new("The rate must be a number"))?,
+            periods: serde_json::from_str(&periods)
+                .map_err(|e| ServerFnError::new(format!("Invalid periods: {e}")))?,
+            timezone: Some(timezone.trim().to_owned()).filter(|timezone| !timezone.is_empty()),
+        },
+        alerts: serde_json::from_str::<Vec<EnergyAlertRule>>(&alerts)
+            .map_err(|e| ServerFnError::new(format!("Invalid alerts: {e}")))?,
+    };
+    update_energy_settings(&pool, settings)
+        .await
+        .map_err(ServerFnError::new)
+}
+
+/// Every device with a power meter, with what it draws and has used today.
+#[server(GetDeviceEnergy)]
+pub async fn get_device_energy() -> Result<Vec<DeviceEnergy>, ServerFnError> {
+    use crate::integrations::iron_nest::energy::get_device_energy_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let tariff = get_energy_settings_query(&pool)
+        .await?
+        .unwrap_or_default()
+        .tariff;
+    get_device_energy_query(&pool, tariff.day_of(chrono::Utc::now()))
+        .await
+        .map_err(Into::into)
+}
+
+/// The power of a device, or of the whole house if `device_id` isn't set, over the last
+/// `hours`.
+#[server(GetPowerHistory)]
+pub async fn get_power_history(
+    device_id: Option<i64>,
+    hours: u32,
+) -> Result<Vec<PowerPoint>, ServerFnError> {
+    use {
+        crate::integrations::iron_nest::energy::get_power_query,
+        chrono::{Duration, Utc},
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let hours = hours.clamp(1, 24 * 14);
+    // About 150 points whatever the range
+    let bucket_minutes = (hours * 60 / 150).max(1);
+    let to = Utc::now();
+    get_power_query(
+        &pool,
+        device_id,
+        to - Duration::hours(hours.into()),
+        to,
+        bucket_minutes,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+/// Energy used and its cost each of the last `days` by a device, or by the whole house if
+/// `device_id` isn't set.
+#[server(GetDailyEnergy)]
+pub async fn get_daily_energy(
+    device_id: Option<i64>,
+    days: u32,
+) -> Result<Vec<DailyEnergy>, ServerFnError> {
+    use {crate::integrations::iron_nest::energy::get_daily_query, chrono::Days};
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let tariff = get_energy_settings_query(&pool)
+        .await?
+        .unwrap_or_default()
+        .tariff;
+    let today = tariff.day_of(chrono::Utc::now());
+    let from = today - Days::new((days.clamp(1, 366) - 1).into());
+    get_daily_query(&pool, device_id, from, today)
+        .await
+        .map_err(Into::into)
+}
+
+#[server(GetEnergyAlerts)]
+pub async fn get_energy_alerts() -> Result<Vec<EnergyAlert>, ServerFnError> {
+    use crate::integrations::iron_nest::energy::get_alerts_query;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_alerts_query(&pool, 20).await.map_err(Into::into)
+}
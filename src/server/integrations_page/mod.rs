@@ -1,4 +1,7 @@
-use {crate::integrations::iron_nest::types::Integration, leptos::prelude::*};
+use {
+    crate::integrations::iron_nest::types::{Integration, IntegrationStatus},
+    leptos::prelude::*,
+};
 
 #[server(GetIntegrations)]
 pub async fn get_integrations() -> Result<Vec<Integration>, ServerFnError> {
@@ -23,15 +26,11 @@ pub async fn get_integrations() -> Result<Vec<Integration>, ServerFnError> {
 #[server(ToggleIntegration)]
 pub async fn toggle_integration(id: i64, enabled: bool, name: String) -> Result<(), ServerFnError> {
     use {
-        crate::integrations::iron_nest::types::ControlMessage,
+        crate::integrations::iron_nest::{supervisor::supervisor, types::ControlMessage},
         sqlx::PgPool,
-        std::{collections::HashMap, sync::Arc},
-        tokio::sync::{RwLock, mpsc::Sender},
     };
 
     let pool = use_context::<PgPool>().unwrap();
-    let control_senders =
-        use_context::<Arc<RwLock<HashMap<String, Sender<ControlMessage>>>>>().unwrap();
 
     // Update the integration status in the database
     let query = "
@@ -45,23 +44,82 @@ pub async fn toggle_integration(id: i64, enabled: bool, name: String) -> Result<
         .execute(&pool)
         .await?;
 
-    let senders = control_senders.read().await;
-    println!(
-        "Available senders: {:?}",
-        senders.keys().collect::<Vec<&String>>()
-    );
-
-    if let Some(sender) = senders.get(&name.clone()) {
+    // Only some integrations have a task to start or stop
+    if supervisor().integration(&name).is_some() {
         let message = if enabled {
             ControlMessage::Start
         } else {
             ControlMessage::Stop
         };
-        println!("Sending message: {message:?}");
-        sender.send(message).await.unwrap();
-    } else {
-        println!("No sender found for {}", &name.clone());
+        supervisor()
+            .send(&name, message)
+            .await
+            .map_err(ServerFnError::new)?;
     }
 
     Ok(())
 }
+
+/// What the task of each integration that has one is doing.
+#[server(GetIntegrationStatuses)]
+pub async fn get_integration_statuses() -> Result<Vec<IntegrationStatus>, ServerFnError> {
+    use crate::integrations::iron_nest::supervisor::supervisor;
+
+    Ok(supervisor().statuses().await)
+}
+
+/// Turns the integration `name` on or off, starting or stopping its task if that changed.
+#[cfg(feature = "ssr")]
+pub async fn set_integration_enabled_query(
+    pool: &sqlx::PgPool,
+    name: &str,
+    enabled: bool,
+) -> Result<(), sqlx::Error> {
+    if update_integration_enabled_query(pool, name, enabled).await? {
+        apply_integration_enabled(name, enabled).await;
+    }
+    Ok(())
+}
+
+/// Turns the integration `name` on or off in the database only, returning whether that changed.
+/// Call [`apply_integration_enabled`] once it's committed.
+#[cfg(feature = "ssr")]
+pub async fn update_integration_enabled_query(
+    executor: impl sqlx::PgExecutor<'_>,
+    name: &str,
+    enabled: bool,
+) -> Result<bool, sqlx::Error> {
+    let query = "
+        UPDATE integration
+        SET enabled = $1
+        WHERE name = $2 AND enabled IS DISTINCT FROM $1
+        RETURNING id
+    ";
+    sqlx::query_scalar::<_, i64>(query)
+        .bind(enabled)
+        .bind(name)
+        .fetch_optional(executor)
+        .await
+        .map(|id| id.is_some())
+}
+
+/// Starts or stops the task of the integration `name` after it was turned on or off.
+#[cfg(feature = "ssr")]
+pub async fn apply_integration_enabled(name: &str, enabled: bool) {
+    use crate::integrations::iron_nest::{supervisor::supervisor, types::ControlMessage};
+
+    if supervisor().integration(name).is_none() {
+        return;
+    }
+    let message = if enabled {
+        ControlMessage::Start
+    } else {
+        ControlMessage::Stop
+    };
+    if let Err(e) = supervisor().send(name, message).await {
+        log::error!(
+            "Failed to {} integration {name}: {e}",
+            if enabled { "start" } else { "stop" }
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use {crate::integrations::iron_nest::types::scene::Scene, leptos::prelude::*};
+
+#[server(GetScenes)]
+pub async fn get_scenes() -> Result<Vec<Scene>, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_scenes_query(&pool).await.map_err(Into::into)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_scenes_query(pool: &sqlx::PgPool) -> Result<Vec<Scene>, sqlx::Error> {
+    let query = "
+        SELECT name, devices, concurrency, updated_at
+        FROM scenes
+        ORDER BY name
+    ";
+    sqlx::query_as(query).fetch_all(pool).await
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_scene_query(
+    pool: &sqlx::PgPool,
+    name: &str,
+) -> Result<Option<Scene>, sqlx::Error> {
+    let query = "
+        SELECT name, devices, concurrency, updated_at
+        FROM scenes
+        WHERE name = $1
+    ";
+    sqlx::query_as(query).bind(name).fetch_optional(pool).await
+}
+
+#[cfg(feature = "ssr")]
+pub async fn save_scene_query(
+    executor: impl sqlx::PgExecutor<'_>,
+    scene: &Scene,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO scenes (name, devices, concurrency, updated_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO UPDATE
+        SET devices = $2,
+            concurrency = $3,
+            updated_at = $4
+    ";
+    sqlx::query(query)
+        .bind(&scene.name)
+        .bind(sqlx::types::Json(&scene.devices))
+        .bind(scene.concurrency)
+        .bind(scene.updated_at)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Captures the current states of `devices`, JSON of [`SceneTarget`]s, as the scene `name`.
+///
+/// [`SceneTarget`]: crate::integrations::iron_nest::types::scene::SceneTarget
+#[server(CaptureScene)]
+pub async fn capture_scene(
+    name: String,
+    devices: String,
+    concurrency: String,
+) -> Result<(), ServerFnError> {
+    use crate::integrations::iron_nest::{
+        scenes,
+        types::scene::{DEFAULT_CONCURRENCY, SceneTarget},
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ServerFnError::new("Name the scene"));
+    }
+    let targets = serde_json::from_str::<Vec<SceneTarget>>(&devices)
+        .map_err(|e| ServerFnError::new(format!("Invalid devices: {e}")))?;
+    let concurrency = match concurrency.trim() {
+        "" => DEFAULT_CONCURRENCY,
+        concurrency => concurrency
+            .parse::<i32>()
+            .ok()
+            .filter(|concurrency| *concurrency > 0)
+            .ok_or_else(|| ServerFnError::new("Concurrency must be a positive number"))?,
+    };
+    scenes::capture_scene(&pool, name, &targets, concurrency)
+        .await
+        .map_err(ServerFnError::new)?;
+    Ok(())
+}
+
+#[server(ApplyScene)]
+pub async fn apply_scene(name: String) -> Result<(), ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    crate::integrations::iron_nest::scenes::apply_scene(&pool, &name)
+        .await
+        .map_err(ServerFnError::new)?;
+    Ok(())
+}
+
+#[server(DeleteScene)]
+pub async fn delete_scene(name: String) -> Result<(), ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let query = "
+        DELETE FROM scenes
+        WHERE name = $1
+    ";
+    sqlx::query(query).bind(&name).execute(&pool).await?;
+    Ok(())
+}
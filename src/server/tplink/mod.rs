@@ -60,24 +60,34 @@ pub async fn handle_smart_power_strip_toggle(
 
 #[server(HandleSmartLightToggle)]
 pub async fn handle_smart_light_toggle(state: bool, ip: String) -> Result<(), ServerFnError> {
-    use {crate::integrations::tplink::tplink_turn_light_on_off, sqlx::PgPool};
+    use sqlx::PgPool;
+
+    let pool = use_context::<PgPool>().unwrap();
+    smart_light_toggle_query(&pool, state, &ip).await
+}
+
+/// Switches the light at `ip`, only recording its new state if it answered.
+#[cfg(feature = "ssr")]
+pub async fn smart_light_toggle_query(
+    pool: &sqlx::PgPool,
+    state: bool,
+    ip: &str,
+) -> Result<(), ServerFnError> {
+    use {crate::integrations::tplink::tplink_transition_light_state, serde_json::json};
 
     let state = if state { 1 } else { 0 };
 
-    let pool = use_context::<PgPool>().unwrap();
+    tplink_transition_light_state(ip, json!({ "on_off": state }), 0).await?;
     let query = "
         UPDATE device
         SET power_state = $1
         WHERE ip = $2
     ";
-    println!("state: {state}");
-    println!("ip: {ip}");
     sqlx::query(query)
-        .bind(state as i32)
-        .bind(&ip)
-        .execute(&pool)
+        .bind(state)
+        .bind(ip)
+        .execute(pool)
         .await?;
-    tplink_turn_light_on_off(&ip, state).await;
     Ok(())
 }
 
@@ -0,0 +1,95 @@
+use {
+    crate::integrations::iron_nest::types::vacation::{VacationMode, VacationPlan},
+    leptos::prelude::*,
+};
+
+#[server(GetVacationMode)]
+pub async fn get_vacation_mode() -> Result<Option<VacationMode>, ServerFnError> {
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    get_vacation_mode_query(&pool).await.map_err(Into::into)
+}
+
+#[cfg(feature = "ssr")]
+pub async fn get_vacation_mode_query(
+    pool: &sqlx::PgPool,
+) -> Result<Option<VacationMode>, sqlx::Error> {
+    let query = "
+        SELECT data->'vacation'
+        FROM config
+    ";
+    sqlx::query_scalar::<_, Option<sqlx::types::Json<Option<VacationMode>>>>(query)
+        .fetch_optional(pool)
+        .await
+        .map(|mode| mode.flatten().and_then(|mode| mode.0))
+}
+
+#[cfg(feature = "ssr")]
+pub async fn set_vacation_mode_query(
+    pool: &sqlx::PgPool,
+    mode: &VacationMode,
+) -> Result<(), sqlx::Error> {
+    let query = "
+        INSERT INTO config (id, data)
+        VALUES (1, jsonb_build_object('actions', '[]'::JSONB, 'vacation', $1::JSONB))
+        ON CONFLICT (id) DO UPDATE SET
+            data = jsonb_set(config.data, '{vacation}', $1::JSONB)
+    ";
+    sqlx::query(query)
+        .bind(sqlx::types::Json(mode))
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Saves the vacation mode from the settings page. `device_ids` and `windows` are JSON, windows
+/// only for the devices that shouldn't use learned times.
+#[server(SetVacationMode)]
+pub async fn set_vacation_mode(
+    enabled: Option<String>,
+    start_date: String,
+    end_date: String,
+    device_ids: String,
+    windows: String,
+    jitter_minutes: String,
+    timezone: String,
+) -> Result<(), ServerFnError> {
+    use crate::integrations::iron_nest::{
+        types::vacation::DEFAULT_JITTER_MINUTES, vacation::update_vacation_mode,
+    };
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let date = |date: &str, name: &str| {
+        date.parse()
+            .map_err(|e| ServerFnError::new(format!("Invalid {name} date {date:?}: {e}")))
+    };
+    let mode = VacationMode {
+        enabled: enabled.is_some(),
+        start_date: date(&start_date, "start")?,
+        end_date: date(&end_date, "end")?,
+        device_ids: serde_json::from_str(&device_ids)
+            .map_err(|e| ServerFnError::new(format!("Invalid devices: {e}")))?,
+        windows: serde_json::from_str(&windows)
+            .map_err(|e| ServerFnError::new(format!("Invalid windows: {e}")))?,
+        jitter_minutes: match jitter_minutes.trim() {
+            "" => DEFAULT_JITTER_MINUTES,
+            jitter_minutes => jitter_minutes
+                .parse()
+                .map_err(|_| ServerFnError::new("Jitter must be a number of minutes"))?,
+        },
+        timezone: Some(timezone.trim().to_owned()).filter(|timezone| !timezone.is_empty()),
+    };
+    update_vacation_mode(&pool, |_| Ok(mode))
+        .await
+        .map(drop)
+        .map_err(ServerFnError::new)
+}
+
+/// The switches planned for today, or for the first day of a vacation that's yet to start.
+#[server(GetVacationPlan)]
+pub async fn get_vacation_plan() -> Result<Option<VacationPlan>, ServerFnError> {
+    use crate::integrations::iron_nest::vacation::next_plan;
+    let pool = use_context::<sqlx::PgPool>().unwrap();
+    let Some(mode) = get_vacation_mode_query(&pool).await? else {
+        return Ok(None);
+    };
+    next_plan(&pool, &mode).await.map_err(Into::into)
+}